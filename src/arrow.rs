@@ -0,0 +1,168 @@
+//! Export to and import from the Arrow IPC stream format, behind the `arrow` feature.
+//!
+//! The stream holds record batches of a single non-nullable `Decimal128` column named `value`.
+use crate::format::check_decimals;
+use crate::{FloatpackError, PackedDecimals, Packer};
+use arrow_array::{Array, Decimal128Array, RecordBatch};
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use rust_decimal::Decimal;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// Largest precision of a `Decimal128` column.
+const PRECISION: u8 = 38;
+
+impl From<ArrowError> for FloatpackError {
+    fn from(e: ArrowError) -> Self {
+        FloatpackError::Arrow(e.to_string())
+    }
+}
+
+/// Write the values of `packed` as an Arrow IPC stream of batches of `batch_size` values.
+///
+/// The column has the largest scale of all values, smaller scales are rescaled to it. Values are
+/// decoded one batch at a time, after a first pass to find the scale. Fails if a rescaled value
+/// exceeds 38 digits, and with [`FloatpackError::TypeMismatch`] if `packed` holds values of
+/// another type than `Decimal`.
+///
+/// # Panics
+///
+/// Panics if `batch_size` is zero.
+pub fn write_arrow_ipc<W: Write>(
+    packed: &PackedDecimals,
+    writer: W,
+    batch_size: usize,
+) -> Result<(), FloatpackError> {
+    assert!(batch_size > 0, "batches need at least one value");
+    check_decimals(packed)?;
+    let scale = packed.iter().map(|d| d.scale()).max().unwrap_or(0);
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "value",
+        DataType::Decimal128(PRECISION, scale as i8),
+        false,
+    )]));
+    let mut writer = StreamWriter::try_new(writer, &schema)?;
+    let mut values = packed.iter();
+    loop {
+        let batch = values
+            .by_ref()
+            .take(batch_size)
+            .map(|d| rescale(d, scale))
+            .collect::<Result<Vec<i128>, _>>()?;
+        if batch.is_empty() {
+            break;
+        }
+        let column =
+            Decimal128Array::from(batch).with_precision_and_scale(PRECISION, scale as i8)?;
+        writer.write(&RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(column)],
+        )?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Pack the first column of an Arrow IPC stream, which has to be a `Decimal128` without nulls.
+pub fn read_arrow_ipc<R: Read>(reader: R) -> Result<PackedDecimals, FloatpackError> {
+    let reader = StreamReader::try_new(reader, None)?;
+    let scale = match reader.schema().fields().first().map(|f| f.data_type()) {
+        Some(DataType::Decimal128(_, scale)) if (0..=28).contains(scale) => *scale as u32,
+        other => {
+            return Err(FloatpackError::Arrow(format!(
+                "expected a Decimal128 column with a scale of 0 to 28, found {:?}",
+                other
+            )))
+        }
+    };
+    let mut packer = Packer::new();
+    for batch in reader {
+        let batch = batch?;
+        let column = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .expect("schema checked the column type");
+        if column.null_count() > 0 {
+            return Err(FloatpackError::Arrow("the column contains nulls".into()));
+        }
+        for v in column.values() {
+            let d = Decimal::try_from_i128_with_scale(*v, scale)
+                .map_err(|e| FloatpackError::Arrow(e.to_string()))?;
+            packer.load_decimal(&d);
+        }
+    }
+    Ok(packer.finish())
+}
+
+fn rescale(d: Decimal, scale: u32) -> Result<i128, FloatpackError> {
+    10i128
+        .checked_pow(scale - d.scale())
+        .and_then(|factor| d.mantissa().checked_mul(factor))
+        .filter(|v| v.unsigned_abs() < 10u128.pow(PRECISION as u32))
+        .ok_or_else(|| FloatpackError::Arrow(format!("{} exceeds 38 digits at scale {}", d, scale)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_arrow_ipc, write_arrow_ipc};
+    use crate::{pack, unpack};
+    use arrow_array::{Array, Decimal128Array};
+    use arrow_ipc::reader::StreamReader;
+    use arrow_schema::DataType;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::*;
+
+    fn values() -> Vec<Decimal> {
+        (0..1000)
+            .map(|v| Decimal::new(v * 7919 % 100_000 - 50_000, (v % 4) as u32))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let values = values();
+        for batch_size in [1, 100, 256, 257, 5000] {
+            let mut buffer = Vec::new();
+            write_arrow_ipc(&pack(&values), &mut buffer, batch_size).unwrap();
+            assert_eq!(unpack(&read_arrow_ipc(&buffer[..]).unwrap()), values);
+        }
+        let mut buffer = Vec::new();
+        write_arrow_ipc(&pack(&[]), &mut buffer, 10).unwrap();
+        assert!(read_arrow_ipc(&buffer[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn arrow_reader() {
+        let values = values();
+        let mut buffer = Vec::new();
+        write_arrow_ipc(&pack(&values), &mut buffer, 300).unwrap();
+        let reader = StreamReader::try_new(&buffer[..], None).unwrap();
+        let field = reader.schema().field(0).clone();
+        assert_eq!(field.name(), "value");
+        assert_eq!(field.data_type(), &DataType::Decimal128(38, 3));
+        assert!(!field.is_nullable());
+        let mut read = Vec::new();
+        for batch in reader {
+            let batch = batch.unwrap();
+            assert!(batch.num_rows() <= 300);
+            let column = batch.column(0).as_any().downcast_ref::<Decimal128Array>();
+            read.extend(
+                column
+                    .unwrap()
+                    .iter()
+                    .map(|v| Decimal::from_i128_with_scale(v.unwrap(), 3)),
+            );
+        }
+        assert_eq!(read, values);
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut buffer = Vec::new();
+        let packed = pack(&[Decimal::MAX, dec!(0.0000000001)]);
+        assert!(write_arrow_ipc(&packed, &mut buffer, 10).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Packing values of async streams, behind the `futures` feature.
+use crate::{PackedDecimals, Packer};
+use futures_util::{pin_mut, Stream, StreamExt};
+use rust_decimal::Decimal;
+
+/// Pack the values of `stream` as they arrive, without collecting them first.
+///
+/// Packing each value is synchronous and cheap, the future just waits for the next one in
+/// between. Pending values are flushed when the stream ends.
+///
+/// Usage example:
+/// ```
+/// # futures_util::FutureExt::now_or_never(async {
+/// use floatpack::{pack_stream, unpack};
+/// use futures_util::stream;
+/// use rust_decimal_macros::*;
+///
+/// let packed = pack_stream(stream::iter([dec!(1.5), dec!(2.25)])).await;
+/// assert_eq!(unpack(&packed), [dec!(1.5), dec!(2.25)]);
+/// # }).unwrap();
+/// ```
+pub async fn pack_stream<S: Stream<Item = Decimal>>(stream: S) -> PackedDecimals {
+    pack_stream_with(Packer::new(), stream).await
+}
+
+/// Pack the values of `stream` like [`pack_stream`], with the options of `packer`.
+pub async fn pack_stream_with<S: Stream<Item = Decimal>>(
+    mut packer: Packer,
+    stream: S,
+) -> PackedDecimals {
+    pin_mut!(stream);
+    while let Some(value) = stream.next().await {
+        packer.load_decimal(&value);
+    }
+    packer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pack_stream, pack_stream_with};
+    use crate::{pack, Packer};
+    use futures_util::{stream, FutureExt, StreamExt};
+    use rust_decimal::Decimal;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// Polls `future` to completion, as the streams of the tests wake up right away.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn packs_as_values_arrive() {
+        let values: Vec<Decimal> = (0..1000i64)
+            .map(|i| Decimal::new(i * 37 % 501, 2))
+            .collect();
+        // Every other value isn't ready when first polled.
+        let mut pending = false;
+        let stream = stream::iter(values.clone()).then(|v| {
+            pending = !pending;
+            let mut wait = pending;
+            futures_util::future::poll_fn(move |cx| {
+                if std::mem::take(&mut wait) {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                } else {
+                    Poll::Ready(v)
+                }
+            })
+        });
+        let packed = block_on(pack_stream(stream));
+        assert_eq!(packed, pack(&values));
+
+        let packer = Packer::new().with_chained_heads();
+        let chained = block_on(pack_stream_with(packer, stream::iter(values.clone())));
+        assert!(chained.chained());
+        assert_eq!(crate::unpack(&chained), values);
+
+        let empty = pack_stream(stream::empty()).now_or_never().unwrap();
+        assert!(empty.is_empty());
+    }
+}
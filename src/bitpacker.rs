@@ -0,0 +1,156 @@
+use bitpacking::{BitPacker, BitPacker1x, BitPacker4x, BitPacker8x};
+
+/// Largest supported block length, used to size buffers that fit any block.
+pub(crate) const MAX_BLOCK_LEN: usize = BitPacker8x::BLOCK_LEN;
+
+/// One of the `bitpacking` implementations, selected by the block length they work on.
+///
+/// Each produces its own bit layout, so a stream has to be decoded with the same
+/// implementation it was packed with. Which one that is follows from the block length recorded
+/// in the stream. Each picks SIMD instructions (SSE3 for `BitPacker4x`, AVX2 for `BitPacker8x`)
+/// when the CPU has them at runtime and scalar code otherwise, with the same layout either way,
+/// so streams decode on any hardware. The layout is that of [`layout`]: the packers store
+/// 32-bit words in native byte order, which is turned into little endian on big-endian targets,
+/// so packing yields the same bytes on every target.
+#[derive(Clone, Copy)]
+pub(crate) enum AnyBitPacker {
+    X1(BitPacker1x),
+    X4(BitPacker4x),
+    X8(BitPacker8x),
+}
+
+impl AnyBitPacker {
+    /// The bitpacker for blocks of `block_len` deltas, if there is one.
+    pub(crate) fn new(block_len: usize) -> Option<Self> {
+        match block_len {
+            BitPacker1x::BLOCK_LEN => Some(AnyBitPacker::X1(BitPacker1x::new())),
+            BitPacker4x::BLOCK_LEN => Some(AnyBitPacker::X4(BitPacker4x::new())),
+            BitPacker8x::BLOCK_LEN => Some(AnyBitPacker::X8(BitPacker8x::new())),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn block_len(&self) -> usize {
+        match self {
+            AnyBitPacker::X1(_) => BitPacker1x::BLOCK_LEN,
+            AnyBitPacker::X4(_) => BitPacker4x::BLOCK_LEN,
+            AnyBitPacker::X8(_) => BitPacker8x::BLOCK_LEN,
+        }
+    }
+
+    /// Size of a compressed block of `bits` wide values.
+    pub(crate) fn compressed_len(&self, bits: u8) -> usize {
+        bits as usize * self.block_len() / 8
+    }
+
+    pub(crate) fn num_bits(&self, decompressed: &[u32]) -> u8 {
+        match self {
+            AnyBitPacker::X1(p) => p.num_bits(decompressed),
+            AnyBitPacker::X4(p) => p.num_bits(decompressed),
+            AnyBitPacker::X8(p) => p.num_bits(decompressed),
+        }
+    }
+
+    pub(crate) fn compress(&self, decompressed: &[u32], compressed: &mut [u8], bits: u8) -> usize {
+        let len = match self {
+            AnyBitPacker::X1(p) => p.compress(decompressed, compressed, bits),
+            AnyBitPacker::X4(p) => p.compress(decompressed, compressed, bits),
+            AnyBitPacker::X8(p) => p.compress(decompressed, compressed, bits),
+        };
+        if cfg!(target_endian = "big") {
+            swap_words(&mut compressed[..len]);
+        }
+        len
+    }
+
+    pub(crate) fn decompress(
+        &self,
+        compressed: &[u8],
+        decompressed: &mut [u32],
+        bits: u8,
+    ) -> usize {
+        // Blocks of bit width 0 store no values at all, so there is nothing to hand over.
+        if bits == 0 {
+            decompressed.fill(0);
+            return 0;
+        }
+        // Swapped on the stack, so decompressing never allocates.
+        let mut native = [0u8; 4 * MAX_BLOCK_LEN];
+        let compressed = if cfg!(target_endian = "big") {
+            let len = self.compressed_len(bits).min(compressed.len());
+            native[..len].copy_from_slice(&compressed[..len]);
+            swap_words(&mut native[..len]);
+            &native[..len]
+        } else {
+            compressed
+        };
+        match self {
+            AnyBitPacker::X1(p) => p.decompress(compressed, decompressed, bits),
+            AnyBitPacker::X4(p) => p.decompress(compressed, decompressed, bits),
+            AnyBitPacker::X8(p) => p.decompress(compressed, decompressed, bits),
+        }
+    }
+}
+
+/// Turns the 32-bit words of `bytes` from little to native byte order or back.
+fn swap_words(bytes: &mut [u8]) {
+    for word in bytes.chunks_exact_mut(4) {
+        word.reverse();
+    }
+}
+
+/// The bit layout of all bitpackers, packing `decompressed` values of `bits` bits into the
+/// words of `lanes` interleaved lanes: value `j` goes to lane `j % lanes`, whose values are
+/// packed one after the other, least significant bit first. Word `k` of lane `l` is stored at
+/// index `k * lanes + l`, in little endian.
+///
+/// `BitPacker1x` has a single lane, `BitPacker4x` 4 and `BitPacker8x` 8, matching the width of
+/// their SIMD registers.
+#[cfg(test)]
+pub(crate) fn layout(lanes: usize, decompressed: &[u32], bits: u8) -> Vec<u8> {
+    let bits = bits as usize;
+    let mut words = vec![0u32; decompressed.len() * bits / 32];
+    for (j, v) in decompressed.iter().enumerate() {
+        let (lane, offset) = (j % lanes, j / lanes * bits);
+        for b in (0..bits).filter(|b| v >> b & 1 == 1) {
+            let bit = offset + b;
+            words[bit / 32 * lanes + lane] |= 1 << (bit % 32);
+        }
+    }
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{layout, AnyBitPacker};
+
+    #[test]
+    fn golden_layout() {
+        // Whichever implementation `bitpacking` selects for this CPU, scalar or SIMD, has to
+        // produce the layout given by the scalar reference.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u32
+        };
+        for (lanes, block_len) in [(1, 32), (4, 128), (8, 256)] {
+            let bitpacker = AnyBitPacker::new(block_len).unwrap();
+            for bits in 0..=32u8 {
+                let mask = u32::MAX.checked_shr(32 - bits as u32).unwrap_or(0);
+                let values: Vec<u32> = (0..block_len).map(|_| random() & mask).collect();
+                let expected = layout(lanes, &values, bits);
+                let mut compressed = vec![0u8; bitpacker.compressed_len(bits)];
+                assert_eq!(
+                    bitpacker.compress(&values, &mut compressed, bits),
+                    expected.len()
+                );
+                assert_eq!(compressed, expected, "{} bits of {} lanes", bits, lanes);
+                let mut decompressed = vec![u32::MAX; block_len];
+                bitpacker.decompress(&expected, &mut decompressed, bits);
+                assert_eq!(decompressed, values);
+            }
+        }
+    }
+}
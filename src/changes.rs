@@ -0,0 +1,170 @@
+//! First differences and simple returns of values as they are decoded.
+use crate::Values;
+use rust_decimal::Decimal;
+
+impl<'a> Values<'a> {
+    /// The differences `value[i] - value[i - 1]` of consecutive values, one fewer than there are
+    /// values.
+    ///
+    /// Only the previous value is kept, which carries over from one block to the next.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a difference overflows `Decimal`.
+    ///
+    /// Usage example:
+    /// ```
+    /// use floatpack::pack;
+    /// use rust_decimal_macros::*;
+    ///
+    /// let packed = pack(&[dec!(1.5), dec!(2.5), dec!(2.0)]);
+    /// let diffs: Vec<_> = packed.iter().diffs().collect();
+    /// assert_eq!(diffs, [dec!(1.0), dec!(-0.5)]);
+    /// ```
+    pub fn diffs(self) -> Diffs<'a> {
+        Diffs {
+            values: self,
+            previous: None,
+        }
+    }
+
+    /// The simple returns `(value[i] - value[i - 1]) / value[i - 1]` of consecutive values, one
+    /// fewer than there are values.
+    ///
+    /// A return is `None` if the previous value is zero, so the returns stay aligned with the
+    /// values, or if it overflows `Decimal`. Only the previous value is kept, which carries over
+    /// from one block to the next.
+    ///
+    /// Usage example:
+    /// ```
+    /// use floatpack::pack;
+    /// use rust_decimal_macros::*;
+    ///
+    /// let packed = pack(&[dec!(2), dec!(2.5), dec!(0), dec!(1)]);
+    /// let returns: Vec<_> = packed.iter().returns().collect();
+    /// assert_eq!(returns, [Some(dec!(0.25)), Some(dec!(-1)), None]);
+    /// ```
+    pub fn returns(self) -> Returns<'a> {
+        Returns {
+            values: self,
+            previous: None,
+        }
+    }
+}
+
+/// Iterator over the differences of consecutive values, decoding one block at a time.
+///
+/// Created by [`Values::diffs`].
+pub struct Diffs<'a> {
+    values: Values<'a>,
+    previous: Option<Decimal>,
+}
+
+impl Iterator for Diffs<'_> {
+    type Item = Decimal;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (previous, value) = next_pair(&mut self.values, &mut self.previous)?;
+        Some(value - previous)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = remaining(&self.values, self.previous);
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Diffs<'_> {}
+
+/// Iterator over the simple returns of consecutive values, decoding one block at a time.
+///
+/// Created by [`Values::returns`].
+pub struct Returns<'a> {
+    values: Values<'a>,
+    previous: Option<Decimal>,
+}
+
+impl Iterator for Returns<'_> {
+    type Item = Option<Decimal>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (previous, value) = next_pair(&mut self.values, &mut self.previous)?;
+        Some(
+            value
+                .checked_sub(previous)
+                .and_then(|d| d.checked_div(previous)),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = remaining(&self.values, self.previous);
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Returns<'_> {}
+
+/// The next value along with its predecessor, which is read first if there is none yet.
+fn next_pair(values: &mut Values, previous: &mut Option<Decimal>) -> Option<(Decimal, Decimal)> {
+    let last = match *previous {
+        Some(last) => last,
+        None => values.next()?,
+    };
+    let value = values.next()?;
+    *previous = Some(value);
+    Some((last, value))
+}
+
+fn remaining(values: &Values, previous: Option<Decimal>) -> usize {
+    match previous {
+        Some(_) => values.len(),
+        None => values.len().saturating_sub(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pack, unpack, Packer};
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn across_blocks() {
+        let mut values: Vec<Decimal> = (0..2000i64)
+            .map(|i| Decimal::new(10_000 + i * 7919 % 503 - 251, 2))
+            .collect();
+        values[300] = Decimal::ZERO;
+        values[1000] = -Decimal::new(0, 3);
+        values[1001] = Decimal::new(5, 3);
+        let chained = crate::tests::pack_with(Packer::new().with_chained_heads(), &values);
+        let short = crate::tests::pack_with(Packer::new().with_block_len(32).unwrap(), &values);
+        for packed in [pack(&values), chained, short] {
+            let unpacked = unpack(&packed);
+            let diffs: Vec<Decimal> = unpacked.windows(2).map(|w| w[1] - w[0]).collect();
+            assert_eq!(packed.iter().diffs().len(), diffs.len());
+            assert_eq!(packed.iter().diffs().collect::<Vec<_>>(), diffs);
+
+            let returns: Vec<Option<Decimal>> = unpacked
+                .windows(2)
+                .map(|w| Some(w[0]).filter(|p| !p.is_zero()).map(|p| (w[1] - p) / p))
+                .collect();
+            let mut iter = packed.iter().returns();
+            assert_eq!(iter.len(), returns.len());
+            iter.next();
+            assert_eq!(iter.len(), returns.len() - 1);
+            assert_eq!(packed.iter().returns().collect::<Vec<_>>(), returns);
+            assert_eq!(returns[299], Some(Decimal::NEGATIVE_ONE));
+            assert_eq!(returns[300], None);
+            assert_eq!(returns[1000], None);
+        }
+
+        // Overflows yield `None` without ending the returns.
+        let extremes = pack(&[Decimal::MIN, Decimal::MAX, Decimal::MAX]);
+        let returns: Vec<_> = extremes.iter().returns().collect();
+        assert_eq!(returns, [None, Some(Decimal::ZERO)]);
+
+        for short in [&[][..], &values[..1]] {
+            assert_eq!(pack(short).iter().diffs().len(), 0);
+            assert_eq!(pack(short).iter().returns().next(), None);
+        }
+    }
+}
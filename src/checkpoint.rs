@@ -0,0 +1,406 @@
+//! Checkpoints of the complete state of a [`Packer`], to resume packing after a restart.
+//!
+//! A checkpoint starts with the magic bytes `FPCK` and a version byte, followed by the options
+//! of the packer, the current frame and the frames not taken yet (each in the format of
+//! [`PackedDecimals::write_to`]) and the pending block. All integers are little endian.
+use crate::bitpacker::AnyBitPacker;
+use crate::format::{corrupt, read_u32, read_u64, read_u8};
+use crate::{unzip_u8, zip_u8, Cache, FloatpackError, LaneFn, PackedDecimals, Packer, LANE_FN_TAG};
+use rust_decimal::Decimal;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::time::Instant;
+
+const MAGIC: [u8; 4] = *b"FPCK";
+const VERSION: u8 = 1;
+
+const ADAPTIVE: u8 = 1;
+const SIGN_MAGNITUDE: u8 = 2;
+/// The flags are followed by the pad value.
+const PAD: u8 = 4;
+const DELTA_OF_DELTA: u8 = 8;
+const CHAINED: u8 = 16;
+const BLOCK_BOUNDS: u8 = 32;
+const BYTE_SPLIT: u8 = 64;
+const KEYFRAMES: u8 = 128;
+
+impl Packer {
+    /// Serialize the complete state of the packer, including the values not flushed yet.
+    ///
+    /// A packer restored from the checkpoint with [`Packer::restore`] continues exactly where
+    /// this one is now, producing the same output as if it had never been interrupted.
+    pub fn checkpoint(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        let flags = if self.adaptive { ADAPTIVE } else { 0 }
+            | if self.sign_magnitude {
+                SIGN_MAGNITUDE
+            } else {
+                0
+            }
+            | if self.pad.is_some() { PAD } else { 0 }
+            | if self.delta_of_delta {
+                DELTA_OF_DELTA
+            } else {
+                0
+            }
+            | if self.chained { CHAINED } else { 0 }
+            | if self.block_bounds { BLOCK_BOUNDS } else { 0 }
+            | if self.byte_split { BYTE_SPLIT } else { 0 }
+            | if self.keyframes.is_some() {
+                KEYFRAMES
+            } else {
+                0
+            };
+        bytes.push(flags);
+        if let Some(pad) = self.pad {
+            bytes.extend(unzip_u8(pad));
+        }
+        if let Some(k) = self.keyframes {
+            bytes.extend((k as u64).to_le_bytes());
+        }
+        write_option(
+            &mut bytes,
+            self.flush_bytes.map(|n| (n as u64).to_le_bytes()),
+        );
+        write_option(&mut bytes, self.deadband.map(|d| d.serialize()));
+        write_option(&mut bytes, self.stored.map(|d| d.serialize()));
+        bytes.extend((self.dropped as u64).to_le_bytes());
+        write_option(&mut bytes, self.last.map(unzip_u8));
+
+        self.packed
+            .write_to(&mut bytes)
+            .expect("writing to a Vec never fails");
+        bytes.extend((self.frames.len() as u64).to_le_bytes());
+        for frame in &self.frames {
+            frame
+                .write_to(&mut bytes)
+                .expect("writing to a Vec never fails");
+        }
+
+        let cache = &self.cache;
+        write_option(&mut bytes, cache.buffer.map(unzip_u8));
+        bytes.extend(unzip_u8(cache.head));
+        bytes.extend((cache.idx as u64).to_le_bytes());
+        for lane in &cache.compressed {
+            for delta in &lane[..cache.idx] {
+                bytes.extend(delta.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Recreate a packer from a [`Packer::checkpoint`].
+    ///
+    /// [`Packer::with_diagnostics`] isn't part of the checkpoint; enable it again if needed.
+    /// Checkpoints of packers with [`Packer::with_lane_fn`] fail with
+    /// [`FloatpackError::TypeMismatch`], see [`Packer::restore_with_lane_fn`].
+    pub fn restore(bytes: &[u8]) -> Result<Packer, FloatpackError> {
+        Packer::restore_layout(bytes, None)
+    }
+
+    /// Recreate a packer with [`Packer::with_lane_fn`] from its [`Packer::checkpoint`], which
+    /// doesn't hold the functions, given them again.
+    ///
+    /// Checkpoints of packers in the default layout fail with [`FloatpackError::TypeMismatch`].
+    pub fn restore_with_lane_fn(
+        bytes: &[u8],
+        to_lanes: fn(&Decimal) -> [u32; 4],
+        from_lanes: fn([u32; 4]) -> Decimal,
+    ) -> Result<Packer, FloatpackError> {
+        let lane_fn = LaneFn {
+            to_lanes,
+            from_lanes,
+        };
+        Packer::restore_layout(bytes, Some(lane_fn))
+    }
+
+    fn restore_layout(mut bytes: &[u8], lane_fn: Option<LaneFn>) -> Result<Packer, FloatpackError> {
+        let reader = &mut bytes;
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(corrupt("missing checkpoint magic bytes".into()));
+        }
+        if header[4] != VERSION {
+            return Err(FloatpackError::UnsupportedVersion(header[4]));
+        }
+        let flags = read_u8(reader)?;
+        let known = ADAPTIVE
+            | SIGN_MAGNITUDE
+            | PAD
+            | DELTA_OF_DELTA
+            | CHAINED
+            | BLOCK_BOUNDS
+            | BYTE_SPLIT
+            | KEYFRAMES;
+        if flags & !known != 0 {
+            return Err(corrupt(format!("unknown flags {:#x}", flags)));
+        }
+        let pad = if flags & PAD != 0 {
+            Some(read_lanes(reader)?)
+        } else {
+            None
+        };
+        let keyframes = if flags & KEYFRAMES != 0 {
+            match read_u64(reader)? {
+                0 => return Err(corrupt("keyframe interval of 0 blocks".into())),
+                k => Some(k as usize),
+            }
+        } else {
+            None
+        };
+        let flush_bytes = read_option(reader, |r| Ok(read_u64(r)? as usize))?;
+        let deadband = read_option(reader, read_decimal)?;
+        let stored = read_option(reader, read_decimal)?;
+        let dropped = read_u64(reader)? as usize;
+        let last = read_option(reader, read_lanes)?;
+
+        let packed = PackedDecimals::read_from(&mut *reader)?;
+        let frame_count = read_u64(reader)?;
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            frames.push(PackedDecimals::read_from(&mut *reader)?);
+        }
+        let expected = if lane_fn.is_some() { LANE_FN_TAG } else { 0 };
+        if let Some(other) = frames
+            .iter()
+            .chain([&packed])
+            .find(|f| f.type_tag != expected)
+        {
+            return Err(FloatpackError::TypeMismatch {
+                expected,
+                found: other.type_tag,
+            });
+        }
+
+        let mut cache = Cache {
+            buffer: read_option(reader, read_lanes)?,
+            head: read_lanes(reader)?,
+            ..Cache::default()
+        };
+        cache.idx = read_u64(reader)? as usize;
+        if cache.idx >= packed.block_len || (cache.buffer.is_none() && cache.idx > 0) {
+            return Err(corrupt(format!("{} pending deltas", cache.idx)));
+        }
+        for (lane, width) in cache.compressed.iter_mut().zip(cache.widths.iter_mut()) {
+            for delta in lane[..cache.idx].iter_mut() {
+                *delta = read_u32(reader)?;
+                *width |= *delta;
+            }
+        }
+        if !reader.is_empty() {
+            return Err(corrupt(format!("{} trailing bytes", reader.len())));
+        }
+        if flags & BLOCK_BOUNDS != 0 && cache.buffer.is_some() {
+            // The bounds of the pending block follow from its values.
+            let decimal = |lanes| match lane_fn {
+                Some(f) => (f.from_lanes)(lanes),
+                None => Decimal::deserialize(unzip_u8(lanes)),
+            };
+            let mut lanes = cache.head;
+            let first = decimal(lanes);
+            let mut bounds = (first, first);
+            for idx in 0..cache.idx {
+                for (lane, deltas) in lanes.iter_mut().zip(&cache.compressed) {
+                    *lane ^= deltas[idx];
+                }
+                let v = decimal(lanes);
+                bounds = (bounds.0.min(v), bounds.1.max(v));
+            }
+            cache.bounds = Some(bounds);
+        }
+
+        let frame_bytes = packed.blocks.iter().flatten().map(|b| b.size_bytes()).sum();
+        Ok(Packer {
+            bitpacker: AnyBitPacker::new(packed.block_len)
+                .ok_or(FloatpackError::UnsupportedBlockLen(packed.block_len))?,
+            cache,
+            packed,
+            flush_bytes,
+            frame_bytes,
+            frames,
+            // Reserved room is a hint and not checkpointed.
+            frame_blocks: 0,
+            last,
+            deadband,
+            stored,
+            dropped,
+            adaptive: flags & ADAPTIVE != 0,
+            raw: false,
+            byte_split: flags & BYTE_SPLIT != 0,
+            sign_magnitude: flags & SIGN_MAGNITUDE != 0,
+            delta_of_delta: flags & DELTA_OF_DELTA != 0,
+            chained: flags & CHAINED != 0,
+            keyframes,
+            block_bounds: flags & BLOCK_BOUNDS != 0,
+            pad,
+            compacted: Instant::now(),
+            diagnostics: None,
+            lane_fn,
+            _type: PhantomData,
+        })
+    }
+}
+
+fn write_option<const N: usize>(bytes: &mut Vec<u8>, value: Option<[u8; N]>) {
+    match value {
+        Some(value) => {
+            bytes.push(1);
+            bytes.extend(value);
+        }
+        None => bytes.push(0),
+    }
+}
+
+fn read_option<T>(
+    reader: &mut &[u8],
+    read: impl FnOnce(&mut &[u8]) -> Result<T, FloatpackError>,
+) -> Result<Option<T>, FloatpackError> {
+    match read_u8(reader)? {
+        0 => Ok(None),
+        1 => read(reader).map(Some),
+        tag => Err(corrupt(format!("invalid option tag {}", tag))),
+    }
+}
+
+fn read_bytes(reader: &mut &[u8]) -> Result<[u8; 16], FloatpackError> {
+    let mut bytes = [0u8; 16];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_lanes(reader: &mut &[u8]) -> Result<[u32; 4], FloatpackError> {
+    Ok(zip_u8(read_bytes(reader)?))
+}
+
+fn read_decimal(reader: &mut &[u8]) -> Result<Decimal, FloatpackError> {
+    Ok(Decimal::deserialize(read_bytes(reader)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bytes_equal, try_unpack_with_lanes, unpack, unzip_u8, zip_u8, FloatpackError,
+        PackedDecimals, Packer,
+    };
+    use rust_decimal::Decimal;
+
+    fn configured() -> Packer {
+        Packer::new()
+            .with_block_len(128)
+            .unwrap()
+            .with_keyframes(3)
+            .with_adaptive_encoding()
+            .with_sign_magnitude()
+            .with_deadband(Decimal::new(1, 2))
+            .with_flush_bytes(1500)
+            .with_pad_value(Decimal::MAX)
+            .with_block_bounds()
+            .with_byte_split()
+    }
+
+    fn finish(mut packer: Packer, frames: &mut Vec<PackedDecimals>) {
+        frames.extend(packer.take_frames());
+        frames.extend(packer.finish_frames());
+    }
+
+    #[test]
+    fn resumes() {
+        let values: Vec<Decimal> = (0..3000)
+            .map(|v| Decimal::new((v * 37) % 1001 + v, 2))
+            .collect();
+        let mut uninterrupted = configured();
+        for v in &values {
+            uninterrupted.load_decimal(v);
+        }
+        let mut expected = Vec::new();
+        finish(uninterrupted, &mut expected);
+
+        for split in [0, 1, 129, 1000, 2999, 3000] {
+            let mut packer = configured();
+            for v in &values[..split] {
+                packer.load_decimal(v);
+            }
+            let mut packer = Packer::restore(&packer.checkpoint()).unwrap();
+            for v in &values[split..] {
+                packer.load_decimal(v);
+            }
+            let mut frames = Vec::new();
+            finish(packer, &mut frames);
+            assert_eq!(frames.len(), expected.len());
+            assert!(frames.iter().zip(&expected).all(|(a, b)| bytes_equal(a, b)));
+        }
+        let unpacked: Vec<Decimal> = expected.iter().flat_map(unpack).collect();
+        assert!(unpacked.len() > 2000);
+    }
+
+    #[test]
+    fn lane_fn() {
+        fn rotated(d: &Decimal) -> [u32; 4] {
+            zip_u8(d.serialize()).map(|lane| lane.rotate_left(7))
+        }
+        fn unrotated(lanes: [u32; 4]) -> Decimal {
+            Decimal::deserialize(unzip_u8(lanes.map(|lane| lane.rotate_right(7))))
+        }
+        let values: Vec<Decimal> = (0..1000).map(|v| Decimal::new(v * 37 % 1001, 2)).collect();
+        let packer = || {
+            Packer::new()
+                .with_pad_value(Decimal::NEGATIVE_ONE)
+                .with_block_bounds()
+                .with_lane_fn(rotated, unrotated)
+        };
+        let mut uninterrupted = packer();
+        for v in &values {
+            uninterrupted.load_decimal(v);
+        }
+        let expected = uninterrupted.finish();
+
+        let mut packer = packer();
+        for v in &values[..500] {
+            packer.load_decimal(v);
+        }
+        let checkpoint = packer.checkpoint();
+        assert!(matches!(
+            Packer::restore(&checkpoint),
+            Err(FloatpackError::TypeMismatch {
+                expected: 0,
+                found: 6
+            })
+        ));
+        let mut packer = Packer::restore_with_lane_fn(&checkpoint, rotated, unrotated).unwrap();
+        for v in &values[500..] {
+            packer.load_decimal(v);
+        }
+        let packed = packer.finish();
+        assert!(bytes_equal(&packed, &expected));
+        assert_eq!(try_unpack_with_lanes(&packed, unrotated).unwrap(), values);
+
+        let default = Packer::new().checkpoint();
+        assert!(matches!(
+            Packer::restore_with_lane_fn(&default, rotated, unrotated),
+            Err(FloatpackError::TypeMismatch {
+                expected: 6,
+                found: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let mut packer = Packer::new();
+        packer.load_decimal(&Decimal::ONE);
+        packer.load_decimal(&Decimal::TWO);
+        let checkpoint = packer.checkpoint();
+        assert!(Packer::restore(&checkpoint).is_ok());
+        for len in 0..checkpoint.len() {
+            assert!(Packer::restore(&checkpoint[..len]).is_err());
+        }
+        let mut trailing = checkpoint.clone();
+        trailing.push(0);
+        assert!(Packer::restore(&trailing).is_err());
+        let mut version = checkpoint;
+        version[4] = 9;
+        assert!(Packer::restore(&version).is_err());
+    }
+}
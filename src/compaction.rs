@@ -0,0 +1,258 @@
+//! Policies deciding when a [`Packer`] compacts its frames.
+use crate::decode::Columns;
+use crate::{PackedDecimals, Packer};
+use std::time::{Duration, Instant};
+
+/// State of the completed frames of a [`Packer`], the input of a [`CompactionPolicy`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompactionStats {
+    /// Count of frames completed and not taken yet.
+    pub frames: usize,
+    /// Count of blocks per lane in these frames.
+    pub blocks: usize,
+    /// Count of blocks per lane their values need when packed into full blocks.
+    pub min_blocks: usize,
+    /// Share of the block capacity of these frames holding values, 1 if all blocks are full.
+    pub fill_ratio: f64,
+    /// Serialized size of these frames in bytes, see [`PackedDecimals::serialized_len`].
+    pub bytes: usize,
+    /// Time since the packer was created or last compacted.
+    pub since_last: Duration,
+}
+
+/// What [`Packer::maybe_compact`] does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compaction {
+    /// Nothing.
+    Skip,
+    /// Flush the pending block and shrink the buffers, see [`Packer::compact`].
+    Shrink,
+    /// Pack the values of the completed frames again, joining the partial blocks their frames
+    /// end with into full blocks. Frames are split as configured by [`Packer::with_flush_bytes`].
+    Repack,
+}
+
+/// Decides when and how a packer compacts.
+pub trait CompactionPolicy {
+    /// What to do about the completed frames described by `stats`.
+    fn decide(&self, stats: &CompactionStats) -> Compaction;
+}
+
+impl<F: Fn(&CompactionStats) -> Compaction> CompactionPolicy for F {
+    fn decide(&self, stats: &CompactionStats) -> Compaction {
+        self(stats)
+    }
+}
+
+/// Never compacts.
+pub struct Never;
+
+impl CompactionPolicy for Never {
+    fn decide(&self, _: &CompactionStats) -> Compaction {
+        Compaction::Skip
+    }
+}
+
+/// Repacks once the fill ratio of the completed frames drops below the threshold.
+pub struct FillRatioBelow(pub f64);
+
+impl CompactionPolicy for FillRatioBelow {
+    fn decide(&self, stats: &CompactionStats) -> Compaction {
+        if stats.fill_ratio < self.0 {
+            Compaction::Repack
+        } else {
+            Compaction::Skip
+        }
+    }
+}
+
+/// Repacks once the completed frames hold more than this count of blocks per lane beyond the
+/// blocks their values need.
+pub struct ExcessBlocksAbove(pub usize);
+
+impl CompactionPolicy for ExcessBlocksAbove {
+    fn decide(&self, stats: &CompactionStats) -> Compaction {
+        if stats.blocks - stats.min_blocks > self.0 {
+            Compaction::Repack
+        } else {
+            Compaction::Skip
+        }
+    }
+}
+
+impl Packer {
+    /// State of the completed frames, as passed to a [`CompactionPolicy`].
+    pub fn compaction_stats(&self) -> CompactionStats {
+        let blocks: usize = self.frames.iter().map(|f| f.blocks[0].len()).sum();
+        let count: usize = self.frames.iter().map(PackedDecimals::len).sum();
+        let capacity = blocks * self.packed.block_values();
+        CompactionStats {
+            frames: self.frames.len(),
+            blocks,
+            min_blocks: count.div_ceil(self.packed.block_values()),
+            fill_ratio: if capacity == 0 {
+                1.0
+            } else {
+                count as f64 / capacity as f64
+            },
+            bytes: self.frames.iter().map(PackedDecimals::serialized_len).sum(),
+            since_last: self.compacted.elapsed(),
+        }
+    }
+
+    /// Compact as `policy` decides, returning what was done.
+    pub fn maybe_compact(&mut self, policy: &impl CompactionPolicy) -> Compaction {
+        let compaction = policy.decide(&self.compaction_stats());
+        match compaction {
+            Compaction::Skip => return compaction,
+            Compaction::Shrink => {
+                self.compact();
+            }
+            Compaction::Repack => {
+                self.repack_frames();
+                self.compacted = Instant::now();
+            }
+        }
+        compaction
+    }
+
+    fn repack_frames(&mut self) {
+        // The dead band was applied when loading already.
+        let mut packer = Packer {
+            bitpacker: self.bitpacker,
+            packed: PackedDecimals {
+                block_len: self.packed.block_len,
+                type_tag: self.packed.type_tag,
+                ..PackedDecimals::default()
+            },
+            flush_bytes: self.flush_bytes,
+            adaptive: self.adaptive,
+            sign_magnitude: self.sign_magnitude,
+            delta_of_delta: self.delta_of_delta,
+            chained: self.chained,
+            block_bounds: self.block_bounds,
+            pad: self.pad,
+            lane_fn: self.lane_fn,
+            ..Packer::new()
+        };
+        for frame in std::mem::take(&mut self.frames).iter() {
+            for columns in Columns::new(frame) {
+                packer.load_columns(columns);
+            }
+        }
+        self.frames = packer.finish_frames();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Compaction, CompactionPolicy, CompactionStats, ExcessBlocksAbove, FillRatioBelow, Never,
+    };
+    use crate::{unpack, Packer, BLOCK_VALUES};
+    use rust_decimal::Decimal;
+
+    /// Loads `values` in chunks of `chunk`, compacting after each, and returns the chunks after
+    /// which the packer compacted as `policy` decided.
+    fn fragmented(
+        policy: &impl CompactionPolicy,
+        values: &[Decimal],
+        chunk: usize,
+    ) -> (Vec<usize>, Packer) {
+        let mut packer = Packer::new().with_adaptive_encoding();
+        let mut compacted = Vec::new();
+        for (i, chunk) in values.chunks(chunk).enumerate() {
+            for v in chunk {
+                packer.load_decimal(v);
+            }
+            packer.compact();
+            if packer.maybe_compact(policy) != Compaction::Skip {
+                compacted.push(i);
+            }
+        }
+        (compacted, packer)
+    }
+
+    /// The chunks after which `repack` holds for the frames, tracking only their lengths.
+    fn expected(repack: impl Fn(usize, usize) -> bool, len: usize, chunk: usize) -> Vec<usize> {
+        let mut frames: Vec<usize> = Vec::new();
+        let mut compacted = Vec::new();
+        for (i, start) in (0..len).step_by(chunk).enumerate() {
+            frames.push(chunk.min(len - start));
+            let blocks = frames.iter().map(|f| f.div_ceil(BLOCK_VALUES)).sum();
+            let values: usize = frames.iter().sum();
+            if repack(blocks, values) {
+                compacted.push(i);
+                frames = vec![values];
+            }
+        }
+        compacted
+    }
+
+    fn values() -> Vec<Decimal> {
+        (0..10 * BLOCK_VALUES as i64)
+            .map(|v| Decimal::new(v * 31 % 1000, 2))
+            .collect()
+    }
+
+    fn unpacked(packer: Packer) -> Vec<Decimal> {
+        packer.finish_frames().iter().flat_map(unpack).collect()
+    }
+
+    #[test]
+    fn never() {
+        let values = values();
+        let (compacted, packer) = fragmented(&Never, &values, 100);
+        assert!(compacted.is_empty());
+        assert_eq!(packer.compaction_stats().frames, values.len().div_ceil(100));
+        assert_eq!(unpacked(packer), values);
+    }
+
+    #[test]
+    fn fill_ratio() {
+        let values = values();
+        for chunk in [50, 100] {
+            let (compacted, packer) = fragmented(&FillRatioBelow(0.5), &values, chunk);
+            let fill = |blocks, values| (values as f64) < 0.5 * (blocks * BLOCK_VALUES) as f64;
+            assert_eq!(compacted, expected(fill, values.len(), chunk));
+            assert!(!compacted.is_empty());
+            assert!(packer.compaction_stats().fill_ratio >= 0.5);
+            assert_eq!(unpacked(packer), values);
+        }
+        let (compacted, _) = fragmented(&FillRatioBelow(0.99), &values, BLOCK_VALUES);
+        assert!(compacted.is_empty());
+    }
+
+    #[test]
+    fn excess_blocks() {
+        let values = values();
+        for chunk in [50, 100] {
+            let (compacted, packer) = fragmented(&ExcessBlocksAbove(3), &values, chunk);
+            let excess = |blocks, values: usize| blocks - values.div_ceil(BLOCK_VALUES) > 3;
+            assert_eq!(compacted, expected(excess, values.len(), chunk));
+            assert!(!compacted.is_empty());
+            let stats = packer.compaction_stats();
+            assert!(stats.blocks - stats.min_blocks <= 3);
+            assert_eq!(unpacked(packer), values);
+        }
+        let (compacted, packer) = fragmented(&ExcessBlocksAbove(0), &values, 2 * BLOCK_VALUES);
+        assert!(compacted.is_empty());
+        assert_eq!(unpacked(packer), values);
+    }
+
+    #[test]
+    fn custom() {
+        let values = values();
+        let policy = |stats: &CompactionStats| {
+            if stats.frames > 2 {
+                Compaction::Repack
+            } else {
+                Compaction::Shrink
+            }
+        };
+        let (compacted, packer) = fragmented(&policy, &values, 1000);
+        assert_eq!(compacted.len(), values.len().div_ceil(1000));
+        assert!(packer.compaction_stats().frames <= 2);
+        assert_eq!(unpacked(packer), values);
+    }
+}
@@ -0,0 +1,91 @@
+//! CRC-32 (IEEE) checksums of serialized blocks, see [`crate::format`].
+use std::io::{self, Read};
+
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Running CRC-32 of the bytes passed to [`Crc32::update`].
+#[derive(Clone, Copy)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    pub(crate) fn new() -> Self {
+        Crc32(!0)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = TABLE[((self.0 ^ b as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub(crate) fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+/// Computes the CRC-32 of the bytes read through it.
+pub(crate) struct ChecksumReader<'a, R> {
+    reader: &'a mut R,
+    pub(crate) crc: Crc32,
+}
+
+impl<'a, R: Read> ChecksumReader<'a, R> {
+    pub(crate) fn new(reader: &'a mut R) -> Self {
+        ChecksumReader {
+            reader,
+            crc: Crc32::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.crc.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, ChecksumReader};
+    use std::io::Read;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let mut bytes = &b"123456789"[..];
+        let mut reader = ChecksumReader::new(&mut bytes);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(reader.crc.finish(), 0xCBF4_3926);
+    }
+}
@@ -0,0 +1,961 @@
+use crate::bitpacker::{AnyBitPacker, MAX_BLOCK_LEN};
+use crate::format::{check_decimals, validate, validate_block, validate_layout};
+use crate::{
+    decimal_of_columns, mantissa, Block, Encoding, FloatpackError, Packable, PackedColumns,
+    PackedDecimals, LANE_FN_TAG, MANTISSA_BYTES, MANTISSA_MASK,
+};
+use bitpacking::{BitPacker, BitPacker8x};
+use rust_decimal::Decimal;
+
+/// Decodes the blocks of all lanes in order, omitting the padding of a partial tail block.
+#[derive(Clone)]
+pub(crate) struct Blocks<'a, const N: usize = 4> {
+    bitpacker: AnyBitPacker,
+    packed: &'a PackedColumns<N>,
+    n: usize,
+    last: [u32; N],
+    unchecked: bool,
+    /// Lane layout of the values of a pack of `Decimal`s.
+    layout: Option<Layout>,
+    /// Count of blocks decoded, to check that skipping doesn't decode more than needed.
+    #[cfg(test)]
+    pub(crate) decoded: usize,
+}
+
+impl<'a, const N: usize> Blocks<'a, N> {
+    pub(crate) fn new(packed: &'a PackedColumns<N>) -> Self {
+        Blocks {
+            bitpacker: AnyBitPacker::new(packed.block_len).expect("unsupported block length"),
+            packed,
+            n: 0,
+            last: [0; N],
+            unchecked: false,
+            layout: decimal_layout(packed.type_tag),
+            #[cfg(test)]
+            decoded: 0,
+        }
+    }
+
+    /// Decoder skipping bounds checks.
+    ///
+    /// # Safety
+    ///
+    /// `packed` has to pass [`PackedDecimals::verify`].
+    unsafe fn new_unchecked(packed: &'a PackedColumns<N>) -> Self {
+        Blocks {
+            unchecked: true,
+            ..Self::new(packed)
+        }
+    }
+
+    /// Skip to the `n`th block. If it is a [`Block::continuation`], this decodes the blocks
+    /// from the start of its chain up to it.
+    pub(crate) fn skip_to(&mut self, n: usize) {
+        if n >= self.packed.blocks[0].len() {
+            self.n = n;
+            return;
+        }
+        if let Some(start) = (self.n..=n)
+            .rev()
+            .find(|&k| !self.lane_block(0, k).continuation)
+        {
+            self.n = start;
+        }
+        while self.n < n && self.decode_next().is_some() {}
+    }
+
+    /// Continue at the `n`th block, on top of the last values `last` of the preceding block.
+    pub(crate) fn seek(&mut self, n: usize, last: [u32; N]) {
+        self.n = n;
+        self.last = last;
+    }
+
+    fn lane_block(&self, lane: usize, n: usize) -> &'a Block {
+        if self.unchecked {
+            // SAFETY: Only set by `new_unchecked`, requiring a verified pack. `lane < N` and
+            // every lane of it has more than `n` blocks, as `decode_next` checks against lane 0.
+            unsafe { self.packed.blocks.get_unchecked(lane).get_unchecked(n) }
+        } else {
+            &self.packed.blocks[lane][n]
+        }
+    }
+
+    /// Decodes the raw lanes of the next block, padding included, and the count of its values.
+    pub(crate) fn decode_next(&mut self) -> Option<([[u32; MAX_BLOCK_LEN + 1]; N], usize)> {
+        let n = self.n;
+        if n >= self.packed.blocks[0].len() {
+            return None;
+        }
+        let block_len = self.packed.block_len;
+        #[cfg(test)]
+        {
+            self.decoded += 1;
+        }
+        let mut lanes = [[0; MAX_BLOCK_LEN + 1]; N];
+        for (i, lane) in lanes.iter_mut().enumerate() {
+            let block = self.lane_block(i, n);
+            let base = if block.continuation { self.last[i] } else { 0 };
+            *lane = decode_lane(&self.bitpacker, block, base);
+        }
+        // The mantissa encodings only span the lanes of `Decimal`s.
+        if N == 4 {
+            let mantissa_block = self.lane_block(1, n);
+            match mantissa_block.encoding {
+                Encoding::SignMagnitude | Encoding::HybridDelta => {
+                    combine_sign_magnitude(&mut lanes, &mantissa_block.signs, block_len)
+                }
+                Encoding::DeltaOfDelta => {
+                    combine_delta_of_delta(&mut lanes, &mantissa_block.signs, block_len)
+                }
+                Encoding::Xor | Encoding::Raw | Encoding::ByteSplit => {}
+            }
+        }
+        for (last, lane) in self.last.iter_mut().zip(&lanes) {
+            *last = lane[block_len];
+        }
+        self.n += 1;
+        let block_values = self.packed.block_values();
+        let len = self
+            .packed
+            .count
+            .saturating_sub(n * block_values)
+            .min(block_values);
+        Some((lanes, len))
+    }
+}
+
+impl Iterator for Blocks<'_> {
+    type Item = Vec<Decimal>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (lanes, len) = self.decode_next()?;
+        Some((0..len).map(|i| self.decimal_at(&lanes, i)).collect())
+    }
+}
+
+impl Blocks<'_> {
+    /// Decode the values in the custom lane layout of a [`Packer::with_lane_fn`] with `layout`.
+    ///
+    /// [`Packer::with_lane_fn`]: crate::Packer::with_lane_fn
+    pub(crate) fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// The `i`th value of `lanes` decoded by [`Blocks::decode_next`].
+    pub(crate) fn decimal_at(&self, lanes: &[[u32; MAX_BLOCK_LEN + 1]; 4], i: usize) -> Decimal {
+        self.decimal([lanes[0][i], lanes[1][i], lanes[2][i], lanes[3][i]])
+    }
+
+    /// The value of the lanes of a single value.
+    pub(crate) fn decimal(&self, lanes: [u32; 4]) -> Decimal {
+        (self.layout.expect("a pack of Decimals"))(lanes)
+    }
+}
+
+/// Maps the lanes of a value to the `Decimal` they store.
+pub(crate) type Layout = fn([u32; 4]) -> Decimal;
+
+/// The lane layout of packs of `Decimal`s tagged `type_tag`, which every decoder of `Decimal`s
+/// dispatches on (see the [`format`](crate::format) docs). `None` for tags of other types and
+/// of the custom layouts of [`Packer::with_lane_fn`](crate::Packer::with_lane_fn), which only
+/// their own `from_lanes` decodes.
+pub(crate) fn decimal_layout(type_tag: u8) -> Option<Layout> {
+    match type_tag {
+        0 => Some(decimal_of_columns::<4>),
+        #[cfg(test)]
+        tests::LEGACY_TAG => Some(tests::legacy_layout),
+        _ => None,
+    }
+}
+
+/// Decompresses a single block, folding XOR deltas starting from the head.
+///
+/// `base` is XORed into the stored head, which resolves chained heads. Blocks of bit width 0,
+/// typically constant lanes, skip decompressing as all their deltas are zero.
+pub(crate) fn decode_lane(
+    bitpacker: &AnyBitPacker,
+    block: &Block,
+    base: u32,
+) -> [u32; MAX_BLOCK_LEN + 1] {
+    let mut lane = [block.head ^ base; MAX_BLOCK_LEN + 1];
+    let block_len = bitpacker.block_len();
+    if block.encoding == Encoding::ByteSplit {
+        join_bytes(&block.vals, block.signs[0], &mut lane[1..=block_len]);
+        return lane;
+    }
+    if block.bits == 0 {
+        if block.encoding != Encoding::Xor {
+            lane[1..].fill(0);
+        }
+        return lane;
+    }
+    let mut decompress = [0u32; MAX_BLOCK_LEN];
+    bitpacker.decompress(&block.vals, &mut decompress[..block_len], block.bits);
+    match block.encoding {
+        Encoding::Xor => {
+            for (i, v) in decompress.iter().enumerate() {
+                lane[i + 1] = lane[i] ^ v;
+            }
+        }
+        Encoding::Raw
+        | Encoding::SignMagnitude
+        | Encoding::DeltaOfDelta
+        | Encoding::HybridDelta => lane[1..].copy_from_slice(&decompress),
+        Encoding::ByteSplit => unreachable!(),
+    }
+    lane
+}
+
+/// XORs the byte `planes` stored one after the other in `bytes` into `lane`.
+fn join_bytes(bytes: &[u8], planes: u8, lane: &mut [u32]) {
+    let planes = (0..4).filter(|p| planes & 1 << p != 0);
+    for (p, plane) in planes.zip(bytes.chunks_exact(lane.len())) {
+        for (v, b) in lane.iter_mut().zip(plane) {
+            *v ^= (*b as u32) << (8 * p);
+        }
+    }
+}
+
+/// Iterator over the values of a `PackedDecimals`, decoding one block at a time.
+///
+/// Iterating from the back decodes blocks back to front. With chained heads, the bases of all
+/// blocks not consumed yet are collected in a forward pass the first time a block is decoded
+/// from the back.
+///
+/// Created by [`PackedDecimals::iter`].
+pub struct Values<'a> {
+    blocks: Blocks<'a>,
+    block: std::vec::IntoIter<Decimal>,
+    /// Index of the first block consumed from the back.
+    end: usize,
+    back: std::vec::IntoIter<Decimal>,
+    /// Bases of the blocks from index `bases_from` on, for chained heads.
+    bases: Vec<[u32; 4]>,
+    bases_from: usize,
+    remaining: usize,
+}
+
+impl<'a> Values<'a> {
+    pub(crate) fn new(packed: &'a PackedDecimals) -> Self {
+        let packed = packed.decimals();
+        Values {
+            blocks: Blocks::new(packed),
+            block: Vec::new().into_iter(),
+            end: packed.blocks[0].len(),
+            back: Vec::new().into_iter(),
+            bases: Vec::new(),
+            bases_from: 0,
+            remaining: packed.count,
+        }
+    }
+
+    /// The values with indices in `start..end`, decoding only the blocks holding them.
+    ///
+    /// With chained heads, the blocks before `start` are decoded as well.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn slice(packed: &'a PackedDecimals, start: usize, end: usize) -> Self {
+        let packed = packed.decimals();
+        let mut values = Values::new(packed);
+        let end = end.min(packed.count);
+        if start >= end {
+            values.end = 0;
+            values.remaining = 0;
+            return values;
+        }
+        let block_values = packed.block_values();
+        let (first, last) = (start / block_values, (end - 1) / block_values);
+        values.blocks.skip_to(first);
+        let mut head = Vec::new();
+        if first == last {
+            head = values.blocks.next().expect("block index out of bounds");
+            head.truncate(end - first * block_values);
+            values.end = first + 1;
+        } else {
+            if start > first * block_values {
+                head = values.blocks.next().expect("block index out of bounds");
+            }
+            let mut tail = values.decode_back(last);
+            tail.truncate(end - last * block_values);
+            values.back = tail.into_iter();
+            values.end = last;
+        }
+        head.drain(..head.len().min(start - first * block_values));
+        values.block = head.into_iter();
+        values.remaining = end - start;
+        values
+    }
+
+    /// Pair each value with its index in the whole pack, like `enumerate` on a fresh iterator.
+    ///
+    /// Unlike `enumerate`, the indices stay global after skipping or stepping with `nth`.
+    pub fn indexed(self) -> Indexed<'a> {
+        Indexed {
+            index: self.blocks.packed.count - self.remaining,
+            values: self,
+        }
+    }
+
+    /// Skip `n` blocks following the current one, without decoding them unless heads are chained.
+    fn skip_blocks(&mut self, n: usize) {
+        let values = self.blocks.packed.block_values();
+        self.remaining -= self.block.len();
+        self.remaining = self.remaining.saturating_sub(n * values);
+        self.block = Vec::new().into_iter();
+        self.blocks.skip_to(self.blocks.n + n);
+    }
+
+    /// Decode the `n`th block for iterating from the back.
+    fn decode_back(&mut self, n: usize) -> Vec<Decimal> {
+        let packed = self.blocks.packed;
+        let base = if !packed.blocks[0][n].continuation {
+            [0; 4]
+        } else if n == self.blocks.n {
+            self.blocks.last
+        } else {
+            if self.bases.is_empty() {
+                let mut forward = self.blocks.clone();
+                self.bases_from = forward.n;
+                while forward.n <= n {
+                    self.bases.push(forward.last);
+                    forward.decode_next();
+                }
+            }
+            self.bases[n - self.bases_from]
+        };
+        let mut back = Blocks::new(packed);
+        back.seek(n, base);
+        back.next().expect("block index out of bounds")
+    }
+}
+
+impl Iterator for Values<'_> {
+    type Item = Decimal;
+
+    fn next(&mut self) -> Option<Decimal> {
+        loop {
+            if let Some(v) = self.block.next() {
+                self.remaining -= 1;
+                return Some(v);
+            }
+            if self.blocks.n < self.end {
+                self.block = self.blocks.next()?.into_iter();
+            } else {
+                let v = self.back.next()?;
+                self.remaining -= 1;
+                return Some(v);
+            }
+        }
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Decimal> {
+        let mut n = n;
+        if n >= self.block.len() {
+            n -= self.block.len();
+            let values = self.blocks.packed.block_values();
+            let skip = (n / values).min(self.end - self.blocks.n);
+            self.skip_blocks(skip);
+            n -= skip * values;
+            if self.blocks.n < self.end {
+                self.block = self.blocks.next()?.into_iter();
+            }
+        }
+        if n < self.block.len() {
+            self.remaining -= n + 1;
+            return self.block.nth(n);
+        }
+        for _ in 0..n {
+            self.next()?;
+        }
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Values<'_> {
+    fn next_back(&mut self) -> Option<Decimal> {
+        loop {
+            if let Some(v) = self.back.next_back() {
+                self.remaining -= 1;
+                return Some(v);
+            }
+            if self.blocks.n < self.end {
+                self.end -= 1;
+                self.back = self.decode_back(self.end).into_iter();
+            } else {
+                let v = self.block.next_back()?;
+                self.remaining -= 1;
+                return Some(v);
+            }
+        }
+    }
+}
+
+impl ExactSizeIterator for Values<'_> {}
+
+/// Iterator over the values of a `PackedDecimals` along with their indices.
+///
+/// Created by [`Values::indexed`].
+pub struct Indexed<'a> {
+    values: Values<'a>,
+    index: usize,
+}
+
+impl Iterator for Indexed<'_> {
+    type Item = (usize, Decimal);
+
+    fn next(&mut self) -> Option<(usize, Decimal)> {
+        self.nth(0)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<(usize, Decimal)> {
+        let v = self.values.nth(n)?;
+        self.index += n + 1;
+        Some((self.index - 1, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.values.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Indexed<'_> {}
+
+impl PackedDecimals {
+    /// The value at `index`, decoding only the block holding it.
+    ///
+    /// With chained heads, all blocks from the start of its chain up to the one holding the
+    /// value have to be decoded.
+    /// Returns `None` if `index` is out of bounds or the blocks it depends on are malformed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pack holds values of another type than `Decimal`.
+    pub fn get(&self, index: usize) -> Option<Decimal> {
+        if index >= self.decimals().count || validate_layout(self).is_err() {
+            return None;
+        }
+        let n = index / self.block_values();
+        let first = (0..=n)
+            .rev()
+            .find(|&k| !self.blocks[0][k].continuation)
+            .unwrap_or(0);
+        if (first..=n).any(|n| validate_block(self, n).is_err()) {
+            return None;
+        }
+        Some(self.decode_at(Blocks::new(self), index))
+    }
+
+    /// The value at `index`, without any checks.
+    ///
+    /// # Safety
+    ///
+    /// `index` has to be less than `self.len()` and `self` has to pass
+    /// [`PackedDecimals::verify`], which holds for anything produced by [`crate::Packer`] or
+    /// deserialized by this crate, as long as it wasn't modified afterwards. Otherwise the
+    /// behavior is undefined.
+    pub unsafe fn get_unchecked(&self, index: usize) -> Decimal {
+        self.decode_at(Blocks::new_unchecked(self), index)
+    }
+
+    fn decode_at(&self, mut blocks: Blocks<'_>, index: usize) -> Decimal {
+        blocks.skip_to(index / self.block_values());
+        let (lanes, _) = blocks.decode_next().expect("index out of bounds");
+        blocks.decimal_at(&lanes, index % self.block_values())
+    }
+
+    /// Iterate over the values, decoding one block at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block length is not supported by any bitpacker or the pack holds values of
+    /// another type than `Decimal`.
+    pub fn iter(&self) -> Values<'_> {
+        Values::new(self)
+    }
+
+    /// This pack, checked to hold `Decimal`s, so that packs of values of another type aren't
+    /// decoded as garbage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pack holds values of another type than `Decimal`.
+    pub(crate) fn decimals(&self) -> &PackedDecimals {
+        if let Err(e) = check_decimals(self) {
+            panic!("{}", e);
+        }
+        self
+    }
+}
+
+impl<'a> IntoIterator for &'a PackedDecimals {
+    type Item = Decimal;
+    type IntoIter = Values<'a>;
+
+    fn into_iter(self) -> Values<'a> {
+        self.iter()
+    }
+}
+
+/// Turns the magnitudes in lanes 1 to 3 back into the values they are the differences of.
+///
+/// With [`Encoding::HybridDelta`], the `side` data continues with the bitmap of the mantissas
+/// stored as they are, which replace the values at their positions.
+fn combine_sign_magnitude<const N: usize>(
+    lanes: &mut [[u32; MAX_BLOCK_LEN + 1]; N],
+    side: &[u8],
+    block_len: usize,
+) {
+    let (signs, rest) = side.split_at(block_len / 8);
+    let (flags, absolutes) = rest.split_at(rest.len().min(block_len / 8));
+    let mut absolutes = absolutes.chunks_exact(12);
+    let mut value = mantissa([0, lanes[1][0], lanes[2][0], lanes[3][0]]);
+    for j in 0..block_len {
+        let magnitude = mantissa([0, lanes[1][j + 1], lanes[2][j + 1], lanes[3][j + 1]]);
+        let absolute = flags
+            .get(j / 8)
+            .filter(|f| **f & (1 << (j % 8)) != 0)
+            .and_then(|_| absolutes.next());
+        if let Some(absolute) = absolute {
+            let mut bytes = [0u8; 16];
+            bytes[..12].copy_from_slice(absolute);
+            value = u128::from_le_bytes(bytes);
+        } else if signs[j / 8] & (1 << (j % 8)) != 0 {
+            value = value.wrapping_sub(magnitude);
+        } else {
+            value = value.wrapping_add(magnitude);
+        }
+        for (i, lane) in lanes[1..].iter_mut().enumerate() {
+            lane[j + 1] = (value >> (32 * i)) as u32;
+        }
+    }
+}
+
+/// Turns the zigzag encoded changes in lanes 1 to 3 back into the values they are derived of.
+fn combine_delta_of_delta<const N: usize>(
+    lanes: &mut [[u32; MAX_BLOCK_LEN + 1]; N],
+    first: &[u8],
+    block_len: usize,
+) {
+    let mut bytes = [0; 16];
+    bytes[..MANTISSA_BYTES].copy_from_slice(first);
+    let mut delta = u128::from_le_bytes(bytes);
+    let mut value = mantissa([0, lanes[1][0], lanes[2][0], lanes[3][0]]);
+    for j in 0..block_len {
+        let zigzag = mantissa([0, lanes[1][j + 1], lanes[2][j + 1], lanes[3][j + 1]]);
+        let change = if zigzag & 1 == 0 {
+            zigzag >> 1
+        } else {
+            MANTISSA_MASK - (zigzag >> 1)
+        };
+        delta = delta.wrapping_add(change) & MANTISSA_MASK;
+        value = value.wrapping_add(delta) & MANTISSA_MASK;
+        for (i, lane) in lanes[1..].iter_mut().enumerate() {
+            lane[j + 1] = (value >> (32 * i)) as u32;
+        }
+    }
+}
+
+/// Unpack and decompress Decimals, checking them with [`PackedDecimals::verify`] first.
+pub fn try_unpack(values: &PackedDecimals) -> Result<Vec<Decimal>, FloatpackError> {
+    try_unpack_with(values, None)
+}
+
+/// [`try_unpack`], decoding blocks of 256 values with `bitpacker` instead of a new one.
+pub(crate) fn try_unpack_with(
+    values: &PackedDecimals,
+    bitpacker: Option<BitPacker8x>,
+) -> Result<Vec<Decimal>, FloatpackError> {
+    values.verify()?;
+    let mut blocks = Blocks::new(values);
+    if let Some(bitpacker) = bitpacker.filter(|_| values.block_len == BitPacker8x::BLOCK_LEN) {
+        blocks.bitpacker = AnyBitPacker::X8(bitpacker);
+    }
+    let mut result = Vec::with_capacity(values.count);
+    for block in blocks {
+        result.extend(block);
+    }
+    Ok(result)
+}
+
+/// Unpack values of a [`Packable`] type, checking the pack with [`PackedDecimals::verify`]
+/// first and each value with [`Packable::from_columns`].
+pub fn try_unpack_as<T: Packable<N>, const N: usize>(
+    values: &PackedColumns<N>,
+) -> Result<Vec<T>, FloatpackError> {
+    check_type::<T, N>(values)?;
+    validate(values)?;
+    Columns::new(values).map(T::from_columns).collect()
+}
+
+/// Unpack `Decimal`s packed in a custom lane layout (see
+/// [`Packer::with_lane_fn`](crate::Packer::with_lane_fn)), mapping the lanes of each value
+/// back with `from_lanes`.
+///
+/// Returns [`FloatpackError::TypeMismatch`] for packs in another layout or of another type.
+pub fn try_unpack_with_lanes(
+    values: &PackedDecimals,
+    from_lanes: fn([u32; 4]) -> Decimal,
+) -> Result<Vec<Decimal>, FloatpackError> {
+    if values.type_tag != LANE_FN_TAG {
+        return Err(FloatpackError::TypeMismatch {
+            expected: LANE_FN_TAG,
+            found: values.type_tag,
+        });
+    }
+    validate(values)?;
+    Ok(Columns::new(values).map(from_lanes).collect())
+}
+
+/// Iterator over the columns of the values of a pack, decoding one block at a time.
+pub(crate) struct Columns<'a, const N: usize> {
+    blocks: Blocks<'a, N>,
+    lanes: [[u32; MAX_BLOCK_LEN + 1]; N],
+    pos: usize,
+    len: usize,
+}
+
+impl<'a, const N: usize> Columns<'a, N> {
+    pub(crate) fn new(packed: &'a PackedColumns<N>) -> Self {
+        Columns {
+            blocks: Blocks::new(packed),
+            lanes: [[0; MAX_BLOCK_LEN + 1]; N],
+            pos: 0,
+            len: 0,
+        }
+    }
+}
+
+impl<const N: usize> Iterator for Columns<'_, N> {
+    type Item = [u32; N];
+
+    fn next(&mut self) -> Option<[u32; N]> {
+        if self.pos == self.len {
+            (self.lanes, self.len) = self.blocks.decode_next()?;
+            self.pos = 0;
+        }
+        self.pos += 1;
+        Some(std::array::from_fn(|lane| self.lanes[lane][self.pos - 1]))
+    }
+}
+
+fn check_type<T: Packable<N>, const N: usize>(
+    values: &PackedColumns<N>,
+) -> Result<(), FloatpackError> {
+    match values.type_tag {
+        tag if tag == T::TYPE_TAG => Ok(()),
+        found => Err(FloatpackError::TypeMismatch {
+            expected: T::TYPE_TAG,
+            found,
+        }),
+    }
+}
+
+/// Unpack and decompress Decimals, newest first.
+///
+/// Blocks are decoded back to front, reversing each after folding it, so no pass over the whole
+/// output is needed. With chained heads, the base of each block is collected in a forward pass.
+///
+/// # Panics
+///
+/// Panics if `values` is malformed (see [`PackedDecimals::verify`] and [`try_unpack`]).
+pub fn unpack_rev(values: &PackedDecimals) -> Vec<Decimal> {
+    if let Err(e) = values.verify() {
+        panic!("{}", e);
+    }
+    let n = values.blocks[0].len();
+    let mut blocks = Blocks::new(values);
+    let mut bases = vec![[0; 4]; n];
+    if values.chained() {
+        for base in bases.iter_mut() {
+            *base = blocks.last;
+            blocks.decode_next();
+        }
+    }
+    let mut result = Vec::with_capacity(values.count);
+    for (n, base) in bases.into_iter().enumerate().rev() {
+        blocks.seek(n, base);
+        let (lanes, len) = blocks.decode_next().expect("validated block");
+        result.extend((0..len).rev().map(|i| blocks.decimal_at(&lanes, i)));
+    }
+    result
+}
+
+/// Unpack and decompress Decimals, one `Vec` per block, e.g. to process blocks in parallel.
+///
+/// All but the last `Vec` hold [`PackedDecimals::block_values`] values, the last one may hold
+/// fewer. With chained heads, blocks still are decoded one after the other.
+///
+/// # Panics
+///
+/// Panics if `values` is malformed (see [`PackedDecimals::verify`] and [`try_unpack`]).
+pub fn unpack_blocks(values: &PackedDecimals) -> Vec<Vec<Decimal>> {
+    if let Err(e) = values.verify() {
+        panic!("{}", e);
+    }
+    Blocks::new(values).collect()
+}
+
+/// Unpack and decompress Decimals, skipping malformed blocks instead of failing.
+///
+/// Returns the values of all intact blocks along with the indices of the skipped ones. The
+/// values of a skipped block are left out, so indices into the result shift past it. The
+/// continuations (see [`Block::continuation`]) of a skipped block can't be decoded either and
+/// are skipped as well. Lanes of different lengths are decoded as far as all of them reach. All
+/// blocks of packs of values of another type than `Decimal` are skipped.
+pub fn unpack_lenient(values: &PackedDecimals) -> (Vec<Decimal>, Vec<usize>) {
+    let total = values.blocks.iter().map(Vec::len).max().unwrap_or(0);
+    if AnyBitPacker::new(values.block_len).is_none() || check_decimals(values).is_err() {
+        return (Vec::new(), (0..total).collect());
+    }
+    let intact = values.blocks.iter().map(Vec::len).min().unwrap_or(0);
+    let block_values = values.block_values();
+    let mut blocks = Blocks::new(values);
+    let mut result = Vec::with_capacity(intact * block_values);
+    let mut skipped = Vec::new();
+    for n in 0..intact {
+        let broken_chain =
+            values.blocks[0][n].continuation && (n == 0 || skipped.last() == Some(&(n - 1)));
+        if broken_chain || validate_block(values, n).is_err() {
+            skipped.push(n);
+            continue;
+        }
+        blocks.seek(n, blocks.last);
+        let (lanes, _) = blocks.decode_next().expect("block index out of bounds");
+        let len = values
+            .count
+            .saturating_sub(n * block_values)
+            .min(block_values);
+        result.extend((0..len).map(|i| blocks.decimal_at(&lanes, i)));
+    }
+    skipped.extend(intact..total);
+    (result, skipped)
+}
+
+/// Unpack and decompress Decimals, skipping all checks.
+///
+/// This is the fast path for data that is known to be valid, e.g. because it was packed by this
+/// process moments ago.
+///
+/// # Safety
+///
+/// `values` has to pass [`PackedDecimals::verify`], which holds for anything produced by
+/// [`crate::Packer`] or deserialized by this crate, as long as it wasn't modified afterwards.
+/// Otherwise the behavior is undefined.
+pub unsafe fn unpack_unchecked(values: &PackedDecimals) -> Vec<Decimal> {
+    let mut result = Vec::with_capacity(values.count);
+    for block in Blocks::new_unchecked(values) {
+        result.extend(block);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::pack_with;
+    use crate::{
+        decimal_of_columns, pack, rechunk, try_unpack, unpack, Packable, PackedDecimals, Packer,
+        StreamDecoder, BLOCK_VALUES,
+    };
+    use rust_decimal::Decimal;
+
+    fn values() -> Vec<Decimal> {
+        (0..10_000).map(|v| Decimal::new(v * 13 % 777, 2)).collect()
+    }
+
+    /// Tag of a layout of `Decimal`s only known to the tests, with the lanes in reverse order.
+    pub(super) const LEGACY_TAG: u8 = 127;
+
+    pub(super) fn legacy_layout(lanes: [u32; 4]) -> Decimal {
+        let [a, b, c, d] = lanes;
+        decimal_of_columns([d, c, b, a])
+    }
+
+    fn legacy_lanes(value: &Decimal) -> [u32; 4] {
+        let [a, b, c, d] = value.to_columns();
+        [d, c, b, a]
+    }
+
+    #[test]
+    fn layout_of_tag() {
+        let values = values();
+        let mut packer = Packer::new().with_lane_fn(legacy_lanes, legacy_layout);
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        let mut packed = packer.finish();
+        packed.type_tag = LEGACY_TAG;
+        assert_ne!(packed.blocks, pack(&values).blocks);
+        assert!(packed.verify().is_ok());
+        assert_eq!(try_unpack(&packed).unwrap(), values);
+        assert_eq!(packed.iter().collect::<Vec<_>>(), values);
+        assert_eq!(packed.get(1234), Some(values[1234]));
+
+        let bytes = packed.to_bytes();
+        assert_eq!(PackedDecimals::from_bytes(&bytes).unwrap(), packed);
+        let streamed: Result<Vec<_>, _> = StreamDecoder::new(&bytes[..]).unwrap().collect();
+        assert_eq!(streamed.unwrap(), values);
+
+        // Re-packing stores the values in the layout of tag 0.
+        let rechunked = rechunk(&packed, 32).unwrap();
+        assert_eq!(rechunked.type_tag, 0);
+        assert_eq!(unpack(&rechunked), values);
+    }
+
+    #[test]
+    fn blocks() {
+        let values = values();
+        let packer = Packer::new()
+            .with_chained_heads()
+            .with_block_len(32)
+            .unwrap();
+        for packed in [pack(&values), pack_with(packer, &values)] {
+            let blocks = super::unpack_blocks(&packed);
+            assert_eq!(blocks.len(), packed.blocks[0].len());
+            let (last, full) = blocks.split_last().unwrap();
+            assert!(full.iter().all(|b| b.len() == packed.block_values()));
+            assert_eq!(
+                last.len(),
+                values.len() - full.len() * packed.block_values()
+            );
+            assert_eq!(blocks.concat(), values);
+        }
+        assert!(super::unpack_blocks(&pack(&[])).is_empty());
+    }
+
+    #[test]
+    fn constant_lanes() {
+        let values: Vec<Decimal> = (0..1000).map(|v| Decimal::new(v % 3, 2)).collect();
+        let constant = vec![Decimal::new(-12345, 3); 1000];
+        for packer in [
+            Packer::new(),
+            Packer::new().with_adaptive_encoding(),
+            Packer::new().with_sign_magnitude(),
+        ] {
+            let mut packed = crate::tests::pack_with(packer, &values);
+            assert!(packed.blocks[2..].iter().flatten().all(|b| b.bits == 0));
+            for block in packed.blocks[3].iter_mut() {
+                if !block.encoding.is_mantissa() {
+                    block.encoding = crate::Encoding::Raw;
+                }
+            }
+            assert_eq!(unpack(&packed), values);
+        }
+        let sorted = crate::pack_sorted(&constant).unwrap();
+        assert!(sorted.blocks.iter().flatten().all(|b| b.bits == 0));
+        assert_eq!(unpack(&sorted), constant);
+    }
+
+    #[test]
+    fn empty_lanes_random_access() {
+        // Only lane 1 holding the low bits of the mantissas changes, and not in the second block.
+        let mut values: Vec<Decimal> = (0..1000).map(|v| Decimal::new(v % 7, 1)).collect();
+        values[BLOCK_VALUES..2 * BLOCK_VALUES].fill(Decimal::new(3, 1));
+        let packers: [fn() -> Packer; 4] = [
+            Packer::new,
+            || Packer::new().with_chained_heads(),
+            || Packer::new().with_sign_magnitude(),
+            || Packer::new().with_block_len(32).unwrap(),
+        ];
+        for packer in packers {
+            let packed = pack_with(packer(), &values);
+            let blocks = packed.blocks.iter().flatten();
+            assert!(blocks.filter(|b| b.bits == 0).all(|b| b.vals.is_empty()));
+            assert!(packed.blocks[2..].iter().flatten().all(|b| b.bits == 0));
+            assert!(packed.blocks[0].iter().all(|b| b.bits == 0));
+            assert!(packed.blocks[1].iter().any(|b| b.bits == 0));
+            // Ranges spanning the blocks, including the one of only empty lanes.
+            let range = 100..BLOCK_VALUES * 2 + 100;
+            let got: Vec<Decimal> = range.clone().map(|i| packed.get(i).unwrap()).collect();
+            assert_eq!(got, values[range.clone()]);
+            let unchecked = range.clone().map(|i| unsafe { packed.get_unchecked(i) });
+            assert_eq!(unchecked.collect::<Vec<_>>(), values[range.clone()]);
+            let skipped: Vec<Decimal> = packed.iter().skip(range.start).take(range.len()).collect();
+            assert_eq!(skipped, values[range]);
+        }
+    }
+
+    #[test]
+    fn skip_blocks() {
+        let values = values();
+        let packed = pack(&values);
+        let mut iter = packed.iter();
+        assert_eq!(iter.nth(9000), Some(values[9000]));
+        assert_eq!(iter.blocks.decoded, 1);
+        assert_eq!(iter.len(), 999);
+        let tail: Vec<Decimal> = iter.by_ref().take(100).collect();
+        assert_eq!(tail, unpack(&packed)[9001..9101]);
+        assert_eq!(iter.blocks.decoded, 1);
+        assert_eq!(iter.nth(10_000), None);
+        assert_eq!(iter.len(), 0);
+
+        let skipped: Vec<Decimal> = packed.iter().skip(9000).take(300).collect();
+        assert_eq!(skipped, values[9000..9300]);
+        assert_eq!(packed.iter().nth(10_000), None);
+        assert_eq!(packed.iter().nth(9999), values.last().copied());
+    }
+
+    #[test]
+    fn skip_chained() {
+        let values = values();
+        let packer = Packer::new().with_chained_heads();
+        let packed = crate::tests::pack_with(packer, &values);
+        let mut iter = packed.iter();
+        assert_eq!(iter.nth(9000), Some(values[9000]));
+        assert_eq!(iter.blocks.decoded, 9000 / BLOCK_VALUES + 1);
+        assert_eq!(iter.collect::<Vec<_>>(), values[9001..]);
+    }
+
+    #[test]
+    fn indexed() {
+        let values = values();
+        let packed = pack(&values);
+        let mut iter = packed.iter();
+        iter.nth(499);
+        let indexed: Vec<(usize, Decimal)> = iter.indexed().step_by(1000).collect();
+        let expected: Vec<(usize, Decimal)> = (500..10_000)
+            .step_by(1000)
+            .map(|i| (i, values[i]))
+            .collect();
+        assert_eq!(indexed, expected);
+    }
+
+    #[test]
+    fn double_ended() {
+        let values = values();
+        for packer in [Packer::new(), Packer::new().with_chained_heads()] {
+            let packed = crate::tests::pack_with(packer, &values);
+            let mut reversed = unpack(&packed);
+            reversed.reverse();
+            assert_eq!(packed.iter().rev().collect::<Vec<_>>(), reversed);
+
+            let mut iter = packed.iter();
+            let (mut front, mut back) = (Vec::new(), Vec::new());
+            for i in 0.. {
+                let v = if i % 3 == 0 {
+                    iter.next().map(|v| front.push(v))
+                } else {
+                    iter.next_back().map(|v| back.push(v))
+                };
+                if v.is_none() {
+                    break;
+                }
+                assert_eq!(iter.len(), values.len() - front.len() - back.len());
+            }
+            back.reverse();
+            front.extend(back);
+            assert_eq!(front, values);
+
+            let mut iter = packed.iter();
+            assert_eq!(iter.next_back(), values.last().copied());
+            assert_eq!(iter.nth(5000), Some(values[5000]));
+            assert_eq!(iter.nth_back(100), Some(values[9898]));
+            assert_eq!(iter.nth(4896), Some(values[9897]));
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.next_back(), None);
+        }
+    }
+}
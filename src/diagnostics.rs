@@ -0,0 +1,96 @@
+//! Diagnostics collected while packing, see
+//! [`Packer::with_diagnostics`](crate::Packer::with_diagnostics).
+use crate::{Block, Encoding};
+use std::collections::BTreeMap;
+
+/// What a packer decided for the blocks it packed so far, across all frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostics {
+    /// Count of values packed into blocks. Values dropped by the dead band and values still
+    /// pending are not included.
+    pub total_values: usize,
+    /// Count of blocks packed per lane.
+    pub blocks_per_lane: Vec<usize>,
+    /// Count of blocks of every lane by their bit width, from 0 to 32.
+    pub bits_histogram: [usize; 33],
+    /// Size of the largest block of any lane, see
+    /// [`PackedDecimals::serialized_len`](crate::PackedDecimals::serialized_len).
+    pub max_block_bytes: usize,
+    /// Count of blocks of every lane by the encoding picked for them.
+    pub encodings: BTreeMap<Encoding, usize>,
+}
+
+impl Diagnostics {
+    pub(crate) fn new(lanes: usize) -> Diagnostics {
+        Diagnostics {
+            total_values: 0,
+            blocks_per_lane: vec![0; lanes],
+            bits_histogram: [0; 33],
+            max_block_bytes: 0,
+            encodings: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, lane: usize, block: &Block) {
+        self.blocks_per_lane[lane] += 1;
+        self.bits_histogram[block.bits as usize] += 1;
+        self.max_block_bytes = self.max_block_bytes.max(block.size_bytes());
+        *self.encodings.entry(block.encoding).or_insert(0) += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::pack_with;
+    use crate::{Encoding, Packer, BLOCK_VALUES};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn diagnostics() {
+        let values: Vec<Decimal> = (0..1000).map(|i| Decimal::new(i * 7 % 300, 2)).collect();
+        let mut packer = Packer::new().with_diagnostics(true);
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        let pending = packer.diagnostics().unwrap().clone();
+        assert_eq!(pending.total_values, 3 * BLOCK_VALUES);
+        assert_eq!(pending.blocks_per_lane, vec![3; 4]);
+
+        let mut packer = Packer::new()
+            .with_adaptive_encoding()
+            .with_diagnostics(true);
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        packer.load_decimal(&dec!(1000));
+        let diagnostics = packer.diagnostics().unwrap().clone();
+        let packed = packer.finish();
+        // The last block of every lane was still pending.
+        let blocks: Vec<_> = packed.blocks.iter().flat_map(|lane| &lane[..3]).collect();
+        assert_eq!(diagnostics.total_values, 3 * BLOCK_VALUES);
+        assert_eq!(diagnostics.blocks_per_lane, vec![3; 4]);
+        for (bits, count) in diagnostics.bits_histogram.iter().enumerate() {
+            assert_eq!(
+                blocks.iter().filter(|b| b.bits as usize == bits).count(),
+                *count
+            );
+        }
+        let max = blocks.iter().map(|b| b.size_bytes()).max().unwrap();
+        assert_eq!(diagnostics.max_block_bytes, max);
+        for encoding in [Encoding::Xor, Encoding::Raw] {
+            let count = blocks.iter().filter(|b| b.encoding == encoding).count();
+            assert_eq!(diagnostics.encodings[&encoding], count);
+        }
+        // The adaptive encoding picked raw values for some but not all blocks.
+        assert!(diagnostics.encodings[&Encoding::Xor] > 0);
+        assert!(diagnostics.encodings[&Encoding::Raw] > 0);
+
+        assert!(Packer::new().diagnostics().is_none());
+        let without = pack_with(Packer::new().with_diagnostics(false), &values);
+        assert_eq!(
+            without,
+            pack_with(Packer::new().with_diagnostics(true), &values)
+        );
+    }
+}
@@ -0,0 +1,559 @@
+//! Appending packs to a file so that a crash loses at most the frames not synced yet.
+use crate::crc::Crc32;
+use crate::{repacker, FloatpackError, PackedDecimals, Packer};
+use rust_decimal::Decimal;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bytes of a record in addition to its frame.
+const RECORD_OVERHEAD: u64 = 12;
+
+/// When a [`DurableWriter`] syncs the frames written to the disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// After each frame.
+    EveryFrame,
+    /// After each this many frames.
+    EveryFrames(usize),
+    /// Only on [`DurableWriter::sync`].
+    OnSync,
+}
+
+/// What [`recover`] found in a file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Count of complete frames.
+    pub frames: usize,
+    /// Count of values in these frames.
+    pub values: usize,
+    /// Bytes of the records of these frames, from the start of the file.
+    pub valid_bytes: u64,
+    /// Bytes following them that don't form a complete record, e.g. from a torn write.
+    pub discarded_bytes: u64,
+}
+
+/// Appends packs as frames to a file, syncing them to the disk as configured by a
+/// [`SyncPolicy`].
+///
+/// The file is a sequence of records, each the length of a frame (`u64`), the frame as a pack in
+/// the current binary format, and the CRC-32 (IEEE) of the length and frame (`u32`). All
+/// integers are little endian. A write cut short, or an area of the file whose contents didn't
+/// reach the disk, leaves a record that is either incomplete or fails its checksum, so
+/// [`recover`] tells complete frames from torn ones. A crash loses at most the frames written
+/// since the last sync, and never frames synced before.
+pub struct DurableWriter {
+    file: File,
+    policy: SyncPolicy,
+    /// Count of frames written since the last sync.
+    unsynced: usize,
+}
+
+impl DurableWriter {
+    /// Create the file at `path`, replacing any file there.
+    pub fn create<P: AsRef<Path>>(path: P, policy: SyncPolicy) -> Result<Self, FloatpackError> {
+        let file = File::create(&path)?;
+        sync_dir(path.as_ref())?;
+        Ok(DurableWriter {
+            file,
+            policy,
+            unsynced: 0,
+        })
+    }
+
+    /// Open the file at `path` to append to it, creating it if it doesn't exist.
+    ///
+    /// Bytes following the last complete frame, left by a crash, are cut off first; the
+    /// returned report describes what was kept.
+    pub fn append<P: AsRef<Path>>(
+        path: P,
+        policy: SyncPolicy,
+    ) -> Result<(Self, RecoveryReport), FloatpackError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        sync_dir(path.as_ref())?;
+        let report = scan(BufReader::new(&mut file), |_| Ok(()))?;
+        if report.discarded_bytes > 0 {
+            file.set_len(report.valid_bytes)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(report.valid_bytes))?;
+        let writer = DurableWriter {
+            file,
+            policy,
+            unsynced: 0,
+        };
+        Ok((writer, report))
+    }
+
+    /// Append `packed` as the next frame, syncing if the policy says so.
+    pub fn write_frame(&mut self, packed: &PackedDecimals) -> Result<(), FloatpackError> {
+        self.file.write_all(&record(packed))?;
+        self.unsynced += 1;
+        let sync = match self.policy {
+            SyncPolicy::EveryFrame => true,
+            SyncPolicy::EveryFrames(n) => self.unsynced >= n,
+            SyncPolicy::OnSync => false,
+        };
+        if sync {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Sync the frames written so far to the disk.
+    pub fn sync(&mut self) -> Result<(), FloatpackError> {
+        if self.unsynced > 0 {
+            self.file.sync_data()?;
+            self.unsynced = 0;
+        }
+        Ok(())
+    }
+
+    /// Sync and close the file.
+    pub fn finish(mut self) -> Result<(), FloatpackError> {
+        self.sync()
+    }
+}
+
+/// The record of the frame `packed`.
+fn record(packed: &PackedDecimals) -> Vec<u8> {
+    let frame = packed.to_bytes();
+    let mut record = Vec::with_capacity(frame.len() + RECORD_OVERHEAD as usize);
+    record.extend_from_slice(&(frame.len() as u64).to_le_bytes());
+    record.extend_from_slice(&frame);
+    let mut crc = Crc32::new();
+    crc.update(&record);
+    record.extend_from_slice(&crc.finish().to_le_bytes());
+    record
+}
+
+/// Sync the directory holding `path`, so a newly created file survives a crash as well.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// Directories can't be opened to sync them on other platforms.
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// The values of all complete frames of the file at `path` written by a [`DurableWriter`],
+/// packed into one pack with the options of the first frame.
+///
+/// Reading stops at the first record that is incomplete or fails its checksum; it and everything
+/// following it count as discarded. The file is left as it is, see [`DurableWriter::append`] to
+/// cut it off.
+pub fn recover<P: AsRef<Path>>(
+    path: P,
+) -> Result<(PackedDecimals, RecoveryReport), FloatpackError> {
+    recover_from(BufReader::new(File::open(path)?))
+}
+
+fn recover_from<R: Read>(reader: R) -> Result<(PackedDecimals, RecoveryReport), FloatpackError> {
+    let mut packer: Option<Packer> = None;
+    let report = scan(reader, |frame| {
+        let packer = match &mut packer {
+            Some(packer) => packer,
+            None => packer.insert(repacker(frame, frame.block_len)?),
+        };
+        for value in frame {
+            packer.load_decimal(&value);
+        }
+        Ok(())
+    })?;
+    let packed = packer.unwrap_or_default().finish();
+    Ok((packed, report))
+}
+
+/// Pass each complete frame read from `reader` to `f`, up to the first torn record.
+fn scan<R: Read>(
+    mut reader: R,
+    mut f: impl FnMut(&PackedDecimals) -> Result<(), FloatpackError>,
+) -> Result<RecoveryReport, FloatpackError> {
+    let mut report = RecoveryReport::default();
+    loop {
+        match read_record(&mut reader)? {
+            Record::Frame(frame, len) => {
+                f(&frame)?;
+                report.frames += 1;
+                report.values += frame.len();
+                report.valid_bytes += len;
+            }
+            Record::Torn(len) => {
+                report.discarded_bytes = len + count_remaining(&mut reader)?;
+                return Ok(report);
+            }
+            Record::End => return Ok(report),
+        }
+    }
+}
+
+/// What [`FollowReader::poll`] found.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Followed {
+    /// The values of the frames completed since the last poll, possibly none.
+    Values(Vec<Decimal>),
+    /// The file was truncated or replaced by another one. Following starts over at its start
+    /// with the next poll.
+    Reset,
+}
+
+/// Follows a file a [`DurableWriter`] of another process appends to, like `tail -f`.
+///
+/// Each [`FollowReader::poll`] yields the values of the frames completed since the previous one,
+/// starting with the frames already in the file. A frame still being written at the end of the
+/// file is left until it is complete. The file is opened again by its path on each poll, so a
+/// rotated file is followed as well.
+pub struct FollowReader {
+    path: PathBuf,
+    /// Bytes of the complete records read so far.
+    offset: u64,
+    /// The checksum ending the last record read, to notice the file being replaced.
+    last_checksum: Option<[u8; 4]>,
+}
+
+impl FollowReader {
+    /// Follow the file at `path`, which is only read by the first poll.
+    pub fn new<P: AsRef<Path>>(path: P) -> FollowReader {
+        FollowReader {
+            path: path.as_ref().to_path_buf(),
+            offset: 0,
+            last_checksum: None,
+        }
+    }
+
+    /// The values of the frames completed since the last poll.
+    ///
+    /// Returns [`Followed::Reset`] instead if the file is shorter than the frames read so far,
+    /// or the last frame read is no longer where it was.
+    pub fn poll(&mut self) -> Result<Followed, FloatpackError> {
+        let mut file = File::open(&self.path)?;
+        let len = file.metadata()?.len();
+        let replaced = match self.last_checksum {
+            _ if len < self.offset => true,
+            Some(checksum) => {
+                file.seek(SeekFrom::Start(self.offset - 4))?;
+                let mut found = [0u8; 4];
+                file.read_exact(&mut found)?;
+                found != checksum
+            }
+            None => false,
+        };
+        if replaced {
+            self.offset = 0;
+            self.last_checksum = None;
+            return Ok(Followed::Reset);
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut reader = BufReader::new((&mut file).take(len - self.offset));
+        let start = self.offset;
+        let mut values = Vec::new();
+        while let Record::Frame(frame, record_len) = read_record(&mut reader)? {
+            values.extend(&frame);
+            self.offset += record_len;
+        }
+        // Empty frames hold no values but still move the offset past their checksum.
+        if self.offset != start {
+            let mut checksum = [0u8; 4];
+            file.seek(SeekFrom::Start(self.offset - 4))?;
+            file.read_exact(&mut checksum)?;
+            self.last_checksum = Some(checksum);
+        }
+        Ok(Followed::Values(values))
+    }
+
+    /// Poll every `interval` until new values arrive, the file is reset, or `timeout` passed, in
+    /// which case no values are returned.
+    pub fn wait_for_new(
+        &mut self,
+        interval: Duration,
+        timeout: Duration,
+    ) -> Result<Followed, FloatpackError> {
+        let start = Instant::now();
+        loop {
+            match self.poll()? {
+                Followed::Values(values) if values.is_empty() => {}
+                followed => return Ok(followed),
+            }
+            if start.elapsed() >= timeout {
+                return Ok(Followed::Values(Vec::new()));
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+enum Record {
+    /// A complete frame and the bytes of its record.
+    Frame(PackedDecimals, u64),
+    /// A record that is incomplete or fails its checksum, after reading this many bytes of it.
+    Torn(u64),
+    End,
+}
+
+fn read_record<R: Read>(reader: &mut R) -> Result<Record, FloatpackError> {
+    let mut len = [0u8; 8];
+    let read = read_full(reader, &mut len)?;
+    if read == 0 {
+        return Ok(Record::End);
+    } else if read < len.len() {
+        return Ok(Record::Torn(read as u64));
+    }
+    let frame_len = u64::from_le_bytes(len);
+    // Read through `take` instead of allocating the length up front, which may be garbage.
+    let mut frame = Vec::new();
+    let read = reader.by_ref().take(frame_len).read_to_end(&mut frame)?;
+    if (read as u64) < frame_len {
+        return Ok(Record::Torn(8 + read as u64));
+    }
+    let mut checksum = [0u8; 4];
+    let read = read_full(reader, &mut checksum)?;
+    let record_len = 8 + frame_len + read as u64;
+    let mut crc = Crc32::new();
+    crc.update(&len);
+    crc.update(&frame);
+    if read < checksum.len() || crc.finish() != u32::from_le_bytes(checksum) {
+        return Ok(Record::Torn(record_len));
+    }
+    // A frame passing its checksum is complete, so failing to decode it is an error, e.g. of a
+    // frame written by a later version of the format.
+    PackedDecimals::from_bytes(&frame).map(|packed| Record::Frame(packed, record_len))
+}
+
+/// Fill `buf` as far as `reader` has bytes, returning the count read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+fn count_remaining<R: Read>(reader: &mut R) -> io::Result<u64> {
+    io::copy(reader, &mut io::sink())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{record, recover, recover_from, DurableWriter, FollowReader, Followed, SyncPolicy};
+    use crate::{pack, unpack, FloatpackError, PackedDecimals, Packer};
+    use rust_decimal::Decimal;
+    use std::path::PathBuf;
+
+    fn values(n: i64) -> Vec<Decimal> {
+        (0..n)
+            .map(|v| Decimal::new(100_000 + v * 7919 % 503 - 251, 2))
+            .collect()
+    }
+
+    /// A path in the temporary directory, unique to this test.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("floatpack-{}-{}", std::process::id(), name))
+    }
+
+    fn write(path: &PathBuf, frames: &[Vec<Decimal>], policy: SyncPolicy) {
+        let mut writer = DurableWriter::create(path, policy).unwrap();
+        for frame in frames {
+            writer.write_frame(&pack(frame)).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn torn_final_frame() {
+        let values = values(1200);
+        let frames: Vec<Vec<Decimal>> = values.chunks(500).map(<[_]>::to_vec).collect();
+        let path = temp_path("torn");
+        write(&path, &frames, SyncPolicy::EveryFrame);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (packed, report) = recover_from(&bytes[..]).unwrap();
+        assert_eq!(unpack(&packed), values);
+        assert_eq!(report.frames, 3);
+        assert_eq!(report.valid_bytes, bytes.len() as u64);
+        assert_eq!(report.discarded_bytes, 0);
+
+        let last = pack(&frames[2]).serialized_len() + 12;
+        let complete = bytes.len() - last;
+        for cut in complete..bytes.len() {
+            let (packed, report) = recover_from(&bytes[..cut]).unwrap();
+            assert_eq!(unpack(&packed), values[..1000], "cut at {}", cut);
+            assert_eq!(report.frames, 2);
+            assert_eq!(report.valid_bytes, complete as u64);
+            assert_eq!(report.discarded_bytes, (cut - complete) as u64);
+        }
+        // Bytes that never reached the disk, read back as zeros or garbage.
+        for junk in [0u8, 0xFF] {
+            let mut torn = bytes.clone();
+            torn[complete + last / 2..].fill(junk);
+            let (packed, report) = recover_from(&torn[..]).unwrap();
+            assert_eq!(unpack(&packed), values[..1000]);
+            assert_eq!(report.discarded_bytes, last as u64);
+        }
+    }
+
+    #[test]
+    fn append_after_crash() {
+        let values = values(900);
+        let path = temp_path("append");
+        write(&path, &[values[..300].to_vec()], SyncPolicy::OnSync);
+        let (mut writer, report) =
+            DurableWriter::append(&path, SyncPolicy::EveryFrames(2)).unwrap();
+        assert_eq!((report.frames, report.discarded_bytes), (1, 0));
+        writer.write_frame(&pack(&values[300..600])).unwrap();
+        let valid = std::fs::metadata(&path).unwrap().len();
+        // A crash while writing the third frame.
+        let mut torn = pack(&values[600..]).to_bytes();
+        torn.truncate(100);
+        std::io::Write::write_all(&mut writer.file, &torn).unwrap();
+        drop(writer);
+
+        let (packed, report) = recover(&path).unwrap();
+        assert_eq!(unpack(&packed), values[..600]);
+        assert_eq!(report.discarded_bytes, 100);
+
+        let (mut writer, report) = DurableWriter::append(&path, SyncPolicy::EveryFrame).unwrap();
+        assert_eq!(report.valid_bytes, valid);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), valid);
+        writer.write_frame(&pack(&values[600..])).unwrap();
+        let (packed, report) = recover(&path).unwrap();
+        assert_eq!(unpack(&packed), values);
+        assert_eq!((report.frames, report.discarded_bytes), (3, 0));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn keeps_options() {
+        let values = values(600);
+        let path = temp_path("options");
+        let mut writer = DurableWriter::create(&path, SyncPolicy::OnSync).unwrap();
+        for chunk in values.chunks(250) {
+            let mut packer = Packer::new()
+                .with_chained_heads()
+                .with_block_len(32)
+                .unwrap();
+            for v in chunk {
+                packer.load_decimal(v);
+            }
+            writer.write_frame(&packer.finish()).unwrap();
+        }
+        writer.sync().unwrap();
+        drop(writer);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let (packed, _) = recover_from(&bytes[..]).unwrap();
+        assert_eq!(packed.block_len, 32);
+        assert!(packed.chained());
+        assert_eq!(unpack(&packed), values);
+
+        let (empty, report) = recover_from(&[0u8; 0][..]).unwrap();
+        assert_eq!(empty, PackedDecimals::default());
+        assert_eq!(report.frames, 0);
+        assert!(matches!(
+            recover(temp_path("missing")),
+            Err(FloatpackError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn follow() {
+        let values = values(1500);
+        let path = temp_path("follow");
+        let mut writer = DurableWriter::create(&path, SyncPolicy::OnSync).unwrap();
+        writer.write_frame(&pack(&values[..400])).unwrap();
+        let mut follower = FollowReader::new(&path);
+        assert_eq!(
+            follower.poll().unwrap(),
+            Followed::Values(values[..400].to_vec())
+        );
+        assert_eq!(follower.poll().unwrap(), Followed::Values(Vec::new()));
+
+        // A frame being written is only yielded once complete.
+        writer.write_frame(&pack(&values[400..700])).unwrap();
+        let record = record(&pack(&values[700..1000]));
+        std::io::Write::write_all(&mut writer.file, &record[..record.len() - 1]).unwrap();
+        assert_eq!(
+            follower.poll().unwrap(),
+            Followed::Values(values[400..700].to_vec())
+        );
+        assert_eq!(follower.poll().unwrap(), Followed::Values(Vec::new()));
+        std::io::Write::write_all(&mut writer.file, &record[record.len() - 1..]).unwrap();
+        let timeout = std::time::Duration::from_secs(1);
+        let interval = std::time::Duration::from_millis(1);
+        assert_eq!(
+            follower.wait_for_new(interval, timeout).unwrap(),
+            Followed::Values(values[700..1000].to_vec())
+        );
+        assert_eq!(
+            follower.wait_for_new(interval, interval).unwrap(),
+            Followed::Values(Vec::new())
+        );
+        drop(writer);
+
+        // Truncated to a shorter file.
+        write(
+            &path,
+            &[values[1000..1100].to_vec()],
+            SyncPolicy::EveryFrame,
+        );
+        assert_eq!(follower.poll().unwrap(), Followed::Reset);
+        assert_eq!(
+            follower.poll().unwrap(),
+            Followed::Values(values[1000..1100].to_vec())
+        );
+
+        // Replaced by a longer file.
+        let rotated = temp_path("follow-rotated");
+        write(
+            &rotated,
+            &[values[..50].to_vec(), values[1100..].to_vec()],
+            SyncPolicy::EveryFrame,
+        );
+        std::fs::rename(&rotated, &path).unwrap();
+        assert_eq!(follower.poll().unwrap(), Followed::Reset);
+        let expected = [&values[..50], &values[1100..]].concat();
+        assert_eq!(follower.poll().unwrap(), Followed::Values(expected));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn follow_empty_frame() {
+        let path = temp_path("follow-empty");
+        let mut writer = DurableWriter::create(&path, SyncPolicy::EveryFrame).unwrap();
+        let mut follower = FollowReader::new(&path);
+        writer
+            .write_frame(&pack(&[Decimal::ONE, Decimal::TWO]))
+            .unwrap();
+        assert_eq!(
+            follower.poll().unwrap(),
+            Followed::Values(vec![Decimal::ONE, Decimal::TWO])
+        );
+        writer.write_frame(&pack(&[])).unwrap();
+        assert_eq!(follower.poll().unwrap(), Followed::Values(Vec::new()));
+        writer.write_frame(&pack(&[Decimal::TEN])).unwrap();
+        assert_eq!(
+            follower.poll().unwrap(),
+            Followed::Values(vec![Decimal::TEN])
+        );
+        drop(writer);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
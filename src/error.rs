@@ -0,0 +1,97 @@
+use rust_decimal::Decimal;
+use std::{error, fmt, io};
+
+/// Errors returned by the fallible parts of floatpack.
+#[derive(Debug)]
+pub enum FloatpackError {
+    /// Reading or writing failed.
+    Io(io::Error),
+    /// A line of InfluxDB line protocol could not be parsed.
+    LineProtocol(String),
+    /// No bitpacker packs blocks of this length.
+    UnsupportedBlockLen(usize),
+    /// The data was written by a version of the format this build can't read.
+    UnsupportedVersion(u8),
+    /// The data is malformed.
+    Corrupt(String),
+    /// Reading or writing Arrow data failed.
+    Arrow(String),
+    /// The value of the row at index `row` could not be loaded.
+    Row { row: usize, reason: String },
+    /// Columns that need to be aligned have these different lengths.
+    LengthMismatch(usize, usize),
+    /// The value at this index is less than its predecessor.
+    Unsorted(usize),
+    /// The divisor at this index is zero.
+    DivisionByZero(usize),
+    /// The result for the values at this index overflows `Decimal`.
+    Overflow(usize),
+    /// The values are of the [`Packable`](crate::Packable) type with tag `found`, not `expected`.
+    TypeMismatch { expected: u8, found: u8 },
+    /// The block at this index doesn't match its checksum.
+    ChecksumMismatch(usize),
+    /// The value can't be stored in the representation asked for, like the `f32` of an
+    /// [`F32Packer`](crate::F32Packer).
+    OutOfRange(Decimal),
+    /// The fixed capacity of this many values is exhausted, see
+    /// [`StaticPacker`](crate::StaticPacker).
+    CapacityExceeded(usize),
+    /// The values of a column are all equal, so a statistic dividing by their variance is
+    /// undefined.
+    ZeroVariance,
+    /// The block at index `block` of lane `lane` is malformed.
+    BlockDecode {
+        lane: usize,
+        block: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for FloatpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FloatpackError::Io(e) => write!(f, "I/O error: {}", e),
+            FloatpackError::LineProtocol(reason) => write!(f, "invalid line protocol: {}", reason),
+            FloatpackError::UnsupportedBlockLen(n) => write!(f, "unsupported block length {}", n),
+            FloatpackError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            FloatpackError::Corrupt(reason) => write!(f, "corrupt data: {}", reason),
+            FloatpackError::Arrow(reason) => write!(f, "arrow error: {}", reason),
+            FloatpackError::Row { row, reason } => write!(f, "row {}: {}", row, reason),
+            FloatpackError::LengthMismatch(a, b) => {
+                write!(f, "columns of {} and {} values don't align", a, b)
+            }
+            FloatpackError::Unsorted(i) => write!(f, "value {} is less than its predecessor", i),
+            FloatpackError::DivisionByZero(i) => write!(f, "divisor {} is zero", i),
+            FloatpackError::Overflow(i) => write!(f, "result {} overflows Decimal", i),
+            FloatpackError::TypeMismatch { expected, found } => {
+                write!(f, "values of type {} instead of {}", found, expected)
+            }
+            FloatpackError::ChecksumMismatch(n) => {
+                write!(f, "block {} doesn't match its checksum", n)
+            }
+            FloatpackError::OutOfRange(v) => write!(f, "value {} is out of range", v),
+            FloatpackError::CapacityExceeded(n) => write!(f, "capacity of {} values exceeded", n),
+            FloatpackError::ZeroVariance => write!(f, "values have zero variance"),
+            FloatpackError::BlockDecode {
+                lane,
+                block,
+                reason,
+            } => write!(f, "corrupt block {} of lane {}: {}", block, lane, reason),
+        }
+    }
+}
+
+impl error::Error for FloatpackError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            FloatpackError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FloatpackError {
+    fn from(e: io::Error) -> Self {
+        FloatpackError::Io(e)
+    }
+}
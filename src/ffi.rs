@@ -0,0 +1,159 @@
+//! C interface for packing `f64` buffers, for bindings to languages like Python.
+//!
+//! Packs are passed around as opaque [`PackedHandle`] pointers, created by [`fp_pack_f64`] and
+//! released with [`fp_free`]. No function unwinds across the boundary: panics are caught and
+//! reported like any other error.
+use crate::{pack, unpack, PackedDecimals};
+use rust_decimal::prelude::*;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::slice;
+
+/// A pack of values owned by the caller of [`fp_pack_f64`].
+pub struct PackedHandle(PackedDecimals);
+
+/// Pack `len` values read from `ptr`, each rounded to `scale` decimal places.
+///
+/// Values are rounded half to even and all stored with the same scale, so only the mantissa
+/// lanes change. Returns null if `scale` exceeds 28, `ptr` is null while `len` isn't 0, or a
+/// value is not finite or out of the range of `Decimal` once scaled: NaN and infinities are an
+/// error, not skipped, so the pack always holds `len` values.
+///
+/// # Safety
+///
+/// `ptr` has to point to `len` readable `f64`s, unless `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn fp_pack_f64(ptr: *const f64, len: usize, scale: u32) -> *mut PackedHandle {
+    if scale > 28 || (ptr.is_null() && len > 0) {
+        return std::ptr::null_mut();
+    }
+    let values = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    };
+    let packed = catch_unwind(AssertUnwindSafe(|| {
+        let decimals = values
+            .iter()
+            .map(|v| scaled(*v, scale))
+            .collect::<Option<Vec<_>>>()?;
+        Some(pack(&decimals))
+    }));
+    match packed {
+        Ok(Some(packed)) => Box::into_raw(Box::new(PackedHandle(packed))),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Count of values of the pack behind `handle`.
+///
+/// # Safety
+///
+/// `handle` has to be returned by [`fp_pack_f64`] and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn fp_len(handle: *const PackedHandle) -> usize {
+    (*handle).0.len()
+}
+
+/// Unpack the values of `handle` into the buffer of `len` values at `out`.
+///
+/// Returns the count of values written, or -1 if the buffer is too small for them. Values
+/// convert to the nearest `f64`.
+///
+/// # Safety
+///
+/// `handle` has to be returned by [`fp_pack_f64`] and not freed yet. `out` has to point to `len`
+/// writable `f64`s, unless `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn fp_unpack_f64(
+    handle: *const PackedHandle,
+    out: *mut f64,
+    len: usize,
+) -> isize {
+    let packed = &(*handle).0;
+    if packed.len() > len {
+        return -1;
+    }
+    if packed.is_empty() {
+        return 0;
+    }
+    let out = slice::from_raw_parts_mut(out, len);
+    let written = catch_unwind(AssertUnwindSafe(|| {
+        for (o, v) in out.iter_mut().zip(unpack(packed)) {
+            *o = v.to_f64()?;
+        }
+        Some(packed.len())
+    }));
+    match written {
+        Ok(Some(n)) => n as isize,
+        _ => -1,
+    }
+}
+
+/// Release the pack behind `handle`. Null handles are ignored.
+///
+/// # Safety
+///
+/// `handle` has to be null or returned by [`fp_pack_f64`] and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn fp_free(handle: *mut PackedHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// `value` rounded to `scale` decimal places, if it is finite and in range.
+fn scaled(value: f64, scale: u32) -> Option<Decimal> {
+    let mut decimal = Decimal::from_f64(value)?.round_dp(scale);
+    decimal.rescale(scale);
+    // Rescaling saturates instead of failing when the mantissa doesn't fit.
+    (decimal.scale() == scale).then_some(decimal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fp_free, fp_len, fp_pack_f64, fp_unpack_f64};
+    use crate::unpack;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn round_trip() {
+        let values: Vec<f64> = (0..1000).map(|i| (10_000 + i) as f64 / 100.0).collect();
+        unsafe {
+            let handle = fp_pack_f64(values.as_ptr(), values.len(), 2);
+            assert!(!handle.is_null());
+            assert_eq!(fp_len(handle), 1000);
+            let decimals = unpack(&(*handle).0);
+            assert_eq!(decimals[1], dec!(100.01));
+            assert!(decimals.iter().all(|d| d.scale() == 2));
+
+            let mut out = vec![0.0; 1000];
+            assert_eq!(fp_unpack_f64(handle, out.as_mut_ptr(), out.len()), 1000);
+            assert_eq!(out, values);
+            assert_eq!(fp_unpack_f64(handle, out.as_mut_ptr(), 999), -1);
+            fp_free(handle);
+
+            let rounded = fp_pack_f64([0.125, -2.5].as_ptr(), 2, 2);
+            assert_eq!(unpack(&(*rounded).0), [dec!(0.12), dec!(-2.50)]);
+            fp_free(rounded);
+
+            let empty = fp_pack_f64(std::ptr::null(), 0, 0);
+            assert_eq!(fp_len(empty), 0);
+            assert_eq!(fp_unpack_f64(empty, std::ptr::null_mut(), 0), 0);
+            fp_free(empty);
+            fp_free(std::ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        unsafe {
+            for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 1e30] {
+                assert!(fp_pack_f64([1.0, value].as_ptr(), 2, 2).is_null());
+            }
+            // Fits at scale 0, but not with 28 decimal places.
+            assert!(fp_pack_f64([1e10].as_ptr(), 1, 28).is_null());
+            assert!(fp_pack_f64([1.0].as_ptr(), 1, 29).is_null());
+            assert!(fp_pack_f64(std::ptr::null(), 1, 2).is_null());
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! Files of packs written one after another, read back by seeking to the packs needed.
+use crate::decode::Blocks;
+use crate::format::{check_decimals, corrupt, read_u64};
+use crate::{FloatpackError, PackedDecimals};
+use rust_decimal::Decimal;
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+const FOOTER_MAGIC: [u8; 4] = *b"FPIX";
+/// Count of frames and magic bytes ending the footer.
+const FOOTER_TRAILER_LEN: u64 = 12;
+const INDEX_ENTRY_LEN: u64 = 16;
+
+/// Writes packs as frames of a file.
+///
+/// A file is a sequence of frames, each a pack in the current binary format, followed by a
+/// footer indexing them: for each frame its offset in the file and count of values (`u64`
+/// each), then the count of frames (`u64`) and the magic bytes `FPIX`. All integers are little
+/// endian.
+///
+/// Nothing but the frames is written until [`FileWriter::finish`] appends the footer. A file
+/// missing it, e.g. because writing was interrupted, can still be read by [`FileReader`].
+pub struct FileWriter<W: Write> {
+    writer: W,
+    offset: u64,
+    index: Vec<(u64, u64)>,
+}
+
+impl<W: Write> FileWriter<W> {
+    pub fn new(writer: W) -> Self {
+        FileWriter {
+            writer,
+            offset: 0,
+            index: Vec::new(),
+        }
+    }
+
+    /// Append `packed` as the next frame.
+    pub fn write_frame(&mut self, packed: &PackedDecimals) -> Result<(), FloatpackError> {
+        let mut counted = CountingWriter {
+            writer: &mut self.writer,
+            written: 0,
+        };
+        packed.write_to(&mut counted)?;
+        let written = counted.written;
+        self.index.push((self.offset, packed.len() as u64));
+        self.offset += written;
+        Ok(())
+    }
+
+    /// Write the footer, returning the inner writer.
+    pub fn finish(mut self) -> Result<W, FloatpackError> {
+        for (offset, count) in &self.index {
+            self.writer.write_all(&offset.to_le_bytes())?;
+            self.writer.write_all(&count.to_le_bytes())?;
+        }
+        self.writer
+            .write_all(&(self.index.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&FOOTER_MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+struct CountingWriter<W> {
+    writer: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.writer.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Random access to the values of a file written by [`FileWriter`], reading only the frames
+/// holding the values asked for.
+///
+/// The frames are located by the footer. Without one, [`FileReader::new`] reads the whole
+/// file once to find them instead.
+pub struct FileReader<R: Read + Seek> {
+    reader: R,
+    /// Offset and index of the first value of each frame.
+    frames: Vec<(u64, usize)>,
+    len: usize,
+    /// The frame read last, to serve further lookups in it without reading it again.
+    cached: Option<(usize, PackedDecimals)>,
+}
+
+impl<R: Read + Seek> FileReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, FloatpackError> {
+        let end = reader.seek(SeekFrom::End(0))?;
+        let frames = match read_footer(&mut reader, end)? {
+            Some(index) => index,
+            None => scan(&mut reader, end)?,
+        };
+        let mut len = 0usize;
+        let frames = frames
+            .into_iter()
+            .map(|(offset, count)| {
+                let start = len;
+                len += count as usize;
+                (offset, start)
+            })
+            .collect();
+        Ok(FileReader {
+            reader,
+            frames,
+            len,
+            cached: None,
+        })
+    }
+
+    /// Count of values in all frames.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Count of frames in the file.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The value at `index`, or `None` if it is out of bounds.
+    pub fn get(&mut self, index: usize) -> Result<Option<Decimal>, FloatpackError> {
+        if index >= self.len {
+            return Ok(None);
+        }
+        let n = self.frame_of(index);
+        let start = self.frames[n].1;
+        Ok(self.frame(n)?.get(index - start))
+    }
+
+    /// The values with indices in `range`, reading only the frames holding them.
+    ///
+    /// `range` is clamped to the values in the file.
+    pub fn unpack_range(&mut self, range: Range<usize>) -> Result<Vec<Decimal>, FloatpackError> {
+        let (start, end) = (range.start, range.end.min(self.len));
+        let mut values = Vec::with_capacity(end.saturating_sub(start));
+        for block in self.blocks(start..end) {
+            let (first, block) = block?;
+            let from = start.saturating_sub(first);
+            let to = (end - first).min(block.len());
+            values.extend_from_slice(&block[from..to]);
+        }
+        Ok(values)
+    }
+
+    /// The blocks holding values with indices in `range`, each with the index of its first
+    /// value. Blocks are decoded whole, so the first and last may start before and extend past
+    /// `range`.
+    pub fn blocks(&mut self, range: Range<usize>) -> FileBlocks<'_, R> {
+        let end = range.end.min(self.len);
+        let next = if range.start < end {
+            self.frame_of(range.start)
+        } else {
+            self.frames.len()
+        };
+        FileBlocks {
+            file: self,
+            range: range.start..end,
+            next,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Index of the frame holding the value at `index`, which has to be in bounds.
+    fn frame_of(&self, index: usize) -> usize {
+        // Empty frames share their start with the next, so take the last frame starting here.
+        self.frames.partition_point(|&(_, start)| start <= index) - 1
+    }
+
+    fn frame_end(&self, n: usize) -> usize {
+        self.frames.get(n + 1).map_or(self.len, |&(_, start)| start)
+    }
+
+    fn frame(&mut self, n: usize) -> Result<&PackedDecimals, FloatpackError> {
+        if !matches!(self.cached, Some((cached, _)) if cached == n) {
+            self.reader.seek(SeekFrom::Start(self.frames[n].0))?;
+            let packed = PackedDecimals::read_from(&mut self.reader)?;
+            check_decimals(&packed)?;
+            if packed.len() != self.frame_end(n) - self.frames[n].1 {
+                return Err(corrupt(format!("frame {} doesn't match the index", n)));
+            }
+            self.cached = Some((n, packed));
+        }
+        Ok(&self.cached.as_ref().expect("frame is cached").1)
+    }
+}
+
+/// Offset and count of values of each frame, read from the footer of a file of `end` bytes.
+///
+/// Returns `None` if the file doesn't end with a footer.
+fn read_footer<R: Read + Seek>(
+    reader: &mut R,
+    end: u64,
+) -> Result<Option<Vec<(u64, u64)>>, FloatpackError> {
+    if end < FOOTER_TRAILER_LEN {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(end - FOOTER_TRAILER_LEN))?;
+    let frames = read_u64(reader)?;
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != FOOTER_MAGIC {
+        return Ok(None);
+    }
+    let index_start = frames
+        .checked_mul(INDEX_ENTRY_LEN)
+        .and_then(|len| (end - FOOTER_TRAILER_LEN).checked_sub(len))
+        .ok_or_else(|| corrupt(format!("index of {} frames exceeds the file", frames)))?;
+    reader.seek(SeekFrom::Start(index_start))?;
+    let mut index = Vec::with_capacity(frames as usize);
+    for n in 0..frames {
+        let offset = read_u64(reader)?;
+        let count = read_u64(reader)?;
+        let previous = index.last().map_or(0, |&(offset, _)| offset);
+        if offset >= index_start || offset < previous {
+            return Err(corrupt(format!("frame {} at invalid offset {}", n, offset)));
+        }
+        index.push((offset, count));
+    }
+    Ok(Some(index))
+}
+
+/// Offset and count of values of each frame, found by reading all frames of a file of `end`
+/// bytes.
+fn scan<R: Read + Seek>(reader: &mut R, end: u64) -> Result<Vec<(u64, u64)>, FloatpackError> {
+    let mut index = Vec::new();
+    let mut offset = reader.seek(SeekFrom::Start(0))?;
+    while offset < end {
+        let packed = PackedDecimals::read_from(&mut *reader)?;
+        index.push((offset, packed.len() as u64));
+        offset = reader.stream_position()?;
+    }
+    Ok(index)
+}
+
+/// Iterator over the blocks of a [`FileReader`] holding a range of values.
+///
+/// Created by [`FileReader::blocks`]. Yields the index of the first value of each block and its
+/// values, reading one frame at a time. Stops after the first error.
+pub struct FileBlocks<'a, R: Read + Seek> {
+    file: &'a mut FileReader<R>,
+    range: Range<usize>,
+    /// Index of the next frame to read.
+    next: usize,
+    pending: VecDeque<(usize, Vec<Decimal>)>,
+}
+
+impl<R: Read + Seek> FileBlocks<'_, R> {
+    /// Decode the blocks of the next frame holding values in the range.
+    fn read_next_frame(&mut self) -> Result<(), FloatpackError> {
+        let n = self.next;
+        self.next += 1;
+        let start = self.file.frames[n].1;
+        let end = self.file.frame_end(n).min(self.range.end);
+        if start >= end {
+            return Ok(());
+        }
+        let packed = self.file.frame(n)?;
+        let block_values = packed.block_values();
+        let (first, last) = (
+            self.range.start.saturating_sub(start) / block_values,
+            (end - start - 1) / block_values,
+        );
+        let mut blocks = Blocks::new(packed);
+        blocks.skip_to(first);
+        for (k, block) in (first..=last).zip(blocks) {
+            self.pending.push_back((start + k * block_values, block));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Iterator for FileBlocks<'_, R> {
+    type Item = Result<(usize, Vec<Decimal>), FloatpackError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let frame_start = self.file.frames.get(self.next)?.1;
+            if frame_start >= self.range.end {
+                return None;
+            }
+            if let Err(e) = self.read_next_frame() {
+                self.next = self.file.frames.len();
+                return Some(Err(e));
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileReader, FileWriter};
+    use crate::{pack, FloatpackError, Packer};
+    use rust_decimal::Decimal;
+    use std::cell::Cell;
+    use std::io::{self, Cursor, Read, Seek, SeekFrom};
+    use std::rc::Rc;
+
+    /// Counts the bytes read from the inner reader.
+    struct Counting<R> {
+        inner: R,
+        read: Rc<Cell<usize>>,
+    }
+
+    impl<R: Read> Read for Counting<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read.set(self.read.get() + n);
+            Ok(n)
+        }
+    }
+
+    impl<R: Seek> Seek for Counting<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn values(n: i64) -> Vec<Decimal> {
+        (0..n)
+            .map(|v| Decimal::new(v * 7919 % 100_003 - 50_000, 3))
+            .collect()
+    }
+
+    fn write(values: &[Decimal], frame: usize) -> Vec<u8> {
+        let mut writer = FileWriter::new(Vec::new());
+        for chunk in values.chunks(frame) {
+            let mut packer = Packer::new().with_chained_heads();
+            for value in chunk {
+                packer.load(value);
+            }
+            writer.write_frame(&packer.finish()).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn reads_only_needed_frames() {
+        let values = values(400_000);
+        let bytes = write(&values, 10_000);
+        let read = Rc::new(Cell::new(0));
+        let mut file = FileReader::new(Counting {
+            inner: Cursor::new(&bytes),
+            read: read.clone(),
+        })
+        .unwrap();
+        assert_eq!(file.len(), values.len());
+        assert_eq!(file.frame_count(), 40);
+        let footer = read.get();
+        assert!(footer < 1000, "{} bytes read for the footer", footer);
+
+        assert_eq!(
+            file.unpack_range(200_000..200_500).unwrap(),
+            &values[200_000..200_500]
+        );
+        assert!(
+            read.get() * 20 < bytes.len(),
+            "{} of {}",
+            read.get(),
+            bytes.len()
+        );
+
+        // Spanning two frames.
+        assert_eq!(
+            file.unpack_range(29_990..30_010).unwrap(),
+            &values[29_990..30_010]
+        );
+        assert!(
+            read.get() * 10 < bytes.len(),
+            "{} of {}",
+            read.get(),
+            bytes.len()
+        );
+
+        let before = read.get();
+        assert_eq!(file.get(30_005).unwrap(), Some(values[30_005]));
+        assert_eq!(read.get(), before, "the frame read last is cached");
+        assert_eq!(file.get(399_999).unwrap(), Some(values[399_999]));
+        assert_eq!(file.get(400_000).unwrap(), None);
+
+        assert_eq!(
+            file.unpack_range(399_990..500_000).unwrap(),
+            &values[399_990..]
+        );
+        assert!(file.unpack_range(10..10).unwrap().is_empty());
+        assert_eq!(file.unpack_range(0..values.len()).unwrap(), values);
+    }
+
+    #[test]
+    fn blocks() {
+        let values = values(5000);
+        let bytes = write(&values, 1000);
+        let mut file = FileReader::new(Cursor::new(bytes)).unwrap();
+        let blocks = file
+            .blocks(900..1300)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let starts: Vec<_> = blocks.iter().map(|(start, _)| *start).collect();
+        assert_eq!(starts, [771, 1000, 1257]);
+        for (start, block) in &blocks {
+            assert_eq!(block[..], values[*start..*start + block.len()]);
+        }
+        // The last block of each frame is partial.
+        assert_eq!(blocks[0].1.len(), 229);
+        assert_eq!(file.blocks(5000..6000).count(), 0);
+        assert_eq!(file.blocks(0..5000).count(), 20);
+    }
+
+    #[test]
+    fn missing_footer() {
+        let values = values(30_000);
+        let mut bytes = Vec::new();
+        for chunk in values.chunks(7000) {
+            pack(chunk).write_to(&mut bytes).unwrap();
+        }
+        let mut file = FileReader::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(file.len(), values.len());
+        assert_eq!(file.frame_count(), 5);
+        assert_eq!(file.unpack_range(6990..7010).unwrap(), &values[6990..7010]);
+        assert_eq!(file.get(29_999).unwrap(), Some(values[29_999]));
+
+        let empty = FileWriter::new(Vec::new()).finish().unwrap();
+        let mut file = FileReader::new(Cursor::new(empty)).unwrap();
+        assert!(file.is_empty());
+        assert_eq!(file.get(0).unwrap(), None);
+        assert!(FileReader::new(Cursor::new(Vec::new())).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let values = values(3000);
+        let bytes = write(&values, 1000);
+
+        let mut truncated = bytes.clone();
+        truncated.truncate(bytes.len() - 1);
+        assert!(FileReader::new(Cursor::new(truncated)).is_err());
+
+        let mut overlong = bytes.clone();
+        let frames = overlong.len() - 12;
+        overlong[frames..frames + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            FileReader::new(Cursor::new(overlong)),
+            Err(FloatpackError::Corrupt(_))
+        ));
+
+        // The last frame claims more values than it holds.
+        let mut miscounted = bytes;
+        let count = miscounted.len() - 12 - 16 + 8;
+        miscounted[count..count + 8].copy_from_slice(&1001u64.to_le_bytes());
+        let mut file = FileReader::new(Cursor::new(miscounted)).unwrap();
+        assert!(file.get(1500).unwrap().is_some());
+        assert!(matches!(file.get(2500), Err(FloatpackError::Corrupt(_))));
+    }
+}
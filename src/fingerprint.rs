@@ -0,0 +1,101 @@
+//! Fingerprints of the decoded values of packs.
+use crate::format::check_decimals;
+use crate::PackedDecimals;
+
+const FNV_OFFSET: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+const FNV_PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013B;
+
+/// A 128-bit hash of the values of `packed`, e.g. to key a cache on the content of a column.
+///
+/// The hash is taken over the decoded values, not the compressed bytes, so packs of the same
+/// values have the same fingerprint whatever their encoding, block length or other options.
+/// Values are hashed by representation, including their scale, so `1.0` and `1.00` differ.
+/// The hash (FNV-1a) is fixed and stays the same across versions and platforms, but it isn't
+/// cryptographic.
+///
+/// # Panics
+///
+/// Panics if `packed` holds values of another type than `Decimal`.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{fingerprint, pack, Packer};
+/// use rust_decimal_macros::*;
+///
+/// let values = [dec!(1.5), dec!(2.25), dec!(2.25)];
+/// let mut packer = Packer::new().with_block_len(32).unwrap();
+/// for v in &values {
+///     packer.load_decimal(v);
+/// }
+/// assert_eq!(fingerprint(&packer.finish()), fingerprint(&pack(&values)));
+/// assert_ne!(fingerprint(&pack(&values[1..])), fingerprint(&pack(&values)));
+/// ```
+pub fn fingerprint(packed: &PackedDecimals) -> [u8; 16] {
+    if let Err(e) = check_decimals(packed) {
+        panic!("{}", e);
+    }
+    let mut hash = Fnv128::default();
+    hash.update(&(packed.len() as u64).to_le_bytes());
+    for value in packed.iter() {
+        hash.update(&value.serialize());
+    }
+    hash.0.to_le_bytes()
+}
+
+/// Running 128-bit FNV-1a hash of the bytes passed to [`Fnv128::update`].
+struct Fnv128(u128);
+
+impl Default for Fnv128 {
+    fn default() -> Self {
+        Fnv128(FNV_OFFSET)
+    }
+}
+
+impl Fnv128 {
+    fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u128).wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fingerprint;
+    use crate::tests::pack_with;
+    use crate::{pack, Packer};
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn independent_of_options() {
+        let values: Vec<Decimal> = (0..1000i64)
+            .map(|i| Decimal::new(100_000 + i * 7919 % 503 - 251, 2))
+            .collect();
+        let expected = fingerprint(&pack(&values));
+        for packer in [
+            Packer::new().with_chained_heads(),
+            Packer::new().with_block_len(32).unwrap(),
+            Packer::new().with_adaptive_encoding(),
+            Packer::new().with_block_bounds().with_byte_split(),
+        ] {
+            let packed = pack_with(packer, &values);
+            assert_eq!(fingerprint(&packed), expected);
+        }
+
+        let mut changed = values.clone();
+        changed[500] += Decimal::new(1, 2);
+        assert_ne!(fingerprint(&pack(&changed)), expected);
+        let rescaled: Vec<Decimal> = values.iter().map(|v| v * Decimal::new(10, 1)).collect();
+        assert_ne!(fingerprint(&pack(&rescaled)), expected);
+        // A trailing zero isn't the padding of the last block.
+        let mut padded = values.clone();
+        padded.push(Decimal::ZERO);
+        assert_ne!(fingerprint(&pack(&padded)), expected);
+
+        // Stays the same across versions.
+        assert_eq!(
+            fingerprint(&pack(&[])),
+            0x9d30_c1f7_8465_995b_e47d_da5e_4e4e_77edu128.to_le_bytes()
+        );
+    }
+}
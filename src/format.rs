@@ -0,0 +1,1308 @@
+//! Binary format of `PackedDecimals`.
+//!
+//! Every stream starts with the magic bytes `FPAK` and a version byte. All integers are little
+//! endian, as are the 32-bit words of bit-packed `vals`, so packing the same values with the
+//! same options yields the same bytes on every target.
+//!
+//! Version 2 (current) continues with a header holding everything needed to decode the blocks:
+//! flags (`u8`, bit 1 = block bounds, bit 2 = fixed flags), the block length (`u16`), the count
+//! of decimals (`u64`) and the count of blocks per lane (`u64`). Packs of another
+//! [`Packable`] type than `Decimal` set bit 3 of the flags and follow the
+//! header with their type tag (`u8`), packs of another count of columns than 4 set bit 4 and
+//! follow with it (`u8`). With fixed flags, all values share their scale and sign:
+//! lane 0 is omitted and the flags of the values follow (`u32`). Blocks follow block-major, i.e.
+//! the `n`th block of all lanes before the `n + 1`th. Each block is its `bits` (`u8`),
+//! `encoding` (`u8`, bit 7 = continuation), `head` (`u32`) and `vals`, which are exactly
+//! `bits * block_len / 8` bytes. Packs with chained heads set bit 6 of the flags and store the
+//! heads of continuation blocks, which are XOR deltas, as LEB128 varints instead.
+//! Blocks of lane 1 with the sign-magnitude encoding are followed by their sign bitmap of
+//! `block_len / 8` bytes, with the delta-of-delta encoding by their initial difference of 12
+//! bytes, with the hybrid delta encoding by their sign bitmap, the bitmap of the values stored
+//! as they are (both `block_len / 8` bytes) and the 12 bytes of the mantissa of each of them.
+//! Blocks of any lane with the byte-split encoding are followed by the bitmap of their byte
+//! planes (`u8`). With block bounds, blocks of lane 0 are followed by the smallest and
+//! largest value of the block, each as the 16 bytes of `Decimal::serialize`. With checksums
+//! (bit 5 of the flags), the blocks of all lanes at each index are followed by the CRC-32
+//! (IEEE) of their bytes (`u32`), see [`PackedDecimals::write_to_with_checksums`]. Earlier
+//! releases marked chained heads with bit 0 of the flags instead, making every block but the
+//! first a continuation; this is still read, but no longer written.
+//!
+//! The type tag also versions the lane layout. Packs of `Decimal` (tag 0, also without the
+//! typed flag) hold in lane `i` the little endian bytes `4 * i..4 * i + 4` of
+//! `Decimal::serialize`: the flags, then the mantissa from its low to its high word. This
+//! layout is fixed; a different split of `Decimal`s takes a tag of its own, like the custom
+//! layouts of [`Packer::with_lane_fn`](crate::Packer::with_lane_fn) do (tag 6), so that packs
+//! of tag 0 written by any release keep decoding the same. Decoders of `Decimal`s look up the
+//! layout of a pack by its tag, so a new layout comes with a tag and a mapping of its lanes to
+//! `Decimal`s of its own, next to the one of tag 0. Decoding a pack of one tag as another fails
+//! with [`FloatpackError::TypeMismatch`] instead of yielding other values.
+//!
+//! Version 1 stored the lanes one after another, each as the count of blocks (`u64`) followed by
+//! the blocks as `bits` (`u8`), `head` (`u32`), length of `vals` (`u64`) and `vals`, with the count
+//! of decimals as a trailer (`u64`). Block length (256), encoding (XOR) and head chaining (off)
+//! were fixed. It is still decoded by [`PackedDecimals::from_bytes`] and
+//! [`PackedDecimals::read_from`], and [`migrate`] converts it to the current version.
+use crate::bitpacker::AnyBitPacker;
+use crate::crc::{crc32, ChecksumReader};
+use crate::decode::decimal_layout;
+use crate::{
+    Block, Encoding, FloatpackError, Packable, PackedColumns, PackedDecimals, BLOCK_VALUES,
+};
+use bitpacking::{BitPacker, BitPacker8x};
+use rust_decimal::Decimal;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+/// Magic bytes every packed stream starts with.
+pub const MAGIC: [u8; 4] = *b"FPAK";
+/// Version of the format written by this version of floatpack.
+pub const VERSION: u8 = 2;
+
+pub(crate) const CHAINED: u8 = 1;
+/// Blocks of lane 0 are followed by their bounds.
+pub(crate) const BOUNDS: u8 = 2;
+/// Lane 0 is omitted, the flags of all values follow the header.
+pub(crate) const FIXED_FLAGS: u8 = 4;
+/// The type tag of the values follows the header.
+pub(crate) const TYPED: u8 = 8;
+/// The count of columns follows the header.
+pub(crate) const COLUMNS: u8 = 16;
+/// Each record of the blocks of all lanes is followed by its CRC-32.
+pub(crate) const CHECKSUMS: u8 = 32;
+/// Heads of continuation blocks are LEB128 varints.
+pub(crate) const VARINT_HEADS: u8 = 64;
+/// Bit of the encoding tag marking a [`Block::continuation`].
+pub(crate) const CONTINUATION: u8 = 0x80;
+
+/// Bytes of the magic, version, flags, block length, count and count of blocks.
+pub(crate) const HEADER_LEN: usize = 24;
+/// Bytes of the flags of all values following the header, see [`FIXED_FLAGS`].
+pub(crate) const FIXED_FLAGS_LEN: usize = 4;
+/// Bytes of the type tag following the header, see [`TYPED`].
+const TYPE_TAG_LEN: usize = 1;
+/// Bytes of the count of columns following the header, see [`COLUMNS`].
+const COLUMNS_LEN: usize = 1;
+
+impl<const N: usize> PackedColumns<N> {
+    /// Serialize into the current binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.serialized_len());
+        self.write_to(&mut bytes)
+            .expect("writing to a Vec never fails");
+        debug_assert_eq!(bytes.len(), self.serialized_len());
+        bytes
+    }
+
+    /// Exact length of the output of [`PackedDecimals::to_bytes`] and
+    /// [`PackedDecimals::write_to`], without serializing.
+    pub fn serialized_len(&self) -> usize {
+        let (header, lanes) = self.serialized_lanes();
+        header + lanes.iter().flatten().map(Block::size_bytes).sum::<usize>()
+    }
+
+    /// The flags all values share, if lane 0 holds nothing else: every block of it is of bit
+    /// width 0 with them as its head, without bounds and not a continuation. Such a lane 0 is
+    /// omitted when serializing.
+    pub(crate) fn fixed_flags(&self) -> Option<u32> {
+        // Without lane 0, a single lane would leave nothing to read per block.
+        if N == 1 {
+            return None;
+        }
+        let head = self.blocks[0].first()?.head;
+        self.blocks[0]
+            .iter()
+            .all(|b| {
+                b.bits == 0
+                    && b.encoding == Encoding::Xor
+                    && b.head == head
+                    && !b.continuation
+                    && b.bounds.is_none()
+            })
+            .then_some(head)
+    }
+
+    /// Length of the header and the lanes that are serialized.
+    fn serialized_lanes(&self) -> (usize, &[Vec<Block>]) {
+        let mut header = HEADER_LEN;
+        if self.type_tag != 0 {
+            header += TYPE_TAG_LEN;
+        }
+        if N != 4 {
+            header += COLUMNS_LEN;
+        }
+        match self.fixed_flags() {
+            Some(_) => (header + FIXED_FLAGS_LEN, &self.blocks[1..]),
+            None => (header, &self.blocks),
+        }
+    }
+
+    /// For each block, the index of its first value and the bytes the records of the block in
+    /// all lanes serialized occupy in the output of [`PackedDecimals::to_bytes`], without
+    /// serializing.
+    ///
+    /// Blocks are serialized block-major, so the ranges are contiguous, following the header.
+    pub fn block_ranges(&self) -> Vec<(usize, Range<usize>)> {
+        let (mut offset, lanes) = self.serialized_lanes();
+        (0..self.blocks[0].len())
+            .map(|n| {
+                let start = offset;
+                offset += lanes.iter().map(|lane| lane[n].size_bytes()).sum::<usize>();
+                (n * self.block_values(), start..offset)
+            })
+            .collect()
+    }
+
+    /// Serialize into the current binary format, writing to `writer`.
+    ///
+    /// The counts of values and blocks are written up front, so a [`crate::StreamDecoder`]
+    /// reading the stream knows them before decoding starts.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), FloatpackError> {
+        self.write_header(&mut writer, false)?;
+        for n in 0..self.blocks[0].len() {
+            self.write_record(&mut writer, n)?;
+        }
+        Ok(())
+    }
+
+    /// [`PackedDecimals::write_to`], following the blocks of all lanes at each index with their
+    /// checksum.
+    ///
+    /// This costs 4 bytes per block. Reading checks them, so a [`crate::StreamDecoder`] detects
+    /// a corrupt block before yielding any of its values.
+    pub fn write_to_with_checksums<W: Write>(&self, mut writer: W) -> Result<(), FloatpackError> {
+        self.write_header(&mut writer, true)?;
+        let mut record = Vec::new();
+        for n in 0..self.blocks[0].len() {
+            record.clear();
+            self.write_record(&mut record, n)?;
+            writer.write_all(&record)?;
+            writer.write_all(&crc32(&record).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Serialize into the current binary format with checksums, see
+    /// [`PackedDecimals::write_to_with_checksums`].
+    pub fn to_bytes_with_checksums(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.serialized_len() + 4 * self.blocks[0].len());
+        self.write_to_with_checksums(&mut bytes)
+            .expect("writing to a Vec never fails");
+        bytes
+    }
+
+    /// A reader yielding the output of [`PackedDecimals::to_bytes`], see [`PackedReader`].
+    pub fn reader(&self) -> PackedReader<'_, N> {
+        PackedReader {
+            packed: self,
+            buffer: Vec::new(),
+            pos: 0,
+            next: 0,
+        }
+    }
+
+    fn write_header<W: Write>(&self, writer: &mut W, checksums: bool) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        let bounds = self.blocks[0].first().is_some_and(|b| b.bounds.is_some());
+        let fixed_flags = self.fixed_flags();
+        let mut flags = if bounds { BOUNDS } else { 0 };
+        if fixed_flags.is_some() {
+            flags |= FIXED_FLAGS;
+        }
+        if self.type_tag != 0 {
+            flags |= TYPED;
+        }
+        if N != 4 {
+            flags |= COLUMNS;
+        }
+        if checksums {
+            flags |= CHECKSUMS;
+        }
+        if self.chained() {
+            flags |= VARINT_HEADS;
+        }
+        writer.write_all(&[VERSION, flags])?;
+        writer.write_all(&(self.block_len as u16).to_le_bytes())?;
+        writer.write_all(&(self.count as u64).to_le_bytes())?;
+        writer.write_all(&(self.blocks[0].len() as u64).to_le_bytes())?;
+        if self.type_tag != 0 {
+            writer.write_all(&[self.type_tag])?;
+        }
+        if N != 4 {
+            writer.write_all(&[N as u8])?;
+        }
+        if let Some(flags) = fixed_flags {
+            writer.write_all(&flags.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Write the `n`th block of all lanes serialized.
+    fn write_record<W: Write>(&self, writer: &mut W, n: usize) -> io::Result<()> {
+        let (_, lanes) = self.serialized_lanes();
+        for lane in lanes {
+            let block = &lane[n];
+            let continuation = if block.continuation { CONTINUATION } else { 0 };
+            writer.write_all(&[block.bits, encoding_tag(block.encoding) | continuation])?;
+            if block.continuation {
+                write_varint(writer, block.head)?;
+            } else {
+                writer.write_all(&block.head.to_le_bytes())?;
+            }
+            writer.write_all(&block.vals)?;
+            writer.write_all(&block.signs)?;
+            if let Some((min, max)) = block.bounds {
+                writer.write_all(&min.serialize())?;
+                writer.write_all(&max.serialize())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the structure is consistent, so decoding it won't fail.
+    ///
+    /// Packs produced by [`Packer`](crate::Packer) always are, deserializing checks this as well.
+    /// Modified or hand-made packs may not be: the lanes need the same count of blocks, holding
+    /// `count` values, every block needs `vals` matching its bit width and a valid combination
+    /// of encodings. Packs of 4 lanes also need to hold `Decimal`s, as anything else would
+    /// decode as wrong `Decimal`s; values of other types of 4 columns are checked by
+    /// [`try_unpack_as`](crate::try_unpack_as).
+    pub fn verify(&self) -> Result<(), FloatpackError> {
+        check_decimals(self)?;
+        validate(self)
+    }
+
+    /// Deserialize from any supported version of the binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FloatpackError> {
+        let mut reader = bytes;
+        let packed = Self::read_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(corrupt(format!("{} trailing bytes", reader.len())));
+        }
+        Ok(packed)
+    }
+
+    /// Deserialize from any supported version of the binary format, reading from `reader`.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, FloatpackError> {
+        let packed = match read_version(&mut reader)? {
+            1 if N == 4 => read_v1(&mut reader)?,
+            2 => read_v2(&mut reader)?,
+            version => return Err(FloatpackError::UnsupportedVersion(version)),
+        };
+        validate(&packed)?;
+        Ok(packed)
+    }
+}
+
+/// Yields the serialized bytes of a pack as they are read, e.g. to `io::copy` it into a file
+/// or socket without building the whole output first.
+///
+/// The header and then one block of all lanes at a time are serialized into a buffer once the
+/// preceding bytes have been read, so memory stays bounded by one block.
+///
+/// Created by [`PackedDecimals::reader`].
+pub struct PackedReader<'a, const N: usize = 4> {
+    packed: &'a PackedColumns<N>,
+    buffer: Vec<u8>,
+    pos: usize,
+    /// What to serialize once the buffer is read: the header at 0, then block `next - 1`.
+    next: usize,
+}
+
+impl<const N: usize> Read for PackedReader<'_, N> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buffer.len() {
+            if self.next > self.packed.blocks[0].len() {
+                return Ok(0);
+            }
+            self.buffer.clear();
+            self.pos = 0;
+            match self.next {
+                0 => self.packed.write_header(&mut self.buffer, false)?,
+                next => self.packed.write_record(&mut self.buffer, next - 1)?,
+            }
+            self.next += 1;
+        }
+        let len = buf.len().min(self.buffer.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buffer[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Summary of a [`migrate`] run.
+#[derive(Debug, Default, PartialEq)]
+pub struct MigrationReport {
+    /// Version of the input.
+    pub from_version: u8,
+    /// Count of decimals written.
+    pub values: usize,
+    /// Count of blocks per lane written.
+    pub blocks: usize,
+    /// Inconsistencies in the input that were repaired.
+    pub anomalies: Vec<Anomaly>,
+}
+
+/// An inconsistency found and repaired by [`migrate`].
+#[derive(Debug, PartialEq)]
+pub enum Anomaly {
+    /// The lanes had different counts of blocks; all lanes were cut to the shortest one.
+    LaneLengths([usize; 4]),
+    /// The count of decimals didn't match the count of blocks and was corrected.
+    Count { stored: usize, repaired: usize },
+    /// A block held more `vals` than its bit width needs; the excess was dropped.
+    ExcessVals {
+        lane: usize,
+        block: usize,
+        bytes: usize,
+    },
+}
+
+/// Convert a stream of version [`VERSION`] or older into the current version.
+///
+/// Blocks of version 1 are copied one at a time, so memory use doesn't depend on the size of
+/// such an input. Version 1 stores the count of decimals at its end, hence the input has to be
+/// seekable. Inputs that already are of the current version are read into memory as a whole
+/// and written as they are, minus checksums, if they hold values of 4 columns like `Decimal`s.
+pub fn migrate<R: Read + Seek, W: Write>(
+    mut reader: R,
+    mut writer: W,
+) -> Result<MigrationReport, FloatpackError> {
+    let start = reader.stream_position()?;
+    let from_version = read_version(&mut reader)?;
+    match from_version {
+        1 => {}
+        VERSION => {
+            reader.seek(SeekFrom::Start(start))?;
+            let packed = PackedDecimals::read_from(&mut reader)?;
+            packed.write_to(&mut writer)?;
+            return Ok(MigrationReport {
+                from_version,
+                values: packed.count,
+                blocks: packed.blocks[0].len(),
+                anomalies: Vec::new(),
+            });
+        }
+        version => return Err(FloatpackError::UnsupportedVersion(version)),
+    }
+
+    // The first pass finds where each lane starts and the trailing count.
+    let mut cursors = [0u64; 4];
+    let mut lane_blocks = [0usize; 4];
+    for i in 0..4 {
+        lane_blocks[i] = read_u64(&mut reader)? as usize;
+        cursors[i] = reader.stream_position()?;
+        for _ in 0..lane_blocks[i] {
+            reader.seek(SeekFrom::Current(5))?;
+            let len = read_u64(&mut reader)?;
+            reader.seek(SeekFrom::Current(len as i64))?;
+        }
+    }
+    let stored = read_u64(&mut reader)? as usize;
+
+    let mut anomalies = Vec::new();
+    let mut blocks = *lane_blocks.iter().min().unwrap();
+    if lane_blocks.iter().any(|&n| n != blocks) {
+        anomalies.push(Anomaly::LaneLengths(lane_blocks));
+    }
+    let values = BLOCK_VALUES;
+    let count = stored.min(blocks * values);
+    blocks = count.div_ceil(values);
+    if count != stored {
+        anomalies.push(Anomaly::Count {
+            stored,
+            repaired: count,
+        });
+    }
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION, 0])?;
+    writer.write_all(&(BitPacker8x::BLOCK_LEN as u16).to_le_bytes())?;
+    writer.write_all(&(count as u64).to_le_bytes())?;
+    writer.write_all(&(blocks as u64).to_le_bytes())?;
+    for n in 0..blocks {
+        for (lane, cursor) in cursors.iter_mut().enumerate() {
+            reader.seek(SeekFrom::Start(*cursor))?;
+            let mut block = read_block_v1(&mut reader)?;
+            *cursor = reader.stream_position()?;
+            let len = block_vals_len(BitPacker8x::BLOCK_LEN, block.bits)?;
+            if block.vals.len() > len {
+                anomalies.push(Anomaly::ExcessVals {
+                    lane,
+                    block: n,
+                    bytes: block.vals.len() - len,
+                });
+                block.vals.truncate(len);
+            }
+            if block.vals.len() < len {
+                return Err(corrupt(format!(
+                    "block {} of lane {} is truncated",
+                    n, lane
+                )));
+            }
+            writer.write_all(&[block.bits, encoding_tag(Encoding::Xor)])?;
+            writer.write_all(&block.head.to_le_bytes())?;
+            writer.write_all(&block.vals)?;
+        }
+    }
+    Ok(MigrationReport {
+        from_version,
+        values: count,
+        blocks,
+        anomalies,
+    })
+}
+
+pub(crate) fn corrupt(reason: String) -> FloatpackError {
+    FloatpackError::Corrupt(reason)
+}
+
+fn encoding_tag(encoding: Encoding) -> u8 {
+    match encoding {
+        Encoding::Xor => 0,
+        Encoding::Raw => 1,
+        Encoding::SignMagnitude => 2,
+        Encoding::DeltaOfDelta => 3,
+        Encoding::ByteSplit => 4,
+        Encoding::HybridDelta => 5,
+    }
+}
+
+fn encoding_from_tag(tag: u8) -> Result<Encoding, FloatpackError> {
+    match tag {
+        0 => Ok(Encoding::Xor),
+        1 => Ok(Encoding::Raw),
+        2 => Ok(Encoding::SignMagnitude),
+        3 => Ok(Encoding::DeltaOfDelta),
+        4 => Ok(Encoding::ByteSplit),
+        5 => Ok(Encoding::HybridDelta),
+        _ => Err(corrupt(format!("unknown encoding {}", tag))),
+    }
+}
+
+pub(crate) fn block_vals_len(block_len: usize, bits: u8) -> Result<usize, FloatpackError> {
+    if bits > 32 {
+        return Err(corrupt(format!("bit width {} exceeds 32", bits)));
+    }
+    Ok(bits as usize * block_len / 8)
+}
+
+/// Check that a pack of 4 lanes holds `Decimal`s, the values the decoders of
+/// [`PackedDecimals`] read it as.
+pub(crate) fn check_decimals<const N: usize>(
+    packed: &PackedColumns<N>,
+) -> Result<(), FloatpackError> {
+    match packed.type_tag {
+        tag if N != 4 || decimal_layout(tag).is_some() => Ok(()),
+        found => Err(FloatpackError::TypeMismatch {
+            expected: Decimal::TYPE_TAG,
+            found,
+        }),
+    }
+}
+
+/// Check the invariants decoding relies on (see [`PackedDecimals::verify`]).
+pub(crate) fn validate<const N: usize>(packed: &PackedColumns<N>) -> Result<(), FloatpackError> {
+    validate_layout(packed)?;
+    for n in 0..packed.blocks[0].len() {
+        validate_block(packed, n)?;
+    }
+    Ok(())
+}
+
+/// Check the block length and that the counts of blocks and values agree.
+pub(crate) fn validate_layout<const N: usize>(
+    packed: &PackedColumns<N>,
+) -> Result<(), FloatpackError> {
+    AnyBitPacker::new(packed.block_len)
+        .ok_or(FloatpackError::UnsupportedBlockLen(packed.block_len))?;
+    let blocks = packed.blocks[0].len();
+    if packed.blocks.iter().any(|lane| lane.len() != blocks) {
+        return Err(corrupt("lanes have different counts of blocks".into()));
+    }
+    let bounds = packed.blocks[0]
+        .iter()
+        .filter(|b| b.bounds.is_some())
+        .count();
+    if bounds != 0 && bounds != blocks {
+        return Err(corrupt(format!(
+            "{} of {} blocks with bounds",
+            bounds, blocks
+        )));
+    }
+    validate_count(packed.count, blocks, packed.block_values())
+}
+
+/// Check that `count` values fill `blocks` blocks, all but the last one completely.
+pub(crate) fn validate_count(
+    count: usize,
+    blocks: usize,
+    block_values: usize,
+) -> Result<(), FloatpackError> {
+    let capacity = blocks.saturating_mul(block_values);
+    if count > capacity || count.saturating_add(block_values) <= capacity {
+        return Err(corrupt(format!(
+            "count {} doesn't fit {} blocks",
+            count, blocks
+        )));
+    }
+    Ok(())
+}
+
+/// Check the `n`th block of all lanes, assuming a valid layout.
+pub(crate) fn validate_block<const N: usize>(
+    packed: &PackedColumns<N>,
+    n: usize,
+) -> Result<(), FloatpackError> {
+    let lanes: [&Block; N] = std::array::from_fn(|i| &packed.blocks[i][n]);
+    validate_lanes(lanes, packed.block_len, n)
+}
+
+/// Check the blocks of all lanes at index `n`.
+pub(crate) fn validate_lanes<const N: usize>(
+    lanes: [&Block; N],
+    block_len: usize,
+    n: usize,
+) -> Result<(), FloatpackError> {
+    // The mantissa encodings only span the lanes of `Decimal`s.
+    let mantissa = (N == 4)
+        .then(|| lanes[1].encoding)
+        .filter(|e| e.is_mantissa());
+    for (i, block) in lanes.iter().enumerate() {
+        let signs = match mantissa {
+            Some(encoding) if i == 1 => {
+                encoding.side_len(block_len) + encoding.absolutes_len(&block.signs, block_len)
+            }
+            _ if block.encoding.is_mantissa() => 0,
+            _ => block.encoding.side_len(block_len),
+        };
+        let consistent = if i == 0 || mantissa.is_none() {
+            !block.encoding.is_mantissa()
+        } else {
+            Some(block.encoding) == mantissa
+        };
+        let reason = if block.bits > 32 {
+            format!("bit width {} exceeds 32", block.bits)
+        } else if block.vals.len() != block_vals_len(block_len, block.bits)? {
+            format!(
+                "{} bytes of vals for bit width {}",
+                block.vals.len(),
+                block.bits
+            )
+        } else if block.signs.len() != signs {
+            format!("{} bytes of signs, expected {}", block.signs.len(), signs)
+        } else if block.encoding == Encoding::ByteSplit
+            && (block.signs[0] > 0xF || 8 * block.signs[0].count_ones() != block.bits as u32)
+        {
+            format!(
+                "byte planes {:#x} for bit width {}",
+                block.signs[0], block.bits
+            )
+        } else if block.continuation != lanes[0].continuation {
+            "continuation doesn't match the other lanes".into()
+        } else if n == 0 && block.continuation {
+            "first block is a continuation".into()
+        } else if i > 0 && block.bounds.is_some() {
+            "bounds outside of lane 0".into()
+        } else if block.bounds.is_some_and(|(min, max)| min > max) {
+            "bounds with min above max".into()
+        } else if !consistent {
+            format!(
+                "encoding {:?} doesn't match the other lanes",
+                block.encoding
+            )
+        } else {
+            continue;
+        };
+        return Err(FloatpackError::BlockDecode {
+            lane: i,
+            block: n,
+            reason,
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn read_version<R: Read>(reader: &mut R) -> Result<u8, FloatpackError> {
+    let mut header = [0u8; 5];
+    reader.read_exact(&mut header)?;
+    if header[..4] != MAGIC {
+        return Err(corrupt("missing magic bytes".into()));
+    }
+    Ok(header[4])
+}
+
+pub(crate) fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// Reads a `u32` written by [`write_varint`].
+pub(crate) fn read_varint<R: Read>(reader: &mut R) -> Result<u32, FloatpackError> {
+    let mut value = 0u64;
+    for i in 0..5 {
+        let byte = read_u8(reader)?;
+        value |= ((byte & 0x7F) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            if value > u32::MAX as u64 {
+                return Err(corrupt(format!("varint {} exceeds u32", value)));
+            }
+            return Ok(value as u32);
+        }
+    }
+    Err(corrupt("varint exceeds 5 bytes".into()))
+}
+
+/// Writes `value` as LEB128: 7 bits per byte, least significant first, bit 7 marking that
+/// another byte follows.
+pub(crate) fn write_varint<W: Write>(writer: &mut W, mut value: u32) -> io::Result<()> {
+    let mut bytes = [0u8; 5];
+    let mut len = 0;
+    loop {
+        bytes[len] = (value & 0x7F) as u8;
+        value >>= 7;
+        len += 1;
+        if value == 0 {
+            break;
+        }
+        bytes[len - 1] |= 0x80;
+    }
+    writer.write_all(&bytes[..len])
+}
+
+/// Bytes [`write_varint`] writes for `value`.
+pub(crate) fn varint_len(value: u32) -> usize {
+    (32 - value.leading_zeros() as usize).max(1).div_ceil(7)
+}
+
+fn read_decimal<R: Read>(reader: &mut R) -> io::Result<Decimal> {
+    let mut bytes = [0u8; 16];
+    reader.read_exact(&mut bytes)?;
+    Ok(Decimal::deserialize(bytes))
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Reads `len` bytes without trusting `len` for the allocation up front.
+fn read_vec<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut vals = Vec::new();
+    reader.take(len).read_to_end(&mut vals)?;
+    if (vals.len() as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(vals)
+}
+
+/// The header of the version 2 format, following the version byte.
+pub(crate) struct Header {
+    flags: u8,
+    pub(crate) block_len: usize,
+    pub(crate) count: usize,
+    pub(crate) blocks: usize,
+    /// [`PackedDecimals::type_tag`] of the values.
+    pub(crate) type_tag: u8,
+    /// Count of columns, i.e. lanes.
+    pub(crate) columns: usize,
+    /// The flags of all values, if lane 0 is omitted.
+    fixed_flags: Option<u32>,
+}
+
+pub(crate) fn read_header_v2<R: Read>(reader: &mut R) -> Result<Header, FloatpackError> {
+    let mut header = Header {
+        flags: read_u8(reader)?,
+        block_len: read_u16(reader)? as usize,
+        count: read_u64(reader)? as usize,
+        blocks: read_u64(reader)? as usize,
+        type_tag: 0,
+        columns: 4,
+        fixed_flags: None,
+    };
+    let known = CHAINED | BOUNDS | FIXED_FLAGS | TYPED | COLUMNS | CHECKSUMS | VARINT_HEADS;
+    if header.flags & !known != 0 {
+        return Err(corrupt(format!("unknown flags {:#x}", header.flags)));
+    }
+    if header.flags & TYPED != 0 {
+        header.type_tag = read_u8(reader)?;
+        if header.type_tag == 0 {
+            return Err(corrupt("type tag 0 is implied".to_string()));
+        }
+    }
+    if header.flags & COLUMNS != 0 {
+        header.columns = read_u8(reader)? as usize;
+        if header.columns == 0 || header.columns == 4 {
+            return Err(corrupt(format!(
+                "invalid count of columns {}",
+                header.columns
+            )));
+        }
+    }
+    if header.flags & FIXED_FLAGS != 0 {
+        if header.flags & (CHAINED | BOUNDS) != 0 || header.columns == 1 {
+            return Err(corrupt(format!("flags {:#x} need lane 0", header.flags)));
+        }
+        header.fixed_flags = Some(read_u32(reader)?);
+    }
+    Ok(header)
+}
+
+/// Reads the `n`th record, pushing its block of each lane to `lanes`. With checksums, they are
+/// checked before returning.
+pub(crate) fn read_record_v2<R: Read, const N: usize>(
+    reader: &mut R,
+    header: &Header,
+    n: usize,
+    lanes: &mut [Vec<Block>; N],
+) -> Result<(), FloatpackError> {
+    if header.flags & CHECKSUMS == 0 {
+        for (i, lane) in lanes.iter_mut().enumerate() {
+            lane.push(read_block_v2(reader, header, i, n)?);
+        }
+        return Ok(());
+    }
+    let mut checked = ChecksumReader::new(reader);
+    let mut blocks = Vec::with_capacity(N);
+    for i in 0..N {
+        blocks.push(read_block_v2(&mut checked, header, i, n)?);
+    }
+    let crc = checked.crc.finish();
+    if read_u32(reader)? != crc {
+        return Err(FloatpackError::ChecksumMismatch(n));
+    }
+    for (lane, block) in lanes.iter_mut().zip(blocks) {
+        lane.push(block);
+    }
+    Ok(())
+}
+
+/// Reads the `n`th block of `lane`. Omitted blocks of lane 0 are made up of the fixed flags.
+pub(crate) fn read_block_v2<R: Read>(
+    reader: &mut R,
+    header: &Header,
+    lane: usize,
+    n: usize,
+) -> Result<Block, FloatpackError> {
+    if let (0, Some(head)) = (lane, header.fixed_flags) {
+        return Ok(Block {
+            bits: 0,
+            encoding: Encoding::Xor,
+            head,
+            vals: Vec::new(),
+            signs: Vec::new(),
+            continuation: false,
+            bounds: None,
+        });
+    }
+    let bits = read_u8(reader)?;
+    let tag = read_u8(reader)?;
+    let encoding = encoding_from_tag(tag & !CONTINUATION)?;
+    let continuation = tag & CONTINUATION != 0 || (header.flags & CHAINED != 0 && n > 0);
+    let head = if continuation && header.flags & VARINT_HEADS != 0 {
+        read_varint(reader)?
+    } else {
+        read_u32(reader)?
+    };
+    let vals = read_vec(reader, block_vals_len(header.block_len, bits)? as u64)?;
+    let signs = if lane == 1 || !encoding.is_mantissa() {
+        let mut side = read_vec(reader, encoding.side_len(header.block_len) as u64)?;
+        let absolutes = encoding.absolutes_len(&side, header.block_len);
+        side.extend(read_vec(reader, absolutes as u64)?);
+        side
+    } else {
+        Vec::new()
+    };
+    let bounds = if lane == 0 && header.flags & BOUNDS != 0 {
+        Some((read_decimal(reader)?, read_decimal(reader)?))
+    } else {
+        None
+    };
+    Ok(Block {
+        bits,
+        encoding,
+        head,
+        vals,
+        signs,
+        continuation,
+        bounds,
+    })
+}
+
+fn read_v2<R: Read, const N: usize>(reader: &mut R) -> Result<PackedColumns<N>, FloatpackError> {
+    let header = read_header_v2(reader)?;
+    if header.columns != N {
+        return Err(corrupt(format!(
+            "{} columns, expected {}",
+            header.columns, N
+        )));
+    }
+    let mut packed = PackedColumns {
+        count: header.count,
+        block_len: header.block_len,
+        type_tag: header.type_tag,
+        ..PackedColumns::default()
+    };
+    validate_count(header.count, header.blocks, header.block_len + 1)?;
+    for n in 0..header.blocks {
+        read_record_v2(reader, &header, n, &mut packed.blocks)?;
+    }
+    Ok(packed)
+}
+
+fn read_block_v1<R: Read>(reader: &mut R) -> Result<Block, FloatpackError> {
+    let bits = read_u8(reader)?;
+    let head = read_u32(reader)?;
+    let len = read_u64(reader)?;
+    let vals = read_vec(reader, len)?;
+    Ok(Block {
+        bits,
+        encoding: Encoding::Xor,
+        head,
+        vals,
+        signs: Vec::new(),
+        continuation: false,
+        bounds: None,
+    })
+}
+
+/// Version 1 only held `Decimal`s, so `N` is 4.
+fn read_v1<R: Read, const N: usize>(reader: &mut R) -> Result<PackedColumns<N>, FloatpackError> {
+    let mut packed = PackedColumns::default();
+    for lane in packed.blocks.iter_mut() {
+        let blocks = read_u64(reader)?;
+        for _ in 0..blocks {
+            lane.push(read_block_v1(reader)?);
+        }
+    }
+    packed.count = read_u64(reader)? as usize;
+    Ok(packed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encoding_tag, migrate, read_block_v2, read_header_v2, read_varint, varint_len,
+        write_varint, Anomaly, MAGIC, VERSION,
+    };
+    use crate::{pack, unpack, FloatpackError, PackedColumns, PackedDecimals, Packer};
+    use rust_decimal::Decimal;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn portable_layout() {
+        // `bitpacking` picks SIMD or scalar code by the features of the CPU at runtime. All of
+        // them produce the same layout (see `bitpacker::tests::golden_layout`), which these
+        // checksums pin across machines.
+        let values: Vec<Decimal> = (0..1000i64)
+            .map(|i| Decimal::new(100_000 + i * 7919 % 503 - 251, 2))
+            .collect();
+        let checksums: Vec<u32> = [32, 128, 256]
+            .iter()
+            .map(|&n| {
+                let mut packer = Packer::new().with_block_len(n).unwrap();
+                for v in &values {
+                    packer.load_decimal(v);
+                }
+                crate::crc::crc32(&packer.finish().to_bytes())
+            })
+            .collect();
+        assert_eq!(checksums, [0xf2e9_d83b, 0x3159_5036, 0x9d06_6f0d]);
+    }
+
+    #[test]
+    fn decimal_lane_layout() {
+        // The layout of tag 0, which packs written by any release decode with.
+        let value = Decimal::from_i128_with_scale(-0x0123_4567_89ab_cdef_0011_2233, 7);
+        let packed = pack(&[value]);
+        let heads: Vec<u32> = packed.blocks.iter().map(|lane| lane[0].head).collect();
+        assert_eq!(heads, [0x8007_0000, 0x0011_2233, 0x89ab_cdef, 0x0123_4567]);
+        let bytes = packed.to_bytes();
+        assert_eq!(bytes[4], VERSION);
+        assert_eq!(PackedDecimals::from_bytes(&bytes).unwrap(), packed);
+
+        // Any other layout is told apart by its tag.
+        let mut other = packed.clone();
+        other.type_tag = 7;
+        let other = PackedDecimals::from_bytes(&other.to_bytes()).unwrap();
+        assert!(matches!(
+            crate::try_unpack(&other),
+            Err(FloatpackError::TypeMismatch {
+                expected: 0,
+                found: 7
+            })
+        ));
+    }
+
+    /// The writer of version 1, only kept to test reading it.
+    fn write_v1(packed: &PackedDecimals) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(1);
+        for lane in &packed.blocks {
+            bytes.extend((lane.len() as u64).to_le_bytes());
+            for block in lane {
+                bytes.push(block.bits);
+                bytes.extend(block.head.to_le_bytes());
+                bytes.extend((block.vals.len() as u64).to_le_bytes());
+                bytes.extend(&block.vals);
+            }
+        }
+        bytes.extend((packed.count as u64).to_le_bytes());
+        bytes
+    }
+
+    fn values(n: i64) -> Vec<Decimal> {
+        (0..n).map(|v| Decimal::new(v * v - 7 * v, 2)).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let values = values(1000);
+        let mut packer = Packer::new()
+            .with_block_len(32)
+            .unwrap()
+            .with_adaptive_encoding()
+            .with_chained_heads();
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        for packed in [pack(&values), packer.finish(), pack(&[])] {
+            let bytes = packed.to_bytes();
+            assert_eq!(bytes[4], VERSION);
+            let decoded = PackedDecimals::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded, packed);
+            let read = PackedDecimals::read_from(&bytes[..]).unwrap();
+            assert_eq!(read, packed);
+        }
+    }
+
+    #[test]
+    fn reads_chained_flag() {
+        let packed = crate::tests::pack_with(Packer::new().with_chained_heads(), &values(1000));
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([VERSION, 1]);
+        bytes.extend((packed.block_len as u16).to_le_bytes());
+        bytes.extend((packed.count as u64).to_le_bytes());
+        bytes.extend((packed.blocks[0].len() as u64).to_le_bytes());
+        for n in 0..packed.blocks[0].len() {
+            for lane in &packed.blocks {
+                let block = &lane[n];
+                bytes.extend([block.bits, encoding_tag(block.encoding)]);
+                bytes.extend(block.head.to_le_bytes());
+                bytes.extend(&block.vals);
+                bytes.extend(&block.signs);
+            }
+        }
+        assert_eq!(PackedDecimals::from_bytes(&bytes).unwrap(), packed);
+        assert_ne!(bytes, packed.to_bytes());
+    }
+
+    #[test]
+    fn varints() {
+        for value in [0, 1, 127, 128, 16_383, 16_384, 1 << 28, u32::MAX] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value).unwrap();
+            assert_eq!(bytes.len(), varint_len(value));
+            assert_eq!(read_varint(&mut &bytes[..]).unwrap(), value);
+        }
+        assert!(read_varint(&mut &[0xFF, 0xFF, 0xFF, 0xFF, 0x10][..]).is_err());
+        assert!(read_varint(&mut &[0x80; 5][..]).is_err());
+        assert!(read_varint(&mut &[0x80][..]).is_err());
+
+        let packed = crate::tests::pack_with(Packer::new().with_chained_heads(), &values(1000));
+        let independent = crate::pack(&values(1000));
+        assert!(packed.serialized_len() < independent.serialized_len());
+        assert_eq!(
+            PackedDecimals::from_bytes(&packed.to_bytes()).unwrap(),
+            packed
+        );
+    }
+
+    #[test]
+    fn reads_v1() {
+        let values = values(600);
+        let v1 = write_v1(&pack(&values));
+        let decoded = PackedDecimals::from_bytes(&v1).unwrap();
+        assert_eq!(unpack(&decoded), values);
+    }
+
+    #[test]
+    fn migrates_v1() {
+        let values = values(5000);
+        let v1 = write_v1(&pack(&values));
+        let mut v2 = Vec::new();
+        let report = migrate(Cursor::new(&v1), &mut v2).unwrap();
+        assert_eq!(report.from_version, 1);
+        assert_eq!(report.values, values.len());
+        assert_eq!(report.blocks, 20);
+        assert!(report.anomalies.is_empty());
+        assert_eq!(v2[4], VERSION);
+        assert_eq!(v2, pack(&values).to_bytes());
+        assert_eq!(unpack(&PackedDecimals::from_bytes(&v2).unwrap()), values);
+
+        let mut again = Vec::new();
+        let report = migrate(Cursor::new(&v2), &mut again).unwrap();
+        assert_eq!(report.from_version, VERSION);
+        assert_eq!(again, v2);
+    }
+
+    #[test]
+    fn migration_repairs() {
+        let values = values(600);
+        let mut packed = pack(&values);
+        packed.count = 10_000;
+        packed.blocks[2][1].vals.extend([1, 2, 3]);
+        packed.blocks[3].push(packed.blocks[3][0].clone());
+        let v1 = write_v1(&packed);
+        assert!(PackedDecimals::from_bytes(&v1).is_err());
+
+        let mut v2 = Vec::new();
+        let report = migrate(Cursor::new(&v1), &mut v2).unwrap();
+        assert_eq!(
+            report.anomalies,
+            vec![
+                Anomaly::LaneLengths([3, 3, 3, 4]),
+                Anomaly::Count {
+                    stored: 10_000,
+                    repaired: 771
+                },
+                Anomaly::ExcessVals {
+                    lane: 2,
+                    block: 1,
+                    bytes: 3
+                },
+            ]
+        );
+        let decoded = unpack(&PackedDecimals::from_bytes(&v2).unwrap());
+        assert_eq!(&decoded[..600], &values[..]);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let mut bytes = pack(&values(10)).to_bytes();
+        bytes[4] = VERSION + 1;
+        assert!(matches!(
+            PackedDecimals::from_bytes(&bytes),
+            Err(FloatpackError::UnsupportedVersion(3))
+        ));
+        let mut out = Vec::new();
+        assert!(matches!(
+            migrate(Cursor::new(&bytes), &mut out),
+            Err(FloatpackError::UnsupportedVersion(3))
+        ));
+        assert!(out.is_empty());
+        assert!(PackedDecimals::from_bytes(b"NOPE\x02").is_err());
+    }
+
+    #[test]
+    fn serialized_len() {
+        for packed in [
+            pack(&[]),
+            pack(&values(1000)),
+            crate::pack_sorted(&values(1000)[10..]).unwrap(),
+            crate::tests::pack_with(Packer::new().with_sign_magnitude(), &values(300)),
+            crate::tests::pack_with(Packer::new().with_block_bounds(), &values(300)),
+            crate::pack_fixed_scale(&[1050, 1075, 1025], 2),
+        ] {
+            assert_eq!(packed.serialized_len(), packed.to_bytes().len());
+        }
+    }
+
+    #[test]
+    fn block_bounds() {
+        let values = values(1000);
+        let mut packed = crate::tests::pack_with(Packer::new().with_block_bounds(), &values);
+        let bytes = packed.to_bytes();
+        assert_eq!(PackedDecimals::from_bytes(&bytes).unwrap(), packed);
+        assert_eq!(bytes.len(), pack(&values).to_bytes().len() + 4 * 32);
+
+        packed.blocks[0][2].bounds = None;
+        assert!(packed.verify().is_err());
+        packed.blocks[0][2].bounds = Some((Decimal::ONE, Decimal::ZERO));
+        assert!(packed.verify().is_err());
+        packed.blocks[0][2].bounds = Some((Decimal::ZERO, Decimal::ONE));
+        packed.blocks[1][2].bounds = Some((Decimal::ZERO, Decimal::ONE));
+        assert!(packed.verify().is_err());
+    }
+
+    #[test]
+    fn fixed_flags() {
+        let mantissas: Vec<i128> = (0..1000).map(|m| 10_000 + m * 7 % 300).collect();
+        let packed = crate::pack_fixed_scale(&mantissas, 2);
+        assert_eq!(packed.fixed_flags(), Some(2 << 16));
+        let bytes = packed.to_bytes();
+        assert_eq!(bytes[5], super::FIXED_FLAGS);
+        let lane0: usize = packed.blocks[0].iter().map(|b| b.size_bytes()).sum();
+        assert_eq!(lane0, 4 * 6);
+        let general = super::HEADER_LEN
+            + packed
+                .blocks
+                .iter()
+                .flatten()
+                .map(|b| b.size_bytes())
+                .sum::<usize>();
+        assert_eq!(bytes.len(), general - lane0 + super::FIXED_FLAGS_LEN);
+        assert_eq!(PackedDecimals::from_bytes(&bytes).unwrap(), packed);
+        let mut decoder = crate::StreamDecoder::new(&bytes[..]).unwrap();
+        assert!(decoder.by_ref().map(Result::unwrap).eq(packed.iter()));
+
+        // Lane 0 is kept if it changes or carries more than the flags.
+        let negative: Vec<i128> = mantissas.iter().map(|m| m - 10_100).collect();
+        for kept in [
+            crate::pack_fixed_scale(&negative, 2),
+            crate::tests::pack_with(
+                Packer::new().with_block_bounds(),
+                &packed.iter().collect::<Vec<_>>(),
+            ),
+            crate::tests::pack_with(
+                Packer::new().with_chained_heads(),
+                &packed.iter().collect::<Vec<_>>(),
+            ),
+        ] {
+            assert_eq!(kept.fixed_flags(), None);
+            let bytes = kept.to_bytes();
+            assert_eq!(bytes[5] & super::FIXED_FLAGS, 0);
+            assert_eq!(PackedDecimals::from_bytes(&bytes).unwrap(), kept);
+        }
+
+        let mut bounds = bytes;
+        bounds[5] |= super::BOUNDS;
+        assert!(PackedDecimals::from_bytes(&bounds).is_err());
+    }
+
+    #[test]
+    fn single_lane_keeps_lane_0() {
+        let mut packer = crate::F32Packer::new();
+        for _ in 0..1000 {
+            packer.load(&Decimal::ONE).unwrap();
+        }
+        let packed = packer.finish();
+        assert_eq!(packed.fixed_flags(), None);
+        let bytes = packed.to_bytes();
+        assert_eq!(bytes[5] & super::FIXED_FLAGS, 0);
+        assert_eq!(PackedColumns::<1>::from_bytes(&bytes).unwrap(), packed);
+
+        // Fixed flags of the only lane, claiming blocks that aren't there.
+        let mut forged = MAGIC.to_vec();
+        forged.extend([VERSION, super::FIXED_FLAGS | super::COLUMNS]);
+        forged.extend(256u16.to_le_bytes());
+        forged.extend((100_000_000_000u64 * 257).to_le_bytes());
+        forged.extend(100_000_000_000u64.to_le_bytes());
+        forged.extend([1, 0, 0, 0, 0]);
+        assert_eq!(forged.len(), 29);
+        assert!(matches!(
+            PackedColumns::<1>::from_bytes(&forged),
+            Err(FloatpackError::Corrupt(_))
+        ));
+        let mut header = &forged[5..];
+        assert!(read_header_v2(&mut header).is_err());
+    }
+
+    #[test]
+    fn reader() {
+        let values = values(2000);
+        for packed in [
+            pack(&values),
+            crate::tests::pack_with(
+                Packer::new().with_block_bounds().with_chained_heads(),
+                &values,
+            ),
+            crate::pack_fixed_scale(&[1, 2, 3], 2),
+            pack(&[]),
+        ] {
+            let bytes = packed.to_bytes();
+            let mut copied = Vec::new();
+            std::io::copy(&mut packed.reader(), &mut copied).unwrap();
+            assert_eq!(copied, bytes);
+
+            let largest = packed
+                .block_ranges()
+                .into_iter()
+                .map(|(_, r)| r.len())
+                .max();
+            for chunk in [1, 7, 4096] {
+                let mut reader = packed.reader();
+                let mut read: Vec<u8> = Vec::new();
+                let mut buf = vec![0; chunk];
+                loop {
+                    let len = reader.read(&mut buf).unwrap();
+                    if len == 0 {
+                        break;
+                    }
+                    read.extend(&buf[..len]);
+                    assert!(
+                        reader.buffer.len()
+                            <= largest
+                                .unwrap_or(0)
+                                .max(super::HEADER_LEN + super::FIXED_FLAGS_LEN)
+                    );
+                }
+                assert_eq!(read, bytes);
+                assert_eq!(reader.read(&mut buf).unwrap(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn block_ranges() {
+        let values = values(1000);
+        let (packed, ranges) = crate::pack_with_ranges(&values);
+        assert_eq!(ranges, packed.block_ranges());
+        let sign_magnitude =
+            crate::tests::pack_with(Packer::new().with_sign_magnitude(), &values[..600]);
+        let bounds = crate::tests::pack_with(Packer::new().with_block_bounds(), &values[..600]);
+        let mantissas: Vec<i128> = (0..600).map(|m| m * 3 % 500).collect();
+        let fixed = crate::pack_fixed_scale(&mantissas, 2);
+        for packed in [packed, sign_magnitude, bounds, fixed, pack(&[])] {
+            let bytes = packed.to_bytes();
+            let header = read_header_v2(&mut &bytes[5..]).unwrap();
+            let ranges = packed.block_ranges();
+            assert_eq!(ranges.len(), packed.blocks[0].len());
+            let mut end = super::HEADER_LEN;
+            if packed.fixed_flags().is_some() {
+                end += super::FIXED_FLAGS_LEN;
+            }
+            for (n, (first, range)) in ranges.into_iter().enumerate() {
+                assert_eq!(first, n * packed.block_values());
+                assert_eq!(range.start, end);
+                end = range.end;
+                let mut record = &bytes[range];
+                for (i, lane) in packed.blocks.iter().enumerate() {
+                    assert_eq!(read_block_v2(&mut record, &header, i, n).unwrap(), lane[n]);
+                }
+                assert!(record.is_empty());
+            }
+            assert_eq!(end, bytes.len());
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_input() {
+        let bytes = pack(&values(1000)).to_bytes();
+        for len in [0, 3, 5, 20, 100, bytes.len() - 1] {
+            assert!(PackedDecimals::from_bytes(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(PackedDecimals::from_bytes(&trailing).is_err());
+        let mut bits = bytes.clone();
+        bits[20] = 33;
+        assert!(PackedDecimals::from_bytes(&bits).is_err());
+        let mut count = bytes;
+        count[10] = 1;
+        assert!(PackedDecimals::from_bytes(&count).is_err());
+    }
+}
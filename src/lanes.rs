@@ -0,0 +1,47 @@
+//! Serde of the lanes of [`PackedColumns`](crate::PackedColumns) of any count of columns.
+//!
+//! Serde only implements its traits for arrays of the lengths up to 32 one by one, not for
+//! arrays of a const generic length. The lanes are (de)serialized like those arrays are, as a
+//! tuple, so packs of 4 columns keep their representation.
+use crate::Block;
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserializer, Serializer};
+use std::fmt;
+
+pub(crate) fn serialize<S: Serializer, const N: usize>(
+    lanes: &[Vec<Block>; N],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut tuple = serializer.serialize_tuple(N)?;
+    for lane in lanes {
+        tuple.serialize_element(lane)?;
+    }
+    tuple.end()
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+    deserializer: D,
+) -> Result<[Vec<Block>; N], D::Error> {
+    deserializer.deserialize_tuple(N, LanesVisitor::<N>)
+}
+
+struct LanesVisitor<const N: usize>;
+
+impl<'de, const N: usize> Visitor<'de> for LanesVisitor<N> {
+    type Value = [Vec<Block>; N];
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} lanes of blocks", N)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut lanes: [Vec<Block>; N] = std::array::from_fn(|_| Vec::new());
+        for (i, lane) in lanes.iter_mut().enumerate() {
+            *lane = seq
+                .next_element()?
+                .ok_or_else(|| A::Error::invalid_length(i, &self))?;
+        }
+        Ok(lanes)
+    }
+}
@@ -1,8 +1,104 @@
 use bitpacking::{BitPacker, BitPacker8x};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::time::Instant;
 
-/// Represents `Decimals` in packed form.  (.0 = Compressed blocks, .1 = Count of decimals)
+use bitpacker::{AnyBitPacker, MAX_BLOCK_LEN};
+use decode::Blocks;
+
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "futures")]
+mod async_stream;
+mod bitpacker;
+mod changes;
+mod checkpoint;
+mod compaction;
+mod crc;
+mod decode;
+mod diagnostics;
+mod durable;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod file;
+mod fingerprint;
+pub mod format;
+mod lanes;
+#[cfg(feature = "line-protocol")]
+mod line_protocol;
+mod map;
+mod merge;
+mod multires;
+mod nested;
+#[cfg(feature = "npy")]
+mod npy;
+mod packable;
+mod pair;
+#[cfg(feature = "rayon")]
+mod par;
+#[cfg(feature = "sqlx")]
+mod pg;
+mod quantized;
+mod reverse;
+mod salvage;
+mod scaled;
+mod series;
+mod static_packer;
+mod stats;
+mod stream;
+mod zip;
+
+#[cfg(feature = "arrow")]
+pub use arrow::{read_arrow_ipc, write_arrow_ipc};
+#[cfg(feature = "futures")]
+pub use async_stream::{pack_stream, pack_stream_with};
+pub use changes::{Diffs, Returns};
+pub use compaction::{
+    Compaction, CompactionPolicy, CompactionStats, ExcessBlocksAbove, FillRatioBelow, Never,
+};
+pub use decode::{
+    try_unpack, try_unpack_as, try_unpack_with_lanes, unpack_blocks, unpack_lenient, unpack_rev,
+    unpack_unchecked, Indexed, Values,
+};
+pub use diagnostics::Diagnostics;
+pub use durable::{recover, DurableWriter, FollowReader, Followed, RecoveryReport, SyncPolicy};
+pub use error::FloatpackError;
+pub use file::{FileBlocks, FileReader, FileWriter};
+pub use fingerprint::fingerprint;
+pub use format::{migrate, MigrationReport, PackedReader};
+#[cfg(feature = "line-protocol")]
+pub use line_protocol::LineProtocolSummary;
+pub use merge::{difference_sorted, intersect_sorted, merge_sorted, Duplicates};
+pub use multires::{
+    EveryNth, Last, Max, Min, MinMax, MultiResPacker, MultiResolution, Reducer, Resolution,
+};
+pub use nested::{PackedVecs, Ticks, VecPacker};
+#[cfg(feature = "npy")]
+pub use npy::{read_npy, write_npy, write_npy_strict};
+pub use packable::Packable;
+pub use pair::{PackedPairs, PairPacker};
+#[cfg(feature = "rayon")]
+pub use par::{unpack_parallel, ParValues};
+pub use quantized::{unpack_f32, F32Packer};
+pub use reverse::NewestFirst;
+pub use salvage::{salvage, SalvageReport};
+pub use scaled::{PackedScaled, ScalePacker};
+pub use series::{FillPolicy, TimeSeries};
+pub use static_packer::{decode_each, StaticPacker};
+pub use stats::{
+    active_lanes, block_heads, change_mask, correlation, covariance, histogram, monotonicity,
+    quantile, savings, weighted_mean, Monotonicity, Savings,
+};
+pub use stream::StreamDecoder;
+pub use zip::{add, div, sub, zip_decode, zip_many, zip_map, ZipMany};
+
+/// Represents `Decimals` in packed form.
 ///
 /// Usage example:
 /// ```
@@ -17,93 +113,1023 @@ use serde::{Deserialize, Serialize};
 /// 1. The `Decimal` values are serialized in their components (4 x u32)
 /// 2. The 4 component streams are individually compressed by storing their cumulative difference (XOR).
 /// 3. The 4 compressed component streams are then bit-packed
-pub type PackedDecimals = ([Vec<Block>; 4], usize);
+///
+/// Packs of other values of 4 columns share this type, like those of [`Packer::with_lane_fn`].
+/// Reading them as `Decimal`s fails with [`FloatpackError::TypeMismatch`] where a `Result` is
+/// returned, like [`try_unpack`] does, and panics otherwise, like [`unpack`], iterating,
+/// [`PackedDecimals::get`] and the analytics do.
+pub type PackedDecimals = PackedColumns<4>;
+
+/// Represents values of `N` columns in packed form, see [`Packable`] and [`GenericPacker`].
+///
+/// [`PackedDecimals`] are the packs of `Decimal`s, which have 4 columns. Narrower values need
+/// fewer lanes, and thus fewer blocks per value.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct PackedColumns<const N: usize> {
+    /// Compressed blocks, one `Vec` per component stream (lane).
+    #[serde(with = "lanes")]
+    pub blocks: [Vec<Block>; N],
+    /// Count of values.
+    pub count: usize,
+    /// Count of deltas per block, which selects the bitpacker (see [`Packer::with_block_len`]).
+    pub block_len: usize,
+    /// [`Packable::TYPE_TAG`] of the packed values, 0 for `Decimal`s.
+    #[serde(default)]
+    pub type_tag: u8,
+}
+
+impl<const N: usize> Default for PackedColumns<N> {
+    fn default() -> Self {
+        PackedColumns {
+            blocks: std::array::from_fn(|_| Vec::new()),
+            count: 0,
+            block_len: BitPacker8x::BLOCK_LEN,
+            type_tag: 0,
+        }
+    }
+}
+
+impl<const N: usize> PackedColumns<N> {
+    /// Count of values.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Count of values a full block holds: the head and `block_len` deltas.
+    ///
+    /// Value `i` is stored in block `i / block_values()`.
+    pub fn block_values(&self) -> usize {
+        self.block_len + 1
+    }
+
+    /// Whether any block is a [`Block::continuation`] of its predecessor.
+    pub fn chained(&self) -> bool {
+        self.blocks[0].iter().any(|b| b.continuation)
+    }
+}
+
+/// Count of values a full block of the default length holds: the head and 256 deltas.
+pub const BLOCK_VALUES: usize = BitPacker8x::BLOCK_LEN + 1;
+
+/// Packs `Decimals` one at a time, e.g. while consuming a stream.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{unpack, Packer};
+/// use rust_decimal_macros::*;
+///
+/// let mut packer = Packer::new();
+/// packer.load_decimal(&dec!(1.0));
+/// packer.load_decimal(&dec!(2.0));
+/// assert_eq!(vec![dec!(1.0), dec!(2.0)], unpack(&packer.finish()));
+/// ```
+pub type Packer = GenericPacker<Decimal>;
+
+/// Packs values of any [`Packable`] type of `N` columns one at a time, like [`Packer`] does for
+/// `Decimal`s.
+///
+/// Block lengths, chained heads and frames work for all types. The encodings, dead band, block
+/// bounds and padding need the order and arithmetic of `Decimal`s, so only [`Packer`] offers them.
+pub struct GenericPacker<T = Decimal, const N: usize = 4> {
+    bitpacker: AnyBitPacker,
+    cache: Cache<N>,
+    packed: PackedColumns<N>,
+    flush_bytes: Option<usize>,
+    frame_bytes: usize,
+    frames: Vec<PackedColumns<N>>,
+    /// Count of blocks per lane to reserve for each new frame.
+    frame_blocks: usize,
+    last: Option<[u32; N]>,
+    deadband: Option<Decimal>,
+    stored: Option<Decimal>,
+    dropped: usize,
+    adaptive: bool,
+    /// Every block uses [`Encoding::Raw`], only to compare encodings.
+    raw: bool,
+    byte_split: bool,
+    sign_magnitude: bool,
+    delta_of_delta: bool,
+    chained: bool,
+    /// With chained heads, every this many blocks of a frame store their head as it is.
+    keyframes: Option<usize>,
+    block_bounds: bool,
+    /// Columns of the value padding partial blocks.
+    pad: Option<[u32; N]>,
+    /// When the packer was created or last compacted.
+    compacted: Instant,
+    diagnostics: Option<Diagnostics>,
+    /// Custom layout of the lanes of `Decimal`s, see [`Packer::with_lane_fn`].
+    lane_fn: Option<LaneFn>,
+    _type: PhantomData<fn() -> T>,
+}
 
-struct Packer {
-    bitpacker: BitPacker8x,
-    cache: Cache,
-    packed: PackedDecimals,
+/// The mapping of `Decimal`s to lanes and back of [`Packer::with_lane_fn`].
+#[derive(Clone, Copy)]
+struct LaneFn {
+    to_lanes: fn(&Decimal) -> [u32; 4],
+    from_lanes: fn([u32; 4]) -> Decimal,
 }
 
-impl Default for Packer {
+/// [`PackedDecimals::type_tag`] of packs of `Decimal`s in a custom lane layout.
+pub(crate) const LANE_FN_TAG: u8 = 6;
+
+impl<T: Packable<N>, const N: usize> Default for GenericPacker<T, N> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-struct Cache {
-    buffer: Option<[u32; 4]>,
-    head: [u32; 4],
-    compressed: [[u32; BitPacker8x::BLOCK_LEN]; 4],
+struct Cache<const N: usize = 4> {
+    buffer: Option<[u32; N]>,
+    head: [u32; N],
+    compressed: [[u32; MAX_BLOCK_LEN]; N],
+    widths: [u32; N],
     idx: usize,
+    /// Smallest and largest value of the pending block, if tracked.
+    bounds: Option<(Decimal, Decimal)>,
+}
+
+impl<const N: usize> Cache<N> {
+    /// Count of values in the pending block.
+    fn len(&self) -> usize {
+        self.buffer.map_or(0, |_| self.idx + 1)
+    }
 }
 
-impl Default for Cache {
+impl<const N: usize> Default for Cache<N> {
     fn default() -> Self {
         Cache {
             buffer: None,
-            head: [0; 4],
-            compressed: [[0; BitPacker8x::BLOCK_LEN]; 4],
+            head: [0; N],
+            compressed: [[0; MAX_BLOCK_LEN]; N],
+            widths: [0; N],
             idx: 0,
+            bounds: None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Block {
     pub bits: u8,
+    pub encoding: Encoding,
     pub head: u32,
     pub vals: Vec<u8>,
+    /// Side data of the encoding: the byte planes stored by [`Encoding::ByteSplit`], or for the
+    /// encodings spanning lanes 1 to 3, only stored in the block of lane 1, the sign bitmap of
+    /// [`Encoding::SignMagnitude`] or the initial delta of [`Encoding::DeltaOfDelta`].
+    pub signs: Vec<u8>,
+    /// Whether the head is stored as XOR against the last value of the preceding block instead
+    /// of as it is (see [`Packer::with_chained_heads`]). Equal in the blocks of all lanes.
+    pub continuation: bool,
+    /// Smallest and largest value of the block, padding excluded. Only stored in the block of
+    /// lane 0, by packers with [`Packer::with_block_bounds`].
+    pub bounds: Option<(Decimal, Decimal)>,
 }
 
-impl Packer {
-    fn new() -> Packer {
-        Packer {
-            bitpacker: BitPacker8x::new(),
+/// How the values following the head of a block are represented in its `vals`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Encoding {
+    /// XOR of each value with its predecessor.
+    Xor,
+    /// The values as they are.
+    Raw,
+    /// Absolute difference of the 96 bit mantissa to its predecessor, split over lanes 1 to 3.
+    /// The signs of the differences are stored as a bitmap in the block of lane 1.
+    SignMagnitude,
+    /// Zigzag encoded change of the difference of the 96 bit mantissa to its predecessor, split
+    /// over lanes 1 to 3. The difference of the first two values is stored in the block of
+    /// lane 1. Used by [`pack_sorted`].
+    DeltaOfDelta,
+    /// The XOR of each value with the head, split into its 4 bytes. Only the byte planes not
+    /// all zero are stored, one after the other, each as a byte per value; a bitmap of them is
+    /// stored as the side data. See [`Packer::with_byte_split`].
+    ByteSplit,
+    /// Like [`Encoding::SignMagnitude`], but the mantissas whose difference to their predecessor
+    /// is much wider than the others, such as gaps in prices, are stored as they are. The block
+    /// of lane 1 stores the sign bitmap, a bitmap of the values stored as they are and their
+    /// mantissas of 12 bytes each. Picked by [`Packer::with_sign_magnitude`] where smaller.
+    HybridDelta,
+}
+
+impl Encoding {
+    /// Whether this encoding spans the mantissa lanes 1 to 3 of a block.
+    pub(crate) fn is_mantissa(self) -> bool {
+        matches!(
+            self,
+            Encoding::SignMagnitude | Encoding::DeltaOfDelta | Encoding::HybridDelta
+        )
+    }
+
+    /// Length of the side data in the block of lane 1 (see [`Block::signs`]), without the
+    /// mantissas of [`Encoding::HybridDelta`], see [`Encoding::absolutes_len`].
+    pub(crate) fn side_len(self, block_len: usize) -> usize {
+        match self {
+            Encoding::SignMagnitude => block_len / 8,
+            Encoding::HybridDelta => block_len / 4,
+            Encoding::DeltaOfDelta => MANTISSA_BYTES,
+            Encoding::ByteSplit => 1,
+            Encoding::Xor | Encoding::Raw => 0,
+        }
+    }
+
+    /// Length of the mantissas of [`Encoding::HybridDelta`] following the bitmaps in `side`.
+    pub(crate) fn absolutes_len(self, side: &[u8], block_len: usize) -> usize {
+        match side.get(block_len / 8..block_len / 4) {
+            Some(flags) if self == Encoding::HybridDelta => {
+                MANTISSA_BYTES * flags.iter().map(|f| f.count_ones() as usize).sum::<usize>()
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Bytes of the 96 bit mantissa.
+const MANTISSA_BYTES: usize = 12;
+const MANTISSA_MASK: u128 = (1 << 96) - 1;
+
+/// Bytes a block occupies in addition to its `vals` (`bits`, `encoding` and `head`).
+const BLOCK_OVERHEAD: usize = 6;
+/// Bytes of the [`Block::bounds`] of a block.
+const BOUNDS_BYTES: usize = 32;
+
+impl Block {
+    fn size_bytes(&self) -> usize {
+        let bounds = if self.bounds.is_some() {
+            BOUNDS_BYTES
+        } else {
+            0
+        };
+        // The heads of continuation blocks are serialized as varints.
+        let head = if self.continuation {
+            format::varint_len(self.head)
+        } else {
+            4
+        };
+        BLOCK_OVERHEAD - 4 + head + self.vals.len() + self.signs.len() + bounds
+    }
+}
+
+impl<T: Packable<N>, const N: usize> GenericPacker<T, N> {
+    pub fn new() -> Self {
+        GenericPacker {
+            bitpacker: AnyBitPacker::X8(BitPacker8x::new()),
             cache: Cache::default(),
-            packed: ([Vec::new(), Vec::new(), Vec::new(), Vec::new()], 0),
+            packed: PackedColumns {
+                type_tag: T::TYPE_TAG,
+                ..PackedColumns::default()
+            },
+            flush_bytes: None,
+            frame_bytes: 0,
+            frames: Vec::new(),
+            frame_blocks: 0,
+            last: None,
+            deadband: None,
+            stored: None,
+            dropped: 0,
+            adaptive: false,
+            raw: false,
+            byte_split: false,
+            sign_magnitude: false,
+            delta_of_delta: false,
+            chained: false,
+            keyframes: None,
+            block_bounds: false,
+            pad: None,
+            compacted: Instant::now(),
+            diagnostics: None,
+            lane_fn: None,
+            _type: PhantomData,
+        }
+    }
+
+    /// Pack with a shared `bitpacker` instead of a new one.
+    ///
+    /// Creating a bitpacker detects the available CPU features at runtime, which adds up when
+    /// creating many packers; bitpackers are stateless and can be shared freely. This selects
+    /// blocks of 256 deltas, overriding [`Packer::with_block_len`].
+    pub fn with_bitpacker(mut self, bitpacker: BitPacker8x) -> Self {
+        self.bitpacker = AnyBitPacker::X8(bitpacker);
+        self.packed.block_len = BitPacker8x::BLOCK_LEN;
+        self
+    }
+
+    /// Pack blocks of `n` deltas (plus the head) instead of 256.
+    ///
+    /// Supported are the block lengths of the `bitpacking` implementations: 32 (`BitPacker1x`),
+    /// 128 (`BitPacker4x`) and 256 (`BitPacker8x`). Shorter blocks compress worse, but allow to
+    /// flush smaller chunks of a stream. The block length is recorded in the packed result.
+    pub fn with_block_len(mut self, n: usize) -> Result<Self, FloatpackError> {
+        self.bitpacker = AnyBitPacker::new(n).ok_or(FloatpackError::UnsupportedBlockLen(n))?;
+        self.packed.block_len = n;
+        Ok(self)
+    }
+
+    /// Store each block head as XOR against the last value of the preceding block.
+    ///
+    /// For continuous series this turns the heads into small numbers, making the whole column
+    /// one continuous XOR chain. The binary format stores them as varints of 1 to 5 bytes. The
+    /// tradeoff is that blocks can no longer be decoded on their own: decoding any block
+    /// requires all blocks before it, so a single corrupt block also corrupts every block
+    /// following it. Each frame (see [`Packer::with_flush_bytes`]) starts a
+    /// new chain, and the blocks mark whether they continue their predecessor (see
+    /// [`Block::continuation`]), so packs combining chained and independent blocks decode fine.
+    pub fn with_chained_heads(mut self) -> Self {
+        self.chained = true;
+        self
+    }
+
+    /// Chain heads like [`Packer::with_chained_heads`], but store the head of every `k`th block
+    /// of a frame as it is.
+    ///
+    /// These keyframes start a new chain, so decoding a block only requires the blocks since the
+    /// last keyframe: at most `k` blocks are decoded for random access, and a corrupt block only
+    /// corrupts the blocks up to the next keyframe. Each keyframe costs the bytes saved by
+    /// chaining its head.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is zero.
+    pub fn with_keyframes(mut self, k: usize) -> Self {
+        assert!(k > 0, "keyframes need an interval of at least one block");
+        self.chained = true;
+        self.keyframes = Some(k);
+        self
+    }
+
+    /// Split the output into frames of at most roughly `n` compressed bytes.
+    ///
+    /// Before a value is loaded, the compressed size of the current frame including that value is
+    /// estimated from the bit widths of the pending block. If it would exceed `n`, the pending
+    /// block is flushed early and a new frame is started. Blocks still close after
+    /// block length + 1 values, whichever comes first — so a frame is made of full
+    /// blocks plus at most one partial block at its end. A frame always holds at least one value,
+    /// even if that alone exceeds `n`.
+    ///
+    /// Completed frames are collected with [`Packer::take_frames`] or [`Packer::finish_frames`].
+    pub fn with_flush_bytes(mut self, n: usize) -> Self {
+        self.flush_bytes = Some(n);
+        self
+    }
+
+    /// Add a single value to the packer.
+    ///
+    /// Returns whether the value was stored, which is always the case without a dead band.
+    pub fn load(&mut self, value: &T) -> bool {
+        self.load_columns(value.to_columns())
+    }
+
+    /// The `Decimal` of the columns of a packer of `Decimal`s, in its lane layout.
+    fn decimal_of(&self, columns: [u32; N]) -> Decimal {
+        match self.lane_fn {
+            Some(f) => (f.from_lanes)(std::array::from_fn(|i| columns[i])),
+            None => decimal_of_columns(columns),
         }
     }
 
-    fn load_decimal(&mut self, value: &Decimal) {
-        let parsed = zip_u8(value.serialize());
+    fn load_columns(&mut self, parsed: [u32; N]) -> bool {
+        // Only packers of `Decimal`s have a dead band or block bounds.
+        let decimal =
+            (self.deadband.is_some() || self.block_bounds).then(|| self.decimal_of(parsed));
+        if let (Some(epsilon), Some(stored), Some(value)) = (self.deadband, self.stored, decimal) {
+            match value.checked_sub(stored) {
+                Some(diff) if diff.abs() <= epsilon => {
+                    self.dropped += 1;
+                    return false;
+                }
+                _ => {}
+            }
+        }
+        self.stored = decimal;
+        let frame_started = self.cache.buffer.is_some() || self.packed.count > 0;
+        if let Some(limit) = self.flush_bytes.filter(|_| frame_started) {
+            if self.frame_bytes + self.pending_bytes(&parsed) > limit {
+                self.close_frame();
+            }
+        }
         match self.cache.buffer {
             Some(last) => {
-                for i in 0..4 {
-                    self.cache.compressed[i][self.cache.idx] = parsed[i] ^ last[i];
+                for i in 0..N {
+                    let delta = parsed[i] ^ last[i];
+                    self.cache.compressed[i][self.cache.idx] = delta;
+                    self.cache.widths[i] |= delta;
                 }
                 self.cache.idx += 1;
             }
             None => self.cache.head = parsed,
         }
         self.cache.buffer = Some(parsed);
+        if let Some(value) = decimal.filter(|_| self.block_bounds) {
+            self.cache.bounds = Some(match self.cache.bounds {
+                Some((min, max)) => (min.min(value), max.max(value)),
+                None => (value, value),
+            });
+        }
 
-        if self.cache.idx == BitPacker8x::BLOCK_LEN {
+        if self.cache.idx == self.packed.block_len {
             self.pack()
         }
+        true
     }
 
     fn pack(&mut self) {
         if self.cache.buffer.is_none() {
             return;
         }
-        for i in 0..4 {
-            let bits = self.bitpacker.num_bits(&self.cache.compressed[i]);
-            let mut compressed = vec![0u8; (bits as usize) * BitPacker8x::BLOCK_LEN / 8];
+        let block_len = self.packed.block_len;
+        let mut last = self.cache.buffer;
+        let pad = self.pad.filter(|_| self.cache.idx < block_len);
+        if let (Some(pad), Some(buffer)) = (pad, last) {
+            for i in 0..N {
+                self.cache.compressed[i][self.cache.idx] = pad[i] ^ buffer[i];
+            }
+            last = Some(pad);
+        }
+        let mut lanes = [(Encoding::Xor, 0, [0; MAX_BLOCK_LEN]); N];
+        for (i, (encoding, bits, values)) in lanes.iter_mut().enumerate() {
+            *values = self.cache.compressed[i];
+            *bits = self.bitpacker.num_bits(&values[..block_len]);
+            if self.adaptive || self.raw {
+                let mut raw = *values;
+                let mut last = self.cache.head[i];
+                for v in raw[..block_len].iter_mut() {
+                    last ^= *v;
+                    *v = last;
+                }
+                let raw_bits = self.bitpacker.num_bits(&raw[..block_len]);
+                if self.raw || raw_bits <= *bits {
+                    *encoding = Encoding::Raw;
+                    *values = raw;
+                    *bits = raw_bits;
+                }
+            }
+            if self.byte_split {
+                let mut split = self.cache.compressed[i];
+                let mut last = 0;
+                for v in split[..block_len].iter_mut() {
+                    last ^= *v;
+                    *v = last;
+                }
+                let planes = byte_planes(&split[..block_len]).count_ones() as u8;
+                let split_bytes = planes as usize * block_len + 1;
+                if split_bytes < self.bitpacker.compressed_len(*bits) {
+                    *encoding = Encoding::ByteSplit;
+                    *values = split;
+                    *bits = 8 * planes;
+                }
+            }
+        }
+        let mut signs = Vec::new();
+        if self.sign_magnitude {
+            let (mut magnitudes, mut bitmap) =
+                sign_magnitude(self.cache.head, &self.cache.compressed, block_len);
+            let absolutes = absolute_outliers(
+                self.cache.head,
+                &self.cache.compressed,
+                &mut magnitudes,
+                &mut bitmap,
+                block_len,
+            );
+            let encoding = match absolutes {
+                Some(absolutes) => {
+                    bitmap.extend(absolutes);
+                    Encoding::HybridDelta
+                }
+                None => Encoding::SignMagnitude,
+            };
+            let bits = magnitudes.map(|m| self.bitpacker.num_bits(&m[..block_len]));
+            let compressed_len = |bits: u8| self.bitpacker.compressed_len(bits);
+            let split: usize =
+                bits.iter().map(|b| compressed_len(*b)).sum::<usize>() + bitmap.len();
+            let current: usize = lanes[1..].iter().map(|l| compressed_len(l.1)).sum();
+            if !self.adaptive || split < current {
+                for (i, lane) in lanes[1..].iter_mut().enumerate() {
+                    *lane = (encoding, bits[i], magnitudes[i]);
+                }
+                signs = bitmap;
+            }
+        }
+        if self.delta_of_delta {
+            let (zigzags, first) = delta_of_delta(
+                self.cache.head,
+                &self.cache.compressed,
+                self.cache.idx,
+                block_len,
+            );
+            for (i, lane) in lanes[1..].iter_mut().enumerate() {
+                let bits = self.bitpacker.num_bits(&zigzags[i][..block_len]);
+                *lane = (Encoding::DeltaOfDelta, bits, zigzags[i]);
+            }
+            signs = first;
+        }
 
-            let _ = self
-                .bitpacker
-                .compress(&self.cache.compressed[i], &mut compressed[..], bits);
+        let keyframe = self
+            .keyframes
+            .is_some_and(|k| self.packed.blocks[0].len().is_multiple_of(k));
+        let chain = self.last.filter(|_| self.chained && !keyframe);
+        for (i, (encoding, bits, values)) in lanes.iter().enumerate() {
+            let mut compressed = vec![0u8; self.bitpacker.compressed_len(*bits)];
+            let side = if *encoding == Encoding::ByteSplit {
+                let planes = byte_planes(&values[..block_len]);
+                split_bytes(&values[..block_len], planes, &mut compressed);
+                vec![planes]
+            } else {
+                let _ = self
+                    .bitpacker
+                    .compress(&values[..block_len], &mut compressed[..], *bits);
+                if i == 1 {
+                    std::mem::take(&mut signs)
+                } else {
+                    Vec::new()
+                }
+            };
 
-            self.packed.0[i].push(Block {
-                bits,
-                head: self.cache.head[i],
+            let head = match chain {
+                Some(last) => self.cache.head[i] ^ last[i],
+                None => self.cache.head[i],
+            };
+            let block = Block {
+                bits: *bits,
+                encoding: *encoding,
+                head,
                 vals: compressed,
-            });
+                signs: side,
+                continuation: chain.is_some(),
+                bounds: self.cache.bounds.filter(|_| i == 0),
+            };
+            self.frame_bytes += block.size_bytes();
+            if let Some(diagnostics) = &mut self.diagnostics {
+                diagnostics.record(i, &block);
+            }
+            self.packed.blocks[i].push(block);
         }
-        self.packed.1 += self.cache.idx + 1;
+        self.packed.count += self.cache.len();
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.total_values += self.cache.len();
+        }
+        self.last = last;
         self.cache = Cache::default();
     }
+
+    /// Collect [`Diagnostics`] about the blocks packed, see [`Packer::diagnostics`].
+    ///
+    /// Off by default, which costs nothing while packing.
+    pub fn with_diagnostics(mut self, enabled: bool) -> Self {
+        self.diagnostics = enabled.then(|| Diagnostics::new(N));
+        self
+    }
+
+    /// The diagnostics collected so far, if enabled with [`Packer::with_diagnostics`].
+    pub fn diagnostics(&self) -> Option<&Diagnostics> {
+        self.diagnostics.as_ref()
+    }
+
+    /// Store blocks as byte planes ([`Encoding::ByteSplit`]) where that is smaller.
+    ///
+    /// Noisy values often change some of their bytes only: the sign and scale of mixed
+    /// `Decimal`s, or a mantissa of noise in its lowest and highest bytes, flip the high bits
+    /// of the XOR deltas with every value, so bitpacking them saves little. Byte planes that
+    /// never differ from the head cost nothing, the others a byte per value. Each block of
+    /// each lane is only split if that is smaller than the encoding picked otherwise.
+    pub fn with_byte_split(mut self) -> Self {
+        self.byte_split = true;
+        self
+    }
+
+    /// Estimated compressed size of the pending block once `next` is loaded into it.
+    fn pending_bytes(&self, next: &[u32; N]) -> usize {
+        let bounds = if self.block_bounds { BOUNDS_BYTES } else { 0 };
+        let last = match self.cache.buffer {
+            Some(last) => last,
+            None => return N * BLOCK_OVERHEAD + bounds,
+        };
+        (0..N)
+            .map(|i| {
+                let bits = 32 - (self.cache.widths[i] | (next[i] ^ last[i])).leading_zeros();
+                BLOCK_OVERHEAD + self.bitpacker.compressed_len(bits as u8)
+            })
+            .sum::<usize>()
+            + bounds
+    }
+
+    fn close_frame(&mut self) {
+        self.pack();
+        let mut next = PackedColumns {
+            block_len: self.packed.block_len,
+            type_tag: self.packed.type_tag,
+            ..PackedColumns::default()
+        };
+        for lane in next.blocks.iter_mut() {
+            lane.reserve_exact(self.frame_blocks);
+        }
+        self.frames.push(std::mem::replace(&mut self.packed, next));
+        self.frame_bytes = 0;
+        self.last = None;
+    }
+
+    /// Flush the pending block and shrink all buffers to fit, returning the bytes freed.
+    ///
+    /// This is meant for when streaming is done. As only the last block of a frame can be
+    /// partial, flushing a pending block closes the current frame: values loaded afterwards go
+    /// into a new frame, see [`Packer::take_frames`] and [`Packer::finish_frames`].
+    pub fn compact(&mut self) -> usize {
+        if self.cache.buffer.is_some() {
+            self.close_frame();
+        }
+        let mut freed = 0;
+        for packed in self
+            .frames
+            .iter_mut()
+            .chain(std::iter::once(&mut self.packed))
+        {
+            for lane in packed.blocks.iter_mut() {
+                for block in lane.iter_mut() {
+                    freed += block.vals.capacity() - block.vals.len();
+                    freed += block.signs.capacity() - block.signs.len();
+                    block.vals.shrink_to_fit();
+                    block.signs.shrink_to_fit();
+                }
+                freed += (lane.capacity() - lane.len()) * std::mem::size_of::<Block>();
+                lane.shrink_to_fit();
+            }
+        }
+        freed +=
+            (self.frames.capacity() - self.frames.len()) * std::mem::size_of::<PackedColumns<N>>();
+        self.frames.shrink_to_fit();
+        self.compacted = Instant::now();
+        freed
+    }
+
+    /// Take the frames completed so far (see [`Packer::with_flush_bytes`]).
+    pub fn take_frames(&mut self) -> Vec<PackedColumns<N>> {
+        std::mem::take(&mut self.frames)
+    }
+
+    /// Flush pending values and return the packed result.
+    ///
+    /// When packing into frames, only the current frame is returned.
+    pub fn finish(mut self) -> PackedColumns<N> {
+        self.pack();
+        self.packed
+    }
+
+    /// Flush pending values and return all frames not taken yet.
+    pub fn finish_frames(mut self) -> Vec<PackedColumns<N>> {
+        self.pack();
+        if self.packed.count > 0 {
+            self.frames.push(self.packed);
+        }
+        self.frames
+    }
+}
+
+impl Packer {
+    /// Reserve room for the blocks of `total_expected` values, estimating how well they compress
+    /// by packing `sample` with the options of this packer. Returns the estimated count of
+    /// compressed bytes of all values.
+    ///
+    /// The lanes of the current frame get room for the blocks of all values. With
+    /// [`Packer::with_flush_bytes`], frames only take as many blocks as the estimated bytes per
+    /// block allow, so each frame gets room for those instead, and room for the estimated count
+    /// of frames is reserved. The bytes of each block are allocated when it's packed, at their
+    /// exact size. This is a heuristic: a sample compressing better or worse than the values
+    /// makes it reserve too much or too little room for frames. An empty sample only reserves
+    /// room for the blocks of the current frame.
+    pub fn reserve_from_sample(&mut self, sample: &[Decimal], total_expected: usize) -> usize {
+        let mut probe = self.options();
+        for v in sample {
+            probe.load_decimal(v);
+        }
+        let probe = probe.finish();
+        let blocks = total_expected.div_ceil(self.packed.block_values());
+        let estimate = match probe.blocks[0].len() {
+            n if n > 0 && blocks > 0 => {
+                let header = PackedDecimals::default().serialized_len();
+                (probe.serialized_len() - header).div_ceil(n) * blocks + header
+            }
+            _ => 0,
+        };
+        let frame_blocks = match self.flush_bytes {
+            Some(n) if estimate > 0 => {
+                let per_block = (estimate / blocks.max(1)).max(1);
+                self.frames.reserve(estimate.div_ceil(n.max(1)));
+                // Frames hold at least one block, and one more if it's partial.
+                (n / per_block + 1).min(blocks)
+            }
+            _ => blocks,
+        };
+        for lane in self.packed.blocks.iter_mut() {
+            lane.reserve_exact(frame_blocks.saturating_sub(lane.len()));
+        }
+        self.frame_blocks = frame_blocks;
+        estimate
+    }
+
+    /// A new packer with the options of this one, except for frames and diagnostics.
+    fn options(&self) -> Packer {
+        let mut p = Packer::new();
+        p.bitpacker = self.bitpacker;
+        p.packed.block_len = self.packed.block_len;
+        p.deadband = self.deadband;
+        p.adaptive = self.adaptive;
+        p.raw = self.raw;
+        p.byte_split = self.byte_split;
+        p.sign_magnitude = self.sign_magnitude;
+        p.delta_of_delta = self.delta_of_delta;
+        p.chained = self.chained;
+        p.keyframes = self.keyframes;
+        p.block_bounds = self.block_bounds;
+        p.pad = self.pad;
+        p
+    }
+
+    /// Pick the smaller of [`Encoding::Xor`] and [`Encoding::Raw`] for every block of every lane.
+    ///
+    /// The XOR delta of two values is never wider than the wider one of them; still, the deltas
+    /// of a block can be wider than its raw values when the head differs a lot from the rest of
+    /// the block. On ties the raw encoding is chosen, as it decodes without folding the deltas.
+    /// Without this option, every block uses [`Encoding::Xor`].
+    pub fn with_adaptive_encoding(mut self) -> Self {
+        self.adaptive = true;
+        self
+    }
+
+    /// Store the mantissa as sign and magnitude of its difference to the predecessor.
+    ///
+    /// Series oscillating around a level flip many bits of the XOR whenever a value crosses its
+    /// predecessor, while the arithmetic difference stays small. With this option the mantissa
+    /// lanes use [`Encoding::SignMagnitude`], costing an extra bit per value for the signs.
+    /// Blocks where a few differences are much wider than the others, such as jumps over a gap,
+    /// use [`Encoding::HybridDelta`] instead, storing those values as they are so the others
+    /// stay narrow. Combined with [`Packer::with_adaptive_encoding`], it's only used for blocks
+    /// where it is smaller than the per-lane choice.
+    pub fn with_sign_magnitude(mut self) -> Self {
+        self.sign_magnitude = true;
+        self
+    }
+
+    /// Pick the encodings by a single level of `effort`, trading packing time for size.
+    ///
+    /// Every level only adds encodings tried per block, which are picked where they are
+    /// smaller, so higher levels never pack larger:
+    ///
+    /// - 0: every block uses [`Encoding::Xor`], the default.
+    /// - 1: adds [`Packer::with_adaptive_encoding`], comparing XOR to raw values per block.
+    /// - 2: adds [`Packer::with_byte_split`], which pays off for noisy values.
+    /// - 3 and above: adds [`Packer::with_sign_magnitude`], which pays off for oscillating
+    ///   values and costs the most time.
+    ///
+    /// This replaces the encodings selected before, the options set alone afterwards still
+    /// apply. Options changing how the values can be read, like chained heads or block bounds,
+    /// are left alone.
+    pub fn with_effort(mut self, effort: u8) -> Self {
+        self.adaptive = effort >= 1;
+        self.byte_split = effort >= 2;
+        self.sign_magnitude = effort >= 3;
+        self
+    }
+
+    /// Pad partial blocks with `value` instead of repeating their last value.
+    ///
+    /// The padding is trimmed when decoding, so this only shows in raw decoded blocks, where it
+    /// tells real values from padding. It may widen the last block of a frame.
+    pub fn with_pad_value(mut self, value: Decimal) -> Self {
+        self.pad = Some(self.lanes_of(&value));
+        self
+    }
+
+    /// Map `Decimal`s to the lanes packed and back with `to_lanes` and `from_lanes` instead of
+    /// the bytes of `Decimal::serialize`, to experiment with other layouts.
+    ///
+    /// The functions are the caller's responsibility: `from_lanes` needs to invert `to_lanes`
+    /// for values to round-trip, nothing checks that. The encodings, dead band, block bounds and
+    /// padding work on any layout, as do the pending values of the packer. The packs are tagged
+    /// with [`Packable::TYPE_TAG`] 6, so only [`try_unpack_with_lanes`] decodes them, given
+    /// `from_lanes` again. Decoders of the default layout don't read them: [`try_unpack`] and
+    /// [`rechunk`] fail with [`FloatpackError::TypeMismatch`], while [`unpack`], iterating and
+    /// the analytics of [`PackedDecimals`] panic. The analytics of the packer itself, like
+    /// [`Packer::count_where`], decode its frames with `from_lanes`. A [`Packer::checkpoint`]
+    /// doesn't hold the functions, so it restores with [`Packer::restore_with_lane_fn`] only.
+    ///
+    /// Usage example:
+    /// ```
+    /// use floatpack::{try_unpack_with_lanes, Packer};
+    /// use rust_decimal::Decimal;
+    /// use rust_decimal_macros::*;
+    ///
+    /// // The low and high half of the mantissa swapped.
+    /// fn to_lanes(d: &Decimal) -> [u32; 4] {
+    ///     let m = d.mantissa().unsigned_abs();
+    ///     let flags = (d.scale() << 16) | if d.is_sign_negative() { 1 << 31 } else { 0 };
+    ///     [flags, (m >> 64) as u32, (m >> 32) as u32, m as u32]
+    /// }
+    ///
+    /// fn from_lanes(lanes: [u32; 4]) -> Decimal {
+    ///     let [flags, hi, mid, lo] = lanes;
+    ///     Decimal::from_parts(lo, mid, hi, flags >> 31 == 1, (flags >> 16) & 0xFF)
+    /// }
+    ///
+    /// let mut packer = Packer::new().with_lane_fn(to_lanes, from_lanes);
+    /// packer.load_decimal(&dec!(-1.5));
+    /// let packed = packer.finish();
+    /// assert_eq!(try_unpack_with_lanes(&packed, from_lanes).unwrap(), [dec!(-1.5)]);
+    /// ```
+    pub fn with_lane_fn(
+        mut self,
+        to_lanes: fn(&Decimal) -> [u32; 4],
+        from_lanes: fn([u32; 4]) -> Decimal,
+    ) -> Self {
+        let pad = self.pad.map(|pad| to_lanes(&self.decimal_of(pad)));
+        self.pad = pad;
+        self.lane_fn = Some(LaneFn {
+            to_lanes,
+            from_lanes,
+        });
+        self.packed.type_tag = LANE_FN_TAG;
+        self
+    }
+
+    /// The lanes of `value` in the layout of the packer.
+    fn lanes_of(&self, value: &Decimal) -> [u32; 4] {
+        match self.lane_fn {
+            Some(f) => (f.to_lanes)(value),
+            None => zip_u8(value.serialize()),
+        }
+    }
+
+    /// Store the smallest and largest value of each block (see [`Block::bounds`]).
+    ///
+    /// This costs 32 bytes per block and lets analytics like [`PackedDecimals::histogram`]
+    /// skip decoding blocks whose values are known to fall into a single bucket.
+    pub fn with_block_bounds(mut self) -> Self {
+        self.block_bounds = true;
+        self
+    }
+
+    /// Drop values that are within ±`epsilon` of the last stored value.
+    ///
+    /// This is lossy by design: dropped values are gone, `unpack` only returns the stored ones.
+    /// The comparison is against the last value that was *stored*, not the last one seen, so a
+    /// slow drift is still recorded once it accumulates beyond `epsilon`. Whether a value was
+    /// stored is returned by [`Packer::load_decimal`], so anything recorded alongside the values
+    /// (like timestamps) can be dropped as well. [`Packer::dropped`] counts the dropped values.
+    pub fn with_deadband(mut self, epsilon: Decimal) -> Self {
+        self.deadband = Some(epsilon.abs());
+        self
+    }
+
+    /// Count of values dropped by the dead band (see [`Packer::with_deadband`]).
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// XOR of the lanes of `value` against the head of the current block, without loading it.
+    ///
+    /// Wide deltas hint at an expensive block. If no block is in progress, `value` would become
+    /// the new head and the delta is zero.
+    pub fn delta_from_head(&self, value: &Decimal) -> [u32; 4] {
+        if self.cache.buffer.is_none() {
+            return [0; 4];
+        }
+        let parsed = self.lanes_of(value);
+        let mut delta = [0; 4];
+        for (d, (v, head)) in delta.iter_mut().zip(parsed.iter().zip(&self.cache.head)) {
+            *d = v ^ head;
+        }
+        delta
+    }
+
+    /// The values loaded but not flushed into a block yet, oldest first.
+    ///
+    /// They are reconstructed from the head and the XOR deltas of the pending block, so this
+    /// touches at most one block of values. Right after a flush, it's empty.
+    pub fn pending_values(&self) -> Vec<Decimal> {
+        self.pending().collect()
+    }
+
+    /// The values of [`Packer::pending_values`], reconstructed as they are iterated.
+    fn pending(&self) -> impl Iterator<Item = Decimal> + '_ {
+        let mut last = self.cache.head;
+        (0..self.cache.len()).map(move |n| {
+            if n > 0 {
+                for (i, lane) in last.iter_mut().enumerate() {
+                    *lane ^= self.cache.compressed[i][n - 1];
+                }
+            }
+            self.decimal_of(last)
+        })
+    }
+
+    /// Add a single value to the packer.
+    ///
+    /// Returns whether the value was stored, which is always the case without a dead band.
+    pub fn load_decimal(&mut self, value: &Decimal) -> bool {
+        self.load_columns(self.lanes_of(value))
+    }
+
+    /// Add a single value given as the bytes of `Decimal::serialize`, without deserializing it.
+    ///
+    /// Packs exactly like [`Packer::load_decimal`] of the deserialized value. With `validate`,
+    /// flags that no `Decimal` has (bits besides sign and scale, or a scale above 28) are
+    /// rejected as [`FloatpackError::Corrupt`]; without, they are packed as they are, and only
+    /// [`try_unpack_as`] checks them when unpacking. Returns whether the value was stored, see
+    /// [`Packer::load_decimal`].
+    pub fn load_serialized(
+        &mut self,
+        bytes: [u8; 16],
+        validate: bool,
+    ) -> Result<bool, FloatpackError> {
+        let columns = zip_u8(bytes);
+        if validate {
+            Decimal::from_columns(columns)?;
+        }
+        let columns = match self.lane_fn {
+            Some(f) => (f.to_lanes)(&Decimal::deserialize(bytes)),
+            None => columns,
+        };
+        Ok(self.load_columns(columns))
+    }
+
+    /// [`Packer::load_serialized`] for each of `values`.
+    ///
+    /// Returns the count of values loaded. With `validate`, stops at the first value with
+    /// invalid flags and reports its index in [`FloatpackError::Row`]. The preceding values stay
+    /// loaded.
+    pub fn load_serialized_slice(
+        &mut self,
+        values: &[[u8; 16]],
+        validate: bool,
+    ) -> Result<usize, FloatpackError> {
+        for (row, bytes) in values.iter().enumerate() {
+            if let Err(e) = self.load_serialized(*bytes, validate) {
+                let reason = e.to_string();
+                return Err(FloatpackError::Row { row, reason });
+            }
+        }
+        Ok(values.len())
+    }
+
+    /// The bytes of the blocks of `values`, dropping each block as soon as it is measured.
+    fn block_bytes(mut self, values: &[Decimal]) -> usize {
+        for value in values {
+            self.load_decimal(value);
+            if !self.packed.blocks[0].is_empty() {
+                self.packed.blocks.iter_mut().for_each(Vec::clear);
+            }
+        }
+        self.pack();
+        self.frame_bytes
+    }
+}
+
+/// The count of `values` and the length [`pack`] would serialize them to, without packing them.
+///
+/// Only the bit width of each block of each lane is computed, nothing is compressed or
+/// allocated, so this is a cheap check of how well a column compresses before storing it. The
+/// length is exact: it equals the [`PackedDecimals::serialized_len`] of `pack(values)`.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{pack, probe};
+/// use rust_decimal::Decimal;
+///
+/// let values: Vec<Decimal> = (0..1000).map(|i| Decimal::new(i % 100, 2)).collect();
+/// let (count, len) = probe(&values);
+/// assert_eq!((count, len), (1000, pack(&values).serialized_len()));
+/// assert!(len < 2 * count);
+/// ```
+pub fn probe(values: &[Decimal]) -> (usize, usize) {
+    let mut lanes = [0; 4];
+    let mut fixed = values.first().map(|v| zip_u8(v.serialize())[0]);
+    for block in values.chunks(BLOCK_VALUES) {
+        let head = zip_u8(block[0].serialize());
+        let (mut last, mut widths) = (head, [0u32; 4]);
+        for value in &block[1..] {
+            let columns = zip_u8(value.serialize());
+            for (width, (c, l)) in widths.iter_mut().zip(columns.iter().zip(last)) {
+                *width |= c ^ l;
+            }
+            last = columns;
+        }
+        if widths[0] != 0 || fixed != Some(head[0]) {
+            fixed = None;
+        }
+        for (lane, width) in lanes.iter_mut().zip(widths) {
+            let bits = (32 - width.leading_zeros()) as usize;
+            *lane += BLOCK_OVERHEAD + bits * (BLOCK_VALUES - 1) / 8;
+        }
+    }
+    let len = match fixed {
+        // Lane 0 is omitted, all of its blocks are of bit width 0.
+        Some(_) => lanes.iter().skip(1).sum::<usize>() + format::FIXED_FLAGS_LEN,
+        None => lanes.iter().sum(),
+    };
+    (values.len(), format::HEADER_LEN + len)
 }
 
 /// Pack and compress Decimals.
@@ -112,104 +1138,634 @@ pub fn pack(values: &[Decimal]) -> PackedDecimals {
     for d in values {
         p.load_decimal(d);
     }
-    p.pack();
-    p.packed
+    p.finish()
 }
 
-/// Unpack and decompress Decimals.
-pub fn unpack(values: &PackedDecimals) -> Vec<Decimal> {
-    let bitpacker = BitPacker8x::new();
-    let buf = Vec::with_capacity(values.1);
-    let mut unpacked = [buf.clone(), buf.clone(), buf.clone(), buf];
-    for (i, blocks) in values.0.iter().enumerate() {
-        for block in blocks {
-            let mut decompress = [0u32; BitPacker8x::BLOCK_LEN];
-            bitpacker.decompress(&block.vals, &mut decompress, block.bits);
-            let mut last = block.head;
-            unpacked[i].push(last);
-            for v in decompress {
-                last ^= v;
-                unpacked[i].push(last)
-            }
-        }
-    }
-    let mut result = Vec::with_capacity(values.1);
-    for i in 0..values.1 {
-        let v = Decimal::deserialize(unzip_u8([
-            unpacked[0][i],
-            unpacked[1][i],
-            unpacked[2][i],
-            unpacked[3][i],
-        ]));
-        result.push(v);
-    }
-    result
+/// Pack and compress Decimals, along with the byte range of each block in the serialized output.
+///
+/// Each entry maps the index of the first value of a block to the bytes the block occupies in
+/// [`PackedDecimals::to_bytes`], see [`PackedDecimals::block_ranges`].
+pub fn pack_with_ranges(values: &[Decimal]) -> (PackedDecimals, Vec<(usize, Range<usize>)>) {
+    let packed = pack(values);
+    let ranges = packed.block_ranges();
+    (packed, ranges)
 }
 
-fn zip_u8(values: [u8; 16]) -> [u32; 4] {
-    [
-        u32::from_le_bytes([values[0], values[1], values[2], values[3]]),
-        u32::from_le_bytes([values[4], values[5], values[6], values[7]]),
-        u32::from_le_bytes([values[8], values[9], values[10], values[11]]),
-        u32::from_le_bytes([values[12], values[13], values[14], values[15]]),
-    ]
+/// Pack and compress Decimals with a shared bitpacker (see [`Packer::with_bitpacker`]).
+pub fn pack_with_bitpacker(values: &[Decimal], bitpacker: &BitPacker8x) -> PackedDecimals {
+    let mut p = Packer::new().with_bitpacker(*bitpacker);
+    for d in values {
+        p.load_decimal(d);
+    }
+    p.finish()
 }
 
-fn unzip_u8(values: [u32; 4]) -> [u8; 16] {
-    let v0 = values[0].to_le_bytes();
-    let v1 = values[1].to_le_bytes();
-    let v2 = values[2].to_le_bytes();
-    let v3 = values[3].to_le_bytes();
-    [
-        v0[0], v0[1], v0[2], v0[3], v1[0], v1[1], v1[2], v1[3], v2[0], v2[1], v2[2], v2[3], v3[0],
-        v3[1], v3[2], v3[3],
-    ]
+/// Pack and compress ascending Decimals, e.g. timestamps.
+///
+/// The mantissas are stored as the changes of their differences ([`Encoding::DeltaOfDelta`]),
+/// which are zero for evenly spaced values. Blocks without a change in spacing collapse to their
+/// head and the spacing, so a long run of evenly spaced values packs to a few bytes per block.
+///
+/// Returns [`FloatpackError::Unsorted`] if a value is less than its predecessor.
+pub fn pack_sorted(values: &[Decimal]) -> Result<PackedDecimals, FloatpackError> {
+    if let Some(i) = (1..values.len()).find(|i| values[*i] < values[i - 1]) {
+        return Err(FloatpackError::Unsorted(i));
+    }
+    let mut p = Packer::new();
+    p.delta_of_delta = true;
+    for d in values {
+        p.load_decimal(d);
+    }
+    Ok(p.finish())
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{pack, unpack};
-    use rust_decimal::prelude::*;
-    use rust_decimal_macros::*;
+/// The bytes the blocks of `values` take with each [`Encoding`], to compare them on real data.
+///
+/// Each encoding is used for all blocks it applies to: [`Encoding::SignMagnitude`] and
+/// [`Encoding::DeltaOfDelta`] only span the mantissa lanes 1 to 3, lane 0 stays
+/// [`Encoding::Xor`]. [`Encoding::ByteSplit`] and [`Encoding::HybridDelta`] are only picked for
+/// blocks where they are smaller, so they aren't compared; the latter counts as
+/// [`Encoding::SignMagnitude`]. The sizes are the serialized lengths without the header.
+/// Blocks are measured and dropped as they are packed, so none of the packed outputs are kept.
+pub fn compare_encodings(values: &[Decimal]) -> BTreeMap<Encoding, usize> {
+    let packers = [
+        (Encoding::Xor, Packer::new()),
+        (
+            Encoding::Raw,
+            Packer {
+                raw: true,
+                ..Packer::new()
+            },
+        ),
+        (Encoding::SignMagnitude, Packer::new().with_sign_magnitude()),
+        (
+            Encoding::DeltaOfDelta,
+            Packer {
+                delta_of_delta: true,
+                ..Packer::new()
+            },
+        ),
+    ];
+    IntoIterator::into_iter(packers)
+        .map(|(encoding, packer)| (encoding, packer.block_bytes(values)))
+        .collect()
+}
 
-    fn test_packing(values: &Vec<Decimal>) {
-        assert_eq!(values, &unpack(&pack(&values[..])));
+/// Pack fixed-point values given as their `mantissas` and the `scale` they all share.
+///
+/// All values carry the same flags in lane 0 as long as they share their sign too, which the
+/// binary format then stores once in its header instead of a block per [`BLOCK_VALUES`] values
+/// (see [`crate::format`]). Mantissas of mixed signs are packed just as well, with lane 0 kept.
+///
+/// # Panics
+///
+/// Panics if a mantissa doesn't fit 96 bits or `scale` exceeds 28.
+pub fn pack_fixed_scale(mantissas: &[i128], scale: u32) -> PackedDecimals {
+    let mut p = Packer::new();
+    for m in mantissas {
+        p.load_decimal(&Decimal::from_i128_with_scale(*m, scale));
     }
+    p.finish()
+}
 
-    #[test]
-    fn some_values() {
-        test_packing(&vec![
-            dec!(0.866089137820393),
-            dec!(11.866089137820393),
-            Decimal::ZERO,
-            Decimal::ONE,
-            Decimal::NEGATIVE_ONE,
-            Decimal::MAX,
-            Decimal::MIN,
-            Decimal::ONE_HUNDRED,
-            Decimal::ONE_THOUSAND,
-            Decimal::TWO,
-            dec!(-111.866089137820393),
-        ])
+/// The mantissas of the values and the scale they share, the reverse of [`pack_fixed_scale`].
+///
+/// Returns `None` if the values differ in scale. Empty packs have the scale 0.
+///
+/// # Panics
+///
+/// Panics if `values` is malformed (see [`PackedDecimals::verify`]).
+pub fn unpack_fixed_scale(values: &PackedDecimals) -> Option<(Vec<i128>, u32)> {
+    if let Err(e) = values.verify() {
+        panic!("{}", e);
     }
-
-    #[test]
-    fn random_values() {
-        let mut values = Vec::new();
-        for _ in 0..257 {
-            let mut v: f64 = rand::random();
-            if rand::random() {
-                v -= 1.;
-            }
-            let d = Decimal::from_f64(v).unwrap();
-            values.push(d);
+    let mut mantissas = Vec::with_capacity(values.count);
+    let mut scale = None;
+    for v in values {
+        if *scale.get_or_insert(v.scale()) != v.scale() {
+            return None;
         }
-        test_packing(&values)
+        mantissas.push(v.mantissa());
     }
+    Some((mantissas, scale.unwrap_or(0)))
+}
 
-    #[test]
-    fn compression() {
-        let mut values = Vec::with_capacity(1000);
+/// The values as integers of minor units at `scale`, e.g. cents at scale 2.
+///
+/// Each value is rescaled as it is decoded. `values` is checked with
+/// [`PackedDecimals::verify`] first. Values with more decimals than `scale` that aren't zero
+/// fail with [`FloatpackError::OutOfRange`] instead of being rounded, as do values whose
+/// minor units don't fit an `i64`. Zeros are 0 at any `scale`, while above a `scale` of 46 the
+/// minor units of every other value exceed an `i64`.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{pack, unpack_minor_units, FloatpackError};
+/// use rust_decimal_macros::*;
+///
+/// let packed = pack(&[dec!(1.5), dec!(-2.25), dec!(3.100)]);
+/// assert_eq!(unpack_minor_units(&packed, 2).unwrap(), [150, -225, 310]);
+/// assert!(matches!(
+///     unpack_minor_units(&packed, 1),
+///     Err(FloatpackError::OutOfRange(v)) if v == dec!(-2.25)
+/// ));
+/// ```
+pub fn unpack_minor_units(values: &PackedDecimals, scale: u32) -> Result<Vec<i64>, FloatpackError> {
+    values.verify()?;
+    let mut units = Vec::with_capacity(values.count);
+    for v in values {
+        units.push(minor_units(v, scale).ok_or(FloatpackError::OutOfRange(v))?);
+    }
+    Ok(units)
+}
+
+fn minor_units(value: Decimal, scale: u32) -> Option<i64> {
+    if value.mantissa() == 0 {
+        return Some(0);
+    }
+    let mantissa = if value.scale() > scale {
+        let factor = 10i128.checked_pow(value.scale() - scale)?;
+        Some(value.mantissa())
+            .filter(|m| m % factor == 0)
+            .map(|m| m / factor)?
+    } else {
+        let factor = 10i128.checked_pow(scale - value.scale())?;
+        value.mantissa().checked_mul(factor)?
+    };
+    i64::try_from(mantissa).ok()
+}
+
+/// Re-pack `packed` into blocks of `block_len` deltas (see [`Packer::with_block_len`]).
+///
+/// Values are streamed one block at a time. Chained heads are kept, as are the encodings
+/// used: a source with [`Encoding::Raw`] blocks is re-packed with adaptive encoding, one with
+/// [`Encoding::SignMagnitude`] or [`Encoding::DeltaOfDelta`] blocks with that encoding.
+/// Returns [`FloatpackError::TypeMismatch`] if `packed` holds values of another type than
+/// `Decimal`, which re-packing would store as `Decimal`s.
+///
+/// # Panics
+///
+/// Panics if `packed` has a block length no bitpacker supports.
+pub fn rechunk(
+    packed: &PackedDecimals,
+    block_len: usize,
+) -> Result<PackedDecimals, FloatpackError> {
+    let mut p = repacker(packed, block_len)?;
+    for d in packed {
+        p.load_decimal(&d);
+    }
+    Ok(p.finish())
+}
+
+/// A packer with the options `packed` was packed with, see [`rechunk`].
+pub(crate) fn repacker(
+    packed: &PackedDecimals,
+    block_len: usize,
+) -> Result<Packer, FloatpackError> {
+    format::check_decimals(packed)?;
+    let mut p = Packer::new().with_block_len(block_len)?;
+    p.chained = packed.chained();
+    p.block_bounds = packed.blocks[0].iter().any(|b| b.bounds.is_some());
+    for block in packed.blocks.iter().flatten() {
+        match block.encoding {
+            Encoding::Xor => {}
+            Encoding::Raw => p.adaptive = true,
+            Encoding::SignMagnitude | Encoding::HybridDelta => p.sign_magnitude = true,
+            Encoding::DeltaOfDelta => p.delta_of_delta = true,
+            Encoding::ByteSplit => p.byte_split = true,
+        }
+    }
+    Ok(p)
+}
+
+/// Insert `values` in front of the values of `packed`, keeping its options (see [`rechunk`]).
+///
+/// The first block of a pack always has an absolute head, also with chained heads, and serves as
+/// the anchor the following blocks build on. If the count of `values` is a multiple of
+/// [`PackedDecimals::block_values`], `values` are packed into full blocks of their own, put in
+/// front of the existing ones, whose anchor stays valid. This only costs packing `values`.
+///
+/// Any other count shifts the boundaries of all existing blocks, as all but the last block are
+/// full, so the whole pack is decoded and packed again. Prepending one value at a time thus is
+/// quadratic; buffer them and prepend whole blocks where possible.
+///
+/// # Panics
+///
+/// Panics if `packed` holds values of another type than `Decimal` or has a block length no
+/// bitpacker supports.
+pub fn prepend(packed: &mut PackedDecimals, values: &[Decimal]) {
+    if values.is_empty() {
+        return;
+    }
+    let mut p = repacker(packed, packed.block_len).unwrap_or_else(|e| panic!("{}", e));
+    for v in values {
+        p.load_decimal(v);
+    }
+    if values.len().is_multiple_of(packed.block_values()) {
+        let mut front = p.finish();
+        for (lane, blocks) in front.blocks.iter_mut().zip(&mut packed.blocks) {
+            lane.append(blocks);
+        }
+        front.count += packed.count;
+        *packed = front;
+        return;
+    }
+    for v in packed.iter() {
+        p.load_decimal(&v);
+    }
+    *packed = p.finish();
+}
+
+/// Split `packed` into the values whose entry of `mask` is `true` and those whose entry is
+/// `false`, keeping the order of the values and the options of `packed` (see [`rechunk`]).
+///
+/// Values are streamed one block at a time into two packers, without decoding all of them
+/// first.
+///
+/// # Panics
+///
+/// Panics if `mask` isn't as long as `packed`, or if `packed` holds values of another type than
+/// `Decimal` or has a block length no bitpacker supports.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{pack, partition, unpack};
+/// use rust_decimal_macros::*;
+///
+/// let packed = pack(&[dec!(1.5), dec!(2.5), dec!(3.5)]);
+/// let (open, closed) = partition(&packed, &[true, false, true]);
+/// assert_eq!(unpack(&open), [dec!(1.5), dec!(3.5)]);
+/// assert_eq!(unpack(&closed), [dec!(2.5)]);
+/// ```
+pub fn partition(packed: &PackedDecimals, mask: &[bool]) -> (PackedDecimals, PackedDecimals) {
+    assert_eq!(mask.len(), packed.len(), "mask and values differ in length");
+    let mut selected = repacker(packed, packed.block_len).unwrap_or_else(|e| panic!("{}", e));
+    let mut rest = repacker(packed, packed.block_len).unwrap_or_else(|e| panic!("{}", e));
+    for (v, &select) in packed.iter().zip(mask) {
+        if select {
+            selected.load_decimal(&v);
+        } else {
+            rest.load_decimal(&v);
+        }
+    }
+    (selected.finish(), rest.finish())
+}
+
+/// Pack and compress Decimals until the first occurrence of `stop`.
+///
+/// The sentinel itself is not packed, neither is anything following it.
+pub fn pack_until(values: impl Iterator<Item = Decimal>, stop: Decimal) -> PackedDecimals {
+    let mut p = Packer::new();
+    for d in values.take_while(|d| *d != stop) {
+        p.load_decimal(&d);
+    }
+    p.finish()
+}
+
+/// Pack the values of each key separately, e.g. the prices of each symbol of a feed.
+///
+/// Each value goes to the packer of its key, all of which are finished once `pairs` ends. The
+/// values of a key keep their order, while their interleaving with the values of other keys is
+/// not recorded. The pending block of every key is held in memory until the end.
+pub fn pack_grouped<K: Eq + Hash>(
+    pairs: impl Iterator<Item = (K, Decimal)>,
+) -> HashMap<K, PackedDecimals> {
+    let bitpacker = BitPacker8x::new();
+    let mut packers: HashMap<K, Packer> = HashMap::new();
+    for (key, value) in pairs {
+        packers
+            .entry(key)
+            .or_insert_with(|| Packer::new().with_bitpacker(bitpacker))
+            .load_decimal(&value);
+    }
+    packers
+        .into_iter()
+        .map(|(key, packer)| (key, packer.finish()))
+        .collect()
+}
+
+/// Check whether two packs have the exact same compressed representation.
+///
+/// This is stricter than comparing the unpacked values: every block of all 4 lanes, as well as
+/// the count, block length and type tag, have to match. Packing the same values with different
+/// options generally yields packs that are not equal.
+pub fn bytes_equal(a: &PackedDecimals, b: &PackedDecimals) -> bool {
+    a == b
+}
+
+/// Unpack and decompress Decimals.
+///
+/// # Panics
+///
+/// Panics if `values` is malformed (see [`PackedDecimals::verify`] and [`try_unpack`]).
+pub fn unpack(values: &PackedDecimals) -> Vec<Decimal> {
+    match try_unpack(values) {
+        Ok(result) => result,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Unpack and decompress Decimals with a shared bitpacker (see [`Packer::with_bitpacker`]).
+///
+/// Packs of other block lengths than 256 are decoded with a bitpacker of their own.
+///
+/// # Panics
+///
+/// Panics if `values` is malformed (see [`PackedDecimals::verify`] and [`try_unpack`]).
+pub fn unpack_with_bitpacker(values: &PackedDecimals, bitpacker: &BitPacker8x) -> Vec<Decimal> {
+    match decode::try_unpack_with(values, Some(*bitpacker)) {
+        Ok(result) => result,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Unpack and decompress Decimals, pairing each value with the cost of the block it was stored in.
+///
+/// The cost is the sum of `bits` over all 4 lanes of the block, i.e. how many bits every
+/// value in that block occupies in packed form.
+///
+/// # Panics
+///
+/// Panics if `values` holds values of another type than `Decimal`.
+pub fn decode_with_cost(values: &PackedDecimals) -> Vec<(Decimal, u32)> {
+    let values = values.decimals();
+    let mut result = Vec::with_capacity(values.count);
+    for (n, block) in Blocks::new(values).enumerate() {
+        let cost = values.blocks.iter().map(|lane| lane[n].bits as u32).sum();
+        result.extend(block.into_iter().map(|v| (v, cost)));
+    }
+    result
+}
+
+/// Bitmap of the byte planes of `values` that are not all zero.
+fn byte_planes(values: &[u32]) -> u8 {
+    let any = values.iter().fold(0, |any, v| any | v);
+    (0..4)
+        .filter(|p| any >> (8 * p) & 0xFF != 0)
+        .fold(0, |planes, p| planes | 1 << p)
+}
+
+/// Writes the byte `planes` of `values` to `bytes`, one plane after the other.
+fn split_bytes(values: &[u32], planes: u8, bytes: &mut [u8]) {
+    let planes = (0..4).filter(|p| planes & 1 << p != 0);
+    for (p, plane) in planes.zip(bytes.chunks_exact_mut(values.len())) {
+        for (b, v) in plane.iter_mut().zip(values) {
+            *b = (v >> (8 * p)) as u8;
+        }
+    }
+}
+
+/// The 96 bit mantissa held by lanes 1 to 3.
+fn mantissa<const N: usize>(lanes: [u32; N]) -> u128 {
+    lanes[1] as u128 | (lanes[2] as u128) << 32 | (lanes[3] as u128) << 64
+}
+
+/// Splits the mantissa differences of a block into magnitudes (per lane) and a sign bitmap.
+fn sign_magnitude<const N: usize>(
+    head: [u32; N],
+    deltas: &[[u32; MAX_BLOCK_LEN]; N],
+    block_len: usize,
+) -> ([[u32; MAX_BLOCK_LEN]; 3], Vec<u8>) {
+    let mut magnitudes = [[0; MAX_BLOCK_LEN]; 3];
+    let mut signs = vec![0u8; block_len / 8];
+    let mut last = head;
+    let mut prev = mantissa(head);
+    for j in 0..block_len {
+        for (i, lane) in last.iter_mut().enumerate() {
+            *lane ^= deltas[i][j];
+        }
+        let current = mantissa(last);
+        let diff = current as i128 - prev as i128;
+        if diff < 0 {
+            signs[j / 8] |= 1 << (j % 8);
+        }
+        let magnitude = diff.unsigned_abs();
+        for (i, lane) in magnitudes.iter_mut().enumerate() {
+            lane[j] = (magnitude >> (32 * i)) as u32;
+        }
+        prev = current;
+    }
+    (magnitudes, signs)
+}
+
+/// Takes the mantissas whose difference is too wide to be worth bitpacking out of `magnitudes`.
+///
+/// Picks the widest difference kept for which the block is smallest, counting 12 bytes per
+/// mantissa taken and a bitmap of them. Returns the bitmap followed by the mantissas taken,
+/// whose magnitudes and signs are zeroed, or `None` if keeping all differences is no larger.
+fn absolute_outliers<const N: usize>(
+    head: [u32; N],
+    deltas: &[[u32; MAX_BLOCK_LEN]; N],
+    magnitudes: &mut [[u32; MAX_BLOCK_LEN]; 3],
+    signs: &mut [u8],
+    block_len: usize,
+) -> Option<Vec<u8>> {
+    let widths: Vec<usize> = (0..block_len)
+        .map(|j| {
+            let magnitude = mantissa([0, magnitudes[0][j], magnitudes[1][j], magnitudes[2][j]]);
+            (u128::BITS - magnitude.leading_zeros()) as usize
+        })
+        .collect();
+    let mut counts = [0; 97];
+    for w in &widths {
+        counts[*w] += 1;
+    }
+    let full = widths.iter().copied().max().unwrap_or(0);
+    // Sizes in bits, the lanes of a width `b` sum up to `b` bits per value.
+    let mut best = (full * block_len, full);
+    let mut taken = 0;
+    for b in (0..full).rev() {
+        taken += counts[b + 1];
+        let size = b * block_len + 8 * (MANTISSA_BYTES * taken + block_len / 8);
+        if size < best.0 {
+            best = (size, b);
+        }
+    }
+    let width = best.1;
+    if width == full {
+        return None;
+    }
+    let mut side = vec![0u8; block_len / 8];
+    let mut last = head;
+    for (j, w) in widths.iter().enumerate() {
+        for (i, lane) in last.iter_mut().enumerate() {
+            *lane ^= deltas[i][j];
+        }
+        if *w > width {
+            side[j / 8] |= 1 << (j % 8);
+            signs[j / 8] &= !(1 << (j % 8));
+            side.extend_from_slice(&mantissa(last).to_le_bytes()[..MANTISSA_BYTES]);
+            for lane in magnitudes.iter_mut() {
+                lane[j] = 0;
+            }
+        }
+    }
+    Some(side)
+}
+
+/// Zigzag encodes the changes of the mantissa differences of a block of `len` deltas.
+///
+/// Returns the encoded changes (per lane) and the first difference. The padding continues the
+/// last difference, so it costs nothing.
+fn delta_of_delta<const N: usize>(
+    head: [u32; N],
+    deltas: &[[u32; MAX_BLOCK_LEN]; N],
+    len: usize,
+    block_len: usize,
+) -> ([[u32; MAX_BLOCK_LEN]; 3], Vec<u8>) {
+    let mut zigzags = [[0; MAX_BLOCK_LEN]; 3];
+    let mut last = head;
+    let mut prev = mantissa(head);
+    let mut mantissas = [0; MAX_BLOCK_LEN];
+    for (j, m) in mantissas[..len].iter_mut().enumerate() {
+        for (i, lane) in last.iter_mut().enumerate() {
+            *lane ^= deltas[i][j];
+        }
+        *m = mantissa(last);
+    }
+    let first = if len > 0 {
+        mantissas[0].wrapping_sub(prev) & MANTISSA_MASK
+    } else {
+        0
+    };
+    let mut delta = first;
+    for j in 0..block_len {
+        let next = if j < len {
+            mantissas[j].wrapping_sub(prev) & MANTISSA_MASK
+        } else {
+            delta
+        };
+        let change = next.wrapping_sub(delta) & MANTISSA_MASK;
+        let zigzag = if change >> 95 == 0 {
+            change << 1
+        } else {
+            ((MANTISSA_MASK - change) << 1) | 1
+        };
+        for (i, lane) in zigzags.iter_mut().enumerate() {
+            lane[j] = (zigzag >> (32 * i)) as u32;
+        }
+        delta = next;
+        prev = prev.wrapping_add(delta) & MANTISSA_MASK;
+    }
+    (zigzags, first.to_le_bytes()[..MANTISSA_BYTES].to_vec())
+}
+
+/// The `Decimal` of the columns of a packer of `Decimal`s, which has 4 of them.
+fn decimal_of_columns<const N: usize>(columns: [u32; N]) -> Decimal {
+    Decimal::deserialize(unzip_u8(std::array::from_fn(|i| columns[i])))
+}
+
+fn zip_u8(values: [u8; 16]) -> [u32; 4] {
+    [
+        u32::from_le_bytes([values[0], values[1], values[2], values[3]]),
+        u32::from_le_bytes([values[4], values[5], values[6], values[7]]),
+        u32::from_le_bytes([values[8], values[9], values[10], values[11]]),
+        u32::from_le_bytes([values[12], values[13], values[14], values[15]]),
+    ]
+}
+
+fn unzip_u8(values: [u32; 4]) -> [u8; 16] {
+    let v0 = values[0].to_le_bytes();
+    let v1 = values[1].to_le_bytes();
+    let v2 = values[2].to_le_bytes();
+    let v3 = values[3].to_le_bytes();
+    [
+        v0[0], v0[1], v0[2], v0[3], v1[0], v1[1], v1[2], v1[3], v2[0], v2[1], v2[2], v2[3], v3[0],
+        v3[1], v3[2], v3[3],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode::Blocks;
+    use crate::{bitpacker::AnyBitPacker, decode, unzip_u8, zip_u8, LANE_FN_TAG};
+    use crate::{
+        bytes_equal, decode_with_cost, pack, pack_fixed_scale, pack_grouped, pack_sorted,
+        pack_until, pack_with_bitpacker, partition, prepend, probe, rechunk, try_unpack,
+        try_unpack_with_lanes, unpack, unpack_fixed_scale, unpack_lenient, unpack_minor_units,
+        unpack_rev, unpack_unchecked, unpack_with_bitpacker, Encoding, FloatpackError,
+        PackedDecimals, Packer, BLOCK_VALUES,
+    };
+    use bitpacking::{BitPacker, BitPacker8x};
+    use rust_decimal::prelude::*;
+    use rust_decimal_macros::*;
+
+    fn test_packing(values: &Vec<Decimal>) {
+        assert_eq!(values, &unpack(&pack(&values[..])));
+    }
+
+    #[test]
+    fn some_values() {
+        test_packing(&vec![
+            dec!(0.866089137820393),
+            dec!(11.866089137820393),
+            Decimal::ZERO,
+            Decimal::ONE,
+            Decimal::NEGATIVE_ONE,
+            Decimal::MAX,
+            Decimal::MIN,
+            Decimal::ONE_HUNDRED,
+            Decimal::ONE_THOUSAND,
+            Decimal::TWO,
+            dec!(-111.866089137820393),
+        ])
+    }
+
+    /// `n` reproducible values of `seed`, of all mantissa widths, scales and signs. Runs of
+    /// equal values, small steps and sign flips around zero are mixed in between.
+    fn random_decimals(n: usize, seed: u64) -> Vec<Decimal> {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let mut values: Vec<Decimal> = Vec::with_capacity(n);
+        while values.len() < n {
+            let bits = rng.gen_range(0..=96);
+            let mantissa = rng.gen::<u128>() & ((1u128 << bits) - 1);
+            let (lo, mid, hi) = (
+                mantissa as u32,
+                (mantissa >> 32) as u32,
+                (mantissa >> 64) as u32,
+            );
+            let value = Decimal::from_parts(lo, mid, hi, rng.gen(), rng.gen_range(0..=28));
+            let run = rng.gen_range(1..=20).min(n - values.len());
+            match rng.gen_range(0..4) {
+                0 => values.extend(std::iter::repeat_n(value, run)),
+                1 => {
+                    let step = Decimal::new(rng.gen_range(-3..=3), value.scale());
+                    values.extend((0..run).scan(value, |v, _| {
+                        *v = v.checked_add(step).unwrap_or(*v);
+                        Some(*v)
+                    }));
+                }
+                2 => {
+                    let small = Decimal::new(rng.gen_range(0..1000), rng.gen_range(0..=6));
+                    values.extend((0..run).map(|i| if i % 2 == 0 { small } else { -small }));
+                }
+                _ => values.push(value),
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn random_values() {
+        for seed in 0..8 {
+            for len in [0, 1, 2, 255, 256, 257, 258, 1000] {
+                let values = random_decimals(len, seed);
+                assert_exact(&unpack(&pack(&values)), &values);
+                let chained = pack_with(Packer::new().with_chained_heads(), &values);
+                assert_exact(&unpack(&chained), &values);
+                let short = pack_with(Packer::new().with_block_len(32).unwrap(), &values);
+                assert_exact(&unpack(&short), &values);
+                let adaptive = pack_adaptive(&values);
+                let bytes = PackedDecimals::from_bytes(&adaptive.to_bytes()).unwrap();
+                assert_exact(&unpack(&bytes), &values);
+            }
+        }
+    }
+
+    #[test]
+    fn compression() {
+        let mut values = Vec::with_capacity(1000);
         for v in 0..1000 {
             values.push(Decimal::from(v))
         }
@@ -217,4 +1773,1335 @@ mod tests {
         let p_des = bincode::serialize(&pack(&values)).unwrap();
         assert!(v_des.len() > p_des.len() * 3);
     }
+
+    #[test]
+    fn cost_per_value() {
+        let mut values = vec![Decimal::ONE; 257];
+        values.extend((0..10).map(|v| Decimal::from(v * 1000)));
+        let packed = pack(&values);
+        let with_cost = decode_with_cost(&packed);
+        assert_eq!(with_cost.len(), values.len());
+        for (i, (v, cost)) in with_cost.iter().enumerate() {
+            let block = i / 257;
+            let bits: u32 = packed
+                .blocks
+                .iter()
+                .map(|lane| lane[block].bits as u32)
+                .sum();
+            assert_eq!(v, &values[i]);
+            assert_eq!(*cost, bits);
+        }
+        assert_eq!(with_cost[0].1, 0);
+        assert!(with_cost[257].1 > 0);
+    }
+
+    fn frame_sizes(values: &[Decimal], target: usize) -> Vec<usize> {
+        let mut packer = Packer::new().with_flush_bytes(target);
+        let mut frames = Vec::new();
+        for v in values {
+            packer.load_decimal(v);
+            frames.extend(packer.take_frames());
+        }
+        frames.extend(packer.finish_frames());
+        let unpacked: Vec<Decimal> = frames.iter().flat_map(unpack).collect();
+        assert_eq!(values, &unpacked[..]);
+        frames
+            .iter()
+            .map(|f: &PackedDecimals| f.blocks.iter().flatten().map(|b| b.size_bytes()).sum())
+            .collect()
+    }
+
+    #[test]
+    fn flush_bytes() {
+        use rand::{Rng, SeedableRng};
+        let target = 16 * 1024;
+        // A single value can grow the pending block by at most 32 bits in each lane
+        let max_step = 4 * 256 * 4;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let high_entropy: Vec<Decimal> = (0..20_000)
+            .map(|_| Decimal::from_f64(rng.gen::<f64>()).unwrap())
+            .collect();
+        let low_entropy: Vec<Decimal> = (0..200_000).map(Decimal::from).collect();
+        for values in [high_entropy, low_entropy] {
+            let sizes = frame_sizes(&values, target);
+            assert!(sizes.len() > 2);
+            for size in &sizes[..sizes.len() - 1] {
+                assert!(*size <= target);
+                assert!(*size > target - max_step);
+            }
+        }
+    }
+
+    #[test]
+    fn flush_bytes_tiny_target() {
+        let values: Vec<Decimal> = (0..600).map(Decimal::from).collect();
+        let sizes = frame_sizes(&values, 1);
+        assert_eq!(sizes.len(), 600);
+    }
+
+    #[test]
+    fn until_sentinel() {
+        let stop = Decimal::NEGATIVE_ONE;
+        let mut values: Vec<Decimal> = (0..300).map(Decimal::from).collect();
+        values.push(stop);
+        values.extend((0..10).map(Decimal::from));
+        values.push(stop);
+        let packed = pack_until(values.into_iter(), stop);
+        assert_eq!(packed.count, 300);
+        assert_eq!(packed.blocks[0].len(), 2);
+        let expected: Vec<Decimal> = (0..300).map(Decimal::from).collect();
+        assert_eq!(unpack(&packed), expected);
+
+        let packed = pack_until(vec![stop, Decimal::ONE].into_iter(), stop);
+        assert!(unpack(&packed).is_empty());
+    }
+
+    #[test]
+    fn grouped() {
+        let symbols = ["AAPL", "MSFT", "TSLA"];
+        let pairs: Vec<(&str, Decimal)> = (0..3000i64)
+            .map(|i| {
+                (
+                    symbols[(i * 7 % 11 % 3) as usize],
+                    Decimal::new(10_000 + i, 2),
+                )
+            })
+            .collect();
+        let packed = pack_grouped(pairs.iter().copied());
+        assert_eq!(packed.len(), 3);
+        for symbol in symbols {
+            let expected: Vec<Decimal> = pairs
+                .iter()
+                .filter(|(s, _)| *s == symbol)
+                .map(|(_, v)| *v)
+                .collect();
+            assert!(bytes_equal(&packed[symbol], &pack(&expected)));
+        }
+        assert!(pack_grouped(std::iter::empty::<(u8, Decimal)>()).is_empty());
+    }
+
+    fn prices(n: usize) -> Vec<Decimal> {
+        let mut price = dec!(1234.5);
+        (0..n)
+            .map(|i| {
+                price += Decimal::new((i as i64 * 7919) % 11 - 5, 1);
+                price
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chained_heads() {
+        use bincode::Options;
+        let values = prices(20 * 257 + 13);
+        let mut packer = Packer::new().with_chained_heads();
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        let chained = packer.finish();
+        let independent = pack(&values);
+        assert!(chained.chained());
+        assert_eq!(unpack(&chained), values);
+        assert_eq!(chained.blocks[0][0].head, independent.blocks[0][0].head);
+        for (c, i) in chained.blocks[0].iter().zip(&independent.blocks[0]).skip(1) {
+            assert!(c.head < i.head);
+        }
+
+        let varint = bincode::DefaultOptions::new();
+        let chained_size = varint.serialize(&chained).unwrap().len();
+        let independent_size = varint.serialize(&independent).unwrap().len();
+        assert!(chained_size < independent_size);
+    }
+
+    #[test]
+    fn chained_heads_frames() {
+        let values = prices(5000);
+        let mut packer = Packer::new().with_chained_heads().with_flush_bytes(2048);
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        let frames = packer.finish_frames();
+        assert!(frames.len() > 1);
+        assert!(frames.iter().all(|f| f.chained()));
+        let unpacked: Vec<Decimal> = frames.iter().flat_map(unpack).collect();
+        assert_eq!(unpacked, values);
+    }
+
+    #[test]
+    fn keyframes() {
+        let values = prices(50 * 257 + 13);
+        let chained = pack_with(Packer::new().with_chained_heads(), &values);
+        let keyframed = pack_with(Packer::new().with_keyframes(8), &values);
+        let independent = pack(&values);
+        assert_eq!(keyframed.blocks[0].len(), 51);
+        for (n, block) in keyframed.blocks[0].iter().enumerate() {
+            assert_eq!(block.continuation, n % 8 != 0);
+        }
+        assert_exact(&unpack(&keyframed), &values);
+        let decoded = PackedDecimals::from_bytes(&keyframed.to_bytes()).unwrap();
+        assert_eq!(decoded, keyframed);
+
+        // Random access decodes the blocks since the last keyframe only.
+        for n in [0, 7, 8, 13, 50] {
+            let mut blocks = decode::Blocks::new(&keyframed);
+            blocks.skip_to(n);
+            assert_eq!(blocks.decoded, n % 8);
+            let index = n * 257 + 100.min(values.len() - n * 257 - 1);
+            assert_eq!(keyframed.get(index), Some(values[index]));
+        }
+        let mut rev = unpack_rev(&keyframed);
+        rev.reverse();
+        assert_exact(&rev, &values);
+        assert_eq!(
+            keyframed.iter().nth(20 * 257 + 5),
+            Some(values[20 * 257 + 5])
+        );
+
+        let size = |p: &PackedDecimals| p.serialized_len();
+        assert!(size(&chained) < size(&keyframed));
+        assert!(size(&keyframed) < size(&independent));
+        let every = pack_with(Packer::new().with_keyframes(1), &values);
+        assert!(every.blocks[0].iter().all(|b| !b.continuation));
+        assert!(bytes_equal(&every, &independent));
+    }
+
+    #[test]
+    fn block_bounds() {
+        let values = prices(3 * 257 + 10);
+        let mut packer = Packer::new()
+            .with_block_bounds()
+            .with_pad_value(Decimal::MAX)
+            .with_flush_bytes(300);
+        let mut frames = Vec::new();
+        for v in &values {
+            packer.load_decimal(v);
+            frames.extend(packer.take_frames());
+        }
+        frames.extend(packer.finish_frames());
+        assert!(frames.len() > 1);
+        let mut start = 0;
+        for frame in &frames {
+            for (n, block) in frame.blocks[0].iter().enumerate() {
+                let len = (frame.count - n * 257).min(257);
+                let chunk = &values[start..start + len];
+                let min = *chunk.iter().min().unwrap();
+                let max = *chunk.iter().max().unwrap();
+                assert_eq!(block.bounds, Some((min, max)));
+                start += len;
+            }
+            assert!(frame.blocks[1..]
+                .iter()
+                .flatten()
+                .all(|b| b.bounds.is_none()));
+        }
+        assert_eq!(start, values.len());
+        assert!(pack(&values).blocks[0].iter().all(|b| b.bounds.is_none()));
+    }
+
+    #[test]
+    fn continuation_per_block() {
+        let values = prices(6 * 257 + 40);
+        let mut packed = pack_with(Packer::new().with_chained_heads(), &values);
+        assert!(!packed.blocks[0][0].continuation);
+        assert!(packed.blocks[0][1..].iter().all(|b| b.continuation));
+
+        // Restart the chain at block 3 by storing its head as it is.
+        let head = zip_u8(values[3 * 257].serialize());
+        for (lane, head) in packed.blocks.iter_mut().zip(head) {
+            lane[3].head = head;
+            lane[3].continuation = false;
+        }
+        assert!(packed.verify().is_ok());
+        assert_eq!(unpack(&packed), values);
+        assert_eq!(
+            unpack_rev(&packed),
+            values.iter().rev().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(packed.iter().rev().collect::<Vec<_>>(), unpack_rev(&packed));
+        for i in [0, 600, 3 * 257, 4 * 257 + 9, values.len() - 1] {
+            assert_eq!(packed.get(i), Some(values[i]));
+            assert_eq!(packed.iter().nth(i), Some(values[i]));
+        }
+        assert_eq!(
+            PackedDecimals::from_bytes(&packed.to_bytes()).unwrap(),
+            packed
+        );
+
+        // Only the blocks chained to a broken one are lost.
+        packed.blocks[0][1].bits = 40;
+        let (lenient, skipped) = unpack_lenient(&packed);
+        assert_eq!(skipped, vec![1, 2]);
+        assert_eq!(lenient[..257], values[..257]);
+        assert_eq!(lenient[257..], values[3 * 257..]);
+        assert_eq!(packed.get(3 * 257), Some(values[3 * 257]));
+        assert_eq!(packed.get(2 * 257), None);
+
+        packed.blocks[2][5].continuation = false;
+        assert!(packed.verify().is_err());
+    }
+
+    #[test]
+    fn deadband() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        let epsilon = dec!(0.5);
+        let values: Vec<Decimal> = (0..2000)
+            .map(|i| {
+                let signal = Decimal::from((i / 100) * 3);
+                let noise = Decimal::from_f64(rng.gen::<f64>() * 0.4 - 0.2).unwrap();
+                signal + noise.round_dp(3)
+            })
+            .collect();
+        let mut packer = Packer::new().with_deadband(epsilon);
+        let mut expected: Vec<Decimal> = Vec::new();
+        for v in &values {
+            let keep = match expected.last() {
+                Some(last) => (*v - *last).abs() > epsilon,
+                None => true,
+            };
+            assert_eq!(packer.load_decimal(v), keep);
+            if keep {
+                expected.push(*v);
+            }
+        }
+        assert_eq!(packer.dropped(), values.len() - expected.len());
+        assert!(expected.len() >= 20);
+        assert!(expected.len() < values.len() / 10);
+        assert_eq!(unpack(&packer.finish()), expected);
+    }
+
+    #[test]
+    fn deadband_extremes() {
+        let mut packer = Packer::new().with_deadband(Decimal::ONE);
+        assert!(packer.load_decimal(&Decimal::MIN));
+        assert!(packer.load_decimal(&Decimal::MAX));
+        assert!(!packer.load_decimal(&Decimal::MAX));
+        assert_eq!(unpack(&packer.finish()), vec![Decimal::MIN, Decimal::MAX]);
+    }
+
+    fn pack_adaptive(values: &[Decimal]) -> PackedDecimals {
+        let mut packer = Packer::new().with_adaptive_encoding();
+        for v in values {
+            packer.load_decimal(v);
+        }
+        packer.finish()
+    }
+
+    #[test]
+    fn adaptive_encoding() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        let random: Vec<Decimal> = (0..257)
+            .map(|_| Decimal::from_i128_with_scale(rng.gen::<i64>() as i128, 4))
+            .collect();
+        let mut head_outlier = vec![Decimal::from(u32::MAX)];
+        head_outlier.extend((0..256).map(Decimal::from));
+        let steady: Vec<Decimal> = (0..100).map(|v| Decimal::from(1_000_000 + v)).collect();
+        let values: Vec<Decimal> = [random, head_outlier, steady].concat();
+
+        let packed = pack_adaptive(&values);
+        assert_eq!(unpack(&packed), values);
+        let mantissa = &packed.blocks[1];
+        assert_eq!(mantissa[0].encoding, Encoding::Raw);
+        assert_eq!(mantissa[1].encoding, Encoding::Raw);
+        assert_eq!(mantissa[2].encoding, Encoding::Xor);
+        assert_eq!(mantissa[1].bits, 8);
+        assert!(pack(&values).blocks[1][1].bits > 8);
+        assert!(pack(&values).blocks[1]
+            .iter()
+            .all(|b| b.encoding == Encoding::Xor));
+
+        let mut packer = Packer::new().with_adaptive_encoding().with_chained_heads();
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        assert_eq!(unpack(&packer.finish()), values);
+    }
+
+    #[test]
+    fn pack_values() {
+        let packed = pack(&[dec!(1.0), dec!(2.0), dec!(3.0)]);
+        assert_eq!(packed.count, 3);
+        let flags = &packed.blocks[0][0];
+        assert_eq!((flags.bits, flags.head), (0, 1 << 16));
+        assert!(flags.vals.is_empty());
+        let lo = &packed.blocks[1][0];
+        assert_eq!((lo.bits, lo.head, lo.vals.len()), (5, 10, 160));
+        for lane in &packed.blocks[2..] {
+            assert_eq!((lane[0].bits, lane[0].head), (0, 0));
+            assert!(lane[0].vals.is_empty());
+        }
+    }
+
+    #[test]
+    fn equal_bytes() {
+        let values = prices(1000);
+        assert!(bytes_equal(&pack(&values), &pack(&values)));
+        assert!(!bytes_equal(&pack(&values), &pack(&values[1..])));
+        let adaptive = pack_adaptive(&values);
+        assert_eq!(unpack(&adaptive), values);
+        assert!(!bytes_equal(&pack(&values), &adaptive));
+        let mut head = pack(&values);
+        head.blocks[3][1].head ^= 1;
+        assert!(!bytes_equal(&pack(&values), &head));
+        let mut tag = pack(&values);
+        tag.type_tag = LANE_FN_TAG;
+        assert!(!bytes_equal(&pack(&values), &tag));
+        let mut block_len = pack(&[]);
+        block_len.block_len = 32;
+        assert!(!bytes_equal(&pack(&[]), &block_len));
+    }
+
+    #[test]
+    fn block_lengths() {
+        let values = prices(3000);
+        for (block_len, blocks) in [(32, 91), (128, 24), (256, 12)] {
+            let mut packer = Packer::new()
+                .with_block_len(block_len)
+                .unwrap()
+                .with_adaptive_encoding()
+                .with_chained_heads();
+            for v in &values {
+                packer.load_decimal(v);
+            }
+            let packed = packer.finish();
+            assert_eq!(packed.block_len, block_len);
+            assert!(packed.blocks.iter().all(|lane| lane.len() == blocks));
+            assert_eq!(unpack(&packed), values);
+        }
+        assert!(Packer::new().with_block_len(64).is_err());
+    }
+
+    #[test]
+    fn iter() {
+        let values = prices(1000);
+        let packed = pack(&values);
+        assert_eq!(packed.iter().len(), 1000);
+        assert_eq!(packed.iter().collect::<Vec<_>>(), values);
+        assert_eq!((&packed).into_iter().nth(700), Some(values[700]));
+    }
+
+    pub(crate) fn pack_with(packer: Packer, values: &[Decimal]) -> PackedDecimals {
+        let mut packer = packer;
+        for v in values {
+            packer.load_decimal(v);
+        }
+        packer.finish()
+    }
+
+    fn assert_exact(unpacked: &[Decimal], values: &[Decimal]) {
+        let serialize = |v: &[Decimal]| v.iter().map(|d| d.serialize()).collect::<Vec<_>>();
+        assert_eq!(serialize(unpacked), serialize(values));
+    }
+
+    #[test]
+    fn sign_magnitude() {
+        let level = 1 << 20;
+        let ticks: Vec<Decimal> = (0..1000)
+            .map(|i| Decimal::new(level + [0, -1, 1, -2, 0, 2][i % 6], 2))
+            .collect();
+        let split = pack_with(Packer::new().with_sign_magnitude(), &ticks);
+        let xor = pack(&ticks);
+        assert_eq!(unpack(&split), ticks);
+        for (s, x) in split.blocks[1].iter().zip(&xor.blocks[1]) {
+            assert_eq!(s.encoding, Encoding::SignMagnitude);
+            assert!(s.bits <= 3);
+            assert!(x.bits >= 20);
+        }
+        let bytes = split.to_bytes();
+        assert!(bytes.len() * 4 < xor.to_bytes().len());
+        assert_eq!(PackedDecimals::from_bytes(&bytes).unwrap(), split);
+
+        let adaptive = Packer::new().with_sign_magnitude().with_adaptive_encoding();
+        let steady: Vec<Decimal> = (0..300).map(|v| Decimal::from(v % 2)).collect();
+        let mixed = pack_with(adaptive, &[&ticks[..257], &steady[..]].concat());
+        assert_eq!(mixed.blocks[1][0].encoding, Encoding::SignMagnitude);
+        assert_ne!(mixed.blocks[1][1].encoding, Encoding::SignMagnitude);
+        assert_eq!(unpack(&mixed)[257..], steady[..]);
+    }
+
+    #[test]
+    fn compare_encodings() {
+        let block_bytes = |packed: &PackedDecimals| -> usize {
+            packed.blocks.iter().flatten().map(|b| b.size_bytes()).sum()
+        };
+        let timestamps: Vec<_> = (0..5000)
+            .map(|i| Decimal::new(1_700_000 + 15 * i, 0))
+            .collect();
+        let sizes = super::compare_encodings(&timestamps);
+        assert_eq!(sizes.len(), 4);
+        assert_eq!(sizes[&Encoding::Xor], block_bytes(&pack(&timestamps)));
+        let raw = pack_with(
+            Packer {
+                raw: true,
+                ..Packer::new()
+            },
+            &timestamps,
+        );
+        assert!(raw
+            .blocks
+            .iter()
+            .flatten()
+            .all(|b| b.encoding == Encoding::Raw));
+        assert_eq!(unpack(&raw), timestamps);
+        assert_eq!(sizes[&Encoding::Raw], block_bytes(&raw));
+        assert_eq!(
+            sizes[&Encoding::SignMagnitude],
+            block_bytes(&pack_with(Packer::new().with_sign_magnitude(), &timestamps))
+        );
+        assert_eq!(
+            sizes[&Encoding::DeltaOfDelta],
+            block_bytes(&pack_sorted(&timestamps).unwrap())
+        );
+        let smallest = sizes.iter().min_by_key(|(_, size)| **size).unwrap();
+        assert_eq!(*smallest.0, Encoding::DeltaOfDelta);
+
+        assert!(super::compare_encodings(&[])
+            .values()
+            .all(|size| *size == 0));
+    }
+
+    #[test]
+    fn sign_magnitude_signs() {
+        let values = vec![
+            dec!(0.01),
+            dec!(-0.01),
+            Decimal::ZERO,
+            -dec!(0.00),
+            dec!(0.00),
+            Decimal::MAX,
+            Decimal::MIN,
+            dec!(-0.000001),
+            -Decimal::ZERO,
+            dec!(5),
+        ];
+        for block_len in [32, 256] {
+            let packer = Packer::new()
+                .with_block_len(block_len)
+                .unwrap()
+                .with_sign_magnitude()
+                .with_chained_heads();
+            let many: Vec<Decimal> = values.iter().cycle().take(700).copied().collect();
+            assert_exact(&unpack(&pack_with(packer, &many)), &many);
+        }
+    }
+
+    #[test]
+    fn hybrid_delta() {
+        // Ticks around a level that jumps over a gap every 150 values, in both directions.
+        let ticks: Vec<Decimal> = (0..1000i64)
+            .map(|i| {
+                let level = [0, 1_000_000_000_000, -7_000_000][(i / 150 % 3) as usize];
+                Decimal::new(level + 5_000_000 + [0, -1, 1, -2, 0, 2][i as usize % 6], 4)
+            })
+            .collect();
+        for packer in [
+            Packer::new().with_sign_magnitude(),
+            Packer::new().with_sign_magnitude().with_chained_heads(),
+            Packer::new()
+                .with_block_len(32)
+                .unwrap()
+                .with_sign_magnitude()
+                .with_adaptive_encoding(),
+        ] {
+            let packed = pack_with(packer, &ticks);
+            assert_eq!(unpack(&packed), ticks);
+            let bytes = packed.to_bytes();
+            assert_eq!(PackedDecimals::from_bytes(&bytes).unwrap(), packed);
+            assert!(bytes.len() * 2 < pack(&ticks).to_bytes().len());
+        }
+
+        let packed = pack_with(Packer::new().with_sign_magnitude(), &ticks);
+        for (n, block) in packed.blocks[1].iter().enumerate() {
+            let jumps = (n * 257 + 1..(n + 1) * 257)
+                .filter(|i| i % 150 == 0 && *i < 1000)
+                .count();
+            if jumps == 0 {
+                assert_eq!(block.encoding, Encoding::SignMagnitude);
+                continue;
+            }
+            // Only the jumps are stored as they are, the ticks in between stay narrow.
+            assert_eq!(block.encoding, Encoding::HybridDelta);
+            assert_eq!(block.signs.len(), 2 * 32 + 12 * jumps);
+            let bits: u8 = (1..4).map(|i| packed.blocks[i][n].bits).sum();
+            assert!(bits <= 3);
+        }
+
+        let mut corrupt = packed.clone();
+        let block = &mut corrupt.blocks[1][0];
+        assert_eq!(block.encoding, Encoding::HybridDelta);
+        block.signs[32] ^= 1;
+        assert!(try_unpack(&corrupt).is_err());
+
+        // Jumps of the whole range of the mantissa.
+        let extremes: Vec<Decimal> = [Decimal::MAX, dec!(1), Decimal::MIN, dec!(0)]
+            .iter()
+            .chain(&ticks[..300])
+            .copied()
+            .collect();
+        let packer = Packer::new().with_sign_magnitude().with_chained_heads();
+        assert_exact(&unpack(&pack_with(packer, &extremes)), &extremes);
+    }
+
+    #[test]
+    fn lane_fn() {
+        fn rotated(d: &Decimal) -> [u32; 4] {
+            zip_u8(d.serialize()).map(|lane| lane.rotate_left(7))
+        }
+        fn unrotated(lanes: [u32; 4]) -> Decimal {
+            Decimal::deserialize(unzip_u8(lanes.map(|lane| lane.rotate_right(7))))
+        }
+        let values = prices(2000);
+        for packer in [
+            Packer::new(),
+            Packer::new().with_sign_magnitude().with_chained_heads(),
+            Packer::new().with_pad_value(dec!(-1)).with_block_bounds(),
+            Packer::new().with_effort(3).with_block_len(32).unwrap(),
+        ] {
+            let mut packer = packer.with_lane_fn(rotated, unrotated);
+            for v in &values[..1900] {
+                packer.load_decimal(v);
+            }
+            let pending = packer.pending_values();
+            assert!(!pending.is_empty());
+            assert_eq!(pending, values[1900 - pending.len()..1900]);
+            for v in &values[1900..] {
+                packer.load_serialized(v.serialize(), true).unwrap();
+            }
+            let greater = values.iter().filter(|v| **v > values[0]).count();
+            assert_eq!(packer.count_greater(values[0]), greater);
+            assert_eq!(packer.count_where(|v| *v > values[0]), greater);
+            let max = values.iter().max().copied();
+            assert_eq!(packer.argmax().map(|(_, v)| v), max);
+            let packed = packer.finish();
+            assert_eq!(packed.type_tag, LANE_FN_TAG);
+            assert_eq!(try_unpack_with_lanes(&packed, unrotated).unwrap(), values);
+            let bytes = packed.to_bytes();
+            let read = PackedDecimals::from_bytes(&bytes).unwrap();
+            assert_eq!(try_unpack_with_lanes(&read, unrotated).unwrap(), values);
+            if let Some((min, max)) = packed.blocks[0][0].bounds {
+                assert_eq!(min, *values[..257].iter().min().unwrap());
+                assert_eq!(max, *values[..257].iter().max().unwrap());
+            }
+            assert!(matches!(
+                try_unpack(&packed),
+                Err(FloatpackError::TypeMismatch { found: 6, .. })
+            ));
+            // Nothing reads the custom layout as the default one, nor re-tags it.
+            assert!(matches!(
+                rechunk(&packed, 32),
+                Err(FloatpackError::TypeMismatch { found: 6, .. })
+            ));
+            assert!(std::panic::catch_unwind(|| packed.get(0)).is_err());
+            assert!(std::panic::catch_unwind(|| packed.argmax()).is_err());
+        }
+        assert!(try_unpack_with_lanes(&pack(&values), unrotated).is_err());
+
+        // The layout is kept when the packer repacks its frames and pads them.
+        let mut packer = Packer::new()
+            .with_pad_value(dec!(0.5))
+            .with_lane_fn(rotated, unrotated)
+            .with_deadband(dec!(0.001))
+            .with_flush_bytes(2000);
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        packer.load_decimal(&(values[1999] + dec!(0.0005)));
+        packer.maybe_compact(&crate::FillRatioBelow(2.0));
+        let frames = packer.finish_frames();
+        assert!(frames.len() > 1);
+        let unpacked: Vec<Decimal> = frames
+            .iter()
+            .flat_map(|f| try_unpack_with_lanes(f, unrotated).unwrap())
+            .collect();
+        let mut stored = values.clone();
+        stored.dedup();
+        assert_eq!(unpacked, stored);
+        let last = frames.last().unwrap();
+        assert!(!last.len().is_multiple_of(last.block_values()));
+        let mut blocks = Blocks::new(last);
+        let mut padding = blocks.decode_next().unwrap().0;
+        while let Some((lanes, _)) = blocks.decode_next() {
+            padding = lanes;
+        }
+        assert_eq!(padding[1][last.block_len], rotated(&dec!(0.5))[1]);
+    }
+
+    #[test]
+    fn byte_split() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        // Noise in the lowest and highest byte of the mantissa, mixed signs and scales.
+        let noisy: Vec<Decimal> = (0..1000)
+            .map(|_| {
+                let mantissa = (rng.gen::<u8>() as i64) << 24 | rng.gen::<u8>() as i64;
+                let sign = if rng.gen() { -1 } else { 1 };
+                Decimal::new(sign * mantissa, rng.gen_range(0..=4))
+            })
+            .collect();
+        let split = pack_with(Packer::new().with_byte_split(), &noisy);
+        for lane in &split.blocks[..2] {
+            assert!(lane
+                .iter()
+                .all(|b| b.encoding == Encoding::ByteSplit && b.bits == 16));
+        }
+        assert!(split.blocks[2..].iter().flatten().all(|b| b.bits == 0));
+        let xor = pack(&noisy);
+        let adaptive = pack_with(Packer::new().with_adaptive_encoding(), &noisy);
+        // Two byte planes per value instead of 32 bit XOR deltas in lanes 0 and 1.
+        for (split, xor) in split.blocks[..2]
+            .iter()
+            .flatten()
+            .zip(xor.blocks.iter().flatten())
+        {
+            assert_eq!(2 * split.vals.len(), xor.vals.len());
+        }
+        assert!(split.serialized_len() < xor.serialized_len() * 11 / 20);
+        assert!(split.serialized_len() < adaptive.serialized_len());
+        assert_exact(&unpack(&split), &noisy);
+        assert_exact(&try_unpack(&split).unwrap(), &noisy);
+        assert_exact(&split.iter().collect::<Vec<_>>(), &noisy);
+        let mut reversed = unpack_rev(&split);
+        reversed.reverse();
+        assert_exact(&reversed, &noisy);
+        assert_eq!(split.get(999), Some(noisy[999]));
+        let decoded = PackedDecimals::from_bytes(&split.to_bytes()).unwrap();
+        assert_eq!(decoded, split);
+
+        // Smooth values pack smaller as XOR deltas, so byte planes aren't picked.
+        let smooth = prices(1000);
+        let packed = pack_with(Packer::new().with_byte_split(), &smooth);
+        assert!(bytes_equal(&packed, &pack(&smooth)));
+
+        let mixed = [&noisy[..], &smooth[..], &noisy[..]].concat();
+        for packer in [
+            Packer::new().with_byte_split().with_adaptive_encoding(),
+            Packer::new().with_byte_split().with_sign_magnitude(),
+            Packer::new()
+                .with_byte_split()
+                .with_chained_heads()
+                .with_block_len(32)
+                .unwrap(),
+        ] {
+            let packed = pack_with(packer, &mixed);
+            assert!(packed
+                .blocks
+                .iter()
+                .flatten()
+                .any(|b| b.encoding == Encoding::ByteSplit));
+            assert_exact(&unpack(&packed), &mixed);
+            let decoded = PackedDecimals::from_bytes(&packed.to_bytes()).unwrap();
+            assert_exact(&unpack(&decoded), &mixed);
+            assert_exact(&unpack(&rechunk(&packed, 128).unwrap()), &mixed);
+        }
+
+        let mut corrupt = split.clone();
+        corrupt.blocks[1][0].signs[0] = 0b111;
+        assert!(corrupt.verify().is_err());
+    }
+
+    #[test]
+    fn effort() {
+        let noisy: Vec<Decimal> = (0..1000i64)
+            .map(|i| Decimal::new((i * 7919 % 251) << 24 | (i * 104_729 % 241), (i % 5) as u32))
+            .collect();
+        let ticks: Vec<Decimal> = (0..1000i64)
+            .map(|i| Decimal::new(100_000 + [3, -2, 1, -4][i as usize % 4] * (i % 7), 2))
+            .collect();
+        let values = [&noisy[..], &prices(1000)[..], &ticks[..]].concat();
+        let sizes: Vec<_> = (0..=4)
+            .map(|effort| {
+                let packed = pack_with(Packer::new().with_effort(effort), &values);
+                assert_exact(&unpack(&packed), &values);
+                packed.serialized_len()
+            })
+            .collect();
+        assert_eq!(sizes[0], pack(&values).serialized_len());
+        assert!(sizes.windows(2).all(|w| w[1] <= w[0]), "{:?}", sizes);
+        assert!(sizes[3] < sizes[1], "{:?}", sizes);
+        assert_eq!(sizes[4], sizes[3]);
+
+        // Lowering the effort turns the encodings off again.
+        let packer = Packer::new().with_effort(3).with_effort(0);
+        assert!(bytes_equal(&pack_with(packer, &values), &pack(&values)));
+    }
+
+    #[test]
+    fn load_serialized() {
+        let values = prices(1000);
+        let serialized: Vec<[u8; 16]> = values.iter().map(Decimal::serialize).collect();
+        for packer in [
+            Packer::new,
+            || Packer::new().with_adaptive_encoding().with_chained_heads(),
+            || Packer::new().with_deadband(dec!(0.3)).with_block_bounds(),
+        ] {
+            let expected = {
+                let mut p = packer();
+                for bytes in &serialized {
+                    p.load_decimal(&Decimal::deserialize(*bytes));
+                }
+                p.finish()
+            };
+            let mut one = packer();
+            for bytes in &serialized {
+                one.load_serialized(*bytes, true).unwrap();
+            }
+            assert!(bytes_equal(&one.finish(), &expected));
+            let mut batch = packer();
+            assert_eq!(
+                batch.load_serialized_slice(&serialized, false).unwrap(),
+                1000
+            );
+            assert!(bytes_equal(&batch.finish(), &expected));
+        }
+
+        let mut corrupt = serialized.clone();
+        corrupt[10][0] = 1;
+        corrupt[20][2] = 29;
+        let mut packer = Packer::new();
+        assert!(matches!(
+            packer.load_serialized(corrupt[10], true),
+            Err(FloatpackError::Corrupt(_))
+        ));
+        assert!(packer.load_serialized(corrupt[20], true).is_err());
+        assert!(matches!(
+            packer.load_serialized_slice(&corrupt, true),
+            Err(FloatpackError::Row { row: 10, .. })
+        ));
+        assert_eq!(unpack(&packer.finish()), values[..10]);
+
+        // Without validation, the flags are packed as they are.
+        let mut packer = Packer::new();
+        packer.load_serialized_slice(&corrupt, false).unwrap();
+        let packed = packer.finish();
+        assert!(try_unpack(&packed).is_ok());
+        assert!(crate::try_unpack_as::<Decimal, 4>(&packed).is_err());
+    }
+
+    #[test]
+    fn negative_zero() {
+        // `Decimal::from_parts` clears the sign of zero, negating keeps it.
+        assert!(!Decimal::from_parts(0, 0, 0, true, 2).is_sign_negative());
+        let negative_zero = -dec!(0.00);
+        assert!(negative_zero.is_sign_negative());
+        let values: Vec<Decimal> = (0..600)
+            .map(|i| match i % 4 {
+                0 => negative_zero,
+                1 => dec!(0.00),
+                2 => -Decimal::ZERO,
+                _ => Decimal::new(i, 2),
+            })
+            .collect();
+        for packer in [
+            Packer::new(),
+            Packer::new().with_chained_heads(),
+            Packer::new().with_adaptive_encoding(),
+            Packer::new().with_sign_magnitude(),
+            Packer::new().with_block_bounds(),
+            Packer::new().with_block_len(32).unwrap(),
+        ] {
+            let packed = pack_with(packer, &values);
+            assert_exact(&unpack(&packed), &values);
+            assert_exact(&try_unpack(&packed).unwrap(), &values);
+            assert_exact(&packed.iter().collect::<Vec<_>>(), &values);
+            assert_exact(&[packed.get(0).unwrap()], &[negative_zero]);
+            let mut reversed = unpack_rev(&packed);
+            reversed.reverse();
+            assert_exact(&reversed, &values);
+            let decoded = PackedDecimals::from_bytes(&packed.to_bytes()).unwrap();
+            assert_exact(&unpack(&decoded), &values);
+        }
+
+        let sorted = [negative_zero, dec!(0.00), negative_zero, dec!(0.01)];
+        assert_exact(&unpack(&pack_sorted(&sorted).unwrap()), &sorted);
+    }
+
+    // The unchecked paths are meant to be run under Miri as well: `cargo +nightly miri test`.
+    #[test]
+    fn unchecked() {
+        let values = prices(1000);
+        for packer in [
+            Packer::new(),
+            Packer::new().with_chained_heads(),
+            Packer::new().with_sign_magnitude().with_adaptive_encoding(),
+            Packer::new().with_block_len(128).unwrap(),
+        ] {
+            let packed = pack_with(packer, &values);
+            assert!(packed.verify().is_ok());
+            assert_eq!(try_unpack(&packed).unwrap(), values);
+            assert_eq!(unsafe { unpack_unchecked(&packed) }, values);
+            for i in [0, 256, 257, 700, 999] {
+                assert_eq!(packed.get(i), Some(values[i]));
+                assert_eq!(unsafe { packed.get_unchecked(i) }, values[i]);
+            }
+            assert_eq!(packed.get(1000), None);
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let mut packed = pack(&prices(1000));
+        packed.blocks[2].pop();
+        assert!(packed.verify().is_err());
+        assert!(try_unpack(&packed).is_err());
+        assert_eq!(packed.get(0), None);
+
+        let mut packed = pack(&prices(1000));
+        packed.blocks[1][1].vals.clear();
+        assert!(try_unpack(&packed).is_err());
+        assert!(packed.get(0).is_some());
+        assert_eq!(packed.get(300), None);
+
+        let mut packed = pack(&prices(1000));
+        packed.blocks[2][3].vals.push(0);
+        match packed.verify() {
+            Err(FloatpackError::BlockDecode { lane, block, .. }) => {
+                assert_eq!((lane, block), (2, 3))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn delta_from_head() {
+        let mut packer = Packer::new();
+        assert_eq!(packer.delta_from_head(&dec!(1.5)), [0; 4]);
+        packer.load_decimal(&dec!(1.0));
+        packer.load_decimal(&dec!(7.0));
+        assert_eq!(packer.delta_from_head(&dec!(1.0)), [0; 4]);
+        assert_eq!(packer.delta_from_head(&dec!(1.5)), [0, 10 ^ 15, 0, 0]);
+        assert_eq!(packer.delta_from_head(&dec!(-1.0)), [1 << 31, 0, 0, 0]);
+        assert_eq!(unpack(&packer.finish()), vec![dec!(1.0), dec!(7.0)]);
+    }
+
+    #[test]
+    fn shared_bitpacker() {
+        let bitpacker = BitPacker8x::new();
+        let values = prices(1000);
+        let packed = pack_with_bitpacker(&values, &bitpacker);
+        assert!(bytes_equal(&packed, &pack(&values)));
+        assert_eq!(unpack_with_bitpacker(&packed, &bitpacker), values);
+        let short = pack_with(Packer::new().with_block_len(32).unwrap(), &values);
+        assert_eq!(unpack_with_bitpacker(&short, &bitpacker), values);
+        let packer = Packer::new().with_block_len(32).unwrap();
+        let packed = pack_with(packer.with_bitpacker(bitpacker), &values);
+        assert_eq!(packed.block_len, 256);
+        assert_eq!(unpack(&packed), values);
+    }
+
+    #[test]
+    fn pending_values() {
+        let values = prices(1000);
+        let mut packer = Packer::new().with_flush_bytes(500);
+        assert!(packer.pending_values().is_empty());
+        for (n, v) in values.iter().enumerate() {
+            packer.load_decimal(v);
+            if [0, 1, 255, 256, 257, 400, 999].contains(&n) {
+                let mut unloaded: Vec<Decimal> = packer.frames.iter().flat_map(unpack).collect();
+                unloaded.extend(unpack(&packer.packed));
+                unloaded.extend(packer.pending_values());
+                assert_eq!(unloaded, values[..=n]);
+            }
+        }
+        packer.pack();
+        assert!(packer.pending_values().is_empty());
+    }
+
+    #[test]
+    fn reverse() {
+        let values = prices(1000);
+        let mut reversed = values.clone();
+        reversed.reverse();
+        for packer in [
+            Packer::new(),
+            Packer::new().with_chained_heads(),
+            Packer::new()
+                .with_sign_magnitude()
+                .with_block_len(32)
+                .unwrap(),
+        ] {
+            assert_eq!(unpack_rev(&pack_with(packer, &values)), reversed);
+        }
+        assert!(unpack_rev(&pack(&[])).is_empty());
+    }
+
+    #[test]
+    fn lenient() {
+        let values = prices(1000);
+        let mut packed = pack(&values);
+        assert_eq!(unpack_lenient(&packed), (values.clone(), vec![]));
+        packed.blocks[1][1].vals.pop();
+        let mut salvaged = values[..257].to_vec();
+        salvaged.extend_from_slice(&values[514..]);
+        assert_eq!(unpack_lenient(&packed), (salvaged.clone(), vec![1]));
+        packed.blocks[3].pop();
+        salvaged.truncate(514);
+        assert_eq!(unpack_lenient(&packed), (salvaged, vec![1, 3]));
+
+        let mut chained = pack_with(Packer::new().with_chained_heads(), &values);
+        chained.blocks[0][2].bits = 40;
+        assert_eq!(
+            unpack_lenient(&chained),
+            (values[..514].to_vec(), vec![2, 3])
+        );
+
+        // Blocks past a count that is too small hold no values, those of one too large are
+        // full, padding included.
+        let values = prices(3000);
+        let mut packed = pack(&values);
+        packed.count = 10;
+        assert_eq!(unpack_lenient(&packed), (values[..10].to_vec(), vec![]));
+        packed.count = 5000;
+        let (lenient, skipped) = unpack_lenient(&packed);
+        assert!(skipped.is_empty());
+        assert_eq!(lenient.len(), packed.blocks[0].len() * 257);
+        assert_eq!(lenient[..3000], values[..]);
+    }
+
+    #[test]
+    fn pad_value() {
+        let values = prices(300);
+        let pad = dec!(-99.999);
+        let packed = pack_with(Packer::new().with_pad_value(pad), &values);
+        assert_eq!(unpack(&packed), values);
+        let tail = &packed.blocks[1][1];
+        let lane = decode::decode_lane(&AnyBitPacker::new(256).unwrap(), tail, 0);
+        let (real, padding) = lane.split_at(300 - 257);
+        assert_eq!(real.last(), Some(&zip_u8(values[299].serialize())[1]));
+        assert!(padding.iter().all(|v| *v == zip_u8(pad.serialize())[1]));
+
+        let packer = Packer::new()
+            .with_pad_value(pad)
+            .with_chained_heads()
+            .with_sign_magnitude()
+            .with_flush_bytes(300);
+        let frames = {
+            let mut packer = packer;
+            for v in &values {
+                packer.load_decimal(v);
+            }
+            packer.finish_frames()
+        };
+        assert!(frames.len() > 1);
+        assert_eq!(frames.iter().flat_map(unpack).collect::<Vec<_>>(), values);
+    }
+
+    #[test]
+    fn sorted() {
+        let timestamps: Vec<Decimal> = (0..1_000_000)
+            .map(|i| Decimal::new(1_600_000_000_000 + i * 250, 3))
+            .collect();
+        let packed = pack_sorted(&timestamps).unwrap();
+        assert_eq!(unpack(&packed), timestamps);
+        let size = packed.to_bytes().len();
+        assert!(size * 5 < pack(&timestamps).to_bytes().len());
+        assert!(size < timestamps.len() / 4);
+        assert!(packed.blocks[1].iter().all(|b| b.bits == 0));
+
+        let mut gaps = timestamps[..5000].to_vec();
+        for (i, v) in gaps.iter_mut().enumerate() {
+            *v += Decimal::new((i as i64 / 700) * 17 + (i as i64 % 3), 3);
+        }
+        gaps.push(Decimal::MAX);
+        gaps.insert(0, Decimal::MIN);
+        gaps.insert(1, dec!(-1.5));
+        let packed = pack_sorted(&gaps).unwrap();
+        assert_exact(&unpack(&packed), &gaps);
+        assert_eq!(
+            PackedDecimals::from_bytes(&packed.to_bytes()).unwrap(),
+            packed
+        );
+        assert!(pack_sorted(&[]).unwrap().is_empty());
+        assert_eq!(unpack(&pack_sorted(&[dec!(1)]).unwrap()), vec![dec!(1)]);
+
+        match pack_sorted(&[dec!(1), dec!(2), dec!(1.5)]) {
+            Err(FloatpackError::Unsorted(2)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rechunk_block_len() {
+        let values = prices(3000);
+        let short = pack_with(Packer::new().with_block_len(32).unwrap(), &values);
+        assert_eq!(short.blocks[0].len(), 91);
+        let long = rechunk(&short, 256).unwrap();
+        assert_eq!(long.blocks[0].len(), 12);
+        assert!(bytes_equal(&long, &pack(&values)));
+        let back = rechunk(&long, 32).unwrap();
+        assert!(bytes_equal(&back, &short));
+        assert_eq!(unpack(&back), values);
+
+        let chained = pack_with(Packer::new().with_chained_heads(), &values);
+        let rechunked = rechunk(&chained, 128).unwrap();
+        assert!(rechunked.chained());
+        assert_eq!(unpack(&rechunked), values);
+        let sorted = rechunk(&pack_sorted(&values[..1]).unwrap(), 32).unwrap();
+        assert_eq!(sorted.blocks[1][0].encoding, Encoding::DeltaOfDelta);
+
+        match rechunk(&short, 100) {
+            Err(FloatpackError::UnsupportedBlockLen(100)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn prepending() {
+        let values = prices(4 * BLOCK_VALUES + 100);
+        let packers: [fn() -> Packer; 3] = [
+            Packer::new,
+            || Packer::new().with_chained_heads(),
+            || {
+                Packer::new()
+                    .with_block_len(32)
+                    .unwrap()
+                    .with_block_bounds()
+            },
+        ];
+        for packer in packers {
+            // Whole blocks are put in front of the existing ones.
+            let whole = 2 * pack_with(packer(), &values[..1]).block_values();
+            let original = pack_with(packer(), &values[whole..]);
+            let mut packed = original.clone();
+            prepend(&mut packed, &values[..whole]);
+            assert!(packed.verify().is_ok());
+            assert_eq!(unpack(&packed), values);
+            assert_eq!(packed.blocks[0][2..], original.blocks[0][..]);
+
+            let mut packed = pack_with(packer(), &values[10..]);
+            prepend(&mut packed, &values[..10]);
+            assert_eq!(packed, pack_with(packer(), &values));
+        }
+
+        let mut packed = pack(&[]);
+        prepend(&mut packed, &values[..10]);
+        assert_eq!(unpack(&packed), values[..10]);
+        prepend(&mut packed, &[]);
+        assert_eq!(unpack(&packed), values[..10]);
+    }
+
+    #[test]
+    fn partitioning() {
+        let values = prices(3 * BLOCK_VALUES + 50);
+        // Market hours of 100 values, then 60 after hours.
+        let mask: Vec<bool> = (0..values.len()).map(|i| i % 160 < 100).collect();
+        let select = |keep: bool| -> Vec<Decimal> {
+            let masked = values.iter().zip(&mask).filter(|(_, &m)| m == keep);
+            masked.map(|(v, _)| *v).collect()
+        };
+        let packers: [fn() -> Packer; 3] = [
+            Packer::new,
+            || Packer::new().with_chained_heads().with_block_bounds(),
+            || Packer::new().with_block_len(32).unwrap(),
+        ];
+        for packer in packers {
+            let (open, closed) = partition(&pack_with(packer(), &values), &mask);
+            assert_eq!(open, pack_with(packer(), &select(true)));
+            assert_eq!(closed, pack_with(packer(), &select(false)));
+        }
+
+        let packed = pack(&values);
+        let (all, none) = partition(&packed, &vec![true; values.len()]);
+        assert_eq!(all, packed);
+        assert!(none.is_empty());
+        let (none, all) = partition(&pack(&[]), &[]);
+        assert!(none.is_empty() && all.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "mask and values differ in length")]
+    fn partitioning_short_mask() {
+        partition(&pack(&prices(10)), &[true; 9]);
+    }
+
+    #[test]
+    fn block_values() {
+        let values = prices(2 * BLOCK_VALUES + 1);
+        assert_eq!(pack(&values[..BLOCK_VALUES]).blocks[0].len(), 1);
+        assert_eq!(pack(&values).blocks[0].len(), 3);
+        let short = pack_with(Packer::new().with_block_len(32).unwrap(), &values);
+        assert_eq!(short.block_values(), 33);
+        assert_eq!(short.blocks[0].len(), values.len().div_ceil(33));
+    }
+
+    #[test]
+    fn reserve_from_sample() {
+        let values = prices(20 * BLOCK_VALUES);
+        let mut packer = Packer::new().with_chained_heads();
+        let estimate = packer.reserve_from_sample(&values[..1000], values.len());
+        let capacity = packer.packed.blocks[0].capacity();
+        assert_eq!(capacity, 20);
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        assert_eq!(packer.packed.blocks[0].capacity(), capacity);
+        let packed = packer.finish();
+        let actual = packed.serialized_len() as f64;
+        assert!(
+            (estimate as f64 - actual).abs() < 0.2 * actual,
+            "{} {}",
+            estimate,
+            actual
+        );
+
+        let mut packer = Packer::new().with_flush_bytes(2048);
+        let estimate = packer.reserve_from_sample(&values[..1000], values.len());
+        let frame_blocks = packer.packed.blocks[0].capacity();
+        assert!(frame_blocks > 1 && frame_blocks < 20);
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        let frames = packer.finish_frames();
+        assert!(frames.iter().all(|f| f.blocks[0].len() <= frame_blocks));
+        let actual: usize = frames.iter().map(|f| f.serialized_len()).sum();
+        assert!(
+            estimate.abs_diff(actual) < actual / 2,
+            "{} {}",
+            estimate,
+            actual
+        );
+
+        let mut packer = Packer::new();
+        assert_eq!(packer.reserve_from_sample(&[], 1000), 0);
+        assert_eq!(packer.packed.blocks[0].capacity(), 4);
+        assert_eq!(packer.reserve_from_sample(&values, 0), 0);
+    }
+
+    #[test]
+    fn compact() {
+        let values = prices(1000);
+        let mut packer = Packer::new();
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        assert!(packer.compact() > 0);
+        assert_eq!(packer.compact(), 0);
+        assert!(packer.packed.blocks.iter().all(|l| l.capacity() == l.len()));
+        packer.load_decimal(&values[0]);
+        let frames = packer.finish_frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(unpack(&frames[0]), values);
+        assert_eq!(unpack(&frames[1]), values[..1]);
+
+        let mut packer = Packer::new();
+        for v in &values[..BLOCK_VALUES] {
+            packer.load_decimal(v);
+        }
+        packer.compact();
+        packer.load_decimal(&values[0]);
+        assert_eq!(packer.finish_frames().len(), 1);
+    }
+
+    #[test]
+    fn fixed_scale() {
+        let mantissas: Vec<i128> = (0..1000).map(|i| 12_345 + (i * 7919) % 101 - 50).collect();
+        let packed = pack_fixed_scale(&mantissas, 2);
+        assert_eq!(unpack(&packed)[0], dec!(123.45) + Decimal::new(-50, 2));
+        assert_eq!(unpack_fixed_scale(&packed), Some((mantissas.clone(), 2)));
+        assert!(packed.blocks[0].iter().all(|b| b.bits == 0));
+
+        let signed = [-5, 0, 7, -(1 << 95) + 1, (1 << 95) - 1];
+        assert_eq!(
+            unpack_fixed_scale(&pack_fixed_scale(&signed, 28)),
+            Some((signed.to_vec(), 28))
+        );
+        assert_eq!(unpack_fixed_scale(&pack(&[])), Some((Vec::new(), 0)));
+        assert_eq!(unpack_fixed_scale(&pack(&[dec!(1.5), dec!(1.25)])), None);
+    }
+
+    #[test]
+    fn minor_units() {
+        let cents: Vec<i64> = (0..1000)
+            .map(|i| 12_345 + (i * 7919) % 1001 - 500)
+            .collect();
+        let values: Vec<Decimal> = cents.iter().map(|&c| Decimal::new(c, 2)).collect();
+        for packed in [
+            pack(&values),
+            pack_with(Packer::new().with_chained_heads(), &values),
+        ] {
+            assert_eq!(unpack_minor_units(&packed, 2).unwrap(), cents);
+            let mills: Vec<i64> = cents.iter().map(|c| c * 10).collect();
+            assert_eq!(unpack_minor_units(&packed, 3).unwrap(), mills);
+        }
+
+        // Trailing zeros and zeros of any scale are exact.
+        let mixed = pack(&[dec!(7), dec!(-0.50), dec!(0.0000), dec!(1.2300000)]);
+        assert_eq!(unpack_minor_units(&mixed, 2).unwrap(), [700, -50, 0, 123]);
+        assert_eq!(
+            unpack_minor_units(&mixed, 0).unwrap_err().to_string(),
+            "value -0.50 is out of range"
+        );
+
+        let max = pack(&[Decimal::from(i64::MAX), Decimal::from(i64::MIN)]);
+        assert_eq!(unpack_minor_units(&max, 0).unwrap(), [i64::MAX, i64::MIN]);
+        assert!(matches!(
+            unpack_minor_units(&max, 1),
+            Err(FloatpackError::OutOfRange(v)) if v == Decimal::from(i64::MAX)
+        ));
+        let huge = pack(&[Decimal::MAX]);
+        assert!(unpack_minor_units(&huge, 40).is_err());
+        let zeros = pack(&[Decimal::ZERO, dec!(-0.000)]);
+        assert_eq!(unpack_minor_units(&zeros, 40).unwrap(), [0, 0]);
+        assert_eq!(unpack_minor_units(&zeros, u32::MAX).unwrap(), [0, 0]);
+        let tiny = pack(&[Decimal::new(1, 28)]);
+        assert_eq!(unpack_minor_units(&tiny, 46).unwrap(), [10i64.pow(18)]);
+        assert!(unpack_minor_units(&tiny, 47).is_err());
+        assert!(unpack_minor_units(&pack(&[]), 2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn probing() {
+        let mixed: Vec<Decimal> = (0..1000i64)
+            .map(|i| Decimal::new(i * 7919 % 2003 - 1000, (i % 3) as u32))
+            .collect();
+        let inputs = [
+            prices(2000),
+            prices(BLOCK_VALUES),
+            prices(BLOCK_VALUES + 1),
+            mixed,
+            vec![Decimal::MAX, Decimal::MIN, Decimal::ZERO],
+            vec![dec!(1.5)],
+            Vec::new(),
+        ];
+        for values in &inputs {
+            let packed = pack(values);
+            assert_eq!(probe(values), (values.len(), packed.serialized_len()));
+            assert_eq!(probe(values).1, packed.to_bytes().len());
+        }
+    }
+
+    #[test]
+    fn zero_runs() {
+        let zeros = vec![Decimal::ZERO; BLOCK_VALUES];
+        let packed = pack(&zeros);
+        assert_eq!(packed.blocks[0].len(), 1);
+        for block in packed.blocks.iter().flatten() {
+            assert_eq!((block.bits, block.head), (0, 0));
+            assert!(block.vals.is_empty() && block.signs.is_empty());
+        }
+        // The header, the fixed flags and 3 lanes of a block with nothing but its head
+        assert_eq!(packed.to_bytes().len(), 24 + 4 + 3 * 6);
+        let decoded = unpack(&PackedDecimals::from_bytes(&packed.to_bytes()).unwrap());
+        assert_exact(&decoded, &zeros);
+
+        // The padding of a partial block is trimmed.
+        assert_exact(&unpack(&pack(&zeros[..100])), &zeros[..100]);
+
+        // Zeros of different scales and signs differ in lane 0, until normalized.
+        let mixed: Vec<Decimal> = (0..2 * BLOCK_VALUES)
+            .map(|i| match i % 3 {
+                0 => Decimal::ZERO,
+                1 => dec!(0.00),
+                _ => -Decimal::ZERO,
+            })
+            .collect();
+        let packed = pack(&mixed);
+        assert!(packed.blocks[0].iter().all(|b| b.bits > 0));
+        assert!(packed.blocks[1..].iter().flatten().all(|b| b.bits == 0));
+        assert_exact(&unpack(&packed), &mixed);
+        let normalized: Vec<Decimal> = mixed.iter().map(Decimal::normalize).collect();
+        let packed = pack(&normalized);
+        assert!(packed.blocks.iter().flatten().all(|b| b.bits == 0));
+        assert_exact(&unpack(&packed), &[Decimal::ZERO; 2 * BLOCK_VALUES]);
+    }
 }
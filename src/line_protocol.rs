@@ -0,0 +1,228 @@
+//! Ingestion of [InfluxDB line protocol](https://docs.influxdata.com/influxdb/v2/reference/syntax/line-protocol/).
+use crate::{FloatpackError, Packer};
+use rust_decimal::Decimal;
+use std::io::BufRead;
+use std::str::FromStr;
+
+/// Outcome of [`Packer::load_line_protocol_batch`].
+#[derive(Debug, Default)]
+pub struct LineProtocolSummary {
+    /// Count of values loaded into the packer.
+    pub loaded: usize,
+    /// Count of lines that were empty, comments or didn't contain the field.
+    pub skipped: usize,
+    /// Lines that failed to parse, as (1-based line number, error).
+    pub errors: Vec<(usize, FloatpackError)>,
+}
+
+impl Packer {
+    /// Parse a line of InfluxDB line protocol and load the value of `field` from it.
+    ///
+    /// Returns `Ok(false)` if the line is empty, a comment or doesn't contain `field`.
+    /// Float fields and integer fields (with `i` or `u` suffix) are loaded as `Decimal`.
+    /// String and boolean fields are skipped while looking for `field`, but naming one of them as
+    /// `field` is an error, as is a malformed line.
+    pub fn load_line_protocol(&mut self, line: &str, field: &str) -> Result<bool, FloatpackError> {
+        match field_value(line, field)? {
+            Some(value) => {
+                self.load_decimal(&value);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Load `field` from every line read from `reader`.
+    ///
+    /// Lines that fail to parse are reported in the summary instead of aborting the batch;
+    /// only failing to read from `reader` returns an error.
+    pub fn load_line_protocol_batch<R: BufRead>(
+        &mut self,
+        reader: R,
+        field: &str,
+    ) -> Result<LineProtocolSummary, FloatpackError> {
+        let mut summary = LineProtocolSummary::default();
+        for (n, line) in reader.lines().enumerate() {
+            match self.load_line_protocol(&line?, field) {
+                Ok(true) => summary.loaded += 1,
+                Ok(false) => summary.skipped += 1,
+                Err(e) => summary.errors.push((n + 1, e)),
+            }
+        }
+        Ok(summary)
+    }
+}
+
+fn invalid(reason: String) -> FloatpackError {
+    FloatpackError::LineProtocol(reason)
+}
+
+/// Index of the first unescaped byte in `stop`, or the end of `bytes`.
+fn scan(bytes: &[u8], mut pos: usize, stop: &[u8]) -> usize {
+    while pos < bytes.len() && !stop.contains(&bytes[pos]) {
+        pos += if bytes[pos] == b'\\' { 2 } else { 1 };
+    }
+    pos.min(bytes.len())
+}
+
+fn unescape(key: &str) -> String {
+    let mut result = String::with_capacity(key.len());
+    let mut chars = key.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(next @ (',' | '=' | ' '))) => {
+                result.push(next);
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn field_value(line: &str, field: &str) -> Result<Option<Decimal>, FloatpackError> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    if line.trim().is_empty() || line.trim_start().starts_with('#') {
+        return Ok(None);
+    }
+    let bytes = line.as_bytes();
+
+    let mut pos = scan(bytes, 0, b" ");
+    if pos == 0 || bytes[0] == b',' {
+        return Err(invalid("missing measurement".into()));
+    }
+    if pos == bytes.len() {
+        return Err(invalid("missing field set".into()));
+    }
+    pos += 1;
+
+    let mut found = None;
+    loop {
+        let key_end = scan(bytes, pos, b"=, ");
+        if key_end == pos || bytes.get(key_end) != Some(&b'=') {
+            return Err(invalid(format!("malformed field at column {}", pos + 1)));
+        }
+        let key = unescape(&line[pos..key_end]);
+        let start = key_end + 1;
+        pos = if bytes.get(start) == Some(&b'"') {
+            let end = scan(bytes, start + 1, b"\"");
+            if end == bytes.len() {
+                return Err(invalid(format!("unterminated string in field `{}`", key)));
+            }
+            end + 1
+        } else {
+            scan(bytes, start, b", ")
+        };
+        if key == field && found.is_none() {
+            found = Some(parse_value(&key, &line[start..pos])?);
+        }
+        match bytes.get(pos) {
+            Some(b',') => pos += 1,
+            Some(b' ') => {
+                pos += 1;
+                break;
+            }
+            Some(_) => return Err(invalid(format!("unexpected `\"` in field `{}`", key))),
+            None => break,
+        }
+    }
+
+    let timestamp = line[pos.min(line.len())..].trim();
+    if !timestamp.is_empty() && i64::from_str(timestamp).is_err() {
+        return Err(invalid(format!("invalid timestamp `{}`", timestamp)));
+    }
+    Ok(found)
+}
+
+fn parse_value(key: &str, raw: &str) -> Result<Decimal, FloatpackError> {
+    let not_numeric = |kind: &str| invalid(format!("field `{}` is a {}", key, kind));
+    if raw.starts_with('"') {
+        return Err(not_numeric("string"));
+    }
+    if let "t" | "T" | "true" | "True" | "TRUE" | "f" | "F" | "false" | "False" | "FALSE" = raw {
+        return Err(not_numeric("boolean"));
+    }
+    let parsed = if let Some(int) = raw.strip_suffix('i') {
+        i64::from_str(int).ok().map(Decimal::from)
+    } else if let Some(uint) = raw.strip_suffix('u') {
+        u64::from_str(uint).ok().map(Decimal::from)
+    } else {
+        Decimal::from_str(raw)
+            .or_else(|_| Decimal::from_scientific(raw))
+            .ok()
+    };
+    parsed.ok_or_else(|| invalid(format!("invalid value `{}` for field `{}`", raw, key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{unpack, FloatpackError, Packer};
+    use rust_decimal_macros::*;
+
+    fn load(line: &str, field: &str) -> Result<Option<rust_decimal::Decimal>, FloatpackError> {
+        let mut packer = Packer::new();
+        let loaded = packer.load_line_protocol(line, field)?;
+        let values = unpack(&packer.finish());
+        assert_eq!(loaded, !values.is_empty());
+        Ok(values.first().copied())
+    }
+
+    #[test]
+    fn fields() {
+        let line = "cpu,host=a usage=0.64,count=42i,free=7u,ratio=1.5e2 1434055562000000000";
+        assert_eq!(load(line, "usage").unwrap(), Some(dec!(0.64)));
+        assert_eq!(load(line, "count").unwrap(), Some(dec!(42)));
+        assert_eq!(load(line, "free").unwrap(), Some(dec!(7)));
+        assert_eq!(load(line, "ratio").unwrap(), Some(dec!(150)));
+        assert_eq!(load("cpu usage=-1", "usage").unwrap(), Some(dec!(-1)));
+    }
+
+    #[test]
+    fn escaped_tags() {
+        let line = r"cpu\ load,host=server\ 01,region=us\,west,k\=ey=v usage=2.5 1";
+        assert_eq!(load(line, "usage").unwrap(), Some(dec!(2.5)));
+        let line = r"cpu,host=a\ b\,c usage\ pct=3.5,usage=1";
+        assert_eq!(load(line, "usage pct").unwrap(), Some(dec!(3.5)));
+    }
+
+    #[test]
+    fn skips_strings() {
+        let line = r#"log,host=a msg="hello, world usage=9",usage=1.25,ok=true 1"#;
+        assert_eq!(load(line, "usage").unwrap(), Some(dec!(1.25)));
+        assert!(load(line, "msg").is_err());
+        assert!(load(line, "ok").is_err());
+        let line = r#"log msg="say \"hi\", ok",usage=2"#;
+        assert_eq!(load(line, "usage").unwrap(), Some(dec!(2)));
+    }
+
+    #[test]
+    fn missing_field() {
+        assert_eq!(load("cpu,host=a usage=1 1", "temp").unwrap(), None);
+        assert_eq!(load("", "temp").unwrap(), None);
+        assert_eq!(load("# comment", "temp").unwrap(), None);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(load("cpu", "usage").is_err());
+        assert!(load(",host=a usage=1", "usage").is_err());
+        assert!(load("cpu usage", "usage").is_err());
+        assert!(load("cpu usage=abc", "usage").is_err());
+        assert!(load("cpu usage=1 notatime", "usage").is_err());
+        assert!(load(r#"cpu msg="open,usage=1"#, "usage").is_err());
+    }
+
+    #[test]
+    fn batch() {
+        let input = "cpu usage=1\ncpu usage=2 10\n\ncpu temp=3\ncpu usage=x\ncpu usage=4i\n";
+        let mut packer = Packer::new();
+        let summary = packer
+            .load_line_protocol_batch(input.as_bytes(), "usage")
+            .unwrap();
+        assert_eq!(summary.loaded, 3);
+        assert_eq!(summary.skipped, 2);
+        assert_eq!(summary.errors.len(), 1);
+        assert_eq!(summary.errors[0].0, 5);
+        assert_eq!(unpack(&packer.finish()), vec![dec!(1), dec!(2), dec!(4)]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Deriving packs from the values of others.
+use crate::decode::Blocks;
+use crate::{repacker, PackedDecimals};
+use rust_decimal::Decimal;
+use std::convert::Infallible;
+
+impl PackedDecimals {
+    /// Pack `f` of every value, in order.
+    ///
+    /// The result is packed with the options of this pack as [`crate::rechunk`] keeps them,
+    /// keeping its block length. Values are decoded one block at a time and packed as they are
+    /// mapped, so besides the result memory stays bounded by one block of values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pack holds values of another type than `Decimal` or has a block length no
+    /// bitpacker supports.
+    pub fn map(&self, f: impl Fn(Decimal) -> Decimal) -> PackedDecimals {
+        match self.try_map(|v| Ok::<_, Infallible>(f(v))) {
+            Ok(packed) => packed,
+            Err(e) => match e {},
+        }
+    }
+
+    /// [`PackedDecimals::map`] with a fallible `f`, e.g. using `Decimal::checked_mul`. Stops at
+    /// the first error, returning it.
+    ///
+    /// # Panics
+    ///
+    /// Panics as [`PackedDecimals::map`] does.
+    pub fn try_map<E>(
+        &self,
+        f: impl Fn(Decimal) -> Result<Decimal, E>,
+    ) -> Result<PackedDecimals, E> {
+        self.try_map_with(&mut Blocks::new(self), f)
+    }
+
+    fn try_map_with<E>(
+        &self,
+        blocks: &mut Blocks<'_>,
+        f: impl Fn(Decimal) -> Result<Decimal, E>,
+    ) -> Result<PackedDecimals, E> {
+        let mut packer = repacker(self, self.block_len).unwrap_or_else(|e| panic!("{}", e));
+        while let Some((lanes, len)) = blocks.decode_next() {
+            for i in 0..len {
+                packer.load_decimal(&f(blocks.decimal_at(&lanes, i))?);
+            }
+        }
+        Ok(packer.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode::Blocks;
+    use crate::{pack, pack_sorted, unpack, Packer};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::*;
+
+    fn values() -> Vec<Decimal> {
+        (0..2000)
+            .map(|v| Decimal::new(v * 37 % 900 + 100, 2))
+            .collect()
+    }
+
+    #[test]
+    fn multiplier() {
+        let values = values();
+        for packer in [
+            Packer::new(),
+            Packer::new().with_adaptive_encoding().with_chained_heads(),
+            Packer::new().with_block_bounds().with_sign_magnitude(),
+            Packer::new().with_block_len(32).unwrap(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values);
+            let mapped = packed.map(|v| v * dec!(50));
+            let expected: Vec<Decimal> = unpack(&packed).iter().map(|v| v * dec!(50)).collect();
+            assert_eq!(unpack(&mapped), expected);
+            assert!(mapped.verify().is_ok());
+            assert_eq!(mapped.block_len, packed.block_len);
+            assert_eq!(mapped.chained(), packed.chained());
+            let bounds = |p: &crate::PackedDecimals| p.blocks[0].iter().any(|b| b.bounds.is_some());
+            assert_eq!(bounds(&mapped), bounds(&packed));
+        }
+        let ascending: Vec<Decimal> = (0..600).map(|v| Decimal::new(v * 5, 1)).collect();
+        let sorted = pack_sorted(&ascending).unwrap();
+        let negated = sorted.map(|v| -v);
+        assert_eq!(
+            unpack(&negated),
+            unpack(&sorted).iter().map(|v| -v).collect::<Vec<_>>()
+        );
+        assert!(pack(&[]).map(|v| v * dec!(2)).is_empty());
+    }
+
+    #[test]
+    fn overflow() {
+        let mut values = values();
+        values[600] = Decimal::MAX;
+        let packed = pack(&values);
+        let rate = dec!(1.5);
+        let converted = packed.try_map(|v| v.checked_mul(rate).ok_or(v));
+        assert_eq!(converted, Err(Decimal::MAX));
+        let ok = pack(&values[..600]).try_map(|v| v.checked_mul(rate).ok_or(v));
+        let expected: Vec<Decimal> = values[..600].iter().map(|v| v * rate).collect();
+        assert_eq!(unpack(&ok.unwrap()), expected);
+
+        // Decoding stops at the block of the error, the blocks are streamed.
+        let mut blocks = Blocks::new(&packed);
+        let result = packed.try_map_with(&mut blocks, |v| v.checked_mul(rate).ok_or(v));
+        assert!(result.is_err());
+        assert_eq!(blocks.decoded, 3);
+    }
+}
@@ -0,0 +1,284 @@
+//! Merging of sorted packs.
+use crate::decode::Values;
+use crate::format::check_decimals;
+use crate::{FloatpackError, PackedDecimals, Packer};
+use rust_decimal::Decimal;
+use std::iter::Peekable;
+
+/// How [`merge_sorted`], [`intersect_sorted`] and [`difference_sorted`] treat equal values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Duplicates {
+    /// Keep all of them, i.e. treat the inputs as multisets.
+    Keep,
+    /// Keep only the first of a run of equal values, i.e. treat the inputs as sets.
+    Dedup,
+}
+
+/// Merge two ascending packs into one ascending pack.
+///
+/// Both inputs are decoded lazily, one block at a time. Returns [`FloatpackError::Unsorted`]
+/// with the index into the input that turns out not to be ascending, and
+/// [`FloatpackError::TypeMismatch`] if an input holds values of another type than `Decimal`.
+///
+/// # Panics
+///
+/// Panics if a pack has a block length no bitpacker supports.
+pub fn merge_sorted(
+    a: &PackedDecimals,
+    b: &PackedDecimals,
+    duplicates: Duplicates,
+) -> Result<PackedDecimals, FloatpackError> {
+    let mut a = Ascending::new(a)?;
+    let mut b = Ascending::new(b)?;
+    let mut packer = Packer::new();
+    let mut last = None;
+    loop {
+        let next = match (a.peek()?, b.peek()?) {
+            (Some(x), Some(y)) if y < x => b.next(),
+            (Some(_), _) => a.next(),
+            (None, Some(_)) => b.next(),
+            (None, None) => break,
+        };
+        if duplicates == Duplicates::Keep || last != next {
+            packer.load_decimal(&next.expect("peeked a value"));
+        }
+        last = next;
+    }
+    Ok(packer.finish())
+}
+
+/// The values of `a` that are in `b` as well, in ascending order.
+///
+/// With [`Duplicates::Keep`], a value occurring `m` times in `a` and `n` times in `b` occurs
+/// `min(m, n)` times in the result, with [`Duplicates::Dedup`] once. Decoding and errors are as
+/// of [`merge_sorted`], except that reading stops once either input is exhausted, so the rest
+/// of the other one isn't checked.
+pub fn intersect_sorted(
+    a: &PackedDecimals,
+    b: &PackedDecimals,
+    duplicates: Duplicates,
+) -> Result<PackedDecimals, FloatpackError> {
+    let mut a = Ascending::new(a)?;
+    let mut b = Ascending::new(b)?;
+    let mut packer = Packer::new();
+    let mut last = None;
+    while let (Some(x), Some(y)) = (a.peek()?, b.peek()?) {
+        if x < y {
+            a.next();
+        } else if y < x {
+            b.next();
+        } else {
+            if duplicates == Duplicates::Keep || last != Some(x) {
+                packer.load_decimal(&x);
+            }
+            last = a.next();
+            b.next();
+        }
+    }
+    Ok(packer.finish())
+}
+
+/// The values of `a` that are not in `b`, in ascending order.
+///
+/// With [`Duplicates::Keep`], a value occurring `m` times in `a` and `n` times in `b` occurs
+/// `m - n` times in the result, if that's positive. With [`Duplicates::Dedup`] it occurs once
+/// if it isn't in `b` at all. Decoding and errors are as of [`merge_sorted`], except that reading
+/// stops once `a` is exhausted, so the rest of `b` isn't checked.
+pub fn difference_sorted(
+    a: &PackedDecimals,
+    b: &PackedDecimals,
+    duplicates: Duplicates,
+) -> Result<PackedDecimals, FloatpackError> {
+    let mut a = Ascending::new(a)?;
+    let mut b = Ascending::new(b)?;
+    let mut packer = Packer::new();
+    let mut last = None;
+    while let Some(x) = a.peek()? {
+        match b.peek()? {
+            Some(y) if y < x => {
+                b.next();
+            }
+            Some(y) if y == x => {
+                a.next();
+                if duplicates == Duplicates::Keep {
+                    b.next();
+                }
+            }
+            _ => {
+                if duplicates == Duplicates::Keep || last != Some(x) {
+                    packer.load_decimal(&x);
+                }
+                last = a.next();
+            }
+        }
+    }
+    Ok(packer.finish())
+}
+
+/// Values of a pack, checked to be ascending.
+struct Ascending<'a> {
+    values: Peekable<Values<'a>>,
+    last: Option<Decimal>,
+    index: usize,
+}
+
+impl<'a> Ascending<'a> {
+    fn new(packed: &'a PackedDecimals) -> Result<Self, FloatpackError> {
+        check_decimals(packed)?;
+        Ok(Ascending {
+            values: packed.iter().peekable(),
+            last: None,
+            index: 0,
+        })
+    }
+
+    fn peek(&mut self) -> Result<Option<Decimal>, FloatpackError> {
+        match (self.values.peek(), self.last) {
+            (Some(next), Some(last)) if *next < last => Err(FloatpackError::Unsorted(self.index)),
+            (next, _) => Ok(next.copied()),
+        }
+    }
+
+    fn next(&mut self) -> Option<Decimal> {
+        self.last = self.values.next();
+        self.index += 1;
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{difference_sorted, intersect_sorted, merge_sorted, Duplicates};
+    use crate::{pack, unpack, FloatpackError, Packer};
+    use rust_decimal::Decimal;
+    use std::collections::{HashMap, HashSet};
+
+    fn reference(a: &[Decimal], b: &[Decimal], duplicates: Duplicates) -> Vec<Decimal> {
+        let mut merged = [a, b].concat();
+        merged.sort();
+        if duplicates == Duplicates::Dedup {
+            merged.dedup();
+        }
+        merged
+    }
+
+    fn check(a: &[Decimal], b: &[Decimal]) {
+        for duplicates in [Duplicates::Keep, Duplicates::Dedup] {
+            let merged = merge_sorted(&pack(a), &pack(b), duplicates).unwrap();
+            assert_eq!(unpack(&merged), reference(a, b, duplicates));
+        }
+    }
+
+    #[test]
+    fn merges() {
+        let even: Vec<Decimal> = (0..2000).map(|v| Decimal::new(v * 2, 1)).collect();
+        let odd: Vec<Decimal> = (0..1500).map(|v| Decimal::new(v * 2 + 1, 1)).collect();
+        check(&even, &odd);
+        check(&odd, &even);
+        check(&even[..1000], &even[1000..]);
+        check(&even[1000..], &even[..1000]);
+        check(&even, &[]);
+        check(&[], &[]);
+
+        let repeated: Vec<Decimal> = (0..3000).map(|v| Decimal::new(v / 100, 0)).collect();
+        check(&repeated, &repeated[500..2500]);
+        let scaled: Vec<Decimal> = (0..3000).map(|v| Decimal::new(v / 100 * 10, 1)).collect();
+        check(&repeated, &scaled);
+    }
+
+    #[test]
+    fn chained_inputs() {
+        let values: Vec<Decimal> = (0..1000).map(|v| Decimal::new(v * 3, 2)).collect();
+        let chained = crate::tests::pack_with(Packer::new().with_chained_heads(), &values);
+        let merged = merge_sorted(&chained, &pack(&values), Duplicates::Dedup).unwrap();
+        assert_eq!(unpack(&merged), values);
+        assert!(chained.chained() && !merged.chained());
+    }
+
+    #[test]
+    fn rejects_unsorted() {
+        let sorted = pack(&(0..600).map(Decimal::from).collect::<Vec<_>>());
+        let mut values: Vec<Decimal> = (0..600).map(Decimal::from).collect();
+        values.swap(400, 401);
+        for (a, b) in [(&sorted, &pack(&values)), (&pack(&values), &sorted)] {
+            match merge_sorted(a, b, Duplicates::Keep) {
+                Err(FloatpackError::Unsorted(401)) => {}
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    fn random_sorted(n: usize, range: i64, seed: u64) -> Vec<Decimal> {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let mut values: Vec<Decimal> = (0..n)
+            .map(|_| Decimal::new(rng.gen_range(0..range), 1))
+            .collect();
+        values.sort();
+        values
+    }
+
+    fn counts(values: &[Decimal]) -> HashMap<Decimal, usize> {
+        let mut counts = HashMap::new();
+        for v in values {
+            *counts.entry(*v).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    fn check_sets(a: &[Decimal], b: &[Decimal]) {
+        let (set_a, set_b): (HashSet<_>, HashSet<_>) =
+            (a.iter().copied().collect(), b.iter().copied().collect());
+        let sorted = |set: HashSet<Decimal>| {
+            let mut values: Vec<Decimal> = set.into_iter().collect();
+            values.sort();
+            values
+        };
+        let (a_packed, b_packed) = (pack(a), pack(b));
+        let intersection = intersect_sorted(&a_packed, &b_packed, Duplicates::Dedup).unwrap();
+        let expected = sorted(set_a.intersection(&set_b).copied().collect());
+        assert_eq!(unpack(&intersection), expected);
+        let difference = difference_sorted(&a_packed, &b_packed, Duplicates::Dedup).unwrap();
+        let expected = sorted(set_a.difference(&set_b).copied().collect());
+        assert_eq!(unpack(&difference), expected);
+
+        let (counts_a, counts_b) = (counts(a), counts(b));
+        let multiset = |count: &dyn Fn(usize, usize) -> usize| {
+            let mut values = Vec::new();
+            for v in sorted(set_a.clone()) {
+                let n = count(counts_a[&v], counts_b.get(&v).copied().unwrap_or(0));
+                values.extend(std::iter::repeat_n(v, n));
+            }
+            values
+        };
+        let intersection = intersect_sorted(&a_packed, &b_packed, Duplicates::Keep).unwrap();
+        assert_eq!(unpack(&intersection), multiset(&|m, n| m.min(n)));
+        let difference = difference_sorted(&a_packed, &b_packed, Duplicates::Keep).unwrap();
+        assert_eq!(unpack(&difference), multiset(&|m, n| m.saturating_sub(n)));
+    }
+
+    #[test]
+    fn set_operations() {
+        for seed in 0..10 {
+            check_sets(
+                &random_sorted(800, 500, seed),
+                &random_sorted(600, 700, seed + 10),
+            );
+        }
+        let values = random_sorted(1000, 300, 20);
+        check_sets(&values, &values);
+        check_sets(&values, &[]);
+        check_sets(&[], &values);
+        check_sets(&[], &[]);
+    }
+
+    #[test]
+    fn set_operations_reject_unsorted() {
+        let sorted = pack(&(0..600).map(Decimal::from).collect::<Vec<_>>());
+        let mut values: Vec<Decimal> = (0..600).map(Decimal::from).collect();
+        values.swap(400, 401);
+        let unsorted = pack(&values);
+        assert!(intersect_sorted(&sorted, &unsorted, Duplicates::Keep).is_err());
+        assert!(difference_sorted(&unsorted, &sorted, Duplicates::Dedup).is_err());
+    }
+}
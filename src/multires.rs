@@ -0,0 +1,387 @@
+//! Downsampled companion streams packed alongside the full resolution series.
+//!
+//! A container starts with the magic bytes `FPMR` and a version byte, followed by the count of
+//! levels (`u64`) and each level as its factor (`u64`) and its pack in the format of
+//! [`PackedDecimals::write_to`]. All integers are little endian.
+use crate::format::{corrupt, read_u64};
+use crate::{FloatpackError, PackedDecimals, Packer};
+use rust_decimal::Decimal;
+use std::io::{Read, Write};
+
+const MAGIC: [u8; 4] = *b"FPMR";
+const VERSION: u8 = 1;
+
+/// Reduces a bucket of consecutive values to the values representing it in a downsampled level.
+///
+/// Implemented for closures as well, e.g. `|bucket: &[Decimal], out: &mut Vec<Decimal>| ...`.
+pub trait Reducer {
+    /// Append the values representing `bucket`, which is never empty, to `out`.
+    fn reduce(&self, bucket: &[Decimal], out: &mut Vec<Decimal>);
+}
+
+impl<F: Fn(&[Decimal], &mut Vec<Decimal>)> Reducer for F {
+    fn reduce(&self, bucket: &[Decimal], out: &mut Vec<Decimal>) {
+        self(bucket, out)
+    }
+}
+
+/// Keeps the first value of each bucket, i.e. every `factor`th value.
+pub struct EveryNth;
+
+impl Reducer for EveryNth {
+    fn reduce(&self, bucket: &[Decimal], out: &mut Vec<Decimal>) {
+        out.push(bucket[0]);
+    }
+}
+
+/// Keeps the last value of each bucket.
+pub struct Last;
+
+impl Reducer for Last {
+    fn reduce(&self, bucket: &[Decimal], out: &mut Vec<Decimal>) {
+        out.push(bucket[bucket.len() - 1]);
+    }
+}
+
+/// Keeps the smallest value of each bucket.
+pub struct Min;
+
+impl Reducer for Min {
+    fn reduce(&self, bucket: &[Decimal], out: &mut Vec<Decimal>) {
+        out.extend(bucket.iter().min());
+    }
+}
+
+/// Keeps the largest value of each bucket.
+pub struct Max;
+
+impl Reducer for Max {
+    fn reduce(&self, bucket: &[Decimal], out: &mut Vec<Decimal>) {
+        out.extend(bucket.iter().max());
+    }
+}
+
+/// Keeps the smallest and the largest value of each bucket, in the order they occur.
+///
+/// Of repeated extremes, the first occurrence counts. Buckets of a single distinct value
+/// contribute it once, so a level holds up to two values per bucket.
+pub struct MinMax;
+
+impl Reducer for MinMax {
+    fn reduce(&self, bucket: &[Decimal], out: &mut Vec<Decimal>) {
+        let (mut min, mut max) = (0, 0);
+        for (i, v) in bucket.iter().enumerate() {
+            if *v < bucket[min] {
+                min = i;
+            }
+            if *v > bucket[max] {
+                max = i;
+            }
+        }
+        out.push(bucket[min.min(max)]);
+        if min != max {
+            out.push(bucket[min.max(max)]);
+        }
+    }
+}
+
+struct Level {
+    factor: usize,
+    reducer: Box<dyn Reducer>,
+    bucket: Vec<Decimal>,
+    reduced: Vec<Decimal>,
+    packer: Packer,
+}
+
+impl Level {
+    fn flush(&mut self) {
+        if self.bucket.is_empty() {
+            return;
+        }
+        self.reducer.reduce(&self.bucket, &mut self.reduced);
+        for v in self.reduced.drain(..) {
+            self.packer.load_decimal(&v);
+        }
+        self.bucket.clear();
+    }
+}
+
+/// Packs a series along with downsampled copies of it, one packer per level.
+///
+/// Every loaded value goes to the full resolution packer and to the bucket of each level. A full
+/// bucket of `factor` values is reduced (see [`Reducer`]) into the packer of its level. On
+/// [`MultiResPacker::finish`], the partial last bucket of each level is reduced as well, so the
+/// end of the series is represented on every level.
+pub struct MultiResPacker {
+    packer: Packer,
+    levels: Vec<Level>,
+}
+
+impl MultiResPacker {
+    /// Start with `packer` for the full resolution series and no downsampled levels.
+    pub fn new(packer: Packer) -> MultiResPacker {
+        MultiResPacker {
+            packer,
+            levels: Vec::new(),
+        }
+    }
+
+    /// Add a level reducing each bucket of `factor` values with `reducer` into `packer`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is less than 2 or another level has the same factor.
+    pub fn with_level(
+        mut self,
+        factor: usize,
+        reducer: impl Reducer + 'static,
+        packer: Packer,
+    ) -> Self {
+        assert!(factor > 1, "downsample factor must be at least 2");
+        assert!(
+            self.levels.iter().all(|level| level.factor != factor),
+            "duplicate downsample factor {}",
+            factor
+        );
+        self.levels.push(Level {
+            factor,
+            reducer: Box::new(reducer),
+            bucket: Vec::with_capacity(factor),
+            reduced: Vec::new(),
+            packer,
+        });
+        self
+    }
+
+    /// Add a value to the series and the buckets of all levels.
+    pub fn load_decimal(&mut self, value: &Decimal) {
+        self.packer.load_decimal(value);
+        for level in &mut self.levels {
+            level.bucket.push(*value);
+            if level.bucket.len() == level.factor {
+                level.flush();
+            }
+        }
+    }
+
+    /// Reduce the partial buckets, flush all packers and return the levels, finest first.
+    pub fn finish(self) -> MultiResolution {
+        let mut levels = vec![Resolution {
+            factor: 1,
+            packed: self.packer.finish(),
+        }];
+        for mut level in self.levels {
+            level.flush();
+            levels.push(Resolution {
+                factor: level.factor,
+                packed: level.packer.finish(),
+            });
+        }
+        levels.sort_by_key(|level| level.factor);
+        MultiResolution { levels }
+    }
+}
+
+/// A level of a [`MultiResolution`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resolution {
+    /// Count of values of the full resolution series reduced into each bucket of this level.
+    pub factor: usize,
+    pub packed: PackedDecimals,
+}
+
+/// A series packed at several resolutions, see [`MultiResPacker`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiResolution {
+    levels: Vec<Resolution>,
+}
+
+impl MultiResolution {
+    /// The levels ordered by factor, starting with the full resolution series (factor 1).
+    pub fn levels(&self) -> &[Resolution] {
+        &self.levels
+    }
+
+    /// The finest level holding at most `budget` values, or the coarsest level if none does.
+    pub fn select(&self, budget: usize) -> &Resolution {
+        self.levels
+            .iter()
+            .find(|level| level.packed.len() <= budget)
+            .unwrap_or(&self.levels[self.levels.len() - 1])
+    }
+
+    /// Serialize into the container format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)
+            .expect("writing to a Vec never fails");
+        bytes
+    }
+
+    /// Serialize into the container format, writing to `writer`.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), FloatpackError> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&(self.levels.len() as u64).to_le_bytes())?;
+        for level in &self.levels {
+            writer.write_all(&(level.factor as u64).to_le_bytes())?;
+            level.packed.write_to(&mut writer)?;
+        }
+        Ok(())
+    }
+
+    /// Deserialize from the container format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FloatpackError> {
+        let mut reader = bytes;
+        let levels = Self::read_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(corrupt(format!("{} trailing bytes", reader.len())));
+        }
+        Ok(levels)
+    }
+
+    /// Deserialize from the container format, reading from `reader`.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, FloatpackError> {
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(corrupt("missing multi-resolution magic bytes".into()));
+        }
+        if header[4] != VERSION {
+            return Err(FloatpackError::UnsupportedVersion(header[4]));
+        }
+        let count = read_u64(&mut reader)?;
+        let mut levels: Vec<Resolution> = Vec::new();
+        for _ in 0..count {
+            let factor = read_u64(&mut reader)? as usize;
+            let previous = levels.last().map_or(0, |level| level.factor);
+            if factor <= previous || (levels.is_empty() && factor != 1) {
+                return Err(corrupt(format!("invalid level factor {}", factor)));
+            }
+            levels.push(Resolution {
+                factor,
+                packed: PackedDecimals::read_from(&mut reader)?,
+            });
+        }
+        if levels.is_empty() {
+            return Err(corrupt("no levels".into()));
+        }
+        Ok(MultiResolution { levels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EveryNth, Last, Max, Min, MinMax, MultiResPacker, MultiResolution};
+    use crate::{unpack, Packer};
+    use rust_decimal::Decimal;
+
+    fn values(n: i64) -> Vec<Decimal> {
+        (0..n)
+            .map(|v| Decimal::new((v * 7919) % 1000 - 500 + v, 2))
+            .collect()
+    }
+
+    fn min_max(bucket: &[Decimal]) -> Vec<Decimal> {
+        let min = bucket.iter().min().unwrap();
+        let max = bucket.iter().max().unwrap();
+        let first = bucket.iter().position(|v| v == min || v == max).unwrap();
+        let mut result = vec![bucket[first]];
+        if min != max {
+            result.push(if bucket[first] == *min { *max } else { *min });
+        }
+        result
+    }
+
+    #[test]
+    fn levels() {
+        let values = values(12345);
+        let mut packer = MultiResPacker::new(Packer::new())
+            .with_level(100, MinMax, Packer::new())
+            .with_level(10, EveryNth, Packer::new().with_chained_heads())
+            .with_level(7, Last, Packer::new())
+            .with_level(30, Min, Packer::new())
+            .with_level(300, Max, Packer::new())
+            .with_level(
+                1000,
+                |bucket: &[Decimal], out: &mut Vec<Decimal>| out.push(bucket.iter().sum()),
+                Packer::new(),
+            );
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        let multires = packer.finish();
+
+        let factors: Vec<usize> = multires.levels().iter().map(|l| l.factor).collect();
+        assert_eq!(factors, vec![1, 7, 10, 30, 100, 300, 1000]);
+        let level = |factor| {
+            let level = multires.levels().iter().find(|l| l.factor == factor);
+            unpack(&level.unwrap().packed)
+        };
+        assert_eq!(level(1), values);
+        let every_nth: Vec<Decimal> = values.iter().step_by(10).copied().collect();
+        assert_eq!(level(10), every_nth);
+        let last: Vec<Decimal> = values.chunks(7).map(|c| c[c.len() - 1]).collect();
+        assert_eq!(level(7), last);
+        let min: Vec<Decimal> = values
+            .chunks(30)
+            .map(|c| *c.iter().min().unwrap())
+            .collect();
+        assert_eq!(level(30), min);
+        let max: Vec<Decimal> = values
+            .chunks(300)
+            .map(|c| *c.iter().max().unwrap())
+            .collect();
+        assert_eq!(level(300), max);
+        let min_max: Vec<Decimal> = values.chunks(100).flat_map(min_max).collect();
+        assert_eq!(level(100), min_max);
+        let sums: Vec<Decimal> = values.chunks(1000).map(|c| c.iter().sum()).collect();
+        assert_eq!(level(1000), sums);
+
+        let bytes = multires.to_bytes();
+        assert_eq!(MultiResolution::from_bytes(&bytes).unwrap(), multires);
+        for len in 0..bytes.len() {
+            assert!(MultiResolution::from_bytes(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn select() {
+        let mut packer = MultiResPacker::new(Packer::new())
+            .with_level(100, EveryNth, Packer::new())
+            .with_level(10, EveryNth, Packer::new());
+        for v in &values(5000) {
+            packer.load_decimal(v);
+        }
+        let multires = packer.finish();
+        assert_eq!(multires.select(usize::MAX).factor, 1);
+        assert_eq!(multires.select(5000).factor, 1);
+        assert_eq!(multires.select(4999).factor, 10);
+        assert_eq!(multires.select(500).factor, 10);
+        assert_eq!(multires.select(499).factor, 100);
+        assert_eq!(multires.select(50).factor, 100);
+        assert_eq!(multires.select(0).factor, 100);
+
+        let single = MultiResPacker::new(Packer::new()).finish();
+        assert_eq!(single.levels().len(), 1);
+        assert!(single.select(0).packed.is_empty());
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let multires = MultiResPacker::new(Packer::new())
+            .with_level(10, Last, Packer::new())
+            .finish();
+        let bytes = multires.to_bytes();
+        assert!(MultiResolution::from_bytes(&bytes).is_ok());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(MultiResolution::from_bytes(&trailing).is_err());
+        let mut version = bytes.clone();
+        version[4] = 9;
+        assert!(MultiResolution::from_bytes(&version).is_err());
+        let mut factor = bytes;
+        factor[13] = 2;
+        assert!(MultiResolution::from_bytes(&factor).is_err());
+    }
+}
@@ -0,0 +1,425 @@
+//! Ticks of a varying number of values, like the price levels of an order book.
+use crate::decode::Columns;
+use crate::format::{corrupt, validate};
+use crate::pair::Spread;
+use crate::{
+    try_unpack_as, FloatpackError, GenericPacker, Packable, PackedColumns, PackedDecimals, Packer,
+};
+use rust_decimal::Decimal;
+use std::io::{Read, Write};
+
+/// Packs a vector of values per tick, like the bid levels of an order book.
+///
+/// The number of values of each tick is packed as a `u64` of its own, and the values of all ticks
+/// one after the other like [`Packer`] does. Ticks may hold any number of values, including none.
+///
+/// [`VecPacker::with_level_deltas`] packs each value as its difference to the value at the same
+/// level of the previous tick instead, so levels moving together pack to narrow blocks even
+/// though neighbouring levels differ by much more.
+///
+/// Usage example:
+/// ```
+/// use floatpack::VecPacker;
+/// use rust_decimal_macros::*;
+///
+/// let mut packer = VecPacker::new().with_level_deltas();
+/// packer.load_tick(&[dec!(100.01), dec!(100.00)]);
+/// packer.load_tick(&[]);
+/// packer.load_tick(&[dec!(100.02), dec!(100.01), dec!(99.99)]);
+/// let packed = packer.finish();
+/// assert_eq!(
+///     packed.try_unpack().unwrap(),
+///     vec![
+///         vec![dec!(100.01), dec!(100.00)],
+///         vec![],
+///         vec![dec!(100.02), dec!(100.01), dec!(99.99)],
+///     ]
+/// );
+/// ```
+pub struct VecPacker {
+    lengths: GenericPacker<u64, 2>,
+    levels: Levels,
+}
+
+enum Levels {
+    Plain(Packer),
+    /// Values are spreads to the values at the same level of the previous tick, or to zero for
+    /// levels the previous tick didn't have.
+    Deltas(GenericPacker<Spread>, Vec<Decimal>),
+}
+
+impl Default for VecPacker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VecPacker {
+    pub fn new() -> VecPacker {
+        VecPacker {
+            lengths: GenericPacker::new(),
+            levels: Levels::Plain(Packer::new()),
+        }
+    }
+
+    /// Pack each value as its difference to the value at the same level of the previous tick.
+    pub fn with_level_deltas(mut self) -> Self {
+        self.levels = Levels::Deltas(GenericPacker::new(), Vec::new());
+        self
+    }
+
+    /// Add the values of a tick.
+    pub fn load_tick(&mut self, values: &[Decimal]) {
+        self.lengths.load(&(values.len() as u64));
+        match &mut self.levels {
+            Levels::Plain(packer) => {
+                for value in values {
+                    packer.load_decimal(value);
+                }
+            }
+            Levels::Deltas(packer, previous) => {
+                for (level, value) in values.iter().enumerate() {
+                    let reference = previous.get(level).copied().unwrap_or(Decimal::ZERO);
+                    packer.load(&Spread::new(reference, *value));
+                }
+                // Levels beyond the depth of this tick keep their last value as the reference.
+                let depth = previous.len().max(values.len());
+                previous.resize(depth, Decimal::ZERO);
+                previous[..values.len()].copy_from_slice(values);
+            }
+        }
+    }
+
+    /// Flush pending ticks and return the packed result.
+    pub fn finish(self) -> PackedVecs {
+        PackedVecs {
+            lengths: self.lengths.finish(),
+            values: match self.levels {
+                Levels::Plain(packer) => packer.finish(),
+                Levels::Deltas(packer, _) => packer.finish(),
+            },
+        }
+    }
+}
+
+/// Ticks packed by a [`VecPacker`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackedVecs {
+    lengths: PackedColumns<2>,
+    values: PackedDecimals,
+}
+
+impl PackedVecs {
+    /// The number of ticks.
+    pub fn len(&self) -> usize {
+        self.lengths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lengths.is_empty()
+    }
+
+    /// The number of values of all ticks.
+    pub fn value_count(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Unpack the ticks, checking both packs with [`PackedDecimals::verify`] first.
+    pub fn try_unpack(&self) -> Result<Vec<Vec<Decimal>>, FloatpackError> {
+        self.ticks()?.collect()
+    }
+
+    /// Iterate over the ticks, decoding the values one block at a time.
+    ///
+    /// Both packs are checked with [`PackedDecimals::verify`] and the lengths of the ticks are
+    /// decoded up front. A value that fails to decode ends the iteration with its error.
+    pub fn ticks(&self) -> Result<Ticks<'_>, FloatpackError> {
+        let lengths: Vec<u64> = try_unpack_as(&self.lengths)?;
+        validate(&self.values)?;
+        let deltas = match self.values.type_tag {
+            tag if tag == Decimal::TYPE_TAG => false,
+            tag if tag == Spread::TYPE_TAG => true,
+            found => {
+                return Err(FloatpackError::TypeMismatch {
+                    expected: Decimal::TYPE_TAG,
+                    found,
+                })
+            }
+        };
+        let total = lengths
+            .iter()
+            .try_fold(0u64, |total, &n| total.checked_add(n));
+        if total != Some(self.values.len() as u64) {
+            return Err(corrupt(format!(
+                "tick lengths don't add up to {} values",
+                self.values.len()
+            )));
+        }
+        Ok(Ticks {
+            lengths: lengths.into_iter(),
+            values: Columns::new(&self.values),
+            previous: deltas.then(Vec::new),
+            failed: false,
+        })
+    }
+
+    /// Serialize into the binary format: the lengths of the ticks followed by their values, each
+    /// in the format of [`PackedDecimals::to_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.lengths.to_bytes();
+        bytes.extend(self.values.to_bytes());
+        bytes
+    }
+
+    /// Serialize into the binary format, writing to `writer`.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), FloatpackError> {
+        self.lengths.write_to(&mut writer)?;
+        self.values.write_to(&mut writer)
+    }
+
+    /// Deserialize from the output of [`PackedVecs::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FloatpackError> {
+        let mut reader = bytes;
+        let vecs = Self::read_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(corrupt(format!("{} trailing bytes", reader.len())));
+        }
+        Ok(vecs)
+    }
+
+    /// Deserialize from the binary format, reading from `reader`.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, FloatpackError> {
+        let lengths = PackedColumns::<2>::read_from(&mut reader)?;
+        if lengths.type_tag != u64::TYPE_TAG {
+            return Err(FloatpackError::TypeMismatch {
+                expected: u64::TYPE_TAG,
+                found: lengths.type_tag,
+            });
+        }
+        let values = PackedDecimals::read_from(&mut reader)?;
+        if values.type_tag != Decimal::TYPE_TAG && values.type_tag != Spread::TYPE_TAG {
+            return Err(FloatpackError::TypeMismatch {
+                expected: Decimal::TYPE_TAG,
+                found: values.type_tag,
+            });
+        }
+        Ok(PackedVecs { lengths, values })
+    }
+}
+
+/// Iterator over the ticks of [`PackedVecs`], returned by [`PackedVecs::ticks`].
+pub struct Ticks<'a> {
+    lengths: std::vec::IntoIter<u64>,
+    values: Columns<'a, 4>,
+    /// The reference of each level, if the values are level deltas.
+    previous: Option<Vec<Decimal>>,
+    failed: bool,
+}
+
+impl Ticks<'_> {
+    fn decode_tick(&mut self, len: usize) -> Result<Vec<Decimal>, FloatpackError> {
+        let mut tick = Vec::with_capacity(len);
+        for level in 0..len {
+            let columns = self
+                .values
+                .next()
+                .ok_or_else(|| corrupt("fewer values than tick lengths".to_string()))?;
+            let value = match &self.previous {
+                None => Decimal::from_columns(columns)?,
+                Some(previous) => {
+                    let reference = previous.get(level).copied().unwrap_or(Decimal::ZERO);
+                    Spread::from_columns(columns)?
+                        .leg_b(reference)
+                        .ok_or_else(|| {
+                            corrupt(format!("level {} doesn't fit its reference", level))
+                        })?
+                }
+            };
+            tick.push(value);
+        }
+        if let Some(previous) = &mut self.previous {
+            let depth = previous.len().max(len);
+            previous.resize(depth, Decimal::ZERO);
+            previous[..len].copy_from_slice(&tick);
+        }
+        Ok(tick)
+    }
+}
+
+impl Iterator for Ticks<'_> {
+    type Item = Result<Vec<Decimal>, FloatpackError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let len = self.lengths.next()? as usize;
+        let tick = self.decode_tick(len);
+        self.failed = tick.is_err();
+        Some(tick)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed {
+            (0, Some(0))
+        } else {
+            (0, Some(self.lengths.len()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PackedVecs, VecPacker};
+    use crate::{pack, FloatpackError, GenericPacker};
+    use rand::{Rng, SeedableRng};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn pack_ticks(packer: VecPacker, ticks: &[Vec<Decimal>]) -> PackedVecs {
+        let mut packer = packer;
+        for tick in ticks {
+            packer.load_tick(tick);
+        }
+        packer.finish()
+    }
+
+    /// Bid levels of a random walk in cents, 1 to 2 cents apart, of a random depth up to
+    /// `max_depth` that is 0 every 100th tick.
+    fn snapshots(n: usize, max_depth: usize, seed: u64) -> Vec<Vec<Decimal>> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let mut best = 10_000i64;
+        (0..n)
+            .map(|i| {
+                best += rng.gen_range(-2..=2);
+                let depth = if i % 100 == 99 {
+                    0
+                } else {
+                    rng.gen_range(0..=max_depth)
+                };
+                let mut price = best;
+                (0..depth)
+                    .map(|_| {
+                        let level = Decimal::new(price, 2);
+                        price -= rng.gen_range(1..=2);
+                        level
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Values are equal by representation, including scale and the sign of zero.
+    fn assert_exact(unpacked: &[Vec<Decimal>], ticks: &[Vec<Decimal>]) {
+        assert_eq!(unpacked.len(), ticks.len());
+        for (u, t) in unpacked.iter().zip(ticks) {
+            assert_eq!(u.len(), t.len());
+            for (u, t) in u.iter().zip(t) {
+                assert_eq!(u.serialize(), t.serialize());
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for seed in 0..4 {
+            let ticks = snapshots(3000, 10, seed);
+            for packer in [VecPacker::new(), VecPacker::new().with_level_deltas()] {
+                let packed = pack_ticks(packer, &ticks);
+                assert_eq!(packed.len(), ticks.len());
+                assert_eq!(
+                    packed.value_count(),
+                    ticks.iter().map(Vec::len).sum::<usize>()
+                );
+                assert_exact(&packed.try_unpack().unwrap(), &ticks);
+                let iterated: Vec<_> = packed.ticks().unwrap().map(Result::unwrap).collect();
+                assert_exact(&iterated, &ticks);
+                let decoded = PackedVecs::from_bytes(&packed.to_bytes()).unwrap();
+                assert_eq!(decoded, packed);
+                assert_exact(&decoded.try_unpack().unwrap(), &ticks);
+            }
+        }
+    }
+
+    #[test]
+    fn ragged_ticks() {
+        let mut negative_zero = dec!(0.00);
+        negative_zero.set_sign_negative(true);
+        let ticks = vec![
+            vec![],
+            vec![],
+            vec![dec!(5), dec!(4.5), dec!(4)],
+            vec![dec!(5.01)],
+            vec![],
+            vec![dec!(5.1), dec!(4.57), dec!(4.001), dec!(3), dec!(2)],
+            vec![negative_zero, Decimal::MAX, Decimal::MIN],
+            vec![Decimal::MIN, Decimal::MAX, dec!(-3), dec!(2.0000)],
+            vec![],
+        ];
+        for packer in [VecPacker::new(), VecPacker::new().with_level_deltas()] {
+            let packed = pack_ticks(packer, &ticks);
+            assert_exact(&packed.try_unpack().unwrap(), &ticks);
+        }
+        for packer in [VecPacker::new(), VecPacker::new().with_level_deltas()] {
+            let packed = packer.finish();
+            assert!(packed.is_empty());
+            assert!(packed.try_unpack().unwrap().is_empty());
+            assert_eq!(PackedVecs::from_bytes(&packed.to_bytes()).unwrap(), packed);
+        }
+    }
+
+    #[test]
+    fn smaller_than_packers_per_level() {
+        let ticks: Vec<_> = snapshots(10_000, 10, 7)
+            .into_iter()
+            .filter(|tick| tick.len() == 10)
+            .collect();
+        let naive: usize = (0..10)
+            .map(|level| {
+                let values: Vec<_> = ticks.iter().map(|tick| tick[level]).collect();
+                pack(&values).serialized_len()
+            })
+            .sum();
+        let deltas = pack_ticks(VecPacker::new().with_level_deltas(), &ticks);
+        assert!(
+            deltas.to_bytes().len() < naive,
+            "{} >= {}",
+            deltas.to_bytes().len(),
+            naive
+        );
+        assert!(deltas.lengths.blocks.iter().flatten().all(|b| b.bits == 0));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let ticks = snapshots(100, 10, 1);
+        let packed = pack_ticks(VecPacker::new(), &ticks);
+        let bytes = packed.to_bytes();
+        assert!(PackedVecs::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(PackedVecs::from_bytes(&trailing).is_err());
+
+        // Lengths that don't add up to the number of values.
+        let mismatched = PackedVecs {
+            lengths: packed.lengths.clone(),
+            values: pack(&[dec!(1)]),
+        };
+        assert!(matches!(
+            mismatched.try_unpack(),
+            Err(FloatpackError::Corrupt(_))
+        ));
+
+        let mut signed = GenericPacker::<i64, 2>::new();
+        signed.load(&1);
+        let mut bytes = signed.finish().to_bytes();
+        bytes.extend(pack(&[dec!(1)]).to_bytes());
+        assert!(matches!(
+            PackedVecs::from_bytes(&bytes),
+            Err(FloatpackError::TypeMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+}
@@ -0,0 +1,291 @@
+//! Export to and import from NumPy `.npy` files of `float64`s, behind the `npy` feature.
+//!
+//! A file starts with the magic bytes `\x93NUMPY`, the format version (`u8` major, `u8` minor)
+//! and the length of the header (`u16` in version 1, `u32` in versions 2 and 3). The header is a
+//! Python dict literal describing the array, padded with spaces and a newline so the data
+//! following it starts at a multiple of 64 bytes. The data of a one-dimensional array of
+//! little-endian `float64`s is just the values one after the other.
+use crate::format::{check_decimals, corrupt};
+use crate::{FloatpackError, PackedDecimals, Packer};
+use rust_decimal::prelude::*;
+use std::io::{Read, Write};
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+/// Bytes of the magic, version and header length of version 1.
+const PREAMBLE_LEN: usize = 10;
+const ALIGNMENT: usize = 64;
+
+/// Write the values of `packed` as a `.npy` file of a one-dimensional `float64` array.
+///
+/// Each value converts to the nearest `f64`, which is lossy for values of more than about 15
+/// significant digits; see [`write_npy_strict`] to fail instead. Values are decoded and written
+/// one block at a time. Fails with [`FloatpackError::TypeMismatch`] if `packed` holds values of
+/// another type than `Decimal`.
+pub fn write_npy<W: Write>(packed: &PackedDecimals, writer: W) -> Result<(), FloatpackError> {
+    write(packed, writer, false)
+}
+
+/// Write the values of `packed` like [`write_npy`], failing with
+/// [`FloatpackError::OutOfRange`] on the first value that doesn't convert to an `f64` and back
+/// to the same number.
+///
+/// Values that are equal but differ in scale, like `1.50` and `1.5`, count as the same number.
+/// The bytes written until the failure are left in `writer`.
+pub fn write_npy_strict<W: Write>(
+    packed: &PackedDecimals,
+    writer: W,
+) -> Result<(), FloatpackError> {
+    write(packed, writer, true)
+}
+
+fn write<W: Write>(
+    packed: &PackedDecimals,
+    mut writer: W,
+    strict: bool,
+) -> Result<(), FloatpackError> {
+    check_decimals(packed)?;
+    writer.write_all(&header(packed.len()))?;
+    let block_values = packed.block_values();
+    let mut buffer = Vec::with_capacity(8 * block_values);
+    let mut values = packed.iter();
+    loop {
+        buffer.clear();
+        for value in values.by_ref().take(block_values) {
+            let f = value.to_f64().unwrap_or_default();
+            if strict && Decimal::from_f64(f) != Some(value) {
+                return Err(FloatpackError::OutOfRange(value));
+            }
+            buffer.extend_from_slice(&f.to_le_bytes());
+        }
+        if buffer.is_empty() {
+            break;
+        }
+        writer.write_all(&buffer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The preamble and header of version 1 for a one-dimensional `float64` array of `len` values.
+fn header(len: usize) -> Vec<u8> {
+    let dict = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': ({},), }}",
+        len
+    );
+    // Pad with spaces so the newline ends the header at the alignment.
+    let padded = (PREAMBLE_LEN + dict.len() + 1).div_ceil(ALIGNMENT) * ALIGNMENT - PREAMBLE_LEN;
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(padded as u16).to_le_bytes());
+    bytes.extend_from_slice(dict.as_bytes());
+    bytes.resize(PREAMBLE_LEN + padded - 1, b' ');
+    bytes.push(b'\n');
+    bytes
+}
+
+/// Pack the values of a `.npy` file of a one-dimensional `float64` array, in any version of
+/// the format.
+///
+/// Each value converts to a `Decimal` like `Decimal::from_f64` does. NaN, infinities and values
+/// beyond the range of `Decimal` fail with [`FloatpackError::Row`]; arrays of another type or
+/// shape with [`FloatpackError::Corrupt`].
+pub fn read_npy<R: Read>(mut reader: R) -> Result<PackedDecimals, FloatpackError> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != MAGIC {
+        return Err(corrupt("missing npy magic bytes".into()));
+    }
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        2 | 3 => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        major => return Err(corrupt(format!("unsupported npy version {}", major))),
+    };
+    let mut header = vec![0u8; header_len];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8(header).map_err(|_| corrupt("npy header is not text".into()))?;
+    let len = parse_header(&header)?;
+
+    let mut packer = Packer::new();
+    let mut bytes = [0u8; 8];
+    for row in 0..len {
+        reader.read_exact(&mut bytes)?;
+        let f = f64::from_le_bytes(bytes);
+        let value = Decimal::from_f64(f).ok_or_else(|| FloatpackError::Row {
+            row,
+            reason: format!("{} is not a decimal", f),
+        })?;
+        packer.load_decimal(&value);
+    }
+    Ok(packer.finish())
+}
+
+/// The length of the array described by `header`, if it is a one-dimensional array of
+/// little-endian `float64`s.
+fn parse_header(header: &str) -> Result<usize, FloatpackError> {
+    let invalid = || corrupt(format!("unsupported npy header {}", header.trim_end()));
+    let descr = dict_value(header, "descr").ok_or_else(invalid)?;
+    if !matches!(descr, "'<f8'" | "\"<f8\"") {
+        return Err(corrupt(format!("npy array of {} instead of '<f8'", descr)));
+    }
+    // The order doesn't matter for one dimension.
+    if !matches!(dict_value(header, "fortran_order"), Some("False" | "True")) {
+        return Err(invalid());
+    }
+    let shape = dict_value(header, "shape").ok_or_else(invalid)?;
+    let dims = shape
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(invalid)?
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .collect::<Vec<_>>();
+    match dims[..] {
+        [len] => len.parse().map_err(|_| invalid()),
+        _ => Err(corrupt(format!("npy array of shape {}", shape))),
+    }
+}
+
+/// The literal of the value of `key` in the Python dict literal `dict`, assuming values are
+/// strings, names or tuples of numbers.
+fn dict_value<'a>(dict: &'a str, key: &str) -> Option<&'a str> {
+    let start = [format!("'{}'", key), format!("\"{}\"", key)]
+        .iter()
+        .find_map(|quoted| Some(dict.find(quoted.as_str())? + quoted.len()))?;
+    let rest = dict[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')')? + 1
+    } else {
+        rest.find([',', '}'])?
+    };
+    Some(rest[..end].trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{header, read_npy, write_npy, write_npy_strict};
+    use crate::{pack, unpack, FloatpackError};
+    use rust_decimal::prelude::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn header_layout() {
+        // As written by `numpy.save(f, numpy.array([1.5, 2.0, 3.25]))`.
+        let mut expected = b"\x93NUMPY\x01\x00\x76\x00".to_vec();
+        expected.extend_from_slice(b"{'descr': '<f8', 'fortran_order': False, 'shape': (3,), }");
+        expected.resize(127, b' ');
+        expected.push(b'\n');
+        assert_eq!(header(3), expected);
+
+        for len in [0, 1, 999_999, u32::MAX as usize + 1, usize::MAX] {
+            let header = header(len);
+            assert_eq!(header.len() % 64, 0);
+            let header_len = u16::from_le_bytes([header[8], header[9]]) as usize;
+            assert_eq!(header_len + 10, header.len());
+            assert_eq!(header.last(), Some(&b'\n'));
+            let dict = std::str::from_utf8(&header[10..]).unwrap();
+            assert!(dict.contains(&format!("'shape': ({},)", len)), "{}", dict);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let values: Vec<Decimal> = (0..1000i64)
+            .map(|i| Decimal::new(10_000 + i * 7 % 300, 2))
+            .collect();
+        let mut bytes = Vec::new();
+        write_npy(&pack(&values), &mut bytes).unwrap();
+        assert_eq!(bytes.len(), 128 + 8 * values.len());
+        for (v, f) in values.iter().zip(bytes[128..].chunks_exact(8)) {
+            let mut le = [0u8; 8];
+            le.copy_from_slice(f);
+            assert_eq!(f64::from_le_bytes(le), v.to_f64().unwrap());
+        }
+        assert_eq!(unpack(&read_npy(&bytes[..]).unwrap()), values);
+        let mut strict = Vec::new();
+        write_npy_strict(&pack(&values), &mut strict).unwrap();
+        assert_eq!(strict, bytes);
+
+        let mut empty = Vec::new();
+        write_npy(&pack(&[]), &mut empty).unwrap();
+        assert_eq!(empty, header(0));
+        assert!(read_npy(&empty[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn lossy() {
+        let precise = dec!(1.2345678901234567890123);
+        let values = [dec!(1.5), precise];
+        let mut bytes = Vec::new();
+        write_npy(&pack(&values), &mut bytes).unwrap();
+        let unpacked = unpack(&read_npy(&bytes[..]).unwrap());
+        assert_eq!(unpacked[0], dec!(1.5));
+        assert_ne!(unpacked[1], precise);
+        assert!((unpacked[1] - precise).abs() < dec!(1e-15));
+
+        let mut strict = Vec::new();
+        assert!(matches!(
+            write_npy_strict(&pack(&values), &mut strict),
+            Err(FloatpackError::OutOfRange(v)) if v == precise
+        ));
+        // Equal values of another scale are exact.
+        assert!(write_npy_strict(&pack(&[dec!(1.500)]), &mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn reads_other_headers() {
+        // Version 2, double quotes, keys in another order and no trailing comma.
+        let dict = b"{\"shape\": (2,), \"fortran_order\": True, \"descr\": \"<f8\"}\n";
+        let mut bytes = b"\x93NUMPY\x02\x00".to_vec();
+        bytes.extend_from_slice(&(dict.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(dict);
+        bytes.extend_from_slice(&0.25f64.to_le_bytes());
+        bytes.extend_from_slice(&(-3.0f64).to_le_bytes());
+        assert_eq!(
+            unpack(&read_npy(&bytes[..]).unwrap()),
+            [dec!(0.25), dec!(-3)]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let npy = |dict: &str, data: &[f64]| {
+            let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+            bytes.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(dict.as_bytes());
+            data.iter()
+                .for_each(|f| bytes.extend_from_slice(&f.to_le_bytes()));
+            bytes
+        };
+        for dict in [
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (1,), }",
+            "{'descr': '>f8', 'fortran_order': False, 'shape': (1,), }",
+            "{'descr': '<f8', 'fortran_order': False, 'shape': (1, 1), }",
+            "{'descr': '<f8', 'fortran_order': False, 'shape': (), }",
+            "{'descr': '<f8', 'shape': (1,), }",
+        ] {
+            assert!(matches!(
+                read_npy(&npy(dict, &[1.0])[..]),
+                Err(FloatpackError::Corrupt(_))
+            ));
+        }
+        let dict = "{'descr': '<f8', 'fortran_order': False, 'shape': (3,), }";
+        assert!(matches!(
+            read_npy(&npy(dict, &[1.0, f64::NAN, 2.0])[..]),
+            Err(FloatpackError::Row { row: 1, .. })
+        ));
+        assert!(matches!(
+            read_npy(&npy(dict, &[1.0, 2.0])[..]),
+            Err(FloatpackError::Io(_))
+        ));
+        assert!(read_npy(&b"\x93NUMPZ\x01\x00"[..]).is_err());
+    }
+}
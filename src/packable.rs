@@ -0,0 +1,387 @@
+//! Values other than `Decimal`s that pack with the same machinery.
+use crate::decode::decimal_layout;
+use crate::{zip_u8, FloatpackError};
+use rust_decimal::Decimal;
+
+/// A value that packs as `N` `u32` columns, by default four like a `Decimal` does.
+///
+/// Values pack best when their columns change little from one value to the next. Pack them with
+/// a [`GenericPacker`](crate::GenericPacker) and unpack them with
+/// [`try_unpack_as`](crate::try_unpack_as). Each column is a lane of blocks, so narrower values
+/// like `i64`, `u64` and `f64`, which pack into 2 columns, store fewer block headers.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{try_unpack_as, FloatpackError, GenericPacker, Packable};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Tick {
+///     time: u64,
+///     price: u32,
+///     size: u32,
+/// }
+///
+/// impl Packable for Tick {
+///     const TYPE_TAG: u8 = 128;
+///
+///     fn to_columns(&self) -> [u32; 4] {
+///         [self.time as u32, (self.time >> 32) as u32, self.price, self.size]
+///     }
+///
+///     fn from_columns(columns: [u32; 4]) -> Result<Self, FloatpackError> {
+///         let time = columns[0] as u64 | (columns[1] as u64) << 32;
+///         Ok(Tick { time, price: columns[2], size: columns[3] })
+///     }
+/// }
+///
+/// let mut packer = GenericPacker::new();
+/// packer.load(&Tick { time: 1, price: 100, size: 5 });
+/// let packed = packer.finish();
+/// let ticks: Vec<Tick> = try_unpack_as(&packed).unwrap();
+/// assert_eq!(ticks, vec![Tick { time: 1, price: 100, size: 5 }]);
+/// ```
+pub trait Packable<const N: usize = 4>: Sized {
+    /// Identifies the type in packs and their serialization, to reject unpacking them as another
+    /// type. Tags below 128 are reserved for the types floatpack implements this for: 0 is
+    /// `Decimal`, 1 `i64`, 2 `u64`, 3 `f64` and 5 `f32`. 4 marks leg B of
+    /// [`PackedPairs`](crate::PackedPairs) and the level deltas of
+    /// [`PackedVecs`](crate::PackedVecs), 6 `Decimal`s packed in a custom lane layout (see
+    /// [`Packer::with_lane_fn`](crate::Packer::with_lane_fn)).
+    const TYPE_TAG: u8;
+
+    /// The columns of the value.
+    fn to_columns(&self) -> [u32; N];
+
+    /// The value of these columns, or an error if they don't describe a valid value.
+    fn from_columns(columns: [u32; N]) -> Result<Self, FloatpackError>;
+}
+
+impl Packable for Decimal {
+    const TYPE_TAG: u8 = 0;
+
+    fn to_columns(&self) -> [u32; 4] {
+        zip_u8(self.serialize())
+    }
+
+    fn from_columns(columns: [u32; 4]) -> Result<Self, FloatpackError> {
+        let flags = columns[0];
+        if flags & !0x80FF_0000 != 0 || (flags >> 16) & 0xFF > 28 {
+            return Err(FloatpackError::Corrupt(format!(
+                "invalid decimal flags {:#010x}",
+                flags
+            )));
+        }
+        let layout = decimal_layout(Self::TYPE_TAG).expect("layout of Decimals");
+        Ok(layout(columns))
+    }
+}
+
+impl Packable<2> for u64 {
+    const TYPE_TAG: u8 = 2;
+
+    fn to_columns(&self) -> [u32; 2] {
+        [*self as u32, (*self >> 32) as u32]
+    }
+
+    fn from_columns(columns: [u32; 2]) -> Result<Self, FloatpackError> {
+        Ok(columns[0] as u64 | (columns[1] as u64) << 32)
+    }
+}
+
+impl Packable<2> for i64 {
+    const TYPE_TAG: u8 = 1;
+
+    fn to_columns(&self) -> [u32; 2] {
+        (*self as u64).to_columns()
+    }
+
+    fn from_columns(columns: [u32; 2]) -> Result<Self, FloatpackError> {
+        u64::from_columns(columns).map(|v| v as i64)
+    }
+}
+
+impl Packable<2> for f64 {
+    const TYPE_TAG: u8 = 3;
+
+    fn to_columns(&self) -> [u32; 2] {
+        self.to_bits().to_columns()
+    }
+
+    fn from_columns(columns: [u32; 2]) -> Result<Self, FloatpackError> {
+        u64::from_columns(columns).map(f64::from_bits)
+    }
+}
+
+impl Packable<1> for f32 {
+    const TYPE_TAG: u8 = 5;
+
+    fn to_columns(&self) -> [u32; 1] {
+        [self.to_bits()]
+    }
+
+    fn from_columns(columns: [u32; 1]) -> Result<Self, FloatpackError> {
+        Ok(f32::from_bits(columns[0]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Packable;
+    use crate::{
+        monotonicity, pack, quantile, rechunk, try_unpack, try_unpack_as, unpack, unpack_lenient,
+        zip_decode, FloatpackError, GenericPacker, PackedColumns, PackedDecimals, StreamDecoder,
+    };
+    use rust_decimal::Decimal;
+    use std::panic::catch_unwind;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Tick {
+        time: u64,
+        price: u32,
+        size: u32,
+    }
+
+    impl Packable for Tick {
+        const TYPE_TAG: u8 = 200;
+
+        fn to_columns(&self) -> [u32; 4] {
+            [
+                self.time as u32,
+                (self.time >> 32) as u32,
+                self.price,
+                self.size,
+            ]
+        }
+
+        fn from_columns(columns: [u32; 4]) -> Result<Self, FloatpackError> {
+            if columns[3] == 0 {
+                return Err(FloatpackError::Corrupt("tick of size 0".to_string()));
+            }
+            Ok(Tick {
+                time: columns[0] as u64 | (columns[1] as u64) << 32,
+                price: columns[2],
+                size: columns[3],
+            })
+        }
+    }
+
+    fn ticks(n: u64) -> Vec<Tick> {
+        (0..n)
+            .map(|i| Tick {
+                time: 1_700_000_000_000 + i * 250,
+                price: 10_000 + (i % 13) as u32,
+                size: 1 + (i % 5) as u32,
+            })
+            .collect()
+    }
+
+    fn pack_ticks(ticks: &[Tick]) -> PackedDecimals {
+        let mut packer = GenericPacker::new().with_chained_heads();
+        for tick in ticks {
+            packer.load(tick);
+        }
+        packer.finish()
+    }
+
+    #[test]
+    fn round_trip() {
+        for n in [0, 1, 257, 1000] {
+            let ticks = ticks(n);
+            let packed = pack_ticks(&ticks);
+            assert_eq!(packed.type_tag, Tick::TYPE_TAG);
+            assert_eq!(try_unpack_as::<Tick, 4>(&packed).unwrap(), ticks);
+
+            let bytes = packed.to_bytes();
+            assert_eq!(bytes.len(), packed.serialized_len());
+            let decoded = PackedDecimals::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded, packed);
+            assert_eq!(try_unpack_as::<Tick, 4>(&decoded).unwrap(), ticks);
+        }
+    }
+
+    #[test]
+    fn frames_keep_the_tag() {
+        let ticks = ticks(5000);
+        let mut packer = GenericPacker::new().with_flush_bytes(2048);
+        for tick in &ticks {
+            packer.load(tick);
+        }
+        let frames = packer.finish_frames();
+        assert!(frames.len() > 1);
+        let mut unpacked = Vec::new();
+        for frame in &frames {
+            assert_eq!(frame.type_tag, Tick::TYPE_TAG);
+            unpacked.extend(try_unpack_as::<Tick, 4>(frame).unwrap());
+        }
+        assert_eq!(unpacked, ticks);
+    }
+
+    #[test]
+    fn decimals_unchanged() {
+        let values: Vec<Decimal> = (0..600).map(|i| Decimal::new(i * 7, 2)).collect();
+        let packed = pack(&values);
+        assert_eq!(packed.type_tag, 0);
+        assert_eq!(try_unpack_as::<Decimal, 4>(&packed).unwrap(), values);
+        assert_eq!(try_unpack(&packed).unwrap(), values);
+
+        // The tag of decimals is implied, so their serialization didn't grow.
+        let mut tagged = packed.clone();
+        tagged.type_tag = Tick::TYPE_TAG;
+        assert_eq!(tagged.serialized_len(), packed.serialized_len() + 1);
+    }
+
+    #[test]
+    fn type_mismatch() {
+        let packed = pack_ticks(&ticks(300));
+        let mismatch = |e| {
+            matches!(
+                e,
+                FloatpackError::TypeMismatch {
+                    expected: 0,
+                    found: 200
+                }
+            )
+        };
+        assert!(mismatch(try_unpack(&packed).unwrap_err()));
+        assert!(mismatch(try_unpack_as::<Decimal, 4>(&packed).unwrap_err()));
+        let bytes = packed.to_bytes();
+        assert!(mismatch(StreamDecoder::new(&bytes[..]).err().unwrap()));
+
+        let decimals = pack(&[Decimal::ONE]);
+        assert!(matches!(
+            try_unpack_as::<Tick, 4>(&decimals),
+            Err(FloatpackError::TypeMismatch {
+                expected: 200,
+                found: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn not_read_as_decimals() {
+        let packed = pack_ticks(&ticks(3000));
+        let mismatch = |e| {
+            matches!(
+                e,
+                FloatpackError::TypeMismatch {
+                    expected: 0,
+                    found: 200
+                }
+            )
+        };
+        assert!(mismatch(packed.verify().unwrap_err()));
+        assert!(mismatch(rechunk(&packed, 32).unwrap_err()));
+        assert!(mismatch(zip_decode(&packed, &packed).err().unwrap()));
+        // Without a `Result` to fail with, reading them as `Decimal`s panics.
+        assert!(catch_unwind(|| unpack(&packed)).is_err());
+        assert!(catch_unwind(|| packed.iter().count()).is_err());
+        assert!(catch_unwind(|| packed.get(3)).is_err());
+        assert!(catch_unwind(|| packed.histogram(&[Decimal::ZERO, Decimal::ONE])).is_err());
+        assert!(catch_unwind(|| packed.top_k(3)).is_err());
+        assert!(catch_unwind(|| packed.argmin()).is_err());
+        assert!(catch_unwind(|| packed.count_greater(Decimal::ZERO)).is_err());
+        assert!(catch_unwind(|| packed.count_where(|_| true)).is_err());
+        assert!(catch_unwind(|| quantile(&packed, 0.5)).is_err());
+        assert!(catch_unwind(|| monotonicity(&packed)).is_err());
+        let (unpacked, skipped) = unpack_lenient(&packed);
+        assert!(unpacked.is_empty());
+        assert_eq!(skipped.len(), packed.blocks[0].len());
+        // The ticks are still there.
+        assert_eq!(try_unpack_as::<Tick, 4>(&packed).unwrap(), ticks(3000));
+    }
+
+    fn pack_columns<T: Packable<N>, const N: usize>(values: &[T]) -> PackedColumns<N> {
+        let mut packer = GenericPacker::new();
+        for value in values {
+            packer.load(value);
+        }
+        packer.finish()
+    }
+
+    #[test]
+    fn two_columns() {
+        let ints: Vec<i64> = (0..1000).map(|i| 1_700_000_000_000 - i * 37).collect();
+        let packed = pack_columns(&ints);
+        assert_eq!(packed.blocks.len(), 2);
+        let decoded = PackedColumns::<2>::from_bytes(&packed.to_bytes()).unwrap();
+        assert_eq!(decoded, packed);
+        assert_eq!(try_unpack_as::<i64, 2>(&decoded).unwrap(), ints);
+        let serde: PackedColumns<2> =
+            bincode::deserialize(&bincode::serialize(&packed).unwrap()).unwrap();
+        assert_eq!(serde, packed);
+
+        let uints: Vec<u64> = vec![0, 1, u64::MAX, 1 << 32, (1 << 32) - 1];
+        let packed = pack_columns(&uints);
+        assert_eq!(try_unpack_as::<u64, 2>(&packed).unwrap(), uints);
+
+        let floats: Vec<f64> = (0..300).map(|i| i as f64 * 0.25 - 20.0).collect();
+        let packed = pack_columns(&floats);
+        let decoded = PackedColumns::<2>::from_bytes(&packed.to_bytes()).unwrap();
+        assert_eq!(try_unpack_as::<f64, 2>(&decoded).unwrap(), floats);
+        assert!(matches!(
+            try_unpack_as::<i64, 2>(&decoded),
+            Err(FloatpackError::TypeMismatch {
+                expected: 1,
+                found: 3
+            })
+        ));
+
+        // The count of columns is recorded, so they don't decode as 4 column values.
+        let bytes = packed.to_bytes();
+        assert!(matches!(
+            PackedDecimals::from_bytes(&bytes),
+            Err(FloatpackError::Corrupt(_))
+        ));
+        assert!(StreamDecoder::new(&bytes[..]).is_err());
+        assert!(PackedDecimals::from_bytes(&pack(&[Decimal::ONE]).to_bytes()).is_ok());
+        assert!(PackedColumns::<2>::from_bytes(&pack(&[Decimal::ONE]).to_bytes()).is_err());
+    }
+
+    /// An `i64` packed into 4 columns, the upper 2 of them always 0.
+    struct Wide(i64);
+
+    impl Packable for Wide {
+        const TYPE_TAG: u8 = 201;
+
+        fn to_columns(&self) -> [u32; 4] {
+            let [low, high] = self.0.to_columns();
+            [low, high, 0, 0]
+        }
+
+        fn from_columns(columns: [u32; 4]) -> Result<Self, FloatpackError> {
+            i64::from_columns([columns[0], columns[1]]).map(Wide)
+        }
+    }
+
+    #[test]
+    fn fewer_block_headers() {
+        let ints: Vec<i64> = (0..10_000).map(|i| i * i).collect();
+        let narrow = pack_columns(&ints);
+        let wide = pack_columns(&ints.iter().map(|&i| Wide(i)).collect::<Vec<_>>());
+        let blocks = narrow.blocks[0].len();
+        assert_eq!(blocks, wide.blocks[0].len());
+        // Two lanes of bit width 0 less per block, for the byte of the count of columns.
+        assert_eq!(
+            wide.serialized_len() - narrow.serialized_len(),
+            blocks * 2 * 6 - 1
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        let mut ticks = ticks(10);
+        ticks[4].size = 0;
+        let packed = pack_ticks(&ticks);
+        assert!(matches!(
+            try_unpack_as::<Tick, 4>(&packed),
+            Err(FloatpackError::Corrupt(_))
+        ));
+
+        assert!(Decimal::from_columns([29 << 16, 1, 0, 0]).is_err());
+        assert!(Decimal::from_columns([1, 1, 0, 0]).is_err());
+        assert_eq!(
+            Decimal::from_columns([0x8002_0000, 5, 0, 0]).unwrap(),
+            Decimal::new(-5, 2)
+        );
+    }
+}
@@ -0,0 +1,365 @@
+//! Pairs of correlated values, like bid and ask, packing the second leg against the first.
+use crate::format::corrupt;
+use crate::{
+    mantissa, try_unpack, try_unpack_as, zip_u8, FloatpackError, GenericPacker, Packable,
+    PackedDecimals, Packer, MANTISSA_MASK,
+};
+use rust_decimal::Decimal;
+use std::io::{Read, Write};
+
+/// Bit of the flags of a [`Spread`] marking that it holds the mantissa of leg B as it is. Bits
+/// 0 to 15 of the flags of a `Decimal` are always 0.
+const RAW: u32 = 1;
+const SIGN: u32 = 1 << 31;
+
+/// Packs pairs of values moving almost in lockstep, like the bid and ask of a quote.
+///
+/// Leg A is packed like [`Packer`] does. Leg B is packed as its difference to leg A at the same
+/// index, so lanes of B only change as far as the spread does: a constant spread packs to
+/// blocks of bit width 0. Legs of different scales are rescaled to the larger one before
+/// differencing. B keeps its own scale and sign, so both legs round-trip exactly, also where
+/// the spread inverts.
+///
+/// Usage example:
+/// ```
+/// use floatpack::PairPacker;
+/// use rust_decimal_macros::*;
+///
+/// let mut packer = PairPacker::new();
+/// packer.load_pair(dec!(100.01), dec!(100.03));
+/// packer.load_pair(dec!(100.02), dec!(100.04));
+/// let packed = packer.finish();
+/// assert_eq!(
+///     packed.try_unpack().unwrap(),
+///     vec![(dec!(100.01), dec!(100.03)), (dec!(100.02), dec!(100.04))]
+/// );
+/// ```
+pub struct PairPacker {
+    a: Packer,
+    b: GenericPacker<Spread>,
+}
+
+impl Default for PairPacker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PairPacker {
+    pub fn new() -> PairPacker {
+        PairPacker {
+            a: Packer::new(),
+            b: GenericPacker::new(),
+        }
+    }
+
+    /// Add a pair of values, `a` of leg A and `b` of leg B.
+    pub fn load_pair(&mut self, a: Decimal, b: Decimal) {
+        self.a.load_decimal(&a);
+        self.b.load(&Spread::new(a, b));
+    }
+
+    /// Flush pending pairs and return the packed result.
+    pub fn finish(self) -> PackedPairs {
+        PackedPairs {
+            a: self.a.finish(),
+            b: self.b.finish(),
+        }
+    }
+}
+
+/// Pairs packed by a [`PairPacker`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackedPairs {
+    a: PackedDecimals,
+    b: PackedDecimals,
+}
+
+impl PackedPairs {
+    pub fn len(&self) -> usize {
+        self.a.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.a.is_empty()
+    }
+
+    /// The values of leg A.
+    pub fn a(&self) -> &PackedDecimals {
+        &self.a
+    }
+
+    /// Leg B, packed as the differences to leg A (see [`PairPacker`]). Decoding it on its own
+    /// yields no meaningful `Decimal`s.
+    pub fn spreads(&self) -> &PackedDecimals {
+        &self.b
+    }
+
+    /// Unpack the pairs, checking both legs with [`PackedDecimals::verify`] first.
+    pub fn try_unpack(&self) -> Result<Vec<(Decimal, Decimal)>, FloatpackError> {
+        let a = try_unpack(&self.a)?;
+        let spreads: Vec<Spread> = try_unpack_as(&self.b)?;
+        if a.len() != spreads.len() {
+            return Err(FloatpackError::LengthMismatch(a.len(), spreads.len()));
+        }
+        a.into_iter()
+            .zip(spreads)
+            .enumerate()
+            .map(|(i, (a, spread))| {
+                spread
+                    .leg_b(a)
+                    .map(|b| (a, b))
+                    .ok_or_else(|| corrupt(format!("spread {} doesn't fit its leg", i)))
+            })
+            .collect()
+    }
+
+    /// Serialize into the binary format: leg A followed by leg B, each in the format of
+    /// [`PackedDecimals::to_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.a.to_bytes();
+        bytes.extend(self.b.to_bytes());
+        bytes
+    }
+
+    /// Serialize into the binary format, writing to `writer`.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), FloatpackError> {
+        self.a.write_to(&mut writer)?;
+        self.b.write_to(&mut writer)
+    }
+
+    /// Deserialize from the output of [`PackedPairs::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FloatpackError> {
+        let mut reader = bytes;
+        let pairs = Self::read_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(corrupt(format!("{} trailing bytes", reader.len())));
+        }
+        Ok(pairs)
+    }
+
+    /// Deserialize from the binary format, reading from `reader`.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, FloatpackError> {
+        let a = PackedDecimals::read_from(&mut reader)?;
+        let b = PackedDecimals::read_from(&mut reader)?;
+        if b.type_tag != Spread::TYPE_TAG {
+            return Err(FloatpackError::TypeMismatch {
+                expected: Spread::TYPE_TAG,
+                found: b.type_tag,
+            });
+        }
+        if a.len() != b.len() {
+            return Err(FloatpackError::LengthMismatch(a.len(), b.len()));
+        }
+        Ok(PackedPairs { a, b })
+    }
+}
+
+/// A value of leg B relative to the value of leg A at the same index.
+///
+/// Lane 0 holds the flags of B, lanes 1 to 3 the zigzag encoded difference of the mantissas of
+/// the legs at their common scale. Where rescaling or differencing exceeds 96 bits, the flags
+/// are marked [`RAW`] and lanes 1 to 3 hold the mantissa of B instead.
+pub(crate) struct Spread([u32; 4]);
+
+impl Spread {
+    pub(crate) fn new(a: Decimal, b: Decimal) -> Spread {
+        let flags = zip_u8(b.serialize())[0];
+        match difference(a, b) {
+            Some(diff) => {
+                let zigzag = ((diff << 1) ^ (diff >> 127)) as u128 & MANTISSA_MASK;
+                Spread([
+                    flags,
+                    zigzag as u32,
+                    (zigzag >> 32) as u32,
+                    (zigzag >> 64) as u32,
+                ])
+            }
+            None => {
+                let lanes = zip_u8(b.serialize());
+                Spread([flags | RAW, lanes[1], lanes[2], lanes[3]])
+            }
+        }
+    }
+
+    /// The value of leg B, given the value `a` of leg A.
+    pub(crate) fn leg_b(&self, a: Decimal) -> Option<Decimal> {
+        let flags = self.0[0];
+        let scale = (flags >> 16) & 0xFF;
+        let magnitude = mantissa(self.0);
+        let mut b = if flags & RAW != 0 {
+            Decimal::try_from_i128_with_scale(magnitude as i128, scale).ok()?
+        } else {
+            let diff = (magnitude >> 1) as i128 ^ -((magnitude & 1) as i128);
+            let common = scale.max(a.scale());
+            let a = rescaled(a.mantissa(), a.scale(), common)?;
+            let b = a.checked_add(diff)? / 10i128.pow(common - scale);
+            Decimal::try_from_i128_with_scale(b.checked_abs()?, scale).ok()?
+        };
+        b.set_sign_negative(flags & SIGN != 0);
+        Some(b)
+    }
+}
+
+/// Difference of the mantissas of `b` and `a` at their common scale, if it fits 96 bits
+/// zigzag encoded.
+fn difference(a: Decimal, b: Decimal) -> Option<i128> {
+    let common = a.scale().max(b.scale());
+    let a = rescaled(a.mantissa(), a.scale(), common)?;
+    let diff = rescaled(b.mantissa(), b.scale(), common)?.checked_sub(a)?;
+    Some(diff).filter(|d| d.unsigned_abs() < 1 << 95)
+}
+
+fn rescaled(mantissa: i128, scale: u32, common: u32) -> Option<i128> {
+    10i128
+        .checked_pow(common - scale)
+        .and_then(|factor| mantissa.checked_mul(factor))
+}
+
+impl Packable for Spread {
+    const TYPE_TAG: u8 = 4;
+
+    fn to_columns(&self) -> [u32; 4] {
+        self.0
+    }
+
+    fn from_columns(columns: [u32; 4]) -> Result<Self, FloatpackError> {
+        if columns[0] & !(SIGN | 0x00FF_0000 | RAW) != 0 || (columns[0] >> 16) & 0xFF > 28 {
+            return Err(corrupt(format!(
+                "invalid spread flags {:#010x}",
+                columns[0]
+            )));
+        }
+        Ok(Spread(columns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PackedPairs, PairPacker};
+    use crate::{pack, FloatpackError};
+    use rand::{Rng, SeedableRng};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn pack_pairs(pairs: &[(Decimal, Decimal)]) -> PackedPairs {
+        let mut packer = PairPacker::new();
+        for (a, b) in pairs {
+            packer.load_pair(*a, *b);
+        }
+        packer.finish()
+    }
+
+    /// Bids of a random walk in cents, asks 1 to 3 cents above, inverted every 500th quote.
+    fn quotes(n: usize) -> Vec<(Decimal, Decimal)> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut bid = 10_000i64;
+        (0..n)
+            .map(|i| {
+                bid += rng.gen_range(-3..=3);
+                let spread = if i % 500 == 499 {
+                    -2
+                } else {
+                    rng.gen_range(1..=3)
+                };
+                (Decimal::new(bid, 2), Decimal::new(bid + spread, 2))
+            })
+            .collect()
+    }
+
+    /// Pairs are equal by representation, including scale and the sign of zero.
+    fn assert_exact(unpacked: &[(Decimal, Decimal)], pairs: &[(Decimal, Decimal)]) {
+        assert_eq!(unpacked.len(), pairs.len());
+        for (u, p) in unpacked.iter().zip(pairs) {
+            assert_eq!(u.0.serialize(), p.0.serialize());
+            assert_eq!(u.1.serialize(), p.1.serialize());
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let pairs = quotes(5000);
+        let packed = pack_pairs(&pairs);
+        assert_eq!(packed.len(), pairs.len());
+        assert_exact(&packed.try_unpack().unwrap(), &pairs);
+        let decoded = PackedPairs::from_bytes(&packed.to_bytes()).unwrap();
+        assert_eq!(decoded, packed);
+        assert_exact(&decoded.try_unpack().unwrap(), &pairs);
+
+        assert!(pack_pairs(&[]).try_unpack().unwrap().is_empty());
+    }
+
+    #[test]
+    fn spread_widths() {
+        let pairs = quotes(5000);
+        let packed = pack_pairs(&pairs);
+        let bits = |lane: &[crate::Block]| lane.iter().map(|b| b.bits).max().unwrap();
+        let spreads = packed.spreads();
+        // Leg B keeps its scale and sign, inverted spreads are small zigzag encoded differences.
+        assert_eq!(bits(&spreads.blocks[0]), 0);
+        assert!(bits(&spreads.blocks[1]) <= 3);
+        assert_eq!(bits(&spreads.blocks[2]), 0);
+        assert_eq!(bits(&spreads.blocks[3]), 0);
+
+        let asks: Vec<_> = pairs.iter().map(|(_, b)| *b).collect();
+        let independent = pack(&asks);
+        assert!(bits(&independent.blocks[1]) > bits(&spreads.blocks[1]));
+        assert!(spreads.serialized_len() < independent.serialized_len());
+
+        let constant: Vec<_> = pairs.iter().map(|(a, _)| (*a, a + dec!(0.01))).collect();
+        let packed = pack_pairs(&constant);
+        assert!(packed
+            .spreads()
+            .blocks
+            .iter()
+            .flatten()
+            .all(|b| b.bits == 0));
+    }
+
+    #[test]
+    fn mixed_scales() {
+        let mut negative_zero = dec!(0.000);
+        negative_zero.set_sign_negative(true);
+        let pairs = vec![
+            (dec!(100.01), dec!(100.1)),
+            (dec!(100.1), dec!(100.015)),
+            (dec!(-0.5), dec!(0.5)),
+            (dec!(1), negative_zero),
+            (negative_zero, dec!(-0.25)),
+            (Decimal::MAX, dec!(1.5)),
+            (dec!(0.0000000000000000000000000001), Decimal::MAX),
+            (Decimal::MIN, Decimal::MAX),
+            (dec!(7), dec!(7.000)),
+        ];
+        let packed = pack_pairs(&pairs);
+        assert_exact(&packed.try_unpack().unwrap(), &pairs);
+        assert_exact(
+            &PackedPairs::from_bytes(&packed.to_bytes())
+                .unwrap()
+                .try_unpack()
+                .unwrap(),
+            &pairs,
+        );
+    }
+
+    #[test]
+    fn rejects_other_packs() {
+        let values = pack(&[dec!(1), dec!(2)]);
+        let mut bytes = values.to_bytes();
+        bytes.extend(values.to_bytes());
+        assert!(matches!(
+            PackedPairs::from_bytes(&bytes),
+            Err(FloatpackError::TypeMismatch {
+                expected: 4,
+                found: 0
+            })
+        ));
+
+        let packed = pack_pairs(&quotes(10));
+        let bytes = packed.to_bytes();
+        assert!(PackedPairs::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(PackedPairs::from_bytes(&trailing).is_err());
+    }
+}
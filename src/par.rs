@@ -0,0 +1,217 @@
+//! Parallel iteration with rayon, behind the `rayon` feature.
+use crate::decode::{Blocks, Values};
+use crate::{unpack, PackedDecimals};
+use rayon::iter::plumbing::{bridge, Consumer, Producer, ProducerCallback, UnindexedConsumer};
+use rayon::prelude::*;
+use rust_decimal::Decimal;
+
+/// Parallel iterator over the values of a `PackedDecimals`.
+///
+/// Each task decodes the blocks of its own range of values. Ranges are at least one block long,
+/// so a block is decoded by at most two tasks. With chained heads, a task has to decode all
+/// blocks preceding its range as well, which defeats most of the parallelism.
+///
+/// Created by [`PackedDecimals::par_iter`].
+pub struct ParValues<'a> {
+    packed: &'a PackedDecimals,
+}
+
+impl PackedDecimals {
+    /// Iterate over the values in parallel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block length is not supported by any bitpacker or the pack holds values of
+    /// another type than `Decimal`.
+    pub fn par_iter(&self) -> ParValues<'_> {
+        ParValues {
+            packed: self.decimals(),
+        }
+    }
+}
+
+impl<'a> IntoParallelIterator for &'a PackedDecimals {
+    type Item = Decimal;
+    type Iter = ParValues<'a>;
+
+    fn into_par_iter(self) -> ParValues<'a> {
+        self.par_iter()
+    }
+}
+
+impl ParallelIterator for ParValues<'_> {
+    type Item = Decimal;
+
+    fn drive_unindexed<C: UnindexedConsumer<Decimal>>(self, consumer: C) -> C::Result {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.packed.count)
+    }
+}
+
+impl IndexedParallelIterator for ParValues<'_> {
+    fn len(&self) -> usize {
+        self.packed.count
+    }
+
+    fn drive<C: Consumer<Decimal>>(self, consumer: C) -> C::Result {
+        bridge(self, consumer)
+    }
+
+    fn with_producer<CB: ProducerCallback<Decimal>>(self, callback: CB) -> CB::Output {
+        callback.callback(ValuesProducer {
+            packed: self.packed,
+            start: 0,
+            end: self.packed.count,
+        })
+    }
+}
+
+/// Below this count of blocks, [`unpack_parallel`] decodes serially.
+const PARALLEL_BLOCKS: usize = 16;
+
+/// Unpack and decompress Decimals, decoding blocks in parallel.
+///
+/// Workers decode contiguous ranges of blocks straight into their part of the output. Small
+/// inputs and packs with chained heads, whose blocks can't be decoded independently, are
+/// decoded serially. The result is the same as of [`unpack`].
+///
+/// # Panics
+///
+/// Panics if `values` is malformed (see [`PackedDecimals::verify`]).
+pub fn unpack_parallel(values: &PackedDecimals) -> Vec<Decimal> {
+    if values.chained() || values.blocks[0].len() < PARALLEL_BLOCKS {
+        return unpack(values);
+    }
+    if let Err(e) = values.verify() {
+        panic!("{}", e);
+    }
+    let blocks = Blocks::new(values);
+    let mut result = vec![Decimal::ZERO; values.count];
+    result
+        .par_chunks_mut(values.block_values())
+        .enumerate()
+        .with_min_len(PARALLEL_BLOCKS / 2)
+        .for_each_with(blocks, |blocks, (n, chunk)| {
+            blocks.seek(n, [0; 4]);
+            let (lanes, _) = blocks.decode_next().expect("validated block");
+            for (i, v) in chunk.iter_mut().enumerate() {
+                *v = blocks.decimal_at(&lanes, i);
+            }
+        });
+    result
+}
+
+/// A range of values that rayon splits into tasks.
+struct ValuesProducer<'a> {
+    packed: &'a PackedDecimals,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Producer for ValuesProducer<'a> {
+    type Item = Decimal;
+    type IntoIter = Values<'a>;
+
+    fn into_iter(self) -> Values<'a> {
+        Values::slice(self.packed, self.start, self.end)
+    }
+
+    fn min_len(&self) -> usize {
+        self.packed.block_values()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let mid = self.start + index;
+        (
+            ValuesProducer { end: mid, ..self },
+            ValuesProducer { start: mid, ..self },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unpack_parallel;
+    use crate::{pack, unpack, Packer};
+    use rayon::prelude::*;
+    use rust_decimal::Decimal;
+
+    fn values() -> Vec<Decimal> {
+        (0..20_000)
+            .map(|v| Decimal::new(v * 31 % 1009, 2))
+            .collect()
+    }
+
+    #[test]
+    fn sum() {
+        let packed = pack(&values());
+        let sum = packed
+            .par_iter()
+            .fold(Decimal::default, |a, b| a + b)
+            .reduce(Decimal::default, |a, b| a + b);
+        assert_eq!(sum, values().iter().sum());
+    }
+
+    #[test]
+    fn ordered() {
+        let values = values();
+        for packer in [Packer::new(), Packer::new().with_chained_heads()] {
+            let packed = crate::tests::pack_with(packer, &values);
+            let collected: Vec<Decimal> = packed.par_iter().collect();
+            assert_eq!(collected, unpack(&packed));
+            let reversed: Vec<Decimal> = packed.par_iter().rev().collect();
+            assert!(reversed.iter().eq(values.iter().rev()));
+            let doubled = pack(&values.iter().map(|v| v * Decimal::TWO).collect::<Vec<_>>());
+            assert!(packed
+                .par_iter()
+                .zip(doubled.par_iter())
+                .all(|(v, d)| v * Decimal::TWO == d));
+            let skipped: Vec<Decimal> = packed.par_iter().skip(300).take(1000).collect();
+            assert_eq!(skipped, values[300..1300]);
+        }
+    }
+
+    #[test]
+    fn slices() {
+        let values = values();
+        for packer in [Packer::new(), Packer::new().with_chained_heads()] {
+            let packed = crate::tests::pack_with(packer, &values);
+            for (start, end) in [
+                (0, 0),
+                (5, 5),
+                (0, 257),
+                (3, 250),
+                (256, 258),
+                (300, 20_000),
+            ] {
+                let slice = crate::decode::Values::slice(&packed, start, end);
+                assert_eq!(slice.len(), end - start);
+                assert!(slice.rev().eq(values[start..end].iter().rev().copied()));
+                let slice = crate::decode::Values::slice(&packed, start, end);
+                assert_eq!(slice.collect::<Vec<_>>(), values[start..end]);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_unpack() {
+        let values: Vec<Decimal> = (0..150_000)
+            .map(|v| Decimal::new(v * 31 % 1009, 2))
+            .collect();
+        for packer in [
+            Packer::new(),
+            Packer::new().with_chained_heads(),
+            Packer::new().with_block_len(32).unwrap(),
+            Packer::new().with_sign_magnitude().with_adaptive_encoding(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values);
+            assert_eq!(unpack_parallel(&packed), values);
+        }
+        for n in [0, 1, 257 * 16 - 1, 257 * 16, 257 * 16 + 1] {
+            assert_eq!(unpack_parallel(&pack(&values[..n])), values[..n]);
+        }
+    }
+}
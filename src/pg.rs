@@ -0,0 +1,77 @@
+//! Loading Postgres `NUMERIC` columns with sqlx, behind the `sqlx` feature.
+use crate::{FloatpackError, Packer};
+use rust_decimal::Decimal;
+use sqlx::postgres::PgRow;
+use sqlx::Row;
+
+impl Packer {
+    /// Load the `NUMERIC` column `column` of every row.
+    ///
+    /// Returns the count of values loaded. Stops at the first row whose value can't be converted
+    /// to a `Decimal`, e.g. because it's out of range, NaN or NULL, and reports its index in
+    /// [`FloatpackError::Row`]. The values of the preceding rows stay loaded.
+    pub fn load_pg_rows(&mut self, rows: &[PgRow], column: &str) -> Result<usize, FloatpackError> {
+        self.load_column(rows.iter().map(|row| row.try_get(column)))
+    }
+
+    fn load_column<I>(&mut self, values: I) -> Result<usize, FloatpackError>
+    where
+        I: Iterator<Item = Result<Option<Decimal>, sqlx::Error>>,
+    {
+        let mut loaded = 0;
+        for (row, value) in values.enumerate() {
+            let reason = match value {
+                Ok(Some(value)) => {
+                    self.load_decimal(&value);
+                    loaded += 1;
+                    continue;
+                }
+                Ok(None) => "NULL".to_string(),
+                Err(e) => e.to_string(),
+            };
+            return Err(FloatpackError::Row { row, reason });
+        }
+        Ok(loaded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FloatpackError, Packer};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::*;
+
+    fn out_of_range() -> sqlx::Error {
+        sqlx::Error::ColumnDecode {
+            index: "\"price\"".into(),
+            source: "NUMERIC value exceeds the range of Decimal".into(),
+        }
+    }
+
+    #[test]
+    fn loads_column() {
+        let values = [dec!(1.5), dec!(-2.25), Decimal::MAX];
+        let mut packer = Packer::new();
+        let loaded = packer.load_column(values.iter().map(|v| Ok(Some(*v))));
+        assert_eq!(loaded.unwrap(), 3);
+        assert_eq!(crate::unpack(&packer.finish()), values);
+    }
+
+    #[test]
+    fn reports_rows() {
+        let mut packer = Packer::new();
+        let rows = vec![Ok(Some(dec!(1))), Ok(Some(dec!(2))), Err(out_of_range())];
+        match packer.load_column(rows.into_iter()) {
+            Err(FloatpackError::Row { row: 2, reason }) => assert!(reason.contains("range")),
+            other => panic!("unexpected {:?}", other),
+        }
+        match packer.load_column(vec![Ok(Some(dec!(3))), Ok(None)].into_iter()) {
+            Err(FloatpackError::Row { row: 1, reason }) => assert_eq!(reason, "NULL"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            crate::unpack(&packer.finish()),
+            vec![dec!(1), dec!(2), dec!(3)]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Lossy packing of decimals at `f32` precision.
+use crate::format::corrupt;
+use crate::{try_unpack_as, FloatpackError, GenericPacker, PackedColumns};
+use rust_decimal::prelude::*;
+
+/// Packs decimals rounded to the nearest `f32`, in a single column of its bits.
+///
+/// This is lossy by design and meant for telemetry where about 7 significant digits suffice:
+/// values are 4 bytes instead of the 16 of a `Decimal` before compression starts, and a
+/// single lane of blocks stores fewer block headers. The relative error of each value is at
+/// most 2^-24 (about 6e-8) down to magnitudes of 1e-15; below, the 28 decimal places of a
+/// `Decimal` add an absolute error of up to 5e-29. The scale of the values is lost,
+/// [`unpack_f32`] returns each `f32` as the decimal it is exactly.
+///
+/// Packing the unpacked values again results in the same pack.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{unpack_f32, F32Packer};
+/// use rust_decimal_macros::*;
+///
+/// let mut packer = F32Packer::new();
+/// packer.load(&dec!(21.5)).unwrap();
+/// packer.load(&dec!(0.1)).unwrap();
+/// let values = unpack_f32(&packer.finish()).unwrap();
+/// assert_eq!(values[0], dec!(21.5));
+/// assert_eq!(values[1], dec!(0.100000001490116119384765625));
+/// ```
+pub struct F32Packer {
+    packer: GenericPacker<f32, 1>,
+}
+
+impl Default for F32Packer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl F32Packer {
+    pub fn new() -> F32Packer {
+        F32Packer {
+            packer: GenericPacker::new(),
+        }
+    }
+
+    /// Add a single value, rounded to the nearest `f32`.
+    ///
+    /// Values rounding to an `f32` beyond the range of `Decimal`, i.e. of a magnitude above
+    /// about 7.9e28, are rejected as [`FloatpackError::OutOfRange`], as they wouldn't unpack.
+    pub fn load(&mut self, value: &Decimal) -> Result<(), FloatpackError> {
+        let quantized = value
+            .to_f32()
+            .filter(|f| f.is_finite())
+            .filter(|&f| decimal_of(f).and_then(|d| d.to_f32()) == Some(f))
+            .ok_or(FloatpackError::OutOfRange(*value))?;
+        self.packer.load(&quantized);
+        Ok(())
+    }
+
+    /// Flush pending values and return the packed result.
+    pub fn finish(self) -> PackedColumns<1> {
+        self.packer.finish()
+    }
+}
+
+/// Unpack the values of an [`F32Packer`], each as the decimal its `f32` is exactly.
+pub fn unpack_f32(packed: &PackedColumns<1>) -> Result<Vec<Decimal>, FloatpackError> {
+    try_unpack_as::<f32, 1>(packed)?
+        .into_iter()
+        .map(|f| decimal_of(f).ok_or_else(|| corrupt(format!("f32 {} is not a decimal", f))))
+        .collect()
+}
+
+/// `f` as it is, not rounded to the digits an `f32` guarantees like `Decimal::from_f32` does,
+/// which would round to a different `f32` for a part of the values.
+fn decimal_of(f: f32) -> Option<Decimal> {
+    Decimal::from_f32_retain(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unpack_f32, F32Packer};
+    use crate::{pack, FloatpackError, GenericPacker};
+    use rust_decimal::prelude::*;
+    use rust_decimal_macros::dec;
+
+    fn pack_f32(values: &[Decimal]) -> Result<crate::PackedColumns<1>, FloatpackError> {
+        let mut packer = F32Packer::new();
+        for v in values {
+            packer.load(v)?;
+        }
+        Ok(packer.finish())
+    }
+
+    #[test]
+    fn error_bound() {
+        let mut values: Vec<Decimal> = (0..2000i64)
+            .map(|i| Decimal::new(10_000 + i * 7 % 300, 2))
+            .collect();
+        // Temperatures, counters and small and large magnitudes.
+        values.extend((0..2000i64).map(|i| Decimal::new(21_375 + (i * 37 % 500) - 250, 3)));
+        values.extend((0..2000i64).map(|i| Decimal::new(1_000_000_007 * i, 0)));
+        values.extend((1..2000i64).map(|i| Decimal::new(i * 9973, 12)));
+        values.extend([dec!(-1.5), dec!(1e-15), dec!(-7922816200000000000000000000)]);
+
+        let packed = pack_f32(&values).unwrap();
+        let unpacked = unpack_f32(&packed).unwrap();
+        assert_eq!(unpacked.len(), values.len());
+        let max_error = values
+            .iter()
+            .zip(&unpacked)
+            .filter(|(v, _)| !v.is_zero())
+            .map(|(v, u)| ((u - v) / v).abs().to_f64().unwrap())
+            .fold(0.0, f64::max);
+        assert!(max_error <= 2f64.powi(-24), "{}", max_error);
+        assert!(max_error > 0.0);
+        assert!(packed.serialized_len() < pack(&values).serialized_len());
+
+        // Packing the unpacked values is a fixed point.
+        let again = pack_f32(&unpacked).unwrap();
+        assert_eq!(again, packed);
+        assert_eq!(unpack_f32(&again).unwrap(), unpacked);
+        assert!(pack_f32(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        for v in [Decimal::MAX, Decimal::MIN] {
+            let mut packer = F32Packer::new();
+            assert!(matches!(packer.load(&v), Err(FloatpackError::OutOfRange(e)) if e == v));
+        }
+        // Tiny values round to the same `Decimal` again.
+        let tiny: Vec<_> = (1..1000).map(|i| Decimal::new(i * 7919, 28)).collect();
+        assert_eq!(unpack_f32(&pack_f32(&tiny).unwrap()).unwrap(), tiny);
+        assert!(pack_f32(&[Decimal::ZERO, dec!(-0.00)]).is_ok());
+
+        // f32s beyond the range of `Decimal` don't unpack.
+        let mut packer = GenericPacker::<f32, 1>::new();
+        packer.load(&1e30);
+        assert!(matches!(
+            unpack_f32(&packer.finish()),
+            Err(FloatpackError::Corrupt(_))
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+//! Packing of sources delivering their values newest first.
+use crate::decode::Blocks;
+use crate::{PackedDecimals, Packer};
+use rust_decimal::Decimal;
+
+/// Packs values arriving newest first into chronological order (see [`Packer::newest_first`]).
+///
+/// Values are staged in packed form as they arrive, in a second phase [`NewestFirst::finish`]
+/// replays them one block at a time, oldest block first, into the configured packer. Besides
+/// the packed staging, memory stays bounded by one block of values.
+pub struct NewestFirst {
+    staging: Packer,
+    packer: Packer,
+}
+
+impl Packer {
+    /// Switch to loading values newest first, packing them in chronological order.
+    ///
+    /// The result is the same as loading the reversed input into this packer, the options
+    /// (dead band, frames, encodings) apply in chronological order as well.
+    pub fn newest_first(self) -> NewestFirst {
+        NewestFirst {
+            staging: Packer::new(),
+            packer: self,
+        }
+    }
+}
+
+impl NewestFirst {
+    /// Add the value preceding all values loaded so far.
+    pub fn load_decimal(&mut self, value: &Decimal) {
+        self.staging.load_decimal(value);
+    }
+
+    /// Replay the staged values in chronological order into the packer and return it.
+    pub fn into_packer(self) -> Packer {
+        let staged = self.staging.finish();
+        let mut packer = self.packer;
+        for n in (0..staged.blocks[0].len()).rev() {
+            let mut blocks = Blocks::new(&staged);
+            blocks.skip_to(n);
+            for v in blocks.next().into_iter().flatten().rev() {
+                packer.load_decimal(&v);
+            }
+        }
+        packer
+    }
+
+    /// Flush pending values and return the packed result, see [`Packer::finish`].
+    pub fn finish(self) -> PackedDecimals {
+        self.into_packer().finish()
+    }
+
+    /// Flush pending values and return all frames, see [`Packer::finish_frames`].
+    pub fn finish_frames(self) -> Vec<PackedDecimals> {
+        self.into_packer().finish_frames()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pack, Packer};
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn chronological() {
+        let values: Vec<Decimal> = (0..1000).map(|v| Decimal::new(v * 7 % 90, 1)).collect();
+        for n in [0, 1, 256, 257, 258, 1000] {
+            let mut packer = Packer::new().newest_first();
+            for v in values[..n].iter().rev() {
+                packer.load_decimal(v);
+            }
+            let packed = packer.finish();
+            assert_eq!(packed.to_bytes(), pack(&values[..n]).to_bytes());
+        }
+    }
+
+    #[test]
+    fn options() {
+        let values: Vec<Decimal> = (0..700).map(|v| Decimal::new(v * v % 1000, 2)).collect();
+        let configure = || {
+            Packer::new()
+                .with_chained_heads()
+                .with_adaptive_encoding()
+                .with_flush_bytes(600)
+        };
+        let mut reversed = configure().newest_first();
+        let mut packer = configure();
+        for v in &values {
+            packer.load_decimal(v);
+        }
+        for v in values.iter().rev() {
+            reversed.load_decimal(v);
+        }
+        assert_eq!(reversed.finish_frames(), packer.finish_frames());
+    }
+}
@@ -0,0 +1,280 @@
+//! Reading what's left of damaged streams of packed frames.
+use crate::bitpacker::AnyBitPacker;
+use crate::format::{
+    corrupt, read_header_v2, read_record_v2, validate_count, validate_lanes, MAGIC, VERSION,
+};
+use crate::{repacker, try_unpack, Block, FloatpackError, PackedDecimals, Packer};
+use std::io::{self, Read};
+
+/// Summary of a [`salvage`] run.
+#[derive(Debug, Default)]
+pub struct SalvageReport {
+    /// Count of frames read completely.
+    pub frames: usize,
+    /// Count of decimals salvaged, including those of a frame read partially.
+    pub values: usize,
+    /// Offset in the input of the frame header or block record salvaging stopped at, or the
+    /// length of the input if nothing was damaged.
+    pub offset: u64,
+    /// Why salvaging stopped before the end of the input, `None` if it didn't.
+    pub error: Option<FloatpackError>,
+}
+
+/// Read the frames of serialized [`PackedDecimals`] written one after the other, up to the
+/// first damaged one, e.g. to save what's left of a file whose writer ran out of disk space.
+///
+/// Frames are read one block record at a time and each record is checked before its values
+/// are kept. Salvaging stops at the first structural inconsistency: a wrong checksum, a count
+/// or length that can't be, an invalid block or a truncated record. The values of all blocks
+/// before it are repacked into a single pack with the options of the first frame, and the
+/// report tells where and why salvaging stopped. An input without any frame, including an
+/// empty one, is reported as truncated. No input makes this panic.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{pack, salvage, unpack};
+/// use rust_decimal::Decimal;
+///
+/// let values: Vec<Decimal> = (0..1000).map(Decimal::from).collect();
+/// let bytes = pack(&values).to_bytes();
+/// let (packed, report) = salvage(&bytes[..bytes.len() - 10]);
+/// assert_eq!(unpack(&packed), values[..3 * 257]);
+/// assert!(report.error.is_some());
+/// ```
+pub fn salvage<R: Read>(reader: R) -> (PackedDecimals, SalvageReport) {
+    let mut reader = Counting {
+        reader,
+        read: 0,
+        start: 0,
+    };
+    let mut packer: Option<Packer> = None;
+    let mut report = SalvageReport::default();
+    loop {
+        let mut frame = PackedDecimals::default();
+        let error = match read_frame(&mut reader, &mut frame) {
+            Ok(true) => None,
+            Ok(false) => break,
+            Err(error) => Some(error),
+        };
+        let kept = if frame.is_empty() {
+            Ok(())
+        } else {
+            let packer = match &mut packer {
+                Some(packer) => Ok(packer),
+                None => repacker(&frame, frame.block_len).map(|p| packer.insert(p)),
+            };
+            packer.and_then(|packer| {
+                let values = try_unpack(&frame)?;
+                for value in &values {
+                    packer.load_decimal(value);
+                }
+                report.values += values.len();
+                Ok(())
+            })
+        };
+        if let Some(error) = error.or(kept.err()) {
+            report.error = Some(error);
+            break;
+        }
+        report.frames += 1;
+    }
+    report.offset = reader.start;
+    if report.frames == 0 && report.error.is_none() {
+        report.error = Some(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    (packer.unwrap_or_default().finish(), report)
+}
+
+/// Reads a frame into `packed`, `false` at the end of the input. On an error, `packed` holds
+/// the blocks read before it. [`Counting::start`] is left at the offset of the structure read
+/// last.
+fn read_frame<R: Read>(
+    reader: &mut Counting<R>,
+    packed: &mut PackedDecimals,
+) -> Result<bool, FloatpackError> {
+    reader.start = reader.read;
+    let mut version = [0u8; 5];
+    let len = read_up_to(reader, &mut version)?;
+    if len == 0 {
+        return Ok(false);
+    }
+    if len < version.len() {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    if version[..4] != MAGIC {
+        return Err(corrupt("missing magic bytes".into()));
+    }
+    if version[4] != VERSION {
+        return Err(FloatpackError::UnsupportedVersion(version[4]));
+    }
+    let header = read_header_v2(reader)?;
+    if header.columns != 4 || header.type_tag != 0 {
+        return Err(FloatpackError::TypeMismatch {
+            expected: 0,
+            found: header.type_tag,
+        });
+    }
+    AnyBitPacker::new(header.block_len)
+        .ok_or(FloatpackError::UnsupportedBlockLen(header.block_len))?;
+    validate_count(header.count, header.blocks, header.block_len + 1)?;
+    packed.block_len = header.block_len;
+
+    for n in 0..header.blocks {
+        reader.start = reader.read;
+        let mut record: [Vec<Block>; 4] = Default::default();
+        let read = read_record_v2(reader, &header, n, &mut record).and_then(|_| {
+            let lanes: [&Block; 4] = std::array::from_fn(|i| &record[i][0]);
+            validate_lanes(lanes, header.block_len, n)
+        });
+        if let Err(e) = read {
+            // All blocks but the last one are full.
+            packed.count = n * packed.block_values();
+            return Err(e);
+        }
+        for (lane, mut blocks) in packed.blocks.iter_mut().zip(record) {
+            lane.append(&mut blocks);
+        }
+    }
+    packed.count = header.count;
+    Ok(true)
+}
+
+/// Like `read_exact`, but returns the count of bytes read if the input ends early.
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+/// Counts the bytes read through it.
+struct Counting<R> {
+    reader: R,
+    read: u64,
+    /// Offset of the structure being read.
+    start: u64,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.read += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::salvage;
+    use crate::{pack, unpack, FloatpackError, Packer};
+    use rust_decimal::Decimal;
+
+    fn prices(n: usize) -> Vec<Decimal> {
+        (0..n as i64)
+            .map(|i| Decimal::new(100_000 + i * 7919 % 503 - 251, 2))
+            .collect()
+    }
+
+    #[test]
+    fn intact() {
+        let values = prices(1000);
+        let mut bytes = pack(&values[..600]).to_bytes();
+        bytes.extend(pack(&values[600..]).to_bytes());
+        let (packed, report) = salvage(&bytes[..]);
+        assert_eq!(unpack(&packed), values);
+        assert_eq!((report.frames, report.values), (2, 1000));
+        assert_eq!(report.offset, bytes.len() as u64);
+        assert!(report.error.is_none());
+
+        let (packed, report) = salvage(&[][..]);
+        assert!(packed.is_empty());
+        assert!(matches!(report.error, Some(FloatpackError::Io(_))));
+    }
+
+    #[test]
+    fn corrupted() {
+        let values = prices(2000);
+        let first = pack(&values[..1000]).to_bytes_with_checksums();
+        let mut bytes = first.clone();
+        bytes.extend(pack(&values[1000..]).to_bytes());
+        let salvaged = |bytes: &[u8]| {
+            let (packed, report) = salvage(bytes);
+            assert_eq!(report.values, packed.len());
+            assert_eq!(unpack(&packed), values[..packed.len()]);
+            (packed.len(), report)
+        };
+
+        // The header of the first frame.
+        let mut header = bytes.clone();
+        header[5] = 0xFF;
+        let (len, report) = salvaged(&header);
+        assert_eq!((len, report.frames, report.offset), (0, 0, 0));
+        assert!(matches!(report.error, Some(FloatpackError::Corrupt(_))));
+
+        // The checksum of the last record of the first frame.
+        let mut checksum = bytes.clone();
+        checksum[first.len() - 1] ^= 1;
+        let (len, report) = salvaged(&checksum);
+        assert_eq!((len, report.frames), (3 * 257, 0));
+        assert!(matches!(
+            report.error,
+            Some(FloatpackError::ChecksumMismatch(3))
+        ));
+        assert!(report.offset > 24 && report.offset < first.len() as u64);
+
+        // The magic of the second frame.
+        let mut magic = bytes.clone();
+        magic[first.len()] = 0;
+        let (len, report) = salvaged(&magic);
+        assert_eq!(
+            (len, report.frames, report.offset),
+            (1000, 1, first.len() as u64)
+        );
+        assert!(report.error.is_some());
+
+        // An impossible count of the second frame.
+        let mut count = bytes.clone();
+        count[first.len() + 8..first.len() + 16].copy_from_slice(&u64::MAX.to_le_bytes());
+        let (len, report) = salvaged(&count);
+        assert_eq!(
+            (len, report.frames, report.offset),
+            (1000, 1, first.len() as u64)
+        );
+        assert!(matches!(report.error, Some(FloatpackError::Corrupt(_))));
+
+        // Truncated anywhere.
+        let mut last = 0;
+        for end in (0..bytes.len()).step_by(97) {
+            let (len, report) = salvaged(&bytes[..end]);
+            assert!(len >= last);
+            // Whole blocks of either frame.
+            assert_eq!(len.checked_sub(1000).unwrap_or(len) % 257, 0);
+            assert!(report.offset <= end as u64);
+            assert!(matches!(report.error, Some(FloatpackError::Io(_))));
+            last = len;
+        }
+    }
+
+    #[test]
+    fn never_panics() {
+        let mut packer = Packer::new().with_chained_heads().with_block_bounds();
+        for v in &prices(700) {
+            packer.load_decimal(v);
+        }
+        let bytes = packer.finish().to_bytes();
+        for i in 0..bytes.len() {
+            for flip in [0x01, 0x80, 0xFF] {
+                let mut damaged = bytes.clone();
+                damaged[i] ^= flip;
+                let (packed, report) = salvage(&damaged[..]);
+                assert_eq!(report.values, packed.len());
+            }
+        }
+    }
+}
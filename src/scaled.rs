@@ -0,0 +1,318 @@
+//! Values packed normalized, with their original scales kept on the side.
+use crate::format::corrupt;
+use crate::{
+    try_unpack, try_unpack_as, FloatpackError, GenericPacker, Packable, PackedColumns,
+    PackedDecimals, Packer,
+};
+use rust_decimal::Decimal;
+use std::io::{Read, Write};
+
+/// Bit of a scale run marking that its values are negative zeros, which normalize to zero.
+const NEGATIVE_ZERO: u64 = 1 << 5;
+const SCALE_MASK: u64 = NEGATIVE_ZERO - 1;
+const RUN_SHIFT: u32 = 6;
+
+/// Packs values without their trailing zeros, keeping the original scales of the values on the
+/// side so that `1.50` and `1.5` both round-trip exactly.
+///
+/// Values are stored [normalized](Decimal::normalize) like [`Packer`] does, so a column whose
+/// scale is larger than its digits need, e.g. prices of 2 decimals stored at scale 8, packs its
+/// narrow mantissas instead of ones with trailing zeros. This pays off as long as the
+/// normalized scales stay the same: where only some values end in zeros, normalizing changes
+/// the scale from value to value, which packs wider than the trailing zeros did.
+///
+/// The original scales are stored as runs of equal scales, each a `u64` packed of its own.
+/// Scales rarely change in most columns, so this costs little more than a second frame header
+/// of 24 bytes. A column whose scale changes from value to value pays a bitpacked `u64` per
+/// change.
+///
+/// Usage example:
+/// ```
+/// use floatpack::ScalePacker;
+/// use rust_decimal_macros::*;
+///
+/// let mut packer = ScalePacker::new();
+/// for v in [dec!(1.50000000), dec!(1.5), dec!(2.25000000)] {
+///     packer.load_decimal(&v);
+/// }
+/// let packed = packer.finish();
+/// let unpacked = packed.try_unpack().unwrap();
+/// assert_eq!(unpacked[0].to_string(), "1.50000000");
+/// assert_eq!(unpacked[1].to_string(), "1.5");
+/// assert_eq!(packed.scale_runs(), 3);
+/// ```
+pub struct ScalePacker {
+    values: Packer,
+    runs: GenericPacker<u64, 2>,
+    /// The scale of the current run, with [`NEGATIVE_ZERO`], and its length.
+    run: Option<(u64, u64)>,
+    scale_runs: usize,
+}
+
+impl Default for ScalePacker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScalePacker {
+    pub fn new() -> ScalePacker {
+        ScalePacker::with_packer(Packer::new())
+    }
+
+    /// Pack the normalized values with the options of `packer`.
+    pub fn with_packer(packer: Packer) -> ScalePacker {
+        ScalePacker {
+            values: packer,
+            runs: GenericPacker::new(),
+            run: None,
+            scale_runs: 0,
+        }
+    }
+
+    /// Add a value.
+    pub fn load_decimal(&mut self, value: &Decimal) {
+        let mut scale = value.scale() as u64;
+        if value.is_zero() && value.is_sign_negative() {
+            scale |= NEGATIVE_ZERO;
+        }
+        match &mut self.run {
+            Some((current, len)) if *current == scale => *len += 1,
+            run => {
+                if let Some((current, len)) = run.replace((scale, 1)) {
+                    self.runs.load(&(len << RUN_SHIFT | current));
+                }
+                self.scale_runs += 1;
+            }
+        }
+        self.values.load_decimal(&value.normalize());
+    }
+
+    /// Flush pending values and return the packed result.
+    pub fn finish(mut self) -> PackedScaled {
+        if let Some((scale, len)) = self.run {
+            self.runs.load(&(len << RUN_SHIFT | scale));
+        }
+        PackedScaled {
+            values: self.values.finish(),
+            runs: self.runs.finish(),
+            scale_runs: self.scale_runs,
+        }
+    }
+}
+
+/// Values packed by a [`ScalePacker`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackedScaled {
+    values: PackedDecimals,
+    runs: PackedColumns<2>,
+    scale_runs: usize,
+}
+
+impl PackedScaled {
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The normalized values, without their original scales.
+    pub fn normalized(&self) -> &PackedDecimals {
+        &self.values
+    }
+
+    /// The number of runs of equal scales the values make up.
+    pub fn scale_runs(&self) -> usize {
+        self.scale_runs
+    }
+
+    /// Unpack the values at their original scales, checking both packs with
+    /// [`PackedDecimals::verify`] first.
+    pub fn try_unpack(&self) -> Result<Vec<Decimal>, FloatpackError> {
+        let runs: Vec<u64> = try_unpack_as(&self.runs)?;
+        let mut values = try_unpack(&self.values)?;
+        let mut values_of_runs = values.iter_mut();
+        for run in runs {
+            let scale = (run & SCALE_MASK) as u32;
+            for _ in 0..run >> RUN_SHIFT {
+                let value = values_of_runs
+                    .next()
+                    .ok_or_else(|| corrupt("more scales than values".to_string()))?;
+                let mut rescaled = *value;
+                rescaled.rescale(scale);
+                let negative_zero = run & NEGATIVE_ZERO != 0;
+                if rescaled.scale() != scale
+                    || rescaled != *value
+                    || (negative_zero && !value.is_zero())
+                {
+                    return Err(corrupt(format!(
+                        "value {} doesn't fit scale {}",
+                        value, scale
+                    )));
+                }
+                if negative_zero {
+                    rescaled.set_sign_negative(true);
+                }
+                *value = rescaled;
+            }
+        }
+        if values_of_runs.next().is_some() {
+            return Err(corrupt("fewer scales than values".to_string()));
+        }
+        Ok(values)
+    }
+
+    /// Serialize into the binary format: the scale runs followed by the normalized values, each
+    /// in the format of [`PackedDecimals::to_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.runs.to_bytes();
+        bytes.extend(self.values.to_bytes());
+        bytes
+    }
+
+    /// Serialize into the binary format, writing to `writer`.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), FloatpackError> {
+        self.runs.write_to(&mut writer)?;
+        self.values.write_to(&mut writer)
+    }
+
+    /// Deserialize from the output of [`PackedScaled::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FloatpackError> {
+        let mut reader = bytes;
+        let scaled = Self::read_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(corrupt(format!("{} trailing bytes", reader.len())));
+        }
+        Ok(scaled)
+    }
+
+    /// Deserialize from the binary format, reading from `reader`.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, FloatpackError> {
+        let runs = PackedColumns::<2>::read_from(&mut reader)?;
+        if runs.type_tag != u64::TYPE_TAG {
+            return Err(FloatpackError::TypeMismatch {
+                expected: u64::TYPE_TAG,
+                found: runs.type_tag,
+            });
+        }
+        let values = PackedDecimals::read_from(&mut reader)?;
+        if values.type_tag != Decimal::TYPE_TAG {
+            return Err(FloatpackError::TypeMismatch {
+                expected: Decimal::TYPE_TAG,
+                found: values.type_tag,
+            });
+        }
+        let scale_runs = runs.len();
+        Ok(PackedScaled {
+            values,
+            runs,
+            scale_runs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PackedScaled, ScalePacker};
+    use crate::{pack, FloatpackError, Packer};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn pack_scaled(packer: ScalePacker, values: &[Decimal]) -> PackedScaled {
+        let mut packer = packer;
+        for v in values {
+            packer.load_decimal(v);
+        }
+        packer.finish()
+    }
+
+    fn assert_exact(unpacked: &[Decimal], values: &[Decimal]) {
+        let serialize = |v: &[Decimal]| v.iter().map(|d| d.serialize()).collect::<Vec<_>>();
+        assert_eq!(serialize(unpacked), serialize(values));
+    }
+
+    #[test]
+    fn original_scales() {
+        let mut values = vec![
+            dec!(1.50),
+            dec!(1.5),
+            dec!(2.000),
+            Decimal::ZERO,
+            -dec!(0.00),
+            -dec!(0.00),
+            dec!(100),
+            Decimal::MAX,
+            Decimal::MIN,
+            dec!(0.0000000000000000000000000010),
+        ];
+        values.extend((0..600i64).map(|i| Decimal::new(i * 7919 % 1000, (i / 100) as u32)));
+        for packer in [
+            ScalePacker::new(),
+            ScalePacker::with_packer(Packer::new().with_block_len(32).unwrap()),
+        ] {
+            let packed = pack_scaled(packer, &values);
+            assert_eq!(packed.len(), values.len());
+            assert_exact(&packed.try_unpack().unwrap(), &values);
+            let bytes = PackedScaled::from_bytes(&packed.to_bytes()).unwrap();
+            assert_eq!(bytes, packed);
+            assert_exact(&bytes.try_unpack().unwrap(), &values);
+        }
+
+        let empty = pack_scaled(ScalePacker::new(), &[]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.try_unpack().unwrap(), []);
+        assert_eq!(PackedScaled::from_bytes(&empty.to_bytes()).unwrap(), empty);
+    }
+
+    #[test]
+    fn smaller_than_trailing_zeros() {
+        // Cents not ending in 0, stored at scale 8.
+        let values: Vec<Decimal> = (0..2000i64)
+            .map(|i| {
+                let cents = (10_000 + i * 7919 % 503) * 10 + 1 + i % 9;
+                Decimal::new(cents * 1_000_000, 8)
+            })
+            .collect();
+        let packed = pack_scaled(ScalePacker::new(), &values);
+        assert_exact(&packed.try_unpack().unwrap(), &values);
+        assert_eq!(packed.scale_runs(), 1);
+        assert!(packed.to_bytes().len() < pack(&values).to_bytes().len() / 2);
+    }
+
+    #[test]
+    fn damaged() {
+        let values = [dec!(1.50), dec!(2.5), dec!(3)];
+        let packed = pack_scaled(ScalePacker::new(), &values);
+        let mut bytes = packed.to_bytes();
+        let fewer = pack_scaled(ScalePacker::new(), &values[..2]);
+        bytes.extend(fewer.normalized().to_bytes());
+        assert!(matches!(
+            PackedScaled::from_bytes(&bytes),
+            Err(FloatpackError::Corrupt(_))
+        ));
+
+        // The scales of all values but the last one, followed by the values.
+        let mut mismatched = fewer.runs.to_bytes();
+        mismatched.extend(packed.normalized().to_bytes());
+        let mismatched = PackedScaled::from_bytes(&mismatched).unwrap();
+        assert!(matches!(
+            mismatched.try_unpack(),
+            Err(FloatpackError::Corrupt(_))
+        ));
+
+        // Scale 0 for rounded values.
+        let mut rounding = pack_scaled(ScalePacker::new(), &[dec!(3)]).runs.to_bytes();
+        rounding.extend(pack(&[dec!(2.5)]).to_bytes());
+        let rounding = PackedScaled::from_bytes(&rounding).unwrap();
+        assert!(matches!(
+            rounding.try_unpack(),
+            Err(FloatpackError::Corrupt(_))
+        ));
+
+        let mut swapped = packed.normalized().to_bytes();
+        swapped.extend(packed.runs.to_bytes());
+        assert!(PackedScaled::from_bytes(&swapped).is_err());
+    }
+}
@@ -0,0 +1,641 @@
+//! Values paired with their timestamps, expiring by time and resampling to a regular grid.
+use crate::decode::Blocks;
+use crate::format::check_decimals;
+use crate::{pack, pack_sorted, repacker, FloatpackError, PackedDecimals, Packer};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::ops::Range;
+
+/// A column of values along with the ascending timestamps they were taken at.
+///
+/// The timestamps are packed with [`pack_sorted`], the value at index `i` was taken at the
+/// timestamp at index `i`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeSeries {
+    times: PackedDecimals,
+    values: PackedDecimals,
+}
+
+impl TimeSeries {
+    /// Pair `values` with `times`.
+    ///
+    /// Returns [`FloatpackError::LengthMismatch`] if the columns differ in length and
+    /// [`FloatpackError::Unsorted`] if a timestamp is less than its predecessor or isn't an
+    /// integer in the range of `u64`, and [`FloatpackError::TypeMismatch`] if a column holds
+    /// values of another type than `Decimal`.
+    pub fn new(times: PackedDecimals, values: PackedDecimals) -> Result<Self, FloatpackError> {
+        check_decimals(&times)?;
+        check_decimals(&values)?;
+        if times.len() != values.len() {
+            return Err(FloatpackError::LengthMismatch(times.len(), values.len()));
+        }
+        let mut previous = 0;
+        for (i, t) in times.iter().enumerate() {
+            match timestamp(t) {
+                Some(t) if t >= previous => previous = t,
+                _ => return Err(FloatpackError::Unsorted(i)),
+            }
+        }
+        Ok(TimeSeries { times, values })
+    }
+
+    /// Pack `(timestamp, value)` pairs, see [`TimeSeries::new`].
+    pub fn pack(pairs: &[(u64, Decimal)]) -> Result<Self, FloatpackError> {
+        let times: Vec<Decimal> = pairs.iter().map(|(t, _)| Decimal::from(*t)).collect();
+        let values: Vec<Decimal> = pairs.iter().map(|(_, v)| *v).collect();
+        Ok(TimeSeries {
+            times: pack_sorted(&times)?,
+            values: pack(&values),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn times(&self) -> &PackedDecimals {
+        &self.times
+    }
+
+    pub fn values(&self) -> &PackedDecimals {
+        &self.values
+    }
+
+    /// The first and last timestamp of the `n`th block of the timestamps.
+    pub fn block_times(&self, n: usize) -> Option<(u64, u64)> {
+        let values = self.times.block_values();
+        let first = self.times.get(n.checked_mul(values)?)?;
+        let last = self.times.get(((n + 1) * values).min(self.len()) - 1)?;
+        Some((timestamp(first)?, timestamp(last)?))
+    }
+
+    /// Remove the values taken before `cutoff`, returning how many were removed.
+    ///
+    /// The expired values are found by a binary search over the timestamps, decoding a block per
+    /// step. Whole blocks are dropped without decoding the blocks following them, a block holding
+    /// values on both sides of the cutoff is trimmed by re-packing the values following it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a column is malformed (see [`PackedDecimals::verify`]).
+    pub fn expire_before(&mut self, cutoff: u64) -> usize {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let t = self.times.get(mid).expect("malformed timestamps");
+            if timestamp(t).is_some_and(|t| t < cutoff) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        drain_front(&mut self.times, low);
+        drain_front(&mut self.values, low);
+        low
+    }
+
+    /// The last value taken at or before `t` along with its timestamp, `None` if `t` precedes
+    /// the first value.
+    ///
+    /// The block holding it is found by a binary search over the first timestamps of the blocks,
+    /// decoding a block per step, and searched on its own. Of values taken at the same
+    /// timestamp, the last one is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a column is malformed (see [`PackedDecimals::verify`]).
+    pub fn as_of(&self, t: u64) -> Option<(u64, Decimal)> {
+        AsOf::new(self).find(t)
+    }
+
+    /// [`TimeSeries::as_of`] of each of `ts`.
+    ///
+    /// The last decoded blocks are kept for the next query: ascending queries only search the
+    /// blocks following them and decode each block at most once, apart from the steps of the
+    /// searches. Queries in any order give the same results, just slower.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a column is malformed (see [`PackedDecimals::verify`]).
+    pub fn as_of_many(&self, ts: &[u64]) -> Vec<Option<(u64, Decimal)>> {
+        let mut as_of = AsOf::new(self);
+        ts.iter().map(|&t| as_of.find(t)).collect()
+    }
+}
+
+/// Finds values by timestamp, keeping the blocks decoded last.
+struct AsOf<'a> {
+    series: &'a TimeSeries,
+    /// Index and timestamps of a block of the timestamps.
+    times: Option<(usize, Vec<u64>)>,
+    /// Index and values of a block of the values.
+    values: Option<(usize, Vec<Decimal>)>,
+}
+
+impl<'a> AsOf<'a> {
+    fn new(series: &'a TimeSeries) -> Self {
+        AsOf {
+            series,
+            times: None,
+            values: None,
+        }
+    }
+
+    fn find(&mut self, t: u64) -> Option<(u64, Decimal)> {
+        let times = &self.series.times;
+        let block_values = times.block_values();
+        let n = match &self.times {
+            // Past the first timestamp and before the last one, the value is in this block.
+            Some((n, block)) if block[0] <= t && t < block[block.len() - 1] => *n,
+            // The value is in this or a following block.
+            Some((n, block)) if block[0] <= t => last_block_from(times, *n, t)?,
+            _ => last_block_from(times, 0, t)?,
+        };
+        if self.times.as_ref().is_none_or(|(cached, _)| *cached != n) {
+            let block = decode_block(times, n).into_iter().map(stored_timestamp);
+            self.times = Some((n, block.collect()));
+        }
+        let block = &self.times.as_ref().expect("decoded block").1;
+        let k = block.partition_point(|&s| s <= t);
+        let i = n * block_values + k - 1;
+
+        let values = &self.series.values;
+        let m = i / values.block_values();
+        if self.values.as_ref().is_none_or(|(cached, _)| *cached != m) {
+            self.values = Some((m, decode_block(values, m)));
+        }
+        let value = self.values.as_ref().expect("decoded block").1[i % values.block_values()];
+        Some((block[k - 1], value))
+    }
+}
+
+/// Index of the last block from `low` on whose first timestamp is at most `t`, if there is one.
+fn last_block_from(times: &PackedDecimals, low: usize, t: u64) -> Option<usize> {
+    let block_values = times.block_values();
+    let (mut low, mut high) = (low, times.blocks[0].len());
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let first = times.get(mid * block_values).expect("malformed timestamps");
+        if stored_timestamp(first) <= t {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low.checked_sub(1)
+}
+
+fn decode_block(packed: &PackedDecimals, n: usize) -> Vec<Decimal> {
+    let mut blocks = Blocks::new(packed);
+    blocks.skip_to(n);
+    blocks.next().expect("malformed block")
+}
+
+/// What [`TimeSeries::resample`] emits for a grid point without any value in its interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillPolicy {
+    /// The last value before the interval, nothing if there is none.
+    ForwardFill,
+    /// The given marker value.
+    GapMarker(Decimal),
+    /// Nothing.
+    Skip,
+}
+
+impl TimeSeries {
+    /// Resample to a regular grid of timestamps `interval` apart, streaming through the series
+    /// once.
+    ///
+    /// The grid points are the multiples of `interval`, from the one at or before the first
+    /// timestamp to the one at or before the last. Each grid point `g` takes the last value
+    /// taken in `g..g + interval`, so a value taken exactly at a grid point belongs to that
+    /// point. Grid points without a value in their interval are filled as `fill` says.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is 0 or a column is malformed (see [`PackedDecimals::verify`]).
+    pub fn resample(&self, interval: u64, fill: FillPolicy) -> TimeSeries {
+        assert!(interval > 0, "resampling interval of 0");
+        let bounds = (
+            self.times.get(0),
+            self.times.get(self.len().saturating_sub(1)),
+        );
+        match bounds {
+            (Some(first), Some(last)) => {
+                let (first, last) = (stored_timestamp(first), stored_timestamp(last));
+                self.resample_grid(
+                    first - first % interval,
+                    last - last % interval,
+                    interval,
+                    fill,
+                )
+            }
+            _ => self.resample_grid(1, 0, interval, fill),
+        }
+    }
+
+    /// Resample like [`TimeSeries::resample`], to the grid points in `range`.
+    ///
+    /// Grid points before the first value are filled as `fill` says, with nothing to carry
+    /// forward for [`FillPolicy::ForwardFill`]. Grid points after the last value carry it forward
+    /// or are filled with the marker. Values taken before `range` are carried forward into it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is 0 or a column is malformed (see [`PackedDecimals::verify`]).
+    pub fn resample_range(&self, range: Range<u64>, interval: u64, fill: FillPolicy) -> TimeSeries {
+        assert!(interval > 0, "resampling interval of 0");
+        let first = range.start.div_ceil(interval).checked_mul(interval);
+        let last = range.end.checked_sub(1).map(|end| end - end % interval);
+        match (first, last) {
+            (Some(first), Some(last)) => self.resample_grid(first, last, interval, fill),
+            _ => self.resample_grid(1, 0, interval, fill),
+        }
+    }
+
+    /// Resample to the grid points `first..=last`, both multiples of `interval`.
+    fn resample_grid(&self, first: u64, last: u64, interval: u64, fill: FillPolicy) -> TimeSeries {
+        let mut times = Packer::new();
+        times.delta_of_delta = true;
+        let mut values = repacker(&self.values, self.values.block_len).expect("malformed values");
+        let mut pairs = self
+            .times
+            .iter()
+            .map(stored_timestamp)
+            .zip(&self.values)
+            .peekable();
+        let mut carried = None;
+        let mut g = first;
+        while g <= last {
+            let mut sample = None;
+            while let Some(&(t, v)) = pairs.peek() {
+                if t < g {
+                    carried = Some(v);
+                } else if t - g < interval {
+                    sample = Some(v);
+                } else {
+                    break;
+                }
+                pairs.next();
+            }
+            let filled = match (sample, fill) {
+                (Some(v), _) => Some(v),
+                (None, FillPolicy::ForwardFill) => carried,
+                (None, FillPolicy::GapMarker(marker)) => Some(marker),
+                (None, FillPolicy::Skip) => None,
+            };
+            carried = sample.or(carried);
+            let next = match (filled, pairs.peek()) {
+                (Some(v), _) => {
+                    times.load_decimal(&Decimal::from(g));
+                    values.load_decimal(&v);
+                    g.checked_add(interval)
+                }
+                // Nothing is emitted up to the grid point of the next value.
+                (None, Some(&(t, _))) => Some(t - t % interval),
+                (None, None) => None,
+            };
+            match next {
+                Some(next) => g = next,
+                None => break,
+            }
+        }
+        TimeSeries {
+            times: times.finish(),
+            values: values.finish(),
+        }
+    }
+}
+
+/// The timestamp `t` of a [`TimeSeries`], which were checked when creating it.
+fn stored_timestamp(t: Decimal) -> u64 {
+    timestamp(t).expect("malformed timestamps")
+}
+
+fn timestamp(t: Decimal) -> Option<u64> {
+    Some(t).filter(|t| t.fract().is_zero())?.to_u64()
+}
+
+/// Remove the first `n` values of `packed`.
+fn drain_front(packed: &mut PackedDecimals, n: usize) {
+    let values = packed.block_values();
+    if n == 0 {
+        return;
+    }
+    if n >= packed.count {
+        *packed = PackedDecimals {
+            block_len: packed.block_len,
+            ..PackedDecimals::default()
+        };
+        return;
+    }
+    if !n.is_multiple_of(values) {
+        let mut p = repacker(packed, packed.block_len).expect("validated block length");
+        for d in packed.iter().skip(n) {
+            p.load_decimal(&d);
+        }
+        *packed = p.finish();
+        return;
+    }
+    let blocks = n / values;
+    if packed.blocks[0][blocks].continuation {
+        // The new first block can't continue the dropped ones, store its head as it is.
+        let mut previous = Blocks::new(packed);
+        previous.skip_to(blocks - 1);
+        let (lanes, _) = previous.decode_next().expect("malformed block");
+        for (lane, decoded) in packed.blocks.iter_mut().zip(&lanes) {
+            lane[blocks].head ^= decoded[packed.block_len];
+            lane[blocks].continuation = false;
+        }
+    }
+    for lane in packed.blocks.iter_mut() {
+        lane.drain(..blocks);
+    }
+    packed.count -= n;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FillPolicy, TimeSeries};
+    use crate::{pack, pack_sorted, unpack, FloatpackError, Packer, BLOCK_VALUES};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn pairs(n: u64) -> Vec<(u64, Decimal)> {
+        (0..n)
+            .map(|i| {
+                (
+                    1000 + 10 * i + i % 3,
+                    Decimal::new((i * 7919 % 1000) as i64, 2),
+                )
+            })
+            .collect()
+    }
+
+    fn series(pairs: &[(u64, Decimal)]) -> Vec<TimeSeries> {
+        let times: Vec<Decimal> = pairs.iter().map(|(t, _)| Decimal::from(*t)).collect();
+        let values: Vec<Decimal> = pairs.iter().map(|(_, v)| *v).collect();
+        let chained = crate::tests::pack_with(Packer::new().with_chained_heads(), &values);
+        vec![
+            TimeSeries::pack(pairs).unwrap(),
+            TimeSeries::new(pack_sorted(&times).unwrap(), chained).unwrap(),
+        ]
+    }
+
+    fn check(series: &TimeSeries, pairs: &[(u64, Decimal)]) {
+        let times: Vec<Decimal> = pairs.iter().map(|(t, _)| Decimal::from(*t)).collect();
+        let values: Vec<Decimal> = pairs.iter().map(|(_, v)| *v).collect();
+        assert!(series.times().verify().is_ok() && series.values().verify().is_ok());
+        assert_eq!(unpack(series.times()), times);
+        assert_eq!(unpack(series.values()), values);
+    }
+
+    #[test]
+    fn expire() {
+        let pairs = pairs(5 * BLOCK_VALUES as u64 + 40);
+        let boundary = |i: usize| pairs[i].0;
+        for (cutoff, expired) in [
+            // Before the whole stream
+            (0, 0),
+            (boundary(0), 0),
+            // Between blocks
+            (boundary(2 * BLOCK_VALUES), 2 * BLOCK_VALUES),
+            (boundary(2 * BLOCK_VALUES - 1) + 1, 2 * BLOCK_VALUES),
+            (boundary(5 * BLOCK_VALUES), 5 * BLOCK_VALUES),
+            // Inside a block
+            (boundary(1), 1),
+            (boundary(3 * BLOCK_VALUES + 100), 3 * BLOCK_VALUES + 100),
+            (boundary(pairs.len() - 1), pairs.len() - 1),
+            // After the whole stream
+            (boundary(pairs.len() - 1) + 1, pairs.len()),
+            (u64::MAX, pairs.len()),
+        ] {
+            for mut series in series(&pairs) {
+                assert_eq!(series.expire_before(cutoff), expired, "cutoff {}", cutoff);
+                check(&series, &pairs[expired..]);
+                assert_eq!(series.expire_before(cutoff), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn repeated() {
+        let pairs = pairs(2000);
+        for mut series in series(&pairs) {
+            let mut expired = 0;
+            for cutoff in (1000..22000).step_by(1234) {
+                expired += series.expire_before(cutoff);
+                assert_eq!(expired, pairs.iter().filter(|(t, _)| *t < cutoff).count());
+                check(&series, &pairs[expired..]);
+            }
+            assert!(series.is_empty());
+        }
+    }
+
+    fn unpacked(series: &TimeSeries) -> Vec<(u64, Decimal)> {
+        let times = unpack(series.times())
+            .into_iter()
+            .map(super::stored_timestamp);
+        times.zip(unpack(series.values())).collect()
+    }
+
+    /// Resamples `pairs` to the grid points `grid` over the unpacked pairs.
+    fn reference(
+        pairs: &[(u64, Decimal)],
+        grid: impl Iterator<Item = u64>,
+        interval: u64,
+        fill: FillPolicy,
+    ) -> Vec<(u64, Decimal)> {
+        grid.filter_map(|g| {
+            let sample = pairs
+                .iter()
+                .rev()
+                .find(|(t, _)| *t >= g && *t - g < interval);
+            let carried = pairs.iter().rev().find(|(t, _)| *t < g);
+            let value = match (sample, fill) {
+                (Some((_, v)), _) => Some(*v),
+                (None, FillPolicy::ForwardFill) => carried.map(|(_, v)| *v),
+                (None, FillPolicy::GapMarker(marker)) => Some(marker),
+                (None, FillPolicy::Skip) => None,
+            };
+            Some((g, value?))
+        })
+        .collect()
+    }
+
+    #[test]
+    fn resample_edges() {
+        let pairs = [(10, dec!(1)), (20, dec!(2)), (25, dec!(3)), (40, dec!(4))];
+        let series = TimeSeries::pack(&pairs).unwrap();
+        let resampled = |fill| unpacked(&series.resample(10, fill));
+        // A value exactly at a grid point belongs to it, the last value of an interval wins.
+        let filled = [(10, dec!(1)), (20, dec!(3)), (30, dec!(3)), (40, dec!(4))];
+        assert_eq!(resampled(FillPolicy::ForwardFill), filled);
+        let marked = [(10, dec!(1)), (20, dec!(3)), (30, dec!(-1)), (40, dec!(4))];
+        assert_eq!(resampled(FillPolicy::GapMarker(dec!(-1))), marked);
+        let skipped = [(10, dec!(1)), (20, dec!(3)), (40, dec!(4))];
+        assert_eq!(resampled(FillPolicy::Skip), skipped);
+
+        // The grid starts at or before the first value and ends at or before the last one.
+        let series = TimeSeries::pack(&[(19, dec!(1)), (41, dec!(2))]).unwrap();
+        let resampled = unpacked(&series.resample(10, FillPolicy::ForwardFill));
+        assert_eq!(
+            resampled,
+            [(10, dec!(1)), (20, dec!(1)), (30, dec!(1)), (40, dec!(2))]
+        );
+
+        // Before the first value, there is nothing to carry forward.
+        let range = |range, fill| unpacked(&series.resample_range(range, 10, fill));
+        assert_eq!(
+            range(0..40, FillPolicy::ForwardFill),
+            [(10, dec!(1)), (20, dec!(1)), (30, dec!(1))]
+        );
+        assert_eq!(
+            range(1..21, FillPolicy::GapMarker(dec!(0))),
+            [(10, dec!(1)), (20, dec!(0))]
+        );
+        assert_eq!(range(20..70, FillPolicy::Skip), [(40, dec!(2))]);
+        assert_eq!(
+            range(45..61, FillPolicy::ForwardFill),
+            [(50, dec!(2)), (60, dec!(2))]
+        );
+        assert_eq!(range(0..10, FillPolicy::GapMarker(dec!(0))), [(0, dec!(0))]);
+        assert!(range(11..20, FillPolicy::GapMarker(dec!(0))).is_empty());
+
+        let empty = TimeSeries::pack(&[]).unwrap();
+        assert!(empty.resample(10, FillPolicy::ForwardFill).is_empty());
+        let markers = empty.resample_range(0..30, 10, FillPolicy::GapMarker(dec!(0)));
+        assert_eq!(
+            unpacked(&markers),
+            [(0, dec!(0)), (10, dec!(0)), (20, dec!(0))]
+        );
+        let end = series.resample_range(u64::MAX - 5..u64::MAX, 2, FillPolicy::ForwardFill);
+        assert_eq!(end.len(), 3);
+    }
+
+    #[test]
+    fn resample_reference() {
+        let mut pairs = pairs(2 * BLOCK_VALUES as u64);
+        // A gap and repeated timestamps.
+        for (i, (t, _)) in pairs.iter_mut().enumerate() {
+            if i > 300 {
+                *t += 2500;
+            }
+            if i % 50 == 1 {
+                *t -= 10 + i as u64 % 3;
+            }
+        }
+        pairs.sort_by_key(|(t, _)| *t);
+        let (first, last) = (pairs[0].0, pairs[pairs.len() - 1].0);
+        for series in series(&pairs) {
+            for interval in [1, 7, 10, 30, 1000, 100_000] {
+                for fill in [
+                    FillPolicy::ForwardFill,
+                    FillPolicy::GapMarker(dec!(-1)),
+                    FillPolicy::Skip,
+                ] {
+                    let resampled = series.resample(interval, fill);
+                    assert!(
+                        TimeSeries::new(resampled.times.clone(), resampled.values.clone()).is_ok()
+                    );
+                    let grid = (first - first % interval..=last).step_by(interval as usize);
+                    assert_eq!(
+                        unpacked(&resampled),
+                        reference(&pairs, grid, interval, fill),
+                        "{} {:?}",
+                        interval,
+                        fill
+                    );
+                    for range in [0..500, 0..last + 5000, 3000..4001, last..last + 1] {
+                        let resampled = series.resample_range(range.clone(), interval, fill);
+                        let grid = range.clone().filter(|g| g % interval == 0);
+                        assert_eq!(
+                            unpacked(&resampled),
+                            reference(&pairs, grid, interval, fill),
+                            "{} {:?} {:?}",
+                            interval,
+                            fill,
+                            range
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn as_of() {
+        let mut pairs = pairs(3 * BLOCK_VALUES as u64);
+        // A gap, repeated timestamps and one spanning a block boundary.
+        for (i, (t, _)) in pairs.iter_mut().enumerate() {
+            if i > 400 {
+                *t += 5000;
+            }
+            if i % 40 == 1 || i == BLOCK_VALUES {
+                *t -= 10 + i as u64 % 3;
+            }
+        }
+        pairs.sort_by_key(|(t, _)| *t);
+        let scan = |t: u64| pairs.iter().rev().find(|(s, _)| *s <= t).copied();
+        let mut queries: Vec<u64> = vec![0, 999, u64::MAX];
+        queries.extend(pairs.iter().flat_map(|(t, _)| [t - 1, *t, t + 1]));
+        queries.extend((0..500).map(|i| 900 + i * 7919 % 14_000));
+        queries.sort_unstable();
+
+        let times: Vec<Decimal> = pairs.iter().map(|(t, _)| Decimal::from(*t)).collect();
+        let values: Vec<Decimal> = pairs.iter().map(|(_, v)| *v).collect();
+        let short = crate::tests::pack_with(Packer::new().with_block_len(32).unwrap(), &times);
+        let mut all = series(&pairs);
+        all.push(TimeSeries::new(short, pack(&values)).unwrap());
+        for series in all {
+            for &t in &queries {
+                assert_eq!(series.as_of(t), scan(t), "{}", t);
+            }
+            let expected: Vec<_> = queries.iter().map(|&t| scan(t)).collect();
+            assert_eq!(series.as_of_many(&queries), expected);
+            // Queries in any order.
+            let shuffled: Vec<u64> = (0..queries.len())
+                .map(|i| queries[i * 7919 % queries.len()])
+                .collect();
+            let expected: Vec<_> = shuffled.iter().map(|&t| scan(t)).collect();
+            assert_eq!(series.as_of_many(&shuffled), expected);
+        }
+        let empty = TimeSeries::pack(&[]).unwrap();
+        assert_eq!(empty.as_of(5), None);
+        assert_eq!(empty.as_of_many(&[0, 5]), [None, None]);
+    }
+
+    #[test]
+    fn block_times() {
+        let pairs = pairs(600);
+        let series = TimeSeries::pack(&pairs).unwrap();
+        assert_eq!(series.block_times(0), Some((pairs[0].0, pairs[256].0)));
+        assert_eq!(series.block_times(2), Some((pairs[514].0, pairs[599].0)));
+        assert_eq!(series.block_times(3), None);
+    }
+
+    #[test]
+    fn rejects() {
+        let values = pack(&[Decimal::ONE, Decimal::TWO]);
+        let times = |t: &[Decimal]| pack(t);
+        assert!(matches!(
+            TimeSeries::new(times(&[Decimal::ONE]), values.clone()),
+            Err(FloatpackError::LengthMismatch(1, 2))
+        ));
+        for invalid in [
+            [Decimal::TWO, Decimal::ONE],
+            [Decimal::ONE, Decimal::new(15, 1)],
+            [Decimal::NEGATIVE_ONE, Decimal::ONE],
+        ] {
+            assert!(matches!(
+                TimeSeries::new(times(&invalid), values.clone()),
+                Err(FloatpackError::Unsorted(_))
+            ));
+        }
+        assert!(TimeSeries::pack(&[(2, Decimal::ONE), (1, Decimal::TWO)]).is_err());
+    }
+}
@@ -0,0 +1,455 @@
+//! Packing a bounded window of values in fixed memory, and decoding it, without allocating.
+use crate::bitpacker::{AnyBitPacker, MAX_BLOCK_LEN};
+use crate::crc::crc32;
+use crate::decode::decimal_layout;
+use crate::format::{
+    block_vals_len, corrupt, read_u16, read_u32, read_u64, read_u8, read_varint, validate_count,
+    BOUNDS, CHAINED, CHECKSUMS, COLUMNS, CONTINUATION, FIXED_FLAGS, FIXED_FLAGS_LEN, HEADER_LEN,
+    MAGIC, TYPED, VARINT_HEADS, VERSION,
+};
+use crate::{zip_u8, FloatpackError, Packable};
+use rust_decimal::Decimal;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+/// Bytes of the `vals` of a block of 32 bits per value, the widest there is.
+const MAX_VALS: usize = 4 * MAX_BLOCK_LEN;
+/// Bytes of a block in addition to its `vals`: `bits`, `encoding` and `head`.
+const BLOCK_OVERHEAD: usize = 6;
+
+/// A packer of up to `BLOCKS` blocks of [`crate::BLOCK_VALUES`] values, holding everything in
+/// fixed arrays so that it never allocates, e.g. for firmware without a heap.
+///
+/// The blocks are sized for the worst case of 32 bits per value, about 4 KiB per block of each
+/// of the 4 lanes plus another 4 KiB for the pending block. Values are packed like a
+/// [`Packer`](crate::Packer) with the default options does, and [`StaticPacker::finish`]
+/// serializes them into a buffer of the caller in the format of
+/// [`PackedDecimals::to_bytes`](crate::PackedDecimals::to_bytes), with the same bytes.
+/// [`decode_each`] decodes them from there, again without allocating.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{decode_each, StaticPacker};
+/// use rust_decimal_macros::*;
+///
+/// let mut packer = StaticPacker::<1>::new();
+/// packer.load(&dec!(21.5)).unwrap();
+/// packer.load(&dec!(21.75)).unwrap();
+/// let mut buffer = [0u8; StaticPacker::<1>::MAX_SERIALIZED_LEN];
+/// let len = packer.finish(&mut buffer).unwrap();
+///
+/// let mut sum = dec!(0);
+/// decode_each(&buffer[..len], |v| sum += v).unwrap();
+/// assert_eq!(sum, dec!(43.25));
+/// ```
+pub struct StaticPacker<const BLOCKS: usize> {
+    blocks: [[StaticBlock; 4]; BLOCKS],
+    /// Count of blocks packed.
+    packed: usize,
+    /// Count of values loaded, the pending ones included.
+    count: usize,
+    head: [u32; 4],
+    /// The value loaded last, `None` if there is no pending block.
+    last: Option<[u32; 4]>,
+    /// XOR deltas of the values of the pending block following its head.
+    deltas: [[u32; MAX_BLOCK_LEN]; 4],
+    idx: usize,
+}
+
+#[derive(Clone, Copy)]
+struct StaticBlock {
+    bits: u8,
+    head: u32,
+    vals: [u8; MAX_VALS],
+}
+
+impl StaticBlock {
+    const EMPTY: StaticBlock = StaticBlock {
+        bits: 0,
+        head: 0,
+        vals: [0; MAX_VALS],
+    };
+
+    fn vals(&self) -> &[u8] {
+        &self.vals[..self.bits as usize * MAX_BLOCK_LEN / 8]
+    }
+}
+
+impl<const BLOCKS: usize> Default for StaticPacker<BLOCKS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const BLOCKS: usize> StaticPacker<BLOCKS> {
+    /// Count of values a packer holds at most.
+    pub const CAPACITY: usize = BLOCKS * (MAX_BLOCK_LEN + 1);
+    /// Bytes [`StaticPacker::finish`] writes at most.
+    pub const MAX_SERIALIZED_LEN: usize =
+        HEADER_LEN + FIXED_FLAGS_LEN + BLOCKS * 4 * (BLOCK_OVERHEAD + MAX_VALS);
+
+    /// An empty packer. Being `const`, it can initialize a `static`.
+    pub const fn new() -> Self {
+        StaticPacker {
+            blocks: [[StaticBlock::EMPTY; 4]; BLOCKS],
+            packed: 0,
+            count: 0,
+            head: [0; 4],
+            last: None,
+            deltas: [[0; MAX_BLOCK_LEN]; 4],
+            idx: 0,
+        }
+    }
+
+    /// Count of values loaded.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether no values are loaded.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Add a value, or return [`FloatpackError::CapacityExceeded`] if
+    /// [`StaticPacker::CAPACITY`] values are loaded already.
+    pub fn load(&mut self, value: &Decimal) -> Result<(), FloatpackError> {
+        if self.count == Self::CAPACITY {
+            return Err(FloatpackError::CapacityExceeded(Self::CAPACITY));
+        }
+        let lanes = zip_u8(value.serialize());
+        match self.last {
+            Some(last) => {
+                for (i, deltas) in self.deltas.iter_mut().enumerate() {
+                    deltas[self.idx] = lanes[i] ^ last[i];
+                }
+                self.idx += 1;
+            }
+            None => self.head = lanes,
+        }
+        self.last = Some(lanes);
+        self.count += 1;
+        if self.idx == MAX_BLOCK_LEN {
+            self.pack();
+        }
+        Ok(())
+    }
+
+    /// Packs the pending block, padded by repeating its last value.
+    fn pack(&mut self) {
+        if self.last.take().is_none() {
+            return;
+        }
+        let bitpacker = bitpacker();
+        let block = &mut self.blocks[self.packed];
+        for (i, lane) in block.iter_mut().enumerate() {
+            let deltas = &mut self.deltas[i];
+            deltas[self.idx..].fill(0);
+            lane.bits = bitpacker.num_bits(deltas);
+            lane.head = self.head[i];
+            bitpacker.compress(deltas, &mut lane.vals, lane.bits);
+        }
+        self.packed += 1;
+        self.idx = 0;
+    }
+
+    /// Bytes [`StaticPacker::finish`] writes.
+    pub fn serialized_len(&self) -> usize {
+        let bitpacker = bitpacker();
+        let pending = self.last.map(|_| {
+            let bits = self.deltas.each_ref().map(|d| {
+                let mut deltas = *d;
+                deltas[self.idx..].fill(0);
+                bitpacker.num_bits(&deltas)
+            });
+            (bits, self.head[0])
+        });
+        let blocks = self.blocks[..self.packed]
+            .iter()
+            .map(|b| (b.each_ref().map(|l| l.bits), b[0].head))
+            .chain(pending);
+        let fixed = fixed_flags(blocks.clone()).is_some();
+        let lanes = blocks.map(|(bits, _)| {
+            let lanes = if fixed { &bits[1..] } else { &bits[..] };
+            lanes
+                .iter()
+                .map(|b| BLOCK_OVERHEAD + *b as usize * MAX_BLOCK_LEN / 8)
+                .sum::<usize>()
+        });
+        let flags = if fixed { FIXED_FLAGS_LEN } else { 0 };
+        HEADER_LEN + flags + lanes.sum::<usize>()
+    }
+
+    /// Serialize the values loaded into `out`, returning the count of bytes written, and empty
+    /// the packer for the next window.
+    ///
+    /// Returns an [`io::ErrorKind::WriteZero`] error, leaving the packer as it is, if `out` is
+    /// shorter than [`StaticPacker::serialized_len`]. Serializing into a buffer of
+    /// [`StaticPacker::MAX_SERIALIZED_LEN`] bytes never fails.
+    pub fn finish(&mut self, out: &mut [u8]) -> Result<usize, FloatpackError> {
+        let len = self.serialized_len();
+        if out.len() < len {
+            return Err(io::Error::from(io::ErrorKind::WriteZero).into());
+        }
+        self.pack();
+        let packed = &self.blocks[..self.packed];
+        let fixed = fixed_flags(
+            packed
+                .iter()
+                .map(|b| (b.each_ref().map(|l| l.bits), b[0].head)),
+        );
+        let mut writer = &mut out[..len];
+        writer.write_all(&MAGIC)?;
+        let flags = if fixed.is_some() { FIXED_FLAGS } else { 0 };
+        writer.write_all(&[VERSION, flags])?;
+        writer.write_all(&(MAX_BLOCK_LEN as u16).to_le_bytes())?;
+        writer.write_all(&(self.count as u64).to_le_bytes())?;
+        writer.write_all(&(self.packed as u64).to_le_bytes())?;
+        if let Some(flags) = fixed {
+            writer.write_all(&flags.to_le_bytes())?;
+        }
+        let first = if fixed.is_some() { 1 } else { 0 };
+        for block in packed {
+            for lane in &block[first..] {
+                // XOR encoding, not a continuation.
+                writer.write_all(&[lane.bits, 0])?;
+                writer.write_all(&lane.head.to_le_bytes())?;
+                writer.write_all(lane.vals())?;
+            }
+        }
+        debug_assert!(writer.is_empty());
+        self.packed = 0;
+        self.count = 0;
+        Ok(len)
+    }
+}
+
+/// The flags all values share given the bit widths and the head of lane 0 of each block, which
+/// are then stored once in the header, as
+/// [`PackedDecimals::to_bytes`](crate::PackedDecimals::to_bytes) does.
+fn fixed_flags(mut blocks: impl Iterator<Item = ([u8; 4], u32)>) -> Option<u32> {
+    let (bits, head) = blocks.next()?;
+    (bits[0] == 0 && blocks.all(|(bits, h)| bits[0] == 0 && h == head)).then_some(head)
+}
+
+fn bitpacker() -> AnyBitPacker {
+    AnyBitPacker::new(MAX_BLOCK_LEN).expect("the widest bitpacker exists")
+}
+
+/// Decode serialized [`PackedDecimals`](crate::PackedDecimals) from `bytes`, passing each value
+/// to `f` in order, without allocating. Returns the count of values decoded.
+///
+/// Decodes the output of [`StaticPacker::finish`] and of
+/// [`PackedDecimals::to_bytes`](crate::PackedDecimals::to_bytes) for packs whose blocks are
+/// XOR or raw encoded, with or without chained heads, block bounds and checksums, which are
+/// checked. Other encodings, types or versions are reported as errors instead, as are
+/// malformed `bytes`; only building such errors allocates. Values are passed on one block at a
+/// time once the block was read completely, so an error may follow values already passed.
+pub fn decode_each(bytes: &[u8], mut f: impl FnMut(Decimal)) -> Result<usize, FloatpackError> {
+    let reader = &mut &bytes[..];
+    let mut magic = [0u8; 4];
+    io::Read::read_exact(reader, &mut magic)?;
+    if magic != MAGIC {
+        return Err(corrupt("missing magic bytes".into()));
+    }
+    let version = read_u8(reader)?;
+    if version != VERSION {
+        return Err(FloatpackError::UnsupportedVersion(version));
+    }
+    let flags = read_u8(reader)?;
+    if flags & (TYPED | COLUMNS) != 0 {
+        return Err(corrupt("values of another type than Decimal".into()));
+    }
+    let block_len = read_u16(reader)? as usize;
+    let bitpacker =
+        AnyBitPacker::new(block_len).ok_or(FloatpackError::UnsupportedBlockLen(block_len))?;
+    let count = read_u64(reader)?;
+    let blocks = read_u64(reader)?;
+    let (count, blocks) = match (usize::try_from(count), usize::try_from(blocks)) {
+        (Ok(count), Ok(blocks)) => (count, blocks),
+        _ => return Err(corrupt(format!("count {} doesn't fit", count))),
+    };
+    validate_count(count, blocks, block_len + 1)?;
+    let fixed = if flags & FIXED_FLAGS != 0 {
+        Some(read_u32(reader)?)
+    } else {
+        None
+    };
+
+    let layout = decimal_layout(Decimal::TYPE_TAG).expect("layout of Decimals");
+    let mut last = [0u32; 4];
+    let mut lanes = [[0u32; MAX_BLOCK_LEN + 1]; 4];
+    for n in 0..blocks {
+        let record = *reader;
+        for (i, lane) in lanes.iter_mut().enumerate() {
+            if let (0, Some(flags)) = (i, fixed) {
+                lane.fill(flags);
+                continue;
+            }
+            let bits = read_u8(reader)?;
+            let tag = read_u8(reader)?;
+            let continuation = tag & CONTINUATION != 0 || (flags & CHAINED != 0 && n > 0);
+            let head = if continuation && flags & VARINT_HEADS != 0 {
+                read_varint(reader)?
+            } else {
+                read_u32(reader)?
+            };
+            let len = block_vals_len(block_len, bits)?;
+            if reader.len() < len {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            let (vals, rest) = reader.split_at(len);
+            *reader = rest;
+            if i == 0 && flags & BOUNDS != 0 {
+                *reader = reader
+                    .get(32..)
+                    .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            }
+            let base = if continuation { last[i] } else { 0 };
+            lane[0] = head ^ base;
+            let mut decompressed = [0u32; MAX_BLOCK_LEN];
+            bitpacker.decompress(vals, &mut decompressed[..block_len], bits);
+            match tag & !CONTINUATION {
+                0 => {
+                    for (j, v) in decompressed[..block_len].iter().enumerate() {
+                        lane[j + 1] = lane[j] ^ v;
+                    }
+                }
+                1 => lane[1..=block_len].copy_from_slice(&decompressed[..block_len]),
+                tag => {
+                    return Err(FloatpackError::BlockDecode {
+                        lane: i,
+                        block: n,
+                        reason: format!("encoding {} isn't decoded without allocating", tag),
+                    })
+                }
+            }
+            last[i] = lane[block_len];
+        }
+        if flags & CHECKSUMS != 0 {
+            let len = record.len() - reader.len();
+            if read_u32(reader)? != crc32(&record[..len]) {
+                return Err(FloatpackError::ChecksumMismatch(n));
+            }
+        }
+        let len = (count - n * (block_len + 1)).min(block_len + 1);
+        for value in (0..len).map(|j| std::array::from_fn(|i| lanes[i][j])) {
+            f(layout(value));
+        }
+    }
+    if !reader.is_empty() {
+        return Err(corrupt(format!("{} trailing bytes", reader.len())));
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_each, StaticPacker};
+    use crate::{pack, FloatpackError, PackedDecimals, Packer, BLOCK_VALUES};
+    use rust_decimal::Decimal;
+
+    fn readings(n: usize) -> Vec<Decimal> {
+        (0..n as i64)
+            .map(|i| Decimal::new(2_150 + i * 7919 % 61 - 30, 2))
+            .collect()
+    }
+
+    fn decoded(bytes: &[u8]) -> Result<Vec<Decimal>, FloatpackError> {
+        let mut values = Vec::new();
+        let count = decode_each(bytes, |v| values.push(v))?;
+        assert_eq!(count, values.len());
+        Ok(values)
+    }
+
+    #[test]
+    fn to_capacity() {
+        let values = readings(4 * BLOCK_VALUES);
+        let mut packer = StaticPacker::<4>::new();
+        assert_eq!(StaticPacker::<4>::CAPACITY, values.len());
+        for v in &values {
+            packer.load(v).unwrap();
+        }
+        assert!(matches!(
+            packer.load(&values[0]),
+            Err(FloatpackError::CapacityExceeded(1028))
+        ));
+        assert_eq!(packer.len(), 1028);
+
+        let mut buffer = [0u8; StaticPacker::<4>::MAX_SERIALIZED_LEN];
+        let len = packer.finish(&mut buffer).unwrap();
+        assert_eq!(buffer[..len], pack(&values).to_bytes()[..]);
+        assert_eq!(decoded(&buffer[..len]).unwrap(), values);
+        assert!(packer.is_empty());
+    }
+
+    #[test]
+    fn fixed_buffer() {
+        let mut mixed = readings(600);
+        mixed[10] = -Decimal::new(1, 5);
+        let worst: Vec<Decimal> = (0..300)
+            .map(|i| {
+                if i % 2 == 0 {
+                    Decimal::MAX
+                } else {
+                    Decimal::MIN
+                }
+            })
+            .collect();
+        for values in [readings(600), mixed, worst, readings(1), Vec::new()] {
+            let mut packer = StaticPacker::<3>::default();
+            for v in &values {
+                packer.load(v).unwrap();
+            }
+            let expected = pack(&values).to_bytes();
+            assert_eq!(packer.serialized_len(), expected.len());
+            let mut buffer = [0u8; StaticPacker::<3>::MAX_SERIALIZED_LEN];
+            let short = &mut buffer[..expected.len() - 1];
+            assert!(matches!(packer.finish(short), Err(FloatpackError::Io(_))));
+            assert_eq!(packer.len(), values.len());
+            let len = packer.finish(&mut buffer).unwrap();
+            assert_eq!(buffer[..len], expected[..]);
+            assert_eq!(decoded(&buffer[..len]).unwrap(), values);
+        }
+
+        // Reuse for the next window.
+        let mut packer = StaticPacker::<1>::new();
+        let mut buffer = [0u8; StaticPacker::<1>::MAX_SERIALIZED_LEN];
+        for window in readings(1000).chunks(BLOCK_VALUES) {
+            for v in window {
+                packer.load(v).unwrap();
+            }
+            let len = packer.finish(&mut buffer).unwrap();
+            assert_eq!(decoded(&buffer[..len]).unwrap(), window);
+        }
+    }
+
+    #[test]
+    fn decodes_packs() {
+        let values = readings(2000);
+        let with = |packer: Packer| crate::tests::pack_with(packer, &values);
+        for packed in [
+            with(Packer::new().with_chained_heads().with_block_bounds()),
+            with(Packer::new().with_adaptive_encoding()),
+            with(Packer::new().with_block_len(32).unwrap()),
+        ] {
+            assert_eq!(decoded(&packed.to_bytes()).unwrap(), values);
+            let bytes = packed.to_bytes_with_checksums();
+            assert_eq!(decoded(&bytes).unwrap(), values);
+            let mut corrupt = bytes.clone();
+            corrupt[40] ^= 1;
+            assert!(decoded(&corrupt).is_err());
+        }
+        let split = with(Packer::new().with_sign_magnitude());
+        assert!(matches!(
+            decoded(&split.to_bytes()),
+            Err(FloatpackError::BlockDecode { lane: 1, .. })
+        ));
+        let bytes = pack(&values).to_bytes();
+        for end in [0, 10, 30, bytes.len() - 1] {
+            assert!(decoded(&bytes[..end]).is_err());
+        }
+        assert!(decoded(&[&bytes[..], &[0]].concat()).is_err());
+        assert_eq!(PackedDecimals::from_bytes(&bytes).unwrap(), pack(&values));
+    }
+}
@@ -0,0 +1,1465 @@
+//! Analytics over packed data, computed while streaming through the blocks.
+use crate::bitpacker::{AnyBitPacker, MAX_BLOCK_LEN};
+use crate::decode::{decode_lane, Blocks};
+use crate::{zip_decode, Block, Encoding, FloatpackError, PackedDecimals, Packer};
+use rust_decimal::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Count the values in `buckets` equal-width bins between `low` and `high`.
+///
+/// The result holds `buckets + 2` counts: the values below `low` first, then the bins in
+/// ascending order and the values above `high` last. Bins include their lower edge; the last bin
+/// also includes `high`.
+///
+/// The values are decoded one block at a time, so no vector of all values is built.
+///
+/// # Panics
+///
+/// Panics if `buckets` is zero or `low` is not less than `high`.
+pub fn histogram(
+    packed: &PackedDecimals,
+    buckets: usize,
+    low: Decimal,
+    high: Decimal,
+) -> Vec<usize> {
+    assert!(buckets > 0, "histogram needs at least one bucket");
+    assert!(low < high, "histogram needs low < high");
+    let mut counts = vec![0; buckets + 2];
+    for v in packed {
+        let slot = if v < low {
+            0
+        } else if v > high {
+            buckets + 1
+        } else {
+            bucket(v, low, high, buckets) + 1
+        };
+        counts[slot] += 1;
+    }
+    counts
+}
+
+impl PackedDecimals {
+    /// Count the values between consecutive `edges`.
+    ///
+    /// The result holds `edges.len() + 1` counts: the values below the first edge, then one
+    /// count per bucket from an edge (included) to the next one (excluded), and last the values
+    /// from the last edge on. The values are decoded one block at a time, except for blocks
+    /// whose [`Block::bounds`](crate::Block::bounds) fall into a single bucket, which are counted
+    /// as a whole.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `edges` is empty or not strictly ascending.
+    pub fn histogram(&self, edges: &[Decimal]) -> Vec<u64> {
+        let packed = self.decimals();
+        packed.histogram_with(&mut Blocks::new(packed), edges)
+    }
+
+    fn histogram_with(&self, blocks: &mut Blocks<'_>, edges: &[Decimal]) -> Vec<u64> {
+        assert!(!edges.is_empty(), "histogram needs at least one edge");
+        assert!(
+            edges.windows(2).all(|w| w[0] < w[1]),
+            "histogram edges need to be strictly ascending"
+        );
+        let slot = |v: Decimal| edges.partition_point(|e| *e <= v);
+        let mut counts = vec![0; edges.len() + 1];
+        let block_values = self.block_values();
+        for n in 0..self.blocks[0].len() {
+            let len = self
+                .count
+                .saturating_sub(n * block_values)
+                .min(block_values);
+            if let Some((min, max)) = self.blocks[0][n].bounds {
+                if slot(min) == slot(max) {
+                    counts[slot(min)] += len as u64;
+                    continue;
+                }
+            }
+            blocks.skip_to(n);
+            let (lanes, _) = blocks.decode_next().expect("block index out of bounds");
+            for i in 0..len {
+                counts[slot(blocks.decimal_at(&lanes, i))] += 1;
+            }
+        }
+        counts
+    }
+
+    /// Count the values in up to `buckets` equal-width buckets between the smallest and the
+    /// largest value, returning the edges of the buckets along with the counts.
+    ///
+    /// There is one edge more than buckets, the last bucket includes the last edge. Buckets too
+    /// narrow to tell their edges apart at `Decimal` precision are merged. With
+    /// [`Block::bounds`](crate::Block::bounds) on all blocks, the range is known without
+    /// decoding, otherwise it takes an extra pass. Empty packs have no buckets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buckets` is zero.
+    pub fn auto_histogram(&self, buckets: usize) -> (Vec<Decimal>, Vec<u64>) {
+        assert!(buckets > 0, "histogram needs at least one bucket");
+        let lane = &self.decimals().blocks[0];
+        let bounds = if lane.iter().all(|b| b.bounds.is_some()) {
+            lane.iter().filter_map(|b| b.bounds).reduce(widen)
+        } else {
+            self.iter().map(|v| (v, v)).reduce(widen)
+        };
+        let (min, max) = match bounds {
+            Some(bounds) => bounds,
+            None => return (Vec::new(), Vec::new()),
+        };
+        let n = Decimal::from(buckets);
+        let mut edges: Vec<Decimal> = (0..buckets)
+            .map(|i| match max.checked_sub(min) {
+                _ if i == 0 => min,
+                Some(span) => min + span / n * Decimal::from(i),
+                // Both terms stay below `Decimal::MAX` as `0 < i < buckets`
+                None => min / n * Decimal::from(buckets - i) + max / n * Decimal::from(i),
+            })
+            .collect();
+        edges.dedup();
+        let counts = self.histogram(&edges)[1..].to_vec();
+        edges.push(max);
+        (edges, counts)
+    }
+
+    /// The `k` largest values along with their indices, in descending order.
+    ///
+    /// Of equal values, the ones at lower indices come first. The values are decoded one block at
+    /// a time into a heap of the `k` best so far; once it is full, blocks whose
+    /// [`Block::bounds`](crate::Block::bounds) show they can't improve on it are skipped.
+    pub fn top_k(&self, k: usize) -> Vec<(usize, Decimal)> {
+        let packed = self.decimals();
+        packed.select_with(&mut Blocks::new(packed), k, true)
+    }
+
+    /// The `k` smallest values along with their indices, in ascending order, see
+    /// [`PackedDecimals::top_k`].
+    pub fn bottom_k(&self, k: usize) -> Vec<(usize, Decimal)> {
+        let packed = self.decimals();
+        packed.select_with(&mut Blocks::new(packed), k, false)
+    }
+
+    fn select_with(
+        &self,
+        blocks: &mut Blocks<'_>,
+        k: usize,
+        descending: bool,
+    ) -> Vec<(usize, Decimal)> {
+        let mut heap: BinaryHeap<Candidate> = BinaryHeap::with_capacity(k.min(self.count));
+        let block_values = self.block_values();
+        for n in 0..self.blocks[0].len() {
+            if k == 0 {
+                break;
+            }
+            let worst = heap.peek().filter(|_| heap.len() == k);
+            if let (Some(worst), Some((min, max))) = (worst, self.blocks[0][n].bounds) {
+                // Values equal to the worst lose to it by their greater index.
+                if (descending && max <= worst.value) || (!descending && min >= worst.value) {
+                    continue;
+                }
+            }
+            blocks.skip_to(n);
+            let (lanes, len) = blocks.decode_next().expect("block index out of bounds");
+            for i in 0..len {
+                let candidate = Candidate {
+                    value: blocks.decimal_at(&lanes, i),
+                    index: n * block_values + i,
+                    descending,
+                };
+                if heap.len() < k {
+                    heap.push(candidate);
+                } else if let Some(mut worst) = heap.peek_mut() {
+                    if candidate < *worst {
+                        *worst = candidate;
+                    }
+                }
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|c| (c.index, c.value))
+            .collect()
+    }
+
+    /// Count the values `pred` holds for, decoding one block at a time.
+    pub fn count_where(&self, pred: impl Fn(&Decimal) -> bool) -> usize {
+        self.decimals()
+            .count_where_with(&mut Blocks::new(self.decimals()), pred)
+    }
+
+    fn count_where_with(&self, blocks: &mut Blocks<'_>, pred: impl Fn(&Decimal) -> bool) -> usize {
+        let mut count = 0;
+        while let Some((lanes, len)) = blocks.decode_next() {
+            count += (0..len)
+                .filter(|&i| pred(&blocks.decimal_at(&lanes, i)))
+                .count();
+        }
+        count
+    }
+
+    /// Count the values greater than `threshold`.
+    ///
+    /// Blocks whose [`Block::bounds`](crate::Block::bounds) lie entirely above or at most at
+    /// `threshold` are counted without decoding them.
+    pub fn count_greater(&self, threshold: Decimal) -> usize {
+        let packed = self.decimals();
+        packed.count_greater_with(&mut Blocks::new(packed), threshold)
+    }
+
+    fn count_greater_with(&self, blocks: &mut Blocks<'_>, threshold: Decimal) -> usize {
+        let block_values = self.block_values();
+        let mut count = 0;
+        for n in 0..self.blocks[0].len() {
+            let len = self
+                .count
+                .saturating_sub(n * block_values)
+                .min(block_values);
+            match self.blocks[0][n].bounds {
+                Some((min, _)) if min > threshold => count += len,
+                Some((_, max)) if max <= threshold => {}
+                _ => {
+                    blocks.skip_to(n);
+                    let (lanes, _) = blocks.decode_next().expect("block index out of bounds");
+                    count += (0..len)
+                        .filter(|&i| blocks.decimal_at(&lanes, i) > threshold)
+                        .count();
+                }
+            }
+        }
+        count
+    }
+
+    /// The index and value of the smallest value, the earliest of equal ones. `None` for empty
+    /// packs.
+    ///
+    /// Blocks whose [`Block::bounds`](crate::Block::bounds) show they don't hold the smallest
+    /// value, or not at an earlier index, are skipped; with bounds on all blocks, only the
+    /// first block with the smallest minimum is decoded. Blocks without bounds are decoded.
+    pub fn argmin(&self) -> Option<(usize, Decimal)> {
+        let packed = self.decimals();
+        packed.extreme_with(&mut Blocks::new(packed), false)
+    }
+
+    /// The index and value of the largest value, the earliest of equal ones, see
+    /// [`PackedDecimals::argmin`].
+    pub fn argmax(&self) -> Option<(usize, Decimal)> {
+        let packed = self.decimals();
+        packed.extreme_with(&mut Blocks::new(packed), true)
+    }
+
+    fn extreme_with(&self, blocks: &mut Blocks<'_>, max: bool) -> Option<(usize, Decimal)> {
+        let better = |a: Decimal, b: Decimal| if max { a > b } else { a < b };
+        let extreme = |(low, high): (Decimal, Decimal)| if max { high } else { low };
+        // Held by a block, so blocks not reaching it can't hold the result.
+        let bound = self.blocks[0]
+            .iter()
+            .filter_map(|b| b.bounds.map(extreme))
+            .reduce(|a, b| if better(b, a) { b } else { a });
+        let block_values = self.block_values();
+        let mut best: Option<(usize, Decimal)> = None;
+        for n in 0..self.blocks[0].len() {
+            if let Some(e) = self.blocks[0][n].bounds.map(extreme) {
+                let beaten = best.is_some_and(|(_, v)| !better(e, v));
+                if beaten || bound.is_some_and(|b| better(b, e)) {
+                    continue;
+                }
+            }
+            blocks.skip_to(n);
+            let (lanes, len) = blocks.decode_next().expect("block index out of bounds");
+            for i in 0..len {
+                let value = blocks.decimal_at(&lanes, i);
+                if best.is_none_or(|(_, v)| better(value, v)) {
+                    best = Some((n * block_values + i, value));
+                }
+            }
+        }
+        best
+    }
+}
+
+impl Packer {
+    /// Count the values loaded and not taken yet that `pred` holds for: those of the frames not
+    /// taken (see [`Packer::take_frames`]), of the current frame and of the pending block.
+    pub fn count_where(&self, pred: impl Fn(&Decimal) -> bool) -> usize {
+        let packed: usize = self
+            .packed_frames()
+            .map(|f| f.count_where_with(&mut self.frame_blocks(f), &pred))
+            .sum();
+        packed + self.pending().filter(|v| pred(v)).count()
+    }
+
+    /// Count the values greater than `threshold`, as [`Packer::count_where`] does and pruning
+    /// blocks as [`PackedDecimals::count_greater`] does, the pending block included.
+    pub fn count_greater(&self, threshold: Decimal) -> usize {
+        let packed: usize = self
+            .packed_frames()
+            .map(|f| f.count_greater_with(&mut self.frame_blocks(f), threshold))
+            .sum();
+        let pending = match self.cache.bounds {
+            Some((min, _)) if min > threshold => self.cache.len(),
+            Some((_, max)) if max <= threshold => 0,
+            _ => self.pending().filter(|v| *v > threshold).count(),
+        };
+        packed + pending
+    }
+
+    /// The index and value of the smallest value loaded and not taken yet, the earliest of
+    /// equal ones, pruning blocks as [`PackedDecimals::argmin`] does. Indices count from the
+    /// first value of the frames not taken, the pending block included.
+    pub fn argmin(&self) -> Option<(usize, Decimal)> {
+        self.extreme(false)
+    }
+
+    /// The index and value of the largest value loaded and not taken yet, see
+    /// [`Packer::argmin`].
+    pub fn argmax(&self) -> Option<(usize, Decimal)> {
+        self.extreme(true)
+    }
+
+    fn extreme(&self, max: bool) -> Option<(usize, Decimal)> {
+        let better = |a: Decimal, b: Decimal| if max { a > b } else { a < b };
+        let mut best: Option<(usize, Decimal)> = None;
+        let mut offset = 0;
+        for frame in self.packed_frames() {
+            let found = frame.extreme_with(&mut self.frame_blocks(frame), max);
+            if let Some((i, value)) = found {
+                if best.is_none_or(|(_, v)| better(value, v)) {
+                    best = Some((offset + i, value));
+                }
+            }
+            offset += frame.len();
+        }
+        for (i, value) in self.pending().enumerate() {
+            if best.is_none_or(|(_, v)| better(value, v)) {
+                best = Some((offset + i, value));
+            }
+        }
+        best
+    }
+
+    fn packed_frames(&self) -> impl Iterator<Item = &PackedDecimals> {
+        self.frames.iter().chain(std::iter::once(&self.packed))
+    }
+
+    /// Decoder of a frame of this packer, in its lane layout.
+    fn frame_blocks<'a>(&self, frame: &'a PackedDecimals) -> Blocks<'a> {
+        match self.lane_fn {
+            Some(f) => Blocks::new(frame).with_layout(f.from_lanes),
+            None => Blocks::new(frame),
+        }
+    }
+}
+
+fn widen(a: (Decimal, Decimal), b: (Decimal, Decimal)) -> (Decimal, Decimal) {
+    (a.0.min(b.0), a.1.max(b.1))
+}
+
+/// A value considered by [`PackedDecimals::top_k`] or [`PackedDecimals::bottom_k`], ordered from
+/// best to worst.
+struct Candidate {
+    value: Decimal,
+    index: usize,
+    descending: bool,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let value = if self.descending {
+            other.value.cmp(&self.value)
+        } else {
+            self.value.cmp(&other.value)
+        };
+        value.then(self.index.cmp(&other.index))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+/// The mean of `prices` weighted by `weights`, `sum(p * w) / sum(w)`.
+///
+/// Both columns are decoded in lockstep, one block at a time. Returns `None` if the weights add
+/// up to zero, which includes empty columns, and errors as [`zip_decode`] does. Returns
+/// [`FloatpackError::Overflow`] with the index of the first pair whose product or sums overflow
+/// `Decimal`, or with the count of pairs if the mean does.
+pub fn weighted_mean(
+    prices: &PackedDecimals,
+    weights: &PackedDecimals,
+) -> Result<Option<Decimal>, FloatpackError> {
+    let (mut sum, mut total) = (Decimal::ZERO, Decimal::ZERO);
+    for (i, (p, w)) in zip_decode(prices, weights)?.enumerate() {
+        let overflow = || FloatpackError::Overflow(i);
+        sum = p
+            .checked_mul(w)
+            .and_then(|pw| sum.checked_add(pw))
+            .ok_or_else(overflow)?;
+        total = total.checked_add(w).ok_or_else(overflow)?;
+    }
+    if total.is_zero() {
+        return Ok(None);
+    }
+    let mean = sum.checked_div(total);
+    mean.map(Some).ok_or(FloatpackError::Overflow(prices.len()))
+}
+
+/// The Pearson correlation of the values at equal indices in `a` and `b`, from -1 to 1.
+///
+/// Both columns are decoded in lockstep in a single pass, one block at a time each, updating
+/// the means and co-moments of the values with Welford's method. The values are converted to
+/// `f64` and accumulated as such: values with more than 15 significant digits are rounded, and
+/// the result is off from the exact correlation by no more than the rounding of `f64` over a
+/// few operations per value. Returns [`FloatpackError::LengthMismatch`] if the columns differ
+/// in length and [`FloatpackError::ZeroVariance`] if the values of either are all equal after
+/// the conversion, which includes columns of fewer than 2 values.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{correlation, pack};
+/// use rust_decimal_macros::*;
+///
+/// let a = pack(&[dec!(1), dec!(2), dec!(3)]);
+/// let b = pack(&[dec!(10), dec!(20), dec!(30)]);
+/// assert!((correlation(&a, &b).unwrap() - 1.0).abs() < 1e-12);
+/// ```
+pub fn correlation(a: &PackedDecimals, b: &PackedDecimals) -> Result<f64, FloatpackError> {
+    let moments = CoMoments::of(a, b)?;
+    if moments.m2_a == 0.0 || moments.m2_b == 0.0 {
+        return Err(FloatpackError::ZeroVariance);
+    }
+    let r = moments.c / (moments.m2_a.sqrt() * moments.m2_b.sqrt());
+    Ok(r.clamp(-1.0, 1.0))
+}
+
+/// The population covariance of the values at equal indices in `a` and `b`, with the
+/// accumulation and precision of [`correlation`].
+///
+/// Returns `None` for empty columns and [`FloatpackError::LengthMismatch`] if the columns
+/// differ in length.
+pub fn covariance(a: &PackedDecimals, b: &PackedDecimals) -> Result<Option<f64>, FloatpackError> {
+    let moments = CoMoments::of(a, b)?;
+    Ok((moments.n > 0).then(|| moments.c / moments.n as f64))
+}
+
+/// Means and sums of squared deviations of two columns, see [`correlation`].
+#[derive(Default)]
+struct CoMoments {
+    n: u64,
+    mean_a: f64,
+    mean_b: f64,
+    m2_a: f64,
+    m2_b: f64,
+    /// Sum of the products of the deviations of `a` and `b`.
+    c: f64,
+}
+
+impl CoMoments {
+    fn of(a: &PackedDecimals, b: &PackedDecimals) -> Result<CoMoments, FloatpackError> {
+        let mut m = CoMoments::default();
+        for (x, y) in zip_decode(a, b)? {
+            let (x, y) = (
+                x.to_f64().unwrap_or_default(),
+                y.to_f64().unwrap_or_default(),
+            );
+            m.n += 1;
+            let n = m.n as f64;
+            let dx = x - m.mean_a;
+            let dy = y - m.mean_b;
+            m.mean_a += dx / n;
+            m.mean_b += dy / n;
+            m.m2_a += dx * (x - m.mean_a);
+            m.m2_b += dy * (y - m.mean_b);
+            m.c += dx * (y - m.mean_b);
+        }
+        Ok(m)
+    }
+}
+
+/// Values a level of the quantile sketch holds before it is compacted, see [`quantile`].
+const SKETCH_CAPACITY: usize = 512;
+
+/// Estimate the `q`th quantile of the values of `packed`, for `q` from 0 to 1.
+///
+/// The quantile is the value of rank `ceil(q * n)` (at least 1) in ascending order. The values
+/// are decoded one block at a time into a sketch of bounded memory: levels of at most 512
+/// values, each standing for twice as many values as the level below it. A full level is sorted
+/// and every other value is moved up a level, alternating which half is kept. Up to 512 values
+/// the result is exact, as are the smallest (`q` = 0) and largest (`q` = 1) value; beyond, the
+/// rank of the result is typically off by well under 1% of `n`. Returns `None` for empty packs.
+///
+/// # Panics
+///
+/// Panics if `q` is not between 0 and 1 or `packed` holds values of another type than `Decimal`.
+pub fn quantile(packed: &PackedDecimals, q: f64) -> Option<Decimal> {
+    assert!((0.0..=1.0).contains(&q), "quantile needs 0 <= q <= 1");
+    let mut sketch = Sketch::default();
+    let (mut min, mut max) = (None, None);
+    for v in packed {
+        min = Some(min.map_or(v, |m: Decimal| m.min(v)));
+        max = Some(max.map_or(v, |m: Decimal| m.max(v)));
+        sketch.insert(v);
+    }
+    if q == 0.0 || q == 1.0 {
+        return if q == 0.0 { min } else { max };
+    }
+    let mut weighted: Vec<(Decimal, u64)> = sketch
+        .levels
+        .iter()
+        .enumerate()
+        .flat_map(|(level, values)| values.iter().map(move |v| (*v, 1 << level)))
+        .collect();
+    weighted.sort_unstable_by_key(|(v, _)| *v);
+    let total: u64 = weighted.iter().map(|(_, w)| w).sum();
+    let rank = ((q * total as f64).ceil() as u64).max(1);
+    let mut seen = 0;
+    weighted
+        .into_iter()
+        .find(|(_, w)| {
+            seen += w;
+            seen >= rank
+        })
+        .map(|(v, _)| v)
+}
+
+/// Levels of sampled values, those of level `i` standing for `2^i` values each.
+#[derive(Default)]
+struct Sketch {
+    levels: Vec<Vec<Decimal>>,
+    /// Whether the next compaction keeps the values at odd positions.
+    odd: bool,
+}
+
+impl Sketch {
+    fn insert(&mut self, v: Decimal) {
+        if self.levels.is_empty() {
+            self.levels.push(Vec::with_capacity(SKETCH_CAPACITY));
+        }
+        self.levels[0].push(v);
+        let mut level = 0;
+        while self.levels[level].len() >= SKETCH_CAPACITY {
+            let mut full = std::mem::take(&mut self.levels[level]);
+            full.sort_unstable();
+            if level + 1 == self.levels.len() {
+                self.levels.push(Vec::with_capacity(SKETCH_CAPACITY));
+            }
+            let kept = full.iter().skip(self.odd as usize).step_by(2);
+            self.levels[level + 1].extend(kept);
+            self.odd = !self.odd;
+            full.clear();
+            self.levels[level] = full;
+            level += 1;
+        }
+    }
+}
+
+/// Storage of a pack compared to a `Vec<Decimal>` of its values, see [`savings`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Savings {
+    /// Bytes of the values as `Decimal`s, 16 per value.
+    pub raw_bytes: usize,
+    /// Bytes of the pack serialized, see [`PackedDecimals::serialized_len`].
+    pub packed_bytes: usize,
+    /// `raw_bytes` divided by `packed_bytes`.
+    pub ratio: f64,
+    /// Share of `raw_bytes` saved in percent, negative if the pack is larger. 0 for an empty
+    /// pack.
+    pub percent_saved: f64,
+}
+
+/// How much smaller `packed` is than its values as `Decimal`s, without decoding.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{pack, savings};
+/// use rust_decimal::Decimal;
+///
+/// // Prices moving by a few cents.
+/// let prices: Vec<Decimal> = (0..10_000).map(|i| Decimal::new(10_000 + i % 7, 2)).collect();
+/// let savings = savings(&pack(&prices));
+/// assert_eq!(savings.raw_bytes, 160_000);
+/// assert!(savings.ratio > 10.0);
+/// assert!(savings.percent_saved > 90.0);
+/// ```
+pub fn savings(packed: &PackedDecimals) -> Savings {
+    let raw_bytes = packed.len() * std::mem::size_of::<Decimal>();
+    let packed_bytes = packed.serialized_len();
+    let percent_saved = if raw_bytes == 0 {
+        0.0
+    } else {
+        100.0 * (1.0 - packed_bytes as f64 / raw_bytes as f64)
+    };
+    Savings {
+        raw_bytes,
+        packed_bytes,
+        ratio: raw_bytes as f64 / packed_bytes as f64,
+        percent_saved,
+    }
+}
+
+/// Whether any block of each lane stores values, i.e. has `bits > 0`, without decoding.
+///
+/// A lane without values only stores the heads of its blocks, so it costs a few bytes per block
+/// however its values differ between blocks. Small positive decimals typically keep lanes 0
+/// (scale and sign) and 3 (high mantissa) inactive, and only those of more than 9 to 10 digits
+/// keep lane 2 active.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{active_lanes, pack};
+/// use rust_decimal::Decimal;
+///
+/// let prices: Vec<Decimal> = (0..1000).map(|i| Decimal::new(10_000 + i % 7, 2)).collect();
+/// assert_eq!(active_lanes(&pack(&prices)), [false, true, false, false]);
+/// ```
+pub fn active_lanes(packed: &PackedDecimals) -> [bool; 4] {
+    packed
+        .blocks
+        .each_ref()
+        .map(|lane| lane.iter().any(|b| b.bits > 0))
+}
+
+/// Order of the values of a pack, see [`monotonicity`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Monotonicity {
+    /// No value is less than its predecessor, at least one is greater.
+    Increasing,
+    /// No value is greater than its predecessor, at least one is less.
+    Decreasing,
+    /// All values are equal, which includes packs of less than 2 values.
+    Constant,
+    /// Some value is greater and some is less than its predecessor.
+    None,
+}
+
+/// Whether the values of `packed` only ever increase or decrease.
+///
+/// Values are compared as `Decimal`s, so `1.0` and `1.00` are equal. The values are decoded one
+/// block at a time, stopping at the first block holding both an increase and a decrease.
+///
+/// # Panics
+///
+/// Panics if `packed` holds values of another type than `Decimal`.
+pub fn monotonicity(packed: &PackedDecimals) -> Monotonicity {
+    monotonicity_with(&mut Blocks::new(packed.decimals()))
+}
+
+fn monotonicity_with(blocks: &mut Blocks<'_>) -> Monotonicity {
+    let (mut increase, mut decrease) = (false, false);
+    let mut last = None;
+    while let Some((lanes, len)) = blocks.decode_next() {
+        for i in 0..len {
+            let v = blocks.decimal_at(&lanes, i);
+            match last.map(|last: Decimal| v.cmp(&last)) {
+                Some(Ordering::Greater) => increase = true,
+                Some(Ordering::Less) => decrease = true,
+                _ => {}
+            }
+            last = Some(v);
+        }
+        if increase && decrease {
+            return Monotonicity::None;
+        }
+    }
+    match (increase, decrease) {
+        (true, _) => Monotonicity::Increasing,
+        (_, true) => Monotonicity::Decreasing,
+        _ => Monotonicity::Constant,
+    }
+}
+
+/// Mark each value that differs from its predecessor, the first value included.
+///
+/// Values differ if their representations do, so `1.0` and `1.00` count as a change. Lanes of
+/// XOR deltas are zero exactly where a lane repeats, so their changes are read from the deltas
+/// without reconstructing the values. Blocks whose mantissa is stored as
+/// [`Encoding::SignMagnitude`] or [`Encoding::DeltaOfDelta`] are decoded to compare.
+///
+/// # Panics
+///
+/// Panics if `packed` holds values of another type than `Decimal`.
+pub fn change_mask(packed: &PackedDecimals) -> Vec<bool> {
+    let packed = packed.decimals();
+    let bitpacker = AnyBitPacker::new(packed.block_len).expect("unsupported block length");
+    let block_len = packed.block_len;
+    let block_values = packed.block_values();
+    let mut blocks = Blocks::new(packed);
+    let mut mask = Vec::with_capacity(packed.count);
+    let mut last: Option<[u32; 4]> = None;
+    for n in 0..packed.blocks[0].len() {
+        let base = last.unwrap_or_default();
+        let mut changed = [false; MAX_BLOCK_LEN + 1];
+        let mut tail = [0; 4];
+        if packed.blocks[1][n].encoding.is_mantissa() {
+            blocks.seek(n, base);
+            let (lanes, _) = blocks.decode_next().expect("block index out of bounds");
+            for (lane, tail) in lanes.iter().zip(&mut tail) {
+                for (c, w) in changed[1..].iter_mut().zip(lane[..=block_len].windows(2)) {
+                    *c |= w[0] != w[1];
+                }
+                *tail = lane[block_len];
+            }
+        } else {
+            for (i, tail) in tail.iter_mut().enumerate() {
+                let block = &packed.blocks[i][n];
+                *tail = lane_changes(
+                    &bitpacker,
+                    block,
+                    lane_base(block, base[i]),
+                    &mut changed[1..],
+                );
+            }
+        }
+        changed[0] = match last {
+            Some(last) => (0..4).any(|i| {
+                let block = &packed.blocks[i][n];
+                block.head ^ lane_base(block, last[i]) != last[i]
+            }),
+            None => true,
+        };
+        let len = packed
+            .count
+            .saturating_sub(n * block_values)
+            .min(block_values);
+        mask.extend_from_slice(&changed[..len]);
+        last = Some(tail);
+    }
+    mask
+}
+
+/// The head of every block, i.e. every [`PackedDecimals::block_values`]th value, read from the
+/// block metadata without decompressing.
+///
+/// A cheap downsampled view of the whole column, e.g. for thumbnails. Continuation blocks
+/// store their head as XOR against the last value of the preceding block (see
+/// [`Packer::with_chained_heads`]), so only the blocks preceding them are decoded.
+///
+/// # Panics
+///
+/// Panics if `packed` holds values of another type than `Decimal`.
+pub fn block_heads(packed: &PackedDecimals) -> Vec<Decimal> {
+    let packed = packed.decimals();
+    let lanes = &packed.blocks;
+    let mut blocks = Blocks::new(packed);
+    let mut last = [0; 4];
+    (0..lanes[0].len())
+        .map(|n| {
+            let head = std::array::from_fn(|i| lanes[i][n].head ^ lane_base(&lanes[i][n], last[i]));
+            if lanes[0].get(n + 1).is_some_and(|b| b.continuation) {
+                blocks.seek(n, last);
+                let (decoded, _) = blocks.decode_next().expect("block index out of bounds");
+                last = std::array::from_fn(|i| decoded[i][packed.block_len]);
+            }
+            blocks.decimal(head)
+        })
+        .collect()
+}
+
+fn lane_base(block: &Block, last: u32) -> u32 {
+    if block.continuation {
+        last
+    } else {
+        0
+    }
+}
+
+/// Marks in `changed` where the deltas of an [`Encoding::Xor`] or [`Encoding::Raw`] `block`
+/// change its lane, returning the last value of the lane.
+fn lane_changes(bitpacker: &AnyBitPacker, block: &Block, base: u32, changed: &mut [bool]) -> u32 {
+    let block_len = bitpacker.block_len();
+    if block.encoding != Encoding::Xor {
+        let lane = decode_lane(bitpacker, block, base);
+        for (c, w) in changed.iter_mut().zip(lane[..=block_len].windows(2)) {
+            *c |= w[0] != w[1];
+        }
+        return lane[block_len];
+    }
+    let mut last = block.head ^ base;
+    if block.bits == 0 {
+        return last;
+    }
+    let mut deltas = [0u32; MAX_BLOCK_LEN];
+    bitpacker.decompress(&block.vals, &mut deltas[..block_len], block.bits);
+    for (c, delta) in changed.iter_mut().zip(&deltas[..block_len]) {
+        *c |= *delta != 0;
+        last ^= delta;
+    }
+    last
+}
+
+/// Index of the bin `v` falls into, for `low <= v <= high`.
+fn bucket(v: Decimal, low: Decimal, high: Decimal, buckets: usize) -> usize {
+    let n = Decimal::from(buckets);
+    let position = match (v.checked_sub(low), high.checked_sub(low)) {
+        (Some(offset), Some(span)) => match offset.checked_mul(n) {
+            Some(scaled) => scaled / span,
+            None => offset / (span / n),
+        },
+        // Spans wider than `Decimal::MAX` are so wide that f64 precision suffices
+        _ => {
+            let [v, low, high] = [v, low, high].map(|d| d.to_f64().unwrap_or_default());
+            Decimal::from_f64((v - low) / (high - low) * buckets as f64).unwrap_or_default()
+        }
+    };
+    position.floor().to_usize().unwrap_or(0).min(buckets - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        active_lanes, block_heads, change_mask, correlation, covariance, histogram, monotonicity,
+        monotonicity_with, quantile, savings, weighted_mean, Monotonicity,
+    };
+    use crate::decode::Blocks;
+    use crate::{pack, unpack, FloatpackError, Packer};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::*;
+
+    /// Counts below the first edge, between consecutive edges and from the last edge on.
+    fn brute_force(values: &[Decimal], edges: &[Decimal]) -> Vec<u64> {
+        let count = |f: &dyn Fn(&Decimal) -> bool| values.iter().filter(|v| f(v)).count() as u64;
+        let mut counts = vec![count(&|v| *v < edges[0])];
+        for w in edges.windows(2) {
+            counts.push(count(&|v| w[0] <= *v && *v < w[1]));
+        }
+        counts.push(count(&|v| *v >= edges[edges.len() - 1]));
+        counts
+    }
+
+    /// Blocks of 257 values each, the `n`th between `10 * n` and `10 * n + 5`.
+    fn steps() -> Vec<Decimal> {
+        (0..8 * 257)
+            .map(|i| Decimal::new((i / 257) * 100 + (i * 7) % 51, 1))
+            .collect()
+    }
+
+    #[test]
+    fn buckets() {
+        let values: Vec<Decimal> = (-50..1050).map(|v| Decimal::new(v, 1)).collect();
+        let counts = histogram(&pack(&values), 4, dec!(0), dec!(100));
+        assert_eq!(counts, vec![50, 250, 250, 250, 251, 49]);
+        assert_eq!(counts.iter().sum::<usize>(), values.len());
+
+        let packed = pack(&values);
+        let unpacked = unpack(&packed);
+        let in_first = unpacked
+            .iter()
+            .filter(|v| **v >= dec!(0) && **v < dec!(25))
+            .count();
+        assert_eq!(histogram(&packed, 4, dec!(0), dec!(100))[1], in_first);
+    }
+
+    #[test]
+    fn extreme_range() {
+        let values = vec![Decimal::MIN, Decimal::ZERO, Decimal::MAX];
+        let counts = histogram(&pack(&values), 2, Decimal::MIN, Decimal::MAX);
+        assert_eq!(counts, vec![0, 1, 2, 0]);
+        assert_eq!(histogram(&pack(&[]), 3, dec!(0), dec!(1)), vec![0; 5]);
+    }
+
+    #[test]
+    fn edges() {
+        let values: Vec<Decimal> = (0..3000)
+            .map(|v| Decimal::new((v * 7919) % 2001 - 1000, 1))
+            .collect();
+        let edges = [
+            dec!(-50),
+            dec!(-12.5),
+            dec!(0),
+            dec!(0.1),
+            dec!(33),
+            dec!(99.9),
+        ];
+        for packer in [
+            Packer::new(),
+            Packer::new().with_block_bounds(),
+            Packer::new().with_block_bounds().with_chained_heads(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values);
+            assert_eq!(packed.histogram(&edges), brute_force(&values, &edges));
+            assert_eq!(
+                packed.histogram(&[dec!(0)]),
+                brute_force(&values, &[dec!(0)])
+            );
+        }
+        assert_eq!(pack(&[]).histogram(&edges), vec![0; 7]);
+    }
+
+    #[test]
+    fn block_skip() {
+        let values = steps();
+        for packer in [
+            Packer::new().with_block_bounds(),
+            Packer::new().with_block_bounds().with_chained_heads(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values);
+            let edges: Vec<Decimal> = (0..10).map(|n| Decimal::from(n * 10)).collect();
+            let mut blocks = Blocks::new(&packed);
+            let counts = packed.histogram_with(&mut blocks, &edges);
+            assert_eq!(counts, brute_force(&values, &edges));
+            assert_eq!(blocks.decoded, 0);
+
+            // Only the blocks straddling an edge are decoded.
+            let edges = [dec!(12), dec!(20), dec!(55)];
+            let mut blocks = Blocks::new(&packed);
+            let counts = packed.histogram_with(&mut blocks, &edges);
+            assert_eq!(counts, brute_force(&values, &edges));
+            let straddling = if packed.chained() { 6 } else { 2 };
+            assert_eq!(blocks.decoded, straddling);
+        }
+        let packed = pack(&values);
+        let mut blocks = Blocks::new(&packed);
+        packed.histogram_with(&mut blocks, &[dec!(0)]);
+        assert_eq!(blocks.decoded, 8);
+    }
+
+    #[test]
+    fn auto() {
+        let values: Vec<Decimal> = (0..2000)
+            .map(|v| Decimal::new((v * 37) % 1234 - 300, 2))
+            .collect();
+        for packer in [Packer::new(), Packer::new().with_block_bounds()] {
+            let packed = crate::tests::pack_with(packer, &values);
+            let (edges, counts) = packed.auto_histogram(10);
+            assert_eq!(edges.len(), 11);
+            assert_eq!(edges[0], dec!(-3));
+            assert_eq!(edges[10], dec!(9.33));
+            let mut expected = brute_force(&values, &edges[..10]);
+            assert_eq!(expected.remove(0), 0);
+            assert_eq!(counts, expected);
+            assert_eq!(counts.iter().sum::<u64>(), 2000);
+        }
+
+        let constant = pack(&[dec!(1.5); 300]);
+        assert_eq!(
+            constant.auto_histogram(4),
+            (vec![dec!(1.5), dec!(1.5)], vec![300])
+        );
+        let extremes = pack(&[Decimal::MIN, Decimal::ZERO, Decimal::MAX]);
+        assert_eq!(extremes.auto_histogram(2).1, vec![1, 2]);
+        assert_eq!(pack(&[]).auto_histogram(3), (Vec::new(), Vec::new()));
+    }
+
+    #[test]
+    fn weighted() {
+        let prices: Vec<Decimal> = (0..1000)
+            .map(|v| Decimal::new(10_000 + v % 37, 2))
+            .collect();
+        let volumes: Vec<Decimal> = (0..1000).map(|v| Decimal::new(v % 11, 0)).collect();
+        let sum: Decimal = prices.iter().zip(&volumes).map(|(p, w)| p * w).sum();
+        let total: Decimal = volumes.iter().sum();
+        let mean = weighted_mean(&pack(&prices), &pack(&volumes)).unwrap();
+        assert_eq!(mean, Some(sum / total));
+        assert_eq!(weighted_mean(&pack(&[]), &pack(&[])).unwrap(), None);
+        assert_eq!(
+            weighted_mean(&pack(&[dec!(1)]), &pack(&[dec!(0)])).unwrap(),
+            None
+        );
+        match weighted_mean(&pack(&prices), &pack(&volumes[1..])) {
+            Err(FloatpackError::LengthMismatch(1000, 999)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let max = pack(&[Decimal::MAX; 3]);
+        let overflow = |weights: &[Decimal]| match weighted_mean(&max, &pack(weights)) {
+            Err(FloatpackError::Overflow(i)) => i,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(overflow(&[dec!(0), dec!(2), dec!(0)]), 1);
+        assert_eq!(overflow(&[dec!(1), dec!(0), dec!(1)]), 2);
+        assert_eq!(overflow(&[dec!(1), dec!(1), dec!(-1.5)]), 1);
+        match weighted_mean(
+            &pack(&[Decimal::MAX, dec!(0)]),
+            &pack(&[dec!(0.5), dec!(-0.4)]),
+        ) {
+            Err(FloatpackError::Overflow(2)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            weighted_mean(&max, &pack(&[dec!(1), dec!(-1), dec!(1)])).unwrap(),
+            Some(Decimal::MAX)
+        );
+    }
+
+    #[test]
+    fn co_moments() {
+        use rust_decimal::prelude::ToPrimitive;
+        let prices: Vec<Decimal> = (0..3000i64)
+            .map(|i| Decimal::new(1_000_000 + i * 7919 % 1009 + i / 3, 3))
+            .collect();
+        let other: Vec<Decimal> = (0..3000i64)
+            .map(|i| Decimal::new(500_000 - i * 104_729 % 2003 + i / 2, 2))
+            .collect();
+        let reference = |a: &[Decimal], b: &[Decimal]| {
+            let a: Vec<f64> = a.iter().map(|v| v.to_f64().unwrap()).collect();
+            let b: Vec<f64> = b.iter().map(|v| v.to_f64().unwrap()).collect();
+            let n = a.len() as f64;
+            let (mean_a, mean_b) = (a.iter().sum::<f64>() / n, b.iter().sum::<f64>() / n);
+            let cov = a
+                .iter()
+                .zip(&b)
+                .map(|(x, y)| (x - mean_a) * (y - mean_b))
+                .sum::<f64>();
+            let var_a = a.iter().map(|x| (x - mean_a).powi(2)).sum::<f64>();
+            let var_b = b.iter().map(|y| (y - mean_b).powi(2)).sum::<f64>();
+            (cov / n, cov / (var_a.sqrt() * var_b.sqrt()))
+        };
+        let short = crate::tests::pack_with(Packer::new().with_block_len(32).unwrap(), &other);
+        let negated: Vec<Decimal> = prices.iter().map(|v| -v * dec!(2.5)).collect();
+        for (a, b) in [(&prices, &other), (&prices, &prices), (&prices, &negated)] {
+            let (cov, r) = reference(a, b);
+            let packed = (pack(a), pack(b));
+            let corr = correlation(&packed.0, &packed.1).unwrap();
+            assert!((corr - r).abs() < 1e-12, "{} != {}", corr, r);
+            let covar = covariance(&packed.0, &packed.1).unwrap().unwrap();
+            assert!((covar - cov).abs() <= 1e-10 * cov.abs());
+        }
+        let (_, r) = reference(&prices, &other);
+        assert!((correlation(&pack(&prices), &short).unwrap() - r).abs() < 1e-12);
+        assert!((correlation(&pack(&prices), &pack(&prices)).unwrap() - 1.0).abs() < 1e-12);
+
+        let constant = pack(&[dec!(7.5); 1000]);
+        for (a, b) in [
+            (&constant, &pack(&prices[..1000])),
+            (&pack(&prices[..1000]), &constant),
+        ] {
+            assert!(matches!(
+                correlation(a, b),
+                Err(FloatpackError::ZeroVariance)
+            ));
+            assert_eq!(covariance(a, b).unwrap(), Some(0.0));
+        }
+        assert!(matches!(
+            correlation(&pack(&[dec!(1)]), &pack(&[dec!(2)])),
+            Err(FloatpackError::ZeroVariance)
+        ));
+        assert_eq!(covariance(&pack(&[]), &pack(&[])).unwrap(), None);
+        match correlation(&pack(&prices), &pack(&other[1..])) {
+            Err(FloatpackError::LengthMismatch(3000, 2999)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn changes() {
+        let mut values: Vec<Decimal> = (0..2000)
+            .map(|i| Decimal::new(100 + (i / 7) % 13 - (i / 300) * 5, 2))
+            .collect();
+        // Repeats across a block boundary, a change of scale only and a long constant run
+        values[256] = values[257];
+        values[1000] = dec!(1.0);
+        values[1001] = dec!(1.00);
+        values[1200..1600].fill(dec!(3.5));
+        let expected: Vec<bool> = (0..values.len())
+            .map(|i| i == 0 || values[i].serialize() != values[i - 1].serialize())
+            .collect();
+        let mut sorted = values.clone();
+        sorted.sort();
+        let sorted_expected: Vec<bool> = (0..sorted.len())
+            .map(|i| i == 0 || sorted[i].serialize() != sorted[i - 1].serialize())
+            .collect();
+        assert_eq!(
+            change_mask(&crate::pack_sorted(&sorted).unwrap()),
+            sorted_expected
+        );
+        for packer in [
+            Packer::new(),
+            Packer::new().with_adaptive_encoding(),
+            Packer::new().with_sign_magnitude(),
+            Packer::new().with_chained_heads(),
+            Packer::new().with_adaptive_encoding().with_chained_heads(),
+            Packer::new()
+                .with_block_len(32)
+                .unwrap()
+                .with_chained_heads(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values);
+            assert_eq!(change_mask(&packed), expected);
+        }
+        assert!(change_mask(&pack(&[])).is_empty());
+        assert_eq!(change_mask(&pack(&[Decimal::ONE; 3])), [true, false, false]);
+    }
+
+    #[test]
+    fn savings_of_packs() {
+        let values: Vec<Decimal> = (0..1000).map(|i| Decimal::new(i % 3, 1)).collect();
+        let packed = pack(&values);
+        let s = savings(&packed);
+        assert_eq!(s.raw_bytes, 16_000);
+        assert_eq!(s.packed_bytes, packed.to_bytes().len());
+        assert_eq!(s.ratio, 16_000.0 / s.packed_bytes as f64);
+        assert!((s.percent_saved - 100.0 * (1.0 - 1.0 / s.ratio)).abs() < 1e-9);
+
+        let tiny = savings(&pack(&[dec!(1)]));
+        assert!(tiny.percent_saved < 0.0 && tiny.ratio < 1.0);
+        let empty = savings(&pack(&[]));
+        assert_eq!(
+            (empty.raw_bytes, empty.ratio, empty.percent_saved),
+            (0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn active() {
+        let small: Vec<Decimal> = (0..1000).map(|i| Decimal::new(i % 3, 1)).collect();
+        assert_eq!(active_lanes(&pack(&small)), [false, true, false, false]);
+        let mixed: Vec<Decimal> = (0..1000i64)
+            .map(|i| {
+                Decimal::new(
+                    i * 7_919_000_000_000 - 3_000_000_000_000_000,
+                    (i % 4) as u32,
+                )
+            })
+            .collect();
+        assert_eq!(active_lanes(&pack(&mixed)), [true, true, true, false]);
+        let wide = pack(&[Decimal::MAX, Decimal::MIN, dec!(1)]);
+        assert_eq!(active_lanes(&wide), [true; 4]);
+        // Heads only: constant within each block.
+        let steps: Vec<Decimal> = (0..1000).map(|i| Decimal::from(i / 257)).collect();
+        assert_eq!(active_lanes(&pack(&steps)), [false; 4]);
+        assert_eq!(active_lanes(&pack(&[])), [false; 4]);
+    }
+
+    #[test]
+    fn heads() {
+        let values: Vec<Decimal> = (0..3000i64)
+            .map(|i| Decimal::new(i * 37 % 1000 - 500, (i % 3) as u32))
+            .collect();
+        for packer in [
+            Packer::new(),
+            Packer::new().with_effort(3),
+            Packer::new().with_chained_heads(),
+            Packer::new().with_keyframes(3),
+            Packer::new()
+                .with_block_len(32)
+                .unwrap()
+                .with_chained_heads(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values);
+            let expected: Vec<_> = values.iter().step_by(packed.block_values()).collect();
+            assert_eq!(block_heads(&packed).iter().collect::<Vec<_>>(), expected);
+        }
+        let sorted = crate::pack_sorted(&[dec!(1), dec!(2.5), dec!(4)]).unwrap();
+        assert_eq!(block_heads(&sorted), [dec!(1)]);
+        assert!(block_heads(&pack(&[])).is_empty());
+    }
+
+    /// `k` best `(index, value)` pairs by sorting, for `top_k` if `descending`.
+    fn sorted_k(values: &[Decimal], k: usize, descending: bool) -> Vec<(usize, Decimal)> {
+        let mut indexed: Vec<(usize, Decimal)> = values.iter().copied().enumerate().collect();
+        indexed.sort_by(|a, b| {
+            let value = if descending {
+                b.1.cmp(&a.1)
+            } else {
+                a.1.cmp(&b.1)
+            };
+            value.then(a.0.cmp(&b.0))
+        });
+        indexed.truncate(k);
+        indexed
+    }
+
+    #[test]
+    fn top_and_bottom() {
+        let values: Vec<Decimal> = (0..3000)
+            .map(|v| Decimal::new((v * 7919) % 503 - 200, 1))
+            .collect();
+        for packer in [
+            Packer::new(),
+            Packer::new().with_block_bounds(),
+            Packer::new().with_block_bounds().with_chained_heads(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values);
+            let unpacked = unpack(&packed);
+            for k in [0, 1, 20, 257, 2999, 3000, 5000] {
+                assert_eq!(packed.top_k(k), sorted_k(&unpacked, k, true), "top {}", k);
+                assert_eq!(
+                    packed.bottom_k(k),
+                    sorted_k(&unpacked, k, false),
+                    "bottom {}",
+                    k
+                );
+            }
+        }
+        assert!(pack(&[]).top_k(3).is_empty());
+        let ties = pack(&[dec!(1), dec!(2), dec!(2), dec!(1.0), dec!(2.00)]);
+        assert_eq!(ties.top_k(2), [(1, dec!(2)), (2, dec!(2))]);
+        assert_eq!(ties.bottom_k(2), [(0, dec!(1)), (3, dec!(1.0))]);
+    }
+
+    #[test]
+    fn top_k_skips_blocks() {
+        // The blocks of `steps` in the order 6, 0, 1, ..., 5, 7
+        let steps = steps();
+        let blocks: Vec<&[Decimal]> = steps.chunks(257).collect();
+        let values = [&[blocks[6]], &blocks[..6], &[blocks[7]]].concat().concat();
+        for packer in [
+            Packer::new().with_block_bounds(),
+            Packer::new().with_block_bounds().with_chained_heads(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values);
+            // The first block fills the heap, the second one holds the smallest values.
+            let mut blocks = Blocks::new(&packed);
+            let bottom = packed.select_with(&mut blocks, 20, false);
+            assert_eq!(bottom, sorted_k(&values, 20, false));
+            assert_eq!(blocks.decoded, 2);
+
+            // Only the last block, holding the largest values, and its chain are decoded
+            // once the first block has filled the heap.
+            let mut blocks = Blocks::new(&packed);
+            let top = packed.select_with(&mut blocks, 20, true);
+            assert_eq!(top, sorted_k(&values, 20, true));
+            assert_eq!(blocks.decoded, if packed.chained() { 8 } else { 2 });
+        }
+        let packed = pack(&values);
+        let mut blocks = Blocks::new(&packed);
+        packed.select_with(&mut blocks, 20, false);
+        assert_eq!(blocks.decoded, 8);
+        let mut blocks = Blocks::new(&packed);
+        packed.select_with(&mut blocks, 0, true);
+        assert_eq!(blocks.decoded, 0);
+    }
+
+    /// The earliest smallest or largest value, by a linear scan.
+    fn linear_extreme(values: &[Decimal], max: bool) -> Option<(usize, Decimal)> {
+        let mut best: Option<(usize, Decimal)> = None;
+        for (i, v) in values.iter().enumerate() {
+            if best.is_none_or(|(_, b)| if max { *v > b } else { *v < b }) {
+                best = Some((i, *v));
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn extremes() {
+        let mut values = steps();
+        // Ties within a block, across blocks and of different scales.
+        values[300] = dec!(-5);
+        values[310] = dec!(-5.00);
+        values[1200] = dec!(-5);
+        values[900] = dec!(999);
+        values[1900] = dec!(999.0);
+        for packer in [
+            Packer::new(),
+            Packer::new().with_block_bounds(),
+            Packer::new().with_block_bounds().with_chained_heads(),
+            Packer::new().with_block_len(32).unwrap(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values);
+            assert_eq!(packed.argmin(), linear_extreme(&values, false));
+            assert_eq!(packed.argmax(), linear_extreme(&values, true));
+            assert_eq!(packed.argmin(), Some((300, dec!(-5))));
+            assert_eq!(packed.argmax(), Some((900, dec!(999))));
+        }
+        assert_eq!(pack(&[]).argmin(), None);
+        assert_eq!(pack(&[dec!(1)]).argmax(), Some((0, dec!(1))));
+
+        // Only the first block holding the extreme is decoded, and the blocks it chains to.
+        let packed = crate::tests::pack_with(Packer::new().with_block_bounds(), &values);
+        for max in [false, true] {
+            let mut blocks = Blocks::new(&packed);
+            packed.extreme_with(&mut blocks, max);
+            assert_eq!(blocks.decoded, 1);
+        }
+        let chained = Packer::new().with_block_bounds().with_chained_heads();
+        let packed = crate::tests::pack_with(chained, &values);
+        let mut blocks = Blocks::new(&packed);
+        packed.extreme_with(&mut blocks, true);
+        assert_eq!(blocks.decoded, 900 / 257 + 1);
+        let packed = pack(&values);
+        let mut blocks = Blocks::new(&packed);
+        packed.extreme_with(&mut blocks, false);
+        assert_eq!(blocks.decoded, 8);
+    }
+
+    #[test]
+    fn live_extremes() {
+        let values = steps();
+        let mut loaded = values[..2000].to_vec();
+        loaded.extend([dec!(0), dec!(80), dec!(80.0)]);
+        let mut packer = Packer::new().with_block_bounds().with_flush_bytes(1000);
+        for v in &loaded {
+            packer.load_decimal(v);
+        }
+        assert!(packer.frames.len() > 1 && packer.cache.len() >= 3);
+        // The smallest value ties with the first one, the largest ones are pending.
+        assert_eq!(packer.argmin(), Some((0, dec!(0))));
+        assert_eq!(packer.argmax(), Some((2001, dec!(80))));
+        let taken: usize = packer.take_frames().iter().map(|f| f.len()).sum();
+        assert_eq!(packer.argmin(), linear_extreme(&loaded[taken..], false));
+        assert_eq!(packer.argmax(), Some((2001 - taken, dec!(80))));
+        assert_eq!(Packer::new().argmax(), None);
+    }
+
+    #[test]
+    fn count() {
+        let values = steps();
+        for packer in [
+            Packer::new(),
+            Packer::new().with_block_bounds(),
+            Packer::new().with_block_bounds().with_chained_heads(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values[..2000]);
+            let unpacked = unpack(&packed);
+            for threshold in [dec!(-1), dec!(0), dec!(10), dec!(25.5), dec!(75), dec!(80)] {
+                let greater = unpacked.iter().filter(|v| **v > threshold).count();
+                assert_eq!(packed.count_greater(threshold), greater, "{}", threshold);
+                assert_eq!(packed.count_where(|v| *v > threshold), greater);
+            }
+            // Boundaries of blocks
+            for i in [0, 256, 257, 1541, 1798, 1999] {
+                let at = |i: usize| unpacked.iter().filter(|v| **v == unpacked[i]).count();
+                assert_eq!(packed.count_where(|v| *v == unpacked[i]), at(i));
+            }
+        }
+        assert_eq!(pack(&[]).count_greater(Decimal::ZERO), 0);
+    }
+
+    #[test]
+    fn count_greater_prunes() {
+        let values = steps();
+        let packed = crate::tests::pack_with(Packer::new().with_block_bounds(), &values);
+        let count = |threshold| {
+            let mut blocks = Blocks::new(&packed);
+            let count = packed.count_greater_with(&mut blocks, threshold);
+            assert_eq!(count, values.iter().filter(|v| **v > threshold).count());
+            blocks.decoded
+        };
+        // Between blocks, at the smallest and largest value of a block and inside of one
+        assert_eq!(count(dec!(27)), 0);
+        assert_eq!(count(dec!(30)), 1);
+        assert_eq!(count(dec!(35)), 0);
+        assert_eq!(count(dec!(34.9)), 1);
+        assert_eq!(count(dec!(-1)), 0);
+        assert_eq!(count(dec!(100)), 0);
+
+        let packed = pack(&values);
+        let mut blocks = Blocks::new(&packed);
+        packed.count_greater_with(&mut blocks, dec!(27));
+        assert_eq!(blocks.decoded, 8);
+    }
+
+    #[test]
+    fn packer_counts() {
+        let values = steps();
+        for packer in [
+            Packer::new(),
+            Packer::new().with_block_bounds().with_flush_bytes(1000),
+        ] {
+            let mut packer = packer;
+            for (i, v) in values.iter().enumerate() {
+                packer.load_decimal(v);
+                if i % 97 == 0 || i % 257 == 0 || i % 257 == 256 {
+                    for threshold in [dec!(0), dec!(20), dec!(30.5)] {
+                        let greater = values[..=i].iter().filter(|v| **v > threshold).count();
+                        assert_eq!(packer.count_greater(threshold), greater);
+                        assert_eq!(packer.count_where(|v| *v > threshold), greater);
+                    }
+                }
+            }
+            let taken = packer.take_frames().iter().map(|f| f.len()).sum::<usize>();
+            let rest = values[taken..]
+                .iter()
+                .filter(|v| v.is_sign_positive())
+                .count();
+            assert_eq!(packer.count_where(|v| v.is_sign_positive()), rest);
+        }
+    }
+
+    #[test]
+    fn monotonic() {
+        let increasing: Vec<Decimal> = (0..1000).map(|v| Decimal::new(v / 3, 1)).collect();
+        let decreasing: Vec<Decimal> = increasing.iter().rev().copied().collect();
+        for (values, expected) in [
+            (increasing.clone(), Monotonicity::Increasing),
+            (decreasing.clone(), Monotonicity::Decreasing),
+            (vec![dec!(1.5); 600], Monotonicity::Constant),
+            (
+                vec![dec!(1.5), dec!(1.50), dec!(1.500)],
+                Monotonicity::Constant,
+            ),
+            (vec![dec!(1)], Monotonicity::Constant),
+            (Vec::new(), Monotonicity::Constant),
+            (
+                [&increasing[..], &decreasing[..]].concat(),
+                Monotonicity::None,
+            ),
+        ] {
+            assert_eq!(monotonicity(&pack(&values)), expected);
+            let chained = crate::tests::pack_with(Packer::new().with_chained_heads(), &values);
+            assert_eq!(monotonicity(&chained), expected);
+        }
+        let mut late = increasing.clone();
+        late[999] = Decimal::ZERO;
+        assert_eq!(monotonicity(&pack(&late)), Monotonicity::None);
+
+        // Stops at the first block with both directions.
+        let mut early = increasing;
+        early[300] = Decimal::ZERO;
+        let packed = pack(&early);
+        let mut blocks = Blocks::new(&packed);
+        assert_eq!(monotonicity_with(&mut blocks), Monotonicity::None);
+        assert_eq!(blocks.decoded, 2);
+    }
+
+    #[test]
+    fn quantiles() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        let values: Vec<Decimal> = (0..100_000)
+            .map(|_| Decimal::new(rng.gen_range(0..1_000_000), 2))
+            .collect();
+        let packed = pack(&values);
+        let mut sorted = values.clone();
+        sorted.sort();
+        let n = sorted.len();
+        for q in [0.01, 0.1, 0.25, 0.5, 0.9, 0.99, 0.999] {
+            let estimate = quantile(&packed, q).unwrap();
+            let rank = (q * n as f64).ceil() as usize;
+            // Ranks the estimate may have, given duplicates.
+            let low = sorted.partition_point(|v| *v < estimate);
+            let high = sorted.partition_point(|v| *v <= estimate);
+            let error = if rank < low {
+                low - rank
+            } else {
+                rank.saturating_sub(high)
+            };
+            assert!(
+                error < n / 100,
+                "q {}: rank {} of {}..{}",
+                q,
+                rank,
+                low,
+                high
+            );
+        }
+        assert_eq!(quantile(&packed, 0.0), sorted.first().copied());
+        assert_eq!(quantile(&packed, 1.0), sorted.last().copied());
+
+        // Exact when the sketch holds all values.
+        let small: Vec<Decimal> = (1..=100).rev().map(Decimal::from).collect();
+        let packed = pack(&small);
+        assert_eq!(quantile(&packed, 0.5), Some(dec!(50)));
+        assert_eq!(quantile(&packed, 0.501), Some(dec!(51)));
+        assert_eq!(quantile(&packed, 0.001), Some(dec!(1)));
+        assert_eq!(quantile(&pack(&[dec!(7)]), 0.3), Some(dec!(7)));
+        assert_eq!(quantile(&pack(&[]), 0.5), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "quantile needs 0 <= q <= 1")]
+    fn quantile_out_of_range() {
+        quantile(&pack(&[dec!(1)]), 1.5);
+    }
+}
@@ -0,0 +1,252 @@
+//! Decoding from a reader one block at a time, with the progress known up front.
+use crate::bitpacker::AnyBitPacker;
+use crate::decode::{decimal_layout, Blocks};
+use crate::format::{
+    corrupt, read_header_v2, read_record_v2, read_version, validate_count, validate_lanes, Header,
+};
+use crate::{FloatpackError, PackedDecimals};
+use rust_decimal::Decimal;
+use std::io::Read;
+
+/// Decodes a packed stream in the current format (see [`crate::format`]) as it is read.
+///
+/// The header at the front of the stream holds the count of values and blocks, so
+/// [`StreamDecoder::total_values`] is known as soon as the decoder is created, while
+/// [`StreamDecoder::values_decoded`] counts the values yielded so far. Memory stays bounded by
+/// one block. Iterating yields an error once if the stream turns out to be truncated or
+/// malformed and ends afterwards.
+///
+/// Usage example:
+/// ```
+/// use floatpack::{pack, StreamDecoder};
+/// use rust_decimal_macros::*;
+///
+/// let bytes = pack(&[dec!(1.5), dec!(2.5)]).to_bytes();
+/// let mut decoder = StreamDecoder::new(&bytes[..]).unwrap();
+/// assert_eq!(decoder.total_values(), 2);
+/// assert_eq!(decoder.next().unwrap().unwrap(), dec!(1.5));
+/// assert_eq!(decoder.values_decoded(), 1);
+/// ```
+pub struct StreamDecoder<R> {
+    reader: R,
+    header: Header,
+    n: usize,
+    last: [u32; 4],
+    block: std::vec::IntoIter<Decimal>,
+    decoded: usize,
+    failed: bool,
+}
+
+impl<R: Read> StreamDecoder<R> {
+    /// Read the header from `reader`.
+    ///
+    /// Version 1 streams store the count of values as a trailer, so they are rejected with
+    /// [`FloatpackError::UnsupportedVersion`]; convert them with [`crate::migrate`] first. Streams
+    /// of another [`Packable`](crate::Packable) type are rejected with
+    /// [`FloatpackError::TypeMismatch`].
+    pub fn new(mut reader: R) -> Result<Self, FloatpackError> {
+        let version = read_version(&mut reader)?;
+        if version != crate::format::VERSION {
+            return Err(FloatpackError::UnsupportedVersion(version));
+        }
+        let header = read_header_v2(&mut reader)?;
+        if decimal_layout(header.type_tag).is_none() {
+            return Err(FloatpackError::TypeMismatch {
+                expected: 0,
+                found: header.type_tag,
+            });
+        }
+        if header.columns != 4 {
+            return Err(corrupt(format!("{} columns, expected 4", header.columns)));
+        }
+        AnyBitPacker::new(header.block_len)
+            .ok_or(FloatpackError::UnsupportedBlockLen(header.block_len))?;
+        validate_count(header.count, header.blocks, header.block_len + 1)?;
+        Ok(StreamDecoder {
+            reader,
+            header,
+            n: 0,
+            last: [0; 4],
+            block: Vec::new().into_iter(),
+            decoded: 0,
+            failed: false,
+        })
+    }
+
+    /// Count of values in the stream.
+    pub fn total_values(&self) -> usize {
+        self.header.count
+    }
+
+    /// Count of values yielded so far.
+    pub fn values_decoded(&self) -> usize {
+        self.decoded
+    }
+
+    /// Count of blocks per lane in the stream.
+    pub fn total_blocks(&self) -> usize {
+        self.header.blocks
+    }
+
+    /// Count of blocks per lane read so far.
+    pub fn blocks_decoded(&self) -> usize {
+        self.n
+    }
+
+    /// Count of deltas per block.
+    pub fn block_len(&self) -> usize {
+        self.header.block_len
+    }
+
+    fn decode_block(&mut self) -> Result<Vec<Decimal>, FloatpackError> {
+        let n = self.n;
+        let mut packed = PackedDecimals {
+            count: self.header.count - n * (self.header.block_len + 1),
+            block_len: self.header.block_len,
+            type_tag: self.header.type_tag,
+            ..PackedDecimals::default()
+        };
+        read_record_v2(&mut self.reader, &self.header, n, &mut packed.blocks)?;
+        validate_lanes(
+            [0, 1, 2, 3].map(|i| &packed.blocks[i][0]),
+            packed.block_len,
+            n,
+        )?;
+        let mut blocks = Blocks::new(&packed);
+        blocks.seek(0, self.last);
+        let (lanes, len) = blocks.decode_next().expect("block index out of bounds");
+        for (last, lane) in self.last.iter_mut().zip(&lanes) {
+            *last = lane[packed.block_len];
+        }
+        self.n += 1;
+        Ok((0..len).map(|i| blocks.decimal_at(&lanes, i)).collect())
+    }
+}
+
+impl<R: Read> Iterator for StreamDecoder<R> {
+    type Item = Result<Decimal, FloatpackError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(v) = self.block.next() {
+                self.decoded += 1;
+                return Some(Ok(v));
+            }
+            if self.failed || self.n >= self.header.blocks {
+                return None;
+            }
+            match self.decode_block() {
+                Ok(block) => self.block = block.into_iter(),
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StreamDecoder;
+    use crate::{pack, FloatpackError, PackedDecimals, Packer};
+    use rust_decimal::Decimal;
+
+    fn values(n: i64) -> Vec<Decimal> {
+        (0..n)
+            .map(|v| Decimal::new(v * 13 % 700 - 350, 3))
+            .collect()
+    }
+
+    #[test]
+    fn progress() {
+        let values = values(3000);
+        for packer in [
+            Packer::new(),
+            Packer::new().with_chained_heads().with_sign_magnitude(),
+            Packer::new()
+                .with_block_len(32)
+                .unwrap()
+                .with_adaptive_encoding(),
+        ] {
+            let packed = crate::tests::pack_with(packer, &values);
+            let bytes = packed.to_bytes();
+            let mut decoder = StreamDecoder::new(&bytes[..]).unwrap();
+            assert_eq!(decoder.total_values(), values.len());
+            assert_eq!(decoder.total_blocks(), packed.blocks[0].len());
+            assert_eq!(decoder.block_len(), packed.block_len);
+            let mut decoded = Vec::new();
+            for (i, v) in decoder.by_ref().enumerate() {
+                decoded.push(v.unwrap());
+                assert_eq!(decoded.len(), i + 1);
+            }
+            assert_eq!(decoded, values);
+            assert_eq!(decoder.values_decoded(), values.len());
+            assert_eq!(decoder.blocks_decoded(), packed.blocks[0].len());
+        }
+
+        let bytes = pack(&values).to_bytes();
+        let mut decoder = StreamDecoder::new(&bytes[..]).unwrap();
+        decoder.nth(299).unwrap().unwrap();
+        assert_eq!(decoder.values_decoded(), 300);
+        assert_eq!(decoder.blocks_decoded(), 2);
+    }
+
+    #[test]
+    fn checksums() {
+        let values = values(3000);
+        let packed = pack(&values);
+        let blocks = packed.blocks[0].len();
+        let bytes = packed.to_bytes_with_checksums();
+        assert_eq!(bytes.len(), packed.serialized_len() + 4 * blocks);
+        assert_eq!(PackedDecimals::from_bytes(&bytes).unwrap(), packed);
+        let decoded: Result<Vec<_>, _> = StreamDecoder::new(&bytes[..]).unwrap().collect();
+        assert_eq!(decoded.unwrap(), values);
+
+        // Flip a bit in the middle of block 2, which is preceded by the checksums of 2 blocks.
+        let range = packed.block_ranges()[2].1.clone();
+        let flipped = range.start + 2 * 4 + range.len() / 2;
+        for corrupt in [flipped, range.end + 2 * 4] {
+            let mut bytes = bytes.clone();
+            bytes[corrupt] ^= 0x10;
+            let decoded: Vec<_> = StreamDecoder::new(&bytes[..]).unwrap().collect();
+            assert_eq!(decoded.len(), 2 * 257 + 1);
+            assert!(decoded[..2 * 257].iter().all(Result::is_ok));
+            assert!(matches!(
+                decoded[2 * 257],
+                Err(FloatpackError::ChecksumMismatch(2))
+            ));
+            assert!(matches!(
+                PackedDecimals::from_bytes(&bytes),
+                Err(FloatpackError::ChecksumMismatch(2))
+            ));
+        }
+
+        // Without checksums, the same flip goes unnoticed.
+        let mut plain = packed.to_bytes();
+        plain[range.start + range.len() / 2] ^= 0x10;
+        let decoded: Result<Vec<_>, _> = StreamDecoder::new(&plain[..]).unwrap().collect();
+        assert_ne!(decoded.unwrap(), values);
+    }
+
+    #[test]
+    fn truncated() {
+        let values = values(1000);
+        let bytes = pack(&values).to_bytes();
+        for len in [0, 5, 23] {
+            assert!(StreamDecoder::new(&bytes[..len]).is_err());
+        }
+        let decoder = StreamDecoder::new(&bytes[..bytes.len() - 1]).unwrap();
+        let decoded: Vec<_> = decoder.collect();
+        assert_eq!(decoded.len(), 3 * 257 + 1);
+        assert!(decoded[..3 * 257].iter().all(Result::is_ok));
+        assert!(matches!(decoded[3 * 257], Err(FloatpackError::Io(_))));
+
+        let mut v1 = bytes;
+        v1[4] = 1;
+        assert!(matches!(
+            StreamDecoder::new(&v1[..]),
+            Err(FloatpackError::UnsupportedVersion(1))
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+//! Decoding packs in lockstep and element-wise arithmetic between them.
+use crate::format::check_decimals;
+use crate::{FloatpackError, PackedDecimals, Packer, Values};
+use rust_decimal::Decimal;
+
+/// Pairs of the values at equal indices in `a` and `b`.
+///
+/// Both inputs are decoded lazily, one block at a time each, so at most a block of either is
+/// held in memory. Their blocks don't need to align, e.g. with different block lengths. Returns
+/// [`FloatpackError::LengthMismatch`] with the lengths of `a` and `b` if they differ, and
+/// [`FloatpackError::TypeMismatch`] if either holds values of another type than `Decimal`.
+pub fn zip_decode<'a>(
+    a: &'a PackedDecimals,
+    b: &'a PackedDecimals,
+) -> Result<impl Iterator<Item = (Decimal, Decimal)> + 'a, FloatpackError> {
+    check_decimals(a)?;
+    check_decimals(b)?;
+    if a.len() != b.len() {
+        return Err(FloatpackError::LengthMismatch(a.len(), b.len()));
+    }
+    Ok(a.iter().zip(b))
+}
+
+/// Rows of the values at equal indices in `packs`, like [`zip_decode`] for any count of packs.
+///
+/// Returns [`FloatpackError::LengthMismatch`] with the lengths of the first pack and the
+/// first one of a different length, and [`FloatpackError::TypeMismatch`] as [`zip_decode`]
+/// does. Without any packs, there are no rows.
+pub fn zip_many<'a>(packs: &[&'a PackedDecimals]) -> Result<ZipMany<'a>, FloatpackError> {
+    for packed in packs {
+        check_decimals(packed)?;
+    }
+    let len = packs.first().map_or(0, |p| p.len());
+    if let Some(other) = packs.iter().find(|p| p.len() != len) {
+        return Err(FloatpackError::LengthMismatch(len, other.len()));
+    }
+    Ok(ZipMany {
+        columns: packs.iter().map(|p| p.iter()).collect(),
+        remaining: len,
+    })
+}
+
+/// Iterator over rows of the values of several packs, decoding one block of each at a time.
+///
+/// Created by [`zip_many`].
+pub struct ZipMany<'a> {
+    columns: Vec<Values<'a>>,
+    remaining: usize,
+}
+
+impl Iterator for ZipMany<'_> {
+    type Item = Vec<Decimal>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.columns.iter_mut().map(Iterator::next).collect()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for ZipMany<'_> {}
+
+/// Pack `f` of the values at equal indices in `a` and `b`.
+///
+/// Both inputs are decoded lazily in lockstep, one block at a time. Returns
+/// [`FloatpackError::LengthMismatch`] with the lengths of `a` and `b` if they differ.
+pub fn zip_map(
+    a: &PackedDecimals,
+    b: &PackedDecimals,
+    f: impl Fn(Decimal, Decimal) -> Decimal,
+) -> Result<PackedDecimals, FloatpackError> {
+    try_zip_map(a, b, |_, x, y| Ok(f(x, y)))
+}
+
+/// `a - b` element-wise, see [`zip_map`].
+///
+/// Returns [`FloatpackError::Overflow`] with the index of the first difference overflowing
+/// `Decimal`.
+pub fn sub(a: &PackedDecimals, b: &PackedDecimals) -> Result<PackedDecimals, FloatpackError> {
+    try_zip_map(a, b, |i, x, y| {
+        x.checked_sub(y).ok_or(FloatpackError::Overflow(i))
+    })
+}
+
+/// `a + b` element-wise, see [`zip_map`].
+///
+/// Returns [`FloatpackError::Overflow`] with the index of the first sum overflowing `Decimal`.
+pub fn add(a: &PackedDecimals, b: &PackedDecimals) -> Result<PackedDecimals, FloatpackError> {
+    try_zip_map(a, b, |i, x, y| {
+        x.checked_add(y).ok_or(FloatpackError::Overflow(i))
+    })
+}
+
+/// `a / b` element-wise, see [`zip_map`].
+///
+/// Returns [`FloatpackError::DivisionByZero`] with the index of the first zero in `b`, and
+/// [`FloatpackError::Overflow`] with the index of the first quotient overflowing `Decimal`.
+pub fn div(a: &PackedDecimals, b: &PackedDecimals) -> Result<PackedDecimals, FloatpackError> {
+    try_zip_map(a, b, |i, x, y| {
+        if y.is_zero() {
+            Err(FloatpackError::DivisionByZero(i))
+        } else {
+            x.checked_div(y).ok_or(FloatpackError::Overflow(i))
+        }
+    })
+}
+
+fn try_zip_map(
+    a: &PackedDecimals,
+    b: &PackedDecimals,
+    f: impl Fn(usize, Decimal, Decimal) -> Result<Decimal, FloatpackError>,
+) -> Result<PackedDecimals, FloatpackError> {
+    let mut packer = Packer::new();
+    for (i, (x, y)) in zip_decode(a, b)?.enumerate() {
+        packer.load_decimal(&f(i, x, y)?);
+    }
+    Ok(packer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add, div, sub, zip_decode, zip_many, zip_map};
+    use crate::{pack, unpack, FloatpackError, Packer};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::*;
+
+    fn quotes(n: i64) -> (Vec<Decimal>, Vec<Decimal>) {
+        let bid: Vec<Decimal> = (0..n)
+            .map(|i| Decimal::new(10_000 + i * 7 % 90, 2))
+            .collect();
+        let ask = bid
+            .iter()
+            .enumerate()
+            .map(|(i, b)| b + Decimal::new(1 + i as i64 % 5, 2))
+            .collect();
+        (ask, bid)
+    }
+
+    fn reference(a: &[Decimal], b: &[Decimal], f: fn(Decimal, Decimal) -> Decimal) -> Vec<Decimal> {
+        a.iter().zip(b).map(|(x, y)| f(*x, *y)).collect()
+    }
+
+    #[test]
+    fn element_wise() {
+        let (ask, bid) = quotes(2000);
+        let chained = crate::tests::pack_with(Packer::new().with_chained_heads(), &bid);
+        for bid_packed in [pack(&bid), chained] {
+            let ask_packed = pack(&ask);
+            let spread = sub(&ask_packed, &bid_packed).unwrap();
+            assert_eq!(unpack(&spread), reference(&ask, &bid, |x, y| x - y));
+            let sum = add(&ask_packed, &bid_packed).unwrap();
+            assert_eq!(unpack(&sum), reference(&ask, &bid, |x, y| x + y));
+            let ratio = div(&ask_packed, &bid_packed).unwrap();
+            assert_eq!(unpack(&ratio), reference(&ask, &bid, |x, y| x / y));
+            let mid = zip_map(&ask_packed, &bid_packed, |x, y| (x + y) / dec!(2)).unwrap();
+            assert_eq!(
+                unpack(&mid),
+                reference(&ask, &bid, |x, y| (x + y) / dec!(2))
+            );
+        }
+        assert!(sub(&pack(&[]), &pack(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn length_mismatch() {
+        let (ask, bid) = quotes(600);
+        assert!(matches!(
+            sub(&pack(&ask), &pack(&bid[..599])),
+            Err(FloatpackError::LengthMismatch(600, 599))
+        ));
+        assert!(matches!(
+            zip_map(&pack(&ask[..1]), &pack(&bid), |x, _| x),
+            Err(FloatpackError::LengthMismatch(1, 600))
+        ));
+    }
+
+    #[test]
+    fn lockstep() {
+        let (ask, bid) = quotes(2000);
+        let last: Vec<Decimal> = ask.iter().map(|a| a - dec!(0.01)).collect();
+        let short = crate::tests::pack_with(Packer::new().with_block_len(32).unwrap(), &bid);
+        let chained = crate::tests::pack_with(Packer::new().with_chained_heads(), &last);
+        let ask_packed = pack(&ask);
+
+        let pairs: Vec<_> = zip_decode(&ask_packed, &short).unwrap().collect();
+        let expected: Vec<_> = ask.iter().copied().zip(bid.iter().copied()).collect();
+        assert_eq!(pairs, expected);
+
+        let rows = zip_many(&[&ask_packed, &short, &chained]).unwrap();
+        assert_eq!(rows.len(), 2000);
+        let expected: Vec<_> = (0..2000).map(|i| vec![ask[i], bid[i], last[i]]).collect();
+        assert_eq!(rows.collect::<Vec<_>>(), expected);
+        assert_eq!(zip_many(&[]).unwrap().count(), 0);
+        assert_eq!(zip_many(&[&pack(&[]), &pack(&[])]).unwrap().count(), 0);
+
+        let shorter = pack(&bid[..1999]);
+        assert!(matches!(
+            zip_decode(&ask_packed, &shorter),
+            Err(FloatpackError::LengthMismatch(2000, 1999))
+        ));
+        assert!(matches!(
+            zip_many(&[&ask_packed, &short, &shorter, &pack(&[])]),
+            Err(FloatpackError::LengthMismatch(2000, 1999))
+        ));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let (ask, mut bid) = quotes(600);
+        bid[300] = Decimal::ZERO;
+        bid[400] = Decimal::ZERO;
+        assert!(matches!(
+            div(&pack(&ask), &pack(&bid)),
+            Err(FloatpackError::DivisionByZero(300))
+        ));
+    }
+
+    #[test]
+    fn overflow() {
+        let (mut ask, mut bid) = quotes(600);
+        ask[300] = Decimal::MAX;
+        bid[300] = dec!(-1);
+        ask[400] = Decimal::MIN;
+        bid[400] = dec!(-1);
+        assert!(matches!(
+            sub(&pack(&ask), &pack(&bid)),
+            Err(FloatpackError::Overflow(300))
+        ));
+        assert!(matches!(
+            add(&pack(&ask), &pack(&bid)),
+            Err(FloatpackError::Overflow(400))
+        ));
+        bid[300] = dec!(0.5);
+        assert!(matches!(
+            div(&pack(&ask), &pack(&bid)),
+            Err(FloatpackError::Overflow(300))
+        ));
+    }
+}